@@ -0,0 +1,63 @@
+//! Compares reading many pages one after the other with `Database::parallel_read_pages`.
+//! ```bash
+//! $ cargo +nightly bench --features rayon --bench parallel_read
+//! ```
+//! The database is kept in a temporary file, so results depend on the storage `std::env::temp_dir` lives on. The OS page cache will typically serve most reads after the first iteration.
+
+#![feature(test)]
+
+extern crate test;
+
+use std::fs::File;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tempfile::NamedTempFile;
+use test::Bencher;
+
+use datastore_provider::format::database::Database;
+
+const PAGES: usize = 100;
+const PAGE_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Metadata {}
+
+fn setup() -> (NamedTempFile, Database<File, Metadata>, Vec<String>) {
+    let file = NamedTempFile::new().expect("Failed to create temporary file");
+    let mut db = Database::create(file.reopen().expect("Failed to open temporary file"), Metadata::default())
+        .expect("Failed to create database");
+
+    let names = (0..PAGES)
+        .map(|i| format!("page-{}", i))
+        .collect::<Vec<_>>();
+    for (i, name) in names.iter().enumerate() {
+        db.create_or_replace_page(name, &vec![i as u8; PAGE_SIZE])
+            .expect("Failed to create page");
+    }
+
+    (file, db, names)
+}
+
+#[bench]
+fn sequential(b: &mut Bencher) {
+    let (_file, db, names) = setup();
+
+    b.iter(|| names.iter()
+        .map(|name| {
+            let mut content = Vec::with_capacity(PAGE_SIZE);
+            db.pipe_page(name, &mut content).expect("Failed to read page");
+            content
+        })
+        .collect::<Vec<_>>());
+}
+
+#[bench]
+fn parallel(b: &mut Bencher) {
+    let (_file, db, names) = setup();
+    let names = names.iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+
+    b.iter(|| db.parallel_read_pages(&names).expect("Failed to read pages"));
+}
@@ -1,5 +1,14 @@
-/// Stores access information - this structure does no enforcement of access of any sorts. It is up to the caller to interpret and check this.
-#[derive(Debug, Clone)]
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Stores access information - this structure does no enforcement of access of any sorts. It is up to the caller to interpret and check this, such as with `Access::check`.
+/// The database only enforces access control lists through `Database::open_page_as` and `Database::create_page_as`; every other method ignores them.
+/// Serialises as `{ "type": "ReadWrite", "entity": "alice" }`, with an additional `bits` field for `Custom` entries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "AccessRepr", into = "AccessRepr")]
 pub enum Access {
     None(String),
     Read(String),
@@ -8,3 +17,160 @@ pub enum Access {
     ReadExecute(String),
     Custom(String, u8)
 }
+
+/// The serde representation of `Access`. Internally tagged enums can't hold plain strings, so each variant names its entity.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum AccessRepr {
+    None { entity: String },
+    Read { entity: String },
+    ReadWrite { entity: String },
+    ReadWriteExecute { entity: String },
+    ReadExecute { entity: String },
+    Custom { entity: String, bits: u8 },
+}
+
+impl From<AccessRepr> for Access {
+    fn from(value: AccessRepr) -> Self {
+        match value {
+            AccessRepr::None { entity } => Self::None(entity),
+            AccessRepr::Read { entity } => Self::Read(entity),
+            AccessRepr::ReadWrite { entity } => Self::ReadWrite(entity),
+            AccessRepr::ReadWriteExecute { entity } => Self::ReadWriteExecute(entity),
+            AccessRepr::ReadExecute { entity } => Self::ReadExecute(entity),
+            AccessRepr::Custom { entity, bits } => Self::Custom(entity, bits),
+        }
+    }
+}
+
+impl From<Access> for AccessRepr {
+    fn from(value: Access) -> Self {
+        match value {
+            Access::None(entity) => Self::None { entity },
+            Access::Read(entity) => Self::Read { entity },
+            Access::ReadWrite(entity) => Self::ReadWrite { entity },
+            Access::ReadWriteExecute(entity) => Self::ReadWriteExecute { entity },
+            Access::ReadExecute(entity) => Self::ReadExecute { entity },
+            Access::Custom(entity, bits) => Self::Custom { entity, bits },
+        }
+    }
+}
+
+impl Access {
+    /// The entity the access entry applies to.
+    pub fn entity(&self) -> &str {
+        match self {
+            Self::None(entity) => entity,
+            Self::Read(entity) => entity,
+            Self::ReadWrite(entity) => entity,
+            Self::ReadWriteExecute(entity) => entity,
+            Self::ReadExecute(entity) => entity,
+            Self::Custom(entity, _) => entity,
+        }
+    }
+
+    /// Whether the entry applies to `entity`. The entry's own entity may be a glob pattern, such as `*` or `group:*`.
+    pub fn applies_to(&self, entity: &str) -> bool {
+        glob_match(self.entity(), entity)
+    }
+
+    /// Whether the entry applies to `entity` and grants at least the permissions in `required_bits`. See `AccessLevel` for the common combinations.
+    pub fn check(&self, entity: &str, required_bits: u8) -> bool {
+        self.applies_to(entity) && self.bits() & required_bits == required_bits
+    }
+
+    /// The entry `bits` stands for, as stored in the inode table. Combinations without a variant of their own become `Custom`.
+    pub fn from_bits(entity: String, bits: u8) -> Self {
+        match bits {
+            0b000 => Self::None(entity),
+            0b001 => Self::Read(entity),
+            0b011 => Self::ReadWrite(entity),
+            0b111 => Self::ReadWriteExecute(entity),
+            0b101 => Self::ReadExecute(entity),
+            perm => Self::Custom(entity, perm),
+        }
+    }
+
+    /// The permission-hint byte as stored in the inode table.
+    pub fn bits(&self) -> u8 {
+        match self {
+            Self::None(_) => 0b000,
+            Self::Read(_) => 0b001,
+            Self::ReadWrite(_) => 0b011,
+            Self::ReadWriteExecute(_) => 0b111,
+            Self::ReadExecute(_) => 0b101,
+            Self::Custom(_, perm) => *perm,
+        }
+    }
+}
+
+/// A level of access an entity can be required to hold, as the permission bits it corresponds to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AccessLevel {
+    Read = 0b001,
+    ReadWrite = 0b011,
+    ReadWriteExecute = 0b111,
+    ReadExecute = 0b101,
+}
+
+impl AccessLevel {
+    pub fn bits(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Formats the entry as `entity:rwx`. Bits beyond the first three are appended in hex.
+impl Display for Access {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let bits = self.bits();
+
+        write!(f, "{}:", self.entity())?;
+
+        for (bit, flag) in [(0b001, 'r'), (0b010, 'w'), (0b100, 'x')] {
+            if bits & bit != 0 {
+                write!(f, "{}", flag)?;
+            }
+        }
+
+        if bits & !0b111 != 0 {
+            write!(f, "+{:#04x}", bits & !0b111)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Match `name` against a glob `pattern`, where `*` matches any run of characters (including none) and `?` matches exactly one.
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    let (mut p, mut n) = (0, 0);
+    // The position of the last `*` and the position in `name` it was tried against
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            },
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match backtrack {
+                // Let the last `*` swallow one more character and retry
+                Some((star, from)) => {
+                    backtrack = Some((star, from + 1));
+                    p = star + 1;
+                    n = from + 1;
+                },
+                None => return false,
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
@@ -0,0 +1,78 @@
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+
+use crate::error::Error;
+use crate::format::Array;
+
+/// A request sent by a `DBAgent` to its database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentRequest {
+    /// Reserve a chunk of at least the given number of bytes
+    Allocate(u64),
+    /// Create or replace the named page with the given reserved chunks as its content
+    Install(String, Vec<Array>),
+    /// Return reserved chunks to the free list
+    Release(Vec<Array>),
+}
+
+/// The database's answer to an `AgentRequest`. Responses are sent in the order the requests were received.
+#[derive(Debug)]
+pub enum AgentResponse {
+    /// The chunks reserved in answer to `AgentRequest::Allocate`
+    Allocated(Vec<Array>),
+    /// An `AgentRequest::Install` or `AgentRequest::Release` was carried out
+    Done,
+    /// The request couldn't be carried out
+    Failed(std::io::Error),
+}
+
+/// A handle through which a database can be asked for space without holding it, such as from another thread. Created by `Database::agent`.
+/// Requests are only handled when the database writes its header (or `process_agent_requests` is called), so every call blocks until then. Calling it from the thread which would have to write the header never returns.
+pub struct DBAgent {
+    requests: Sender<AgentRequest>,
+    responses: Receiver<AgentResponse>,
+}
+
+impl DBAgent {
+    pub(crate) fn new(requests: Sender<AgentRequest>, responses: Receiver<AgentResponse>) -> Self {
+        Self { requests, responses }
+    }
+
+    /// Reserve at least `min_space` bytes of the database's backing object. The chunks stay reserved for this agent, even across compaction, until they're installed into a page with `install_chunks` or returned with `release_chunks`.
+    /// Fails if the database has been dropped or has handed out another agent since.
+    pub fn allocate_chunks(&self, min_space: u64) -> Result<Vec<Array>, Error> {
+        match self.request(AgentRequest::Allocate(min_space))? {
+            AgentResponse::Allocated(chunks) => Ok(chunks),
+            _ => Err(Error::misc("The database answered with an unexpected response")),
+        }
+    }
+
+    /// Make the given reserved chunks the content of the page `name`, creating it if it doesn't exist. Whatever the page referenced before is freed.
+    /// Fails with `Error::Busy` if the page is open, and without changing anything if any of the chunks wasn't reserved by `allocate_chunks`.
+    pub fn install_chunks(&self, name: impl AsRef<str>, chunks: Vec<Array>) -> Result<(), Error> {
+        match self.request(AgentRequest::Install(name.as_ref().to_owned(), chunks))? {
+            AgentResponse::Done => Ok(()),
+            _ => Err(Error::misc("The database answered with an unexpected response")),
+        }
+    }
+
+    /// Give reserved chunks back to the database, so they can be handed out again.
+    /// Fails without changing anything if any of the chunks wasn't reserved by `allocate_chunks`.
+    pub fn release_chunks(&self, chunks: Vec<Array>) -> Result<(), Error> {
+        match self.request(AgentRequest::Release(chunks))? {
+            AgentResponse::Done => Ok(()),
+            _ => Err(Error::misc("The database answered with an unexpected response")),
+        }
+    }
+
+    fn request(&self, request: AgentRequest) -> Result<AgentResponse, Error> {
+        self.requests.send(request)
+            .map_err(|_| Error::misc("The database is no longer listening to this agent"))?;
+
+        match self.responses.recv() {
+            Ok(AgentResponse::Failed(err)) => Err(err.into()),
+            Ok(response) => Ok(response),
+            Err(_) => Err(Error::misc("The database is no longer listening to this agent")),
+        }
+    }
+}
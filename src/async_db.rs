@@ -0,0 +1,147 @@
+use std::future::Future;
+use std::io::Cursor;
+use std::io::SeekFrom;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncSeek;
+use tokio::io::AsyncSeekExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+
+use crate::error::Error;
+use crate::format::database::Database as Format;
+use crate::format::Truncatable;
+use crate::page::Page;
+
+/// The granularity at which `AsyncDatabase::flush` compares the database with what it last wrote. Only blocks which differ are written.
+const FLUSH_BLOCK_SIZE: usize = 0x1000;
+
+/// Async backing objects whose length can be set explicitly, so that the database doesn't leave stale bytes behind when it shrinks. The async counterpart of `Truncatable`.
+pub trait AsyncTruncatable {
+    /// Truncate or extend the object to exactly `len` bytes. Extending fills the new space with zeros.
+    fn set_len(&mut self, len: u64) -> impl Future<Output = std::io::Result<()>>;
+}
+
+impl AsyncTruncatable for tokio::fs::File {
+    async fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        tokio::fs::File::set_len(self, len).await
+    }
+}
+
+impl AsyncTruncatable for Cursor<Vec<u8>> {
+    async fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        Truncatable::set_len(self, len)
+    }
+}
+
+/// A database whose backing object is only accessed asynchronously, so that it can be used from async tasks without `spawn_blocking`.
+/// The database is read into memory when it's opened, and parsed and modified there by the same code as `Database`. Changes, including those written through pages, reach the backing object once the database is flushed.
+pub struct AsyncDatabase<AsyncBacking, Metadata> where AsyncBacking: AsyncRead + AsyncWrite + AsyncSeek + AsyncTruncatable + Unpin, Metadata: Serialize + DeserializeOwned + Clone {
+    pub(crate) backing: AsyncBacking,
+    /// The database as of the last read or flush, along with any changes made since
+    format: Format<Cursor<Vec<u8>>, Metadata>,
+    /// The content of the backing object as of the last read or flush, which `flush` compares the database with
+    flushed: Vec<u8>,
+}
+
+impl<AsyncBacking, Metadata> AsyncDatabase<AsyncBacking, Metadata> where AsyncBacking: AsyncRead + AsyncWrite + AsyncSeek + AsyncTruncatable + Unpin, Metadata: Serialize + DeserializeOwned + Clone {
+    /// Read the database stored in `backing` and parse it.
+    pub async fn open(mut backing: AsyncBacking) -> Result<Self, Error> {
+        let mut image = vec![];
+        backing.seek(SeekFrom::Start(0)).await?;
+        backing.read_to_end(&mut image).await?;
+
+        Ok(Self {
+            backing,
+            format: Format::open(Cursor::new(image.clone()))?,
+            flushed: image,
+        })
+    }
+
+    /// Create an empty database and write it to `backing`, overwriting whatever it contains.
+    pub async fn create(backing: AsyncBacking, meta: Metadata) -> Result<Self, Error> {
+        let mut db = Self {
+            backing,
+            format: Format::create(Cursor::new(vec![]), meta)?,
+            flushed: vec![],
+        };
+        db.flush().await?;
+
+        Ok(db)
+    }
+
+    /// Create an empty page and open it, then write the database back to the backing object. Content written to the page is kept in memory until the database is flushed again.
+    /// Fails with `AlreadyExists` if a page of the same name exists.
+    pub async fn create_page(&mut self, name: &str) -> Result<Page<Cursor<Vec<u8>>>, Error> {
+        let page = self.format.create_page(name)?;
+        self.flush().await?;
+
+        Ok(page)
+    }
+
+    /// Open an existing page by name, with its cursor at the start of its content.
+    /// Fails with `NotFound` if there's no such page, and `Busy` while it's opened exclusively.
+    pub async fn open_page(&mut self, name: &str) -> Result<Page<Cursor<Vec<u8>>>, Error> {
+        Ok(self.format.open_page(name)?)
+    }
+
+    /// Record the changes of pages flushed since, and write the database back to the backing object.
+    /// Only the blocks which changed since the last read or flush are written, and the backing object is cut down to the database's length, should it have shrunk.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.format.sync_pages()?;
+
+        // The changed blocks are copied over the previously flushed content while the database is locked, and written from there once it's been released.
+        // Blocks past the end of the previously flushed content are always written, as the backing object may hold anything there.
+        let mut runs: Vec<(usize, usize)> = vec![];
+        {
+            let backing = self.format.backing
+                .lock_backing()
+                .map_err(Error::other)?;
+            let image = backing.get_ref();
+            let previous = self.flushed.len();
+            self.flushed.resize(image.len(), 0);
+
+            let mut offset = 0;
+            while offset < image.len() {
+                let end = (offset + FLUSH_BLOCK_SIZE).min(image.len());
+
+                if end > previous || image[offset..end] != self.flushed[offset..end] {
+                    self.flushed[offset..end].copy_from_slice(&image[offset..end]);
+
+                    // Runs of adjacent changed blocks are written at once
+                    match runs.last_mut() {
+                        Some((_, run_end)) if *run_end == offset => *run_end = end,
+                        _ => runs.push((offset, end)),
+                    }
+                }
+
+                offset = end;
+            }
+        }
+
+        if let Err(err) = self.write_runs(&runs).await {
+            // The backing object may hold only some of the changes, so all of it is written next time
+            self.flushed.clear();
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Write the given ranges of the flushed content to the backing object, and cut it down to the content's length.
+    async fn write_runs(&mut self, runs: &[(usize, usize)]) -> Result<(), Error> {
+        for (start, end) in runs.iter().copied() {
+            self.backing.seek(SeekFrom::Start(start as u64)).await?;
+            self.backing.write_all(&self.flushed[start..end]).await?;
+        }
+
+        // Whatever the backing object held past the end of the database is stale, including when it was created over existing content
+        self.backing.set_len(self.flushed.len() as u64).await?;
+        self.backing.flush().await?;
+
+        Ok(())
+    }
+}
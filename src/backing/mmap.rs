@@ -0,0 +1,132 @@
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+
+use memmap2::MmapMut;
+
+use crate::format::Durable;
+use crate::format::Truncatable;
+
+/// A file accessed through a writable memory map, so that reads and writes are plain copies to and from memory rather than system calls.
+/// Writes reaching past the end of the map at least double the file's size and map it afresh, so that growing it a little at a time doesn't remap it on every write.
+/// The space past the end of the content is trimmed off when the backing object is flushed, synced or dropped.
+/// Changes are only written back to the file with `msync(2)` when the backing object is flushed or synced, such as by a database with `sync_on_write` set.
+pub struct MmapBacking {
+    file: File,
+    map: MmapMut,
+    pos: u64,
+    /// The length of the content, which the file and the map may extend past
+    len: u64,
+}
+
+impl MmapBacking {
+    /// Open the file at `path` for reading and writing, creating it if it doesn't exist, and map it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        let len = map.len() as u64;
+
+        Ok(Self { file, map, pos: 0, len })
+    }
+
+    /// Write the changes made through the map back to the file, blocking until they're written, and trim the file to the length of its content.
+    pub fn flush(&mut self) -> Result<()> {
+        self.map.flush()?;
+
+        match self.map.len() as u64 == self.len {
+            true => Ok(()),
+            false => self.remap(self.len),
+        }
+    }
+
+    /// Resize the file to `len` bytes and map it afresh. Changes made through the old map are already visible through the new one, so they aren't written back.
+    fn remap(&mut self, len: u64) -> Result<()> {
+        self.file.set_len(len)?;
+        self.map = unsafe { MmapMut::map_mut(&self.file)? };
+
+        Ok(())
+    }
+}
+
+impl Read for MmapBacking {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let start = self.pos.min(self.len) as usize;
+        let len = buf.len().min(self.len as usize - start);
+
+        buf[..len].copy_from_slice(&self.map[start..start + len]);
+        self.pos += len as u64;
+
+        Ok(len)
+    }
+}
+
+impl Write for MmapBacking {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let end = self.pos + buf.len() as u64;
+        if end > self.map.len() as u64 {
+            self.remap(end.max(2 * self.map.len() as u64))?;
+        }
+
+        self.map[self.pos as usize..end as usize].copy_from_slice(buf);
+        self.pos = end;
+        self.len = self.len.max(end);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        MmapBacking::flush(self)
+    }
+}
+
+impl Seek for MmapBacking {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+
+        self.pos = pos.ok_or(std::io::Error::new(ErrorKind::InvalidInput, "Seeking to a negative or overflowing position"))?;
+
+        Ok(self.pos)
+    }
+}
+
+/// The file is resized to exactly `len`, so that space the content grows into later reads as zeroes.
+impl Truncatable for MmapBacking {
+    fn set_len(&mut self, len: u64) -> Result<()> {
+        self.remap(len)?;
+        self.len = len;
+
+        Ok(())
+    }
+}
+
+impl Durable for MmapBacking {
+    fn sync(&mut self) -> Result<()> {
+        MmapBacking::flush(self)?;
+        self.file.sync_all()
+    }
+}
+
+/// Trims the space the file was grown by beyond its content. The changes made through the map are left for the operating system to write back.
+impl Drop for MmapBacking {
+    fn drop(&mut self) {
+        if self.map.len() as u64 != self.len {
+            let _ = self.file.set_len(self.len);
+        }
+    }
+}
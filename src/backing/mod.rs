@@ -0,0 +1,7 @@
+//! Backing objects provided by this crate, for when neither a file nor an in-memory buffer fits.
+
+#[cfg(feature = "mmap")]
+pub mod mmap;
+
+#[cfg(feature = "mmap")]
+pub use mmap::MmapBacking;
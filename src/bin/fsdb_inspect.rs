@@ -0,0 +1,43 @@
+use std::fs::OpenOptions;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+
+use datastore_provider::format::database::Database;
+
+/// Dump the tables of an FSDB file to stdout as JSON, or list any problems found in it.
+/// ```bash
+/// $ fsdb_inspect ./test.db [header|strings|inodes|diagnose]
+/// ```
+/// All three tables are printed if no section is specified.
+pub fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+
+    let path = args.next()
+        .ok_or(Error::new(ErrorKind::InvalidInput, "Usage: fsdb_inspect <file> [header|strings|inodes|diagnose]"))?;
+    let section = args.next();
+
+    let file = OpenOptions::new()
+        .read(true)
+        .open(path)?;
+
+    // The metadata object is application-defined, so parse it into a generic value.
+    let db = Database::<_, ron::Value>::open(file)?;
+
+    match section.as_deref() {
+        Some("header") => println!("{}", db.export_header_as_json()?),
+        Some("strings") => println!("{}", db.export_string_table_as_json()?),
+        Some("inodes") => println!("{}", db.export_inode_table_as_json()?),
+        Some("diagnose") => db.diagnose()
+            .iter()
+            .for_each(|message| println!("{}", message)),
+        Some(section) => return Err(Error::new(ErrorKind::InvalidInput, format!("Unknown section '{}'", section))),
+        None => {
+            println!("{}", db.export_header_as_json()?);
+            println!("{}", db.export_string_table_as_json()?);
+            println!("{}", db.export_inode_table_as_json()?);
+        }
+    }
+
+    Ok(())
+}
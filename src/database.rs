@@ -1,34 +1,397 @@
-use std::collections::HashMap;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
 use std::io::Read;
 use std::io::Seek;
 use std::io::Write;
-use std::sync::mpsc::Receiver;
-use std::sync::Arc;
-use std::sync::Mutex;
-use std::sync::RwLock;
-use crate::error::Error;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
 
-use crate::mediator::Mediator;
-use crate::page::Page;
-use crate::page::PageDescriptor;
-use crate::page::PageRequest;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 
-pub struct Database<Backing> where Backing: Read + Write + Seek + 'static  {
-    backing: Mutex<Mediator<Backing>>,
+use crate::access::AccessLevel;
+use crate::agent::DBAgent;
+use crate::error::Error;
+use crate::format::Truncatable;
+use crate::format::database::Database as Format;
+use crate::format::database::DatabaseOpenOptions;
+use crate::format::database::DatabaseError;
+use crate::format::database::DatabaseStats;
+use crate::format::database::IntegrityError;
+use crate::format::metadata::MetadataFormat;
+use crate::format::transaction::Transaction;
+use crate::page::ExclusivePage;
+use crate::page::Page;
 
-    inode_table: HashMap<String, Arc<RwLock<PageDescriptor>>>,
-    string_table: Vec<String>,
+/// The database as seen by applications, managing pages by name.
+/// The binary format (the header, tables and chunk allocation) is left to the format-level database it wraps.
+pub struct Database<Backing, Metadata> where Backing: Read + Write + Seek + 'static, Metadata: Serialize + DeserializeOwned + Clone {
+    /// The parsed database, which owns the backing object and its tables.
+    format: Format<Backing, Metadata>,
+    /// How long opening a page waits for it to stop being busy before failing. Pages fail straight away if unset.
+    lock_timeout: Option<Duration>,
     // TODO: Implement journal
-    command_receiver: Receiver<PageRequest>,
 }
 
-impl<Backing> Database<Backing> where Backing: Read + Write + Seek + 'static  {
-    pub fn change_backing<NewBacking>(self, backing: NewBacking) -> Database<NewBacking>
+impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write + Seek + 'static, Metadata: Serialize + DeserializeOwned + Clone {
+    /// Parse the database stored in `backing`.
+    pub fn open(backing: Backing) -> Result<Self, Error> {
+        Ok(Self::from(Format::open(backing)?))
+    }
+
+    /// Create an empty database in `backing`, overwriting whatever it contains.
+    pub fn create(backing: Backing, meta: Metadata) -> Result<Self, Error> {
+        Ok(Self::from(Format::create(backing, meta)?))
+    }
+
+    /// Move the database into `backing`. See `format::database::Database::change_buffer`.
+    pub fn change_backing<NewBacking>(self, backing: NewBacking) -> Result<Database<NewBacking, Metadata>, Error>
     where NewBacking: Read + Write + Seek + 'static {
-        todo!()
+        Ok(Database {
+            format: self.format.change_buffer(backing)?,
+            lock_timeout: self.lock_timeout,
+        })
     }
 
+    /// Parse the database stored in `backing`, whose page content is encrypted under `key`. Fails with `Corrupted` if `key` doesn't decrypt it.
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted(backing: Backing, key: [u8; 32]) -> Result<Self, Error> {
+        Ok(Self::from(Format::open_encrypted(backing, key)?))
+    }
+
+    /// Create an empty database in `backing` whose page content is encrypted under `key`, overwriting whatever it contains.
+    #[cfg(feature = "encryption")]
+    pub fn create_encrypted(backing: Backing, key: [u8; 32], meta: Metadata) -> Result<Self, Error> {
+        Ok(Self::from(Format::create_encrypted(backing, key, meta)?))
+    }
+
+    /// Create an empty page and open it. Its name is added to the string table and the header is written before it's returned.
+    /// Fails with `AlreadyExists` if a page of the same name exists.
     pub fn create_page<Str: AsRef<str>>(&mut self, page: Str) -> Result<Page<Backing>, Error> {
-        todo!()
+        Ok(self.format.create_page(page.as_ref())?)
+    }
+
+    /// Create the page at `path` along with any of its ancestors which don't exist yet, so that `/a/b/c` also creates `/a` and `/a/b`. See `format::database::Database::create_page_recursive`.
+    pub fn create_page_recursive(&mut self, path: &str) -> Result<Page<Backing>, Error> {
+        Ok(self.format.create_page_recursive(path)?)
+    }
+
+    /// The paths one component deeper than `path`, sorted alphabetically.
+    pub fn list_children(&self, path: &str) -> Vec<String> {
+        self.format.list_children(path)
+    }
+
+    /// The names of all pages starting with `prefix`, sorted alphabetically. See `format::database::Database::find_pages_by_prefix`.
+    pub fn find_pages_by_prefix(&self, prefix: &str) -> Vec<String> {
+        self.format.find_pages_by_prefix(prefix)
+    }
+
+    /// Answer prefix queries from a sorted copy of the page names, trading memory for speed. See `format::database::Database::build_sorted_index`.
+    pub fn build_sorted_index(&mut self) {
+        self.format.build_sorted_index()
+    }
+
+    /// Discard the sorted index built by `build_sorted_index`.
+    pub fn drop_sorted_index(&mut self) {
+        self.format.drop_sorted_index()
+    }
+
+    /// Open an existing page by name, with its cursor at the start of its content.
+    /// Fails with `NotFound` if there's no such page, and `Busy` while it's opened exclusively. The page's access control list isn't checked; see `open_page_as`.
+    pub fn open_page(&mut self, name: &str) -> Result<Page<Backing>, Error> {
+        self.retry_while_busy(|format| format.open_page(name))
+    }
+
+    /// Open an existing page for exclusive access, locking all of its chunks until the returned page is dropped. See `format::database::Database::open_read_write_locked`.
+    /// Fails with `NotFound` if there's no such page, and `Busy` while it's open elsewhere.
+    pub fn open_read_write_locked(&mut self, name: &str) -> Result<ExclusivePage<Backing>, Error> {
+        self.retry_while_busy(|format| format.open_read_write_locked(name))
+    }
+
+    /// Create a page holding everything `reader` yields, allocating `chunk_size` bytes at a time. See `format::database::Database::import_page`.
+    pub fn import_page<R: Read + ?Sized>(&mut self, name: &str, reader: &mut R, chunk_size: usize) -> Result<Page<Backing>, Error> {
+        Ok(self.format.import_page(name, reader, chunk_size)?)
+    }
+
+    /// Create an empty page on behalf of `entity`, which needs write access to the root page `/`, and open it. The new page grants `entity` full access.
+    /// Fails with `NotPermitted` if `entity` may not write to the root page.
+    pub fn create_page_as<Str: AsRef<str>>(&mut self, page: Str, entity: &str) -> Result<Page<Backing>, Error> {
+        Ok(self.format.create_page_as(page.as_ref(), entity)?)
+    }
+
+    /// Open an existing page on behalf of `entity`, which the page's access control list must grant at least `level`.
+    /// Fails with `NotPermitted` if it doesn't, and otherwise as `open_page`.
+    /// ACLs are opt-in: only this and `create_page_as` enforce them, while every other method reaches pages regardless of their access control lists.
+    pub fn open_page_as(&mut self, name: &str, entity: &str, level: AccessLevel) -> Result<Page<Backing>, Error> {
+        self.retry_while_busy(|format| format.open_page_as(name, entity, level))
+    }
+
+    /// Try `open` again for as long as it fails because the page is busy, until the lock timeout passes.
+    fn retry_while_busy<T>(&mut self, mut open: impl FnMut(&mut Format<Backing, Metadata>) -> std::io::Result<T>) -> Result<T, Error> {
+        let deadline = self.lock_timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            match open(&mut self.format) {
+                Err(err) if matches!(err.kind(), ErrorKind::ResourceBusy | ErrorKind::WouldBlock) && deadline.is_some_and(|deadline| Instant::now() < deadline) => std::thread::sleep(Duration::from_millis(1)),
+                result => return Ok(result?),
+            }
+        }
+    }
+
+    /// Delete the named page, releasing its chunks for reuse by the next allocation. Chunks shared with linked pages are kept.
+    /// Fails with `Busy` while the page is open, and `NotFound` if there's no such page.
+    pub fn delete_page(&mut self, name: &str) -> Result<(), Error> {
+        Ok(self.format.delete_page(name)?)
     }
-}
\ No newline at end of file
+
+    /// Start a transaction, whose changes only take effect once it's committed. See `format::transaction::Transaction`.
+    pub fn begin_transaction(&mut self) -> Transaction<'_, Backing, Metadata> {
+        self.format.begin_transaction()
+    }
+
+    /// Remove the strings no page, access control entry or history entry refers to from the string table, returning the number removed.
+    pub fn gc_string_table(&mut self) -> Result<usize, Error> {
+        Ok(self.format.gc_string_table()?)
+    }
+
+    /// Record every write made through pages opened from now on in the write-ahead log at `wal_path` first, replaying any records it already holds.
+    /// See `format::database::Database::enable_wal`.
+    pub fn enable_wal<P: AsRef<Path>>(&mut self, wal_path: P) -> Result<(), Error> {
+        Ok(self.format.enable_wal(wal_path)?)
+    }
+
+    /// Replay the write-ahead log into the backing object and empty it, returning the number of records replayed. Fails with `Busy` while any page is open.
+    pub fn checkpoint(&mut self) -> Result<usize, Error> {
+        Ok(self.format.checkpoint()?)
+    }
+
+    /// Write a self-contained copy of the database to `writer` without writing to the backing object. Pages can't write while the copy is taken.
+    /// See `format::database::Database::snapshot`.
+    pub fn snapshot<W: Write + Seek>(&mut self, writer: W) -> Result<(), Error> {
+        Ok(self.format.snapshot(writer)?)
+    }
+
+    /// Import the pages of the database stored in `source`, returning the number merged. Fails with `AlreadyExists` if any of its pages' names are taken, though the others are still merged.
+    /// See `format::database::Database::merge_from`.
+    pub fn merge_from<SourceMetadata, Source>(&mut self, source: Source) -> Result<usize, Error> where SourceMetadata: Serialize + DeserializeOwned + Clone, Source: Read + Seek {
+        Ok(self.format.merge_from::<SourceMetadata, _>(source)?)
+    }
+
+    /// Compress the named page's content with LZ4, returning the number of bytes it now occupies. Pages opened afterwards read and write the uncompressed content.
+    #[cfg(feature = "compression")]
+    pub fn compress_page(&mut self, name: &str) -> Result<u64, Error> {
+        Ok(self.format.compress_page(name)?)
+    }
+
+    /// Store the named page's content uncompressed again, returning the number of bytes it now occupies.
+    #[cfg(feature = "compression")]
+    pub fn decompress_page(&mut self, name: &str) -> Result<u64, Error> {
+        Ok(self.format.decompress_page(name)?)
+    }
+
+    /// Check the header and every inode table entry against their CRC-32Cs, returning every mismatch found. See `format::database::Database::verify_integrity`.
+    pub fn verify_integrity(&self) -> Result<Vec<IntegrityError>, Error> {
+        Ok(self.format.verify_integrity()?)
+    }
+
+    /// Check the inode and string tables for structural violations, returning every one found. See `format::database::Database::verify`.
+    pub fn verify(&self) -> Result<(), Vec<DatabaseError>> {
+        self.format.verify()
+    }
+
+    /// Rewrite the inode table so that every entry carries all optional fields, returning `false` if they already did. See `format::database::Database::add_missing_entry_fields`.
+    pub fn add_missing_entry_fields(&mut self) -> Result<bool, Error> {
+        Ok(self.format.add_missing_entry_fields()?)
+    }
+
+    /// Upgrade a version 1 database to version 2 in place, keeping all of its pages. See `format::database::Database::migrate_v1_to_v2`.
+    pub fn migrate_v1_to_v2(&mut self) -> Result<(), Error> {
+        Ok(self.format.migrate_v1_to_v2()?)
+    }
+
+    /// Hash the named page's content again and compare it against its recorded checksum, returning `false` if it's been corrupted since.
+    /// Fails if no checksum is recorded. See `Page::content_hash`.
+    pub fn verify_page_hash(&self, name: &str) -> Result<bool, Error> {
+        Ok(self.format.verify_page_integrity(name)?)
+    }
+
+    /// Measure how the backing object's space is divided between page content and free space. See `format::database::DatabaseStats`.
+    pub fn statistics(&self) -> Result<DatabaseStats, Error> {
+        Ok(self.format.statistics()?)
+    }
+
+    /// Rename the page `old` to `new`. The header is written before returning, so the rename is never partially applied.
+    /// Fails with `NotFound` if there's no page named `old`, and `AlreadyExists` if `new` is taken.
+    pub fn rename_page(&mut self, old: &str, new: &str) -> Result<(), Error> {
+        Ok(self.format.rename_page(old, new)?)
+    }
+
+    /// Hand out an agent through which space can be allocated from another thread. See `format::database::Database::agent`.
+    pub fn agent(&mut self) -> DBAgent {
+        self.format.agent()
+    }
+
+    /// Answer the requests the agent has sent since they were last processed, returning the number answered.
+    pub fn process_agent_requests(&mut self) -> Result<usize, Error> {
+        Ok(self.format.process_agent_requests()?)
+    }
+
+    /// Make the database usable again after a thread panicked while holding one of its locks. See `format::database::Database::recover_from_poison`.
+    pub fn recover_from_poison(&mut self) -> Result<(), Error> {
+        Ok(self.format.recover_from_poison()?)
+    }
+
+    /// Serialise the metadata object in `format` from the next time the header is written. See `format::metadata::MetadataFormat`.
+    pub fn set_metadata_format(&mut self, format: MetadataFormat) -> Result<(), Error> {
+        Ok(self.format.set_metadata_format(format)?)
+    }
+
+    /// The number of entries in the free list: chunks released by deleted or rewritten pages, which are reused before searching for gaps.
+    pub fn free_list_len(&self) -> usize {
+        self.format.free_list_len()
+    }
+}
+
+impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write + Seek + Truncatable + 'static, Metadata: Serialize + DeserializeOwned + Clone {
+    /// Move page content into the gaps left by deleted pages and truncate the backing object, returning the number of bytes reclaimed.
+    /// Fails with `Busy` while any page is open. See `format::database::Database::compact`.
+    pub fn compact(&mut self) -> Result<u64, Error> {
+        Ok(self.format.compact()?)
+    }
+}
+
+impl<Backing, Metadata> From<Format<Backing, Metadata>> for Database<Backing, Metadata> where Backing: Read + Write + Seek + 'static, Metadata: Serialize + DeserializeOwned + Clone {
+    fn from(format: Format<Backing, Metadata>) -> Self {
+        Self { format, lock_timeout: None }
+    }
+}
+
+/// Configures how databases are opened from files, in place of opening the file and passing it to `Database::open`, or creating one with `format::blank`.
+/// ```rust,no_run
+/// # use datastore_provider::database::DatabaseBuilder;
+/// # #[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+/// # struct Metadata { friendly_name: String }
+/// let db = DatabaseBuilder::new()
+///     .create_if_missing()
+///     .with_metadata(Metadata::default())
+///     .max_page_size(0x100000)
+///     .sync_on_write(true)
+///     .open("./test-file.db")?;
+/// # Ok::<(), datastore_provider::error::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct DatabaseBuilder<Metadata> where Metadata: Serialize + DeserializeOwned + Clone {
+    read_only: bool,
+    create_if_missing: bool,
+    metadata: Option<Metadata>,
+    verify_on_open: bool,
+    max_page_size: Option<u64>,
+    lock_timeout: Option<Duration>,
+    sync_on_write: bool,
+}
+
+impl<Metadata> DatabaseBuilder<Metadata> where Metadata: Serialize + DeserializeOwned + Clone {
+    /// Options opening an existing database for reading and writing, without verifying it or limiting its pages.
+    pub fn new() -> Self {
+        Self {
+            read_only: false,
+            create_if_missing: false,
+            metadata: None,
+            verify_on_open: false,
+            max_page_size: None,
+            lock_timeout: None,
+            sync_on_write: false,
+        }
+    }
+
+    /// Open the file for reading only, so that anything writing to it fails. Databases are never created in read-only mode.
+    pub fn read_only(&mut self) -> &mut Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Create the database if the file doesn't exist or is empty. The metadata must be given with `with_metadata`.
+    pub fn create_if_missing(&mut self) -> &mut Self {
+        self.create_if_missing = true;
+        self
+    }
+
+    /// The metadata databases are created with. Existing databases keep their own.
+    pub fn with_metadata(&mut self, meta: Metadata) -> &mut Self {
+        self.metadata = Some(meta);
+        self
+    }
+
+    /// Check the header against its CRC-32C and the tables for structural violations when opening an existing database, failing with `Corrupted` if either is found. See `Database::verify`.
+    pub fn verify_on_open(&mut self, verify: bool) -> &mut Self {
+        self.verify_on_open = verify;
+        self
+    }
+
+    /// Stop pages from growing beyond `max_page_size` bytes through `Page::write_stream`. See `format::database::Database::set_max_page_size`.
+    pub fn max_page_size(&mut self, max_page_size: u64) -> &mut Self {
+        self.max_page_size = Some(max_page_size);
+        self
+    }
+
+    /// Wait up to `timeout` for busy pages to be released when opening them, rather than failing with `Busy` straight away.
+    pub fn lock_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Sync the file to stable storage after every header write, so that each write survives power failure once it has returned. Without it, writes may only have reached the operating system's buffers.
+    /// This costs throughput, so it's disabled by default. See `format::database::Database::fsync`.
+    pub fn sync_on_write(&mut self, sync_on_write: bool) -> &mut Self {
+        self.sync_on_write = sync_on_write;
+        self
+    }
+
+    /// Open the database stored in the file at `path`, creating it if it's missing and that's been asked for.
+    /// Created databases hold only the root page `/`, like those made by `format::blank`. Fails if the database is missing and no metadata was given to create it with.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<Database<File, Metadata>, Error> {
+        let create = self.create_if_missing && !self.read_only;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(!self.read_only)
+            .create(create)
+            .truncate(false)
+            .open(path)?;
+
+        let mut format = if create && file.metadata()?.len() == 0 {
+            let meta = self.metadata.clone()
+                .ok_or(Error::misc("Databases can only be created once their metadata is given with `with_metadata`"))?;
+
+            crate::format::blank_with(meta)?.change_buffer(file)?
+        } else {
+            let format = DatabaseOpenOptions::new()
+                .accept_version(1u32)
+                .accept_version(2u32)
+                .verify_header(self.verify_on_open)
+                .open(file)?;
+
+            if self.verify_on_open && format.verify().is_err() {
+                return Err(Error::Corrupted);
+            }
+
+            format
+        };
+
+        format.set_max_page_size(self.max_page_size);
+        format.set_sync_on_write(self.sync_on_write);
+
+        Ok(Database {
+            format,
+            lock_timeout: self.lock_timeout,
+        })
+    }
+}
+
+impl<Metadata> Default for DatabaseBuilder<Metadata> where Metadata: Serialize + DeserializeOwned + Clone {
+    fn default() -> Self {
+        Self::new()
+    }
+}
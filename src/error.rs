@@ -9,8 +9,13 @@ pub enum Error {
     NotFound,
     NotPermitted,
     Busy,
+    AlreadyExists,
     ParseError,
     TooLarge,
+    /// Content failed authentication, such as when it's decrypted with the wrong key
+    Corrupted,
+    /// The database's format version isn't among those accepted
+    VersionMismatch { found: u32, expected: Vec<u32> },
     Other(Box<dyn std::error::Error + Send + Sync>),
     Misc(String)
 }
@@ -33,17 +38,17 @@ impl Display for Error {
 
 impl std::error::Error for Error {}
 
-impl<E: 'static> From<TryLockError<E>> for Error {
+impl<E> From<TryLockError<E>> for Error {
     fn from(value: TryLockError<E>) -> Self {
         match value {
             TryLockError::WouldBlock => Self::Busy,
-            TryLockError::Poisoned(e) => Self::misc("PoisonError")
+            TryLockError::Poisoned(_) => Self::misc("PoisonError")
         }
     }
 }
 
-impl<E: 'static> From<PoisonError<E>> for Error {
-    fn from(value: PoisonError<E>) -> Self {
+impl<E> From<PoisonError<E>> for Error {
+    fn from(_: PoisonError<E>) -> Self {
         Self::misc("PoisonError")
     }
 }
@@ -53,8 +58,30 @@ impl From<std::io::Error> for Error {
         match value.kind() {
             ErrorKind::NotFound => Self::NotFound,
             ErrorKind::PermissionDenied => Self::NotPermitted,
-            _ => Self::other(value)
+            ErrorKind::AlreadyExists => Self::AlreadyExists,
+            ErrorKind::ResourceBusy | ErrorKind::WouldBlock => Self::Busy,
+            // Errors raised by this crate are carried through `std::io::Error`s by the format-level database
+            _ => match value.downcast::<Self>() {
+                Ok(err) => err,
+                Err(value) => Self::other(value),
+            }
         }
     }
 }
 
+impl From<Error> for std::io::Error {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::NotFound => Self::from(ErrorKind::NotFound),
+            Error::NotPermitted => Self::from(ErrorKind::PermissionDenied),
+            Error::Busy => Self::from(ErrorKind::WouldBlock),
+            Error::AlreadyExists => Self::from(ErrorKind::AlreadyExists),
+            Error::Corrupted => Self::new(ErrorKind::InvalidData, Error::Corrupted),
+            Error::Other(err) => match err.downcast::<std::io::Error>() {
+                Ok(err) => *err,
+                Err(err) => Self::other(err),
+            },
+            err => Self::other(err),
+        }
+    }
+}
@@ -1,14 +1,18 @@
 use std::cmp::Ordering;
 
-#[inline]
-pub fn round(x: u64, n: u64) -> u64 {
-    x + (n - x % n)
-}
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The length of the AES-256-GCM authentication tag stored directly after the ciphertext of encrypted chunks.
+pub const TAG_LENGTH: u64 = 16;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Array {
     pub length: u64,
     pub offset: u64,
+    /// The nonce the chunk's content was encrypted with, if it's encrypted. Encrypted chunks end with an authentication tag, which is included in `length`.
+    #[serde(default)]
+    pub nonce: Option<[u8; 12]>,
 }
 
 impl Array {
@@ -19,6 +23,35 @@ impl Array {
     pub fn end(&self) -> u64 {
         self.offset + self.length
     }
+
+    /// Whether the two arrays share at least one byte. Empty arrays hold no bytes, so they never overlap anything.
+    pub fn overlaps(&self, other: &Array) -> bool {
+        self.intersect(other).is_some()
+    }
+
+    /// The range of bytes both arrays cover, if they share any. The result carries no nonce.
+    pub fn intersect(&self, other: &Array) -> Option<Array> {
+        let offset = self.offset.max(other.offset);
+        let end = self.end().min(other.end());
+
+        (offset < end).then(|| Array { offset, length: end - offset, nonce: None })
+    }
+
+    /// The array covering both arrays, if they're adjacent or overlap. The result carries no nonce.
+    pub fn merge(&self, other: &Array) -> Option<Array> {
+        let offset = self.offset.min(other.offset);
+        let end = self.end().max(other.end());
+
+        (self.offset <= other.end() && other.offset <= self.end()).then(|| Array { offset, length: end - offset, nonce: None })
+    }
+
+    /// The number of bytes of content the chunk holds: its length, less the authentication tag if it's encrypted.
+    pub fn content_length(&self) -> u64 {
+        match self.nonce {
+            Some(_) => self.length.saturating_sub(TAG_LENGTH),
+            None => self.length,
+        }
+    }
 }
 
 impl PartialEq for Array {
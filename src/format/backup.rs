@@ -0,0 +1,137 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result;
+use std::io::Seek;
+use std::io::Write;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::format::database::Database;
+
+/// Precedes the pages of a backup stream, allowing the stream to be validated during restore.
+/// Encoded as three little-endian `u64`s: the timestamp in seconds since the Unix epoch, the page count and the total content length in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupManifest {
+    /// When the backup was taken
+    pub timestamp: SystemTime,
+    /// The number of pages in the backup
+    pub page_count: usize,
+    /// The combined length of the content of all pages in the backup
+    pub total_bytes: u64,
+}
+
+impl BackupManifest {
+    fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        let timestamp = self.timestamp.duration_since(UNIX_EPOCH)
+            .map_err(Error::other)?
+            .as_secs();
+
+        w.write_all(&[timestamp, self.page_count as u64, self.total_bytes]
+            .into_iter()
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>())
+    }
+
+    fn read<R: Read>(r: &mut R) -> Result<Self> {
+        Ok(Self {
+            timestamp: UNIX_EPOCH + Duration::from_secs(read_u64(r)?),
+            page_count: read_u64(r)? as usize,
+            total_bytes: read_u64(r)?,
+        })
+    }
+}
+
+fn read_u64<R: Read>(r: &mut R) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Read a `u64` length prefix followed by that many bytes. The buffer only grows as far as the stream does, so a corrupt length can't exhaust memory.
+fn read_prefixed<R: Read>(r: &mut R) -> Result<Vec<u8>> {
+    let len = read_u64(r)?;
+    let mut buf = vec![];
+    r.take(len).read_to_end(&mut buf)?;
+
+    if buf.len() as u64 != len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "The backup ends mid-page"));
+    }
+
+    Ok(buf)
+}
+
+impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write + Seek, Metadata: Serialize + DeserializeOwned + Clone {
+    /// Write every page modified after `since` to `w`, returning the number of pages written.
+    /// The stream starts with a `BackupManifest`, followed by each page as a length-prefixed (`u64`) name and length-prefixed content.
+    /// Pages are written with their content as it reads back, so compressed pages are decompressed and encrypted pages decrypted. The content is read twice: once to find its length, and once to write it.
+    pub fn incremental_backup<W: Write>(&self, w: &mut W, since: SystemTime) -> Result<usize> {
+        let mut pages = self.leak_inode_table()
+            .into_values()
+            .filter(|page| page.modified > since)
+            .collect::<Vec<_>>();
+        pages.sort_unstable_by(|i, j| Ord::cmp(&i.name, &j.name));
+
+        let lengths = pages.iter()
+            .map(|page| self.pipe_content(page, 0, u64::MAX, &mut std::io::sink()))
+            .collect::<Result<Vec<_>>>()?;
+
+        BackupManifest {
+            timestamp: SystemTime::now(),
+            page_count: pages.len(),
+            total_bytes: lengths.iter().sum(),
+        }.write(w)?;
+
+        for (page, len) in pages.iter().zip(lengths) {
+            w.write_all(&(page.name.len() as u64).to_le_bytes())?;
+            w.write_all(page.name.as_bytes())?;
+            w.write_all(&len.to_le_bytes())?;
+            self.pipe_content(page, 0, len, w)?;
+        }
+
+        Ok(pages.len())
+    }
+
+    /// Read a backup produced by `incremental_backup`, creating or updating each page it contains. Returns the number of pages restored.
+    /// Pages which already exist are only overwritten if `overwrite` is set. The whole stream is read and checked against its manifest before any page is touched, so a truncated or corrupt backup leaves the database as it was.
+    /// The header is written once all pages have been restored.
+    /// > **Note**: The content of every page in the backup is held in memory until it's been checked.
+    pub fn restore_incremental<R: Read>(&mut self, mut r: R, overwrite: bool) -> Result<usize> {
+        self.ensure_unsealed()?;
+
+        let manifest = BackupManifest::read(&mut r)?;
+
+        let mut pages = vec![];
+        for _ in 0..manifest.page_count {
+            let name = String::from_utf8(read_prefixed(&mut r)?)
+                .map_err(Error::other)?;
+            let content = read_prefixed(&mut r)?;
+
+            pages.push((name, content));
+        }
+
+        if pages.iter().map(|(_, content)| content.len() as u64).sum::<u64>() != manifest.total_bytes {
+            return Err(Error::new(ErrorKind::InvalidData, "Backup content doesn't match its manifest"));
+        }
+
+        let existing = self.leak_inode_table();
+        let mut restored = 0;
+
+        for (name, content) in pages {
+            if !overwrite && existing.contains_key(&name) {
+                continue;
+            }
+
+            self.write_page_content(&name, &content)?;
+            restored += 1;
+        }
+
+        self.write_header()?;
+
+        Ok(restored)
+    }
+}
@@ -0,0 +1,61 @@
+use std::io::Read;
+use std::io::Result;
+
+use crc::CRC_32_ISCSI;
+use crc::Crc;
+use crc::Digest;
+
+/// CRC-32C (Castagnoli), which the header and inode table entries are checked with.
+static CRC32C: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+/// The location of the header's CRC-32C: the upper half of the flags field at 0x08, which is otherwise unused.
+pub(crate) const HEADER_CRC: std::ops::Range<usize> = 0x0C..0x10;
+
+/// Compute the CRC-32C of `bytes`.
+pub(crate) fn crc32c(bytes: &[u8]) -> u32 {
+    CRC32C.checksum(bytes)
+}
+
+/// Compute the CRC-32C of the fixed header fields in `header`, with the bytes holding the CRC itself taken to be zero.
+pub(crate) fn header_crc(header: &[u8]) -> u32 {
+    let mut digest = CRC32C.digest();
+    digest.update(&header[..HEADER_CRC.start]);
+    digest.update(&[0x00; 4]);
+    digest.update(&header[HEADER_CRC.end..]);
+
+    digest.finalize()
+}
+
+/// Computes the CRC-32C of everything read through it since it was last reset.
+pub(crate) struct CrcReader<R> {
+    inner: R,
+    digest: Digest<'static, u32>,
+}
+
+impl<R: Read> CrcReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            digest: CRC32C.digest(),
+        }
+    }
+
+    /// Start a new checksum from the next byte read.
+    pub fn reset(&mut self) {
+        self.digest = CRC32C.digest();
+    }
+
+    /// The CRC-32C of the bytes read since the last reset.
+    pub fn crc(&self) -> u32 {
+        self.digest.clone().finalize()
+    }
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.digest.update(&buf[..len]);
+
+        Ok(len)
+    }
+}
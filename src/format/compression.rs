@@ -0,0 +1,91 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// How a page's content is compressed before being written to its chunks. Each algorithm other than `None` requires its feature flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CompressionAlgorithm {
+    /// The content is stored as-is
+    #[default]
+    None,
+    /// LZ4 block compression, favouring latency. Requires the `lz4` feature.
+    Lz4,
+    /// Zstandard compression at the given level. Requires the `zstd` feature.
+    Zstd(i32),
+    /// Snappy raw compression. Requires the `snappy` feature.
+    Snappy,
+}
+
+impl CompressionAlgorithm {
+    /// The algorithm byte as stored in the inode table.
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Lz4 => 1,
+            Self::Zstd(_) => 2,
+            Self::Snappy => 3,
+        }
+    }
+
+    /// The compression level as stored in the inode table. Only meaningful for algorithms which take one.
+    pub fn level(&self) -> i32 {
+        match self {
+            Self::Zstd(level) => *level,
+            _ => 0,
+        }
+    }
+
+    /// Reconstruct an algorithm from its algorithm byte and level.
+    pub fn from_parts(id: u8, level: i32) -> Result<Self> {
+        match id {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Lz4),
+            2 => Ok(Self::Zstd(level)),
+            3 => Ok(Self::Snappy),
+            id => Err(Error::new(ErrorKind::InvalidData, format!("Unrecognised compression algorithm {}", id))),
+        }
+    }
+
+    /// Compress `data` with this algorithm.
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(level) => zstd::bulk::compress(data, *level),
+            #[cfg(feature = "snappy")]
+            Self::Snappy => snap::raw::Encoder::new()
+                .compress_vec(data)
+                .map_err(Error::other),
+            #[allow(unreachable_patterns)]
+            algorithm => Err(algorithm.unsupported()),
+        }
+    }
+
+    /// Decompress `data`, which was compressed with this algorithm.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => lz4_flex::decompress_size_prepended(data)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => zstd::stream::decode_all(data),
+            #[cfg(feature = "snappy")]
+            Self::Snappy => snap::raw::Decoder::new()
+                .decompress_vec(data)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err)),
+            #[allow(unreachable_patterns)]
+            algorithm => Err(algorithm.unsupported()),
+        }
+    }
+
+    #[allow(dead_code)]
+    fn unsupported(&self) -> Error {
+        Error::new(ErrorKind::Unsupported, format!("{:?} compression isn't enabled in this build", self))
+    }
+}
@@ -1,34 +1,317 @@
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::fs::File;
+use std::fs::OpenOptions;
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+use std::os::fd::AsFd;
 use std::io::BufReader;
 use std::io::Cursor;
 use std::io::Error;
 use std::io::Result;
+use std::time::Duration;
 use std::time::SystemTime;
-use std::cmp::Ordering;
+use std::time::UNIX_EPOCH;
+use std::time::Instant;
 use std::iter;
-use std::rc::Rc;
-use std::cell::{RefCell, RefMut, Ref};
+use std::cell::{RefCell, Ref};
 use std::io::{Read, Write, Seek, SeekFrom};
-use std::ops::{Deref, DerefMut};
-use std::sync::{Arc, Mutex};
+use std::path::Path;
+use std::ops::{Bound, Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 
 use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
 use serde::de::DeserializeOwned;
 
 use crate::access::Access;
-use crate::format::array::{Array, round};
+use crate::access::AccessLevel;
+use crate::access::glob_match;
+use crate::agent::AgentRequest;
+use crate::agent::AgentResponse;
+use crate::agent::DBAgent;
+use crate::format::array::Array;
+use crate::format::Truncatable;
+use crate::format::Durable;
+use crate::format::compression::CompressionAlgorithm;
+use crate::format::metadata::MetadataFormat;
+use crate::format::journal::Journal;
+use crate::format::journal::JournalEntry;
+use crate::format::wal::Wal;
+use crate::format::wal::WalOp;
+use crate::format::wal::WalRecord;
+use crate::format::wal::wal_path;
+use crate::format::history::DEFAULT_HISTORY_LIMIT;
+use crate::format::history::HistoryEntry;
+use crate::format::history::HistoryOp;
+use crate::format::transaction::Transaction;
+use crate::format::recovery::scan_reader_for_magic;
+use crate::format::hooks::Hooks;
+use crate::format::checksum::CrcReader;
+use crate::format::checksum::HEADER_CRC;
+use crate::format::checksum::crc32c;
+use crate::format::checksum::header_crc;
+use crate::mediator::Mediator;
+use crate::page::ExclusivePage;
+use crate::page::Page;
 use crate::page::PageDescriptor;
+use crate::page::PageRequest;
 
 #[macro_export]
 macro_rules! get_str {
     ($strtab:expr, $n:expr) => ($strtab.get($n as usize).ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No string found for index {}", $n))));
 }
 
+/// Header flag (in the reserved field at 0x08) indicating that every inode table entry is followed by a user data block.
+pub(crate) const FLAG_USER_DATA: u64 = 0x01;
+/// Header flag indicating that every inode table entry records the length of the page's content, ahead of its user data block.
+pub(crate) const FLAG_CONTENT_LENGTH: u64 = 0x02;
+/// Header flag indicating that every inode table entry records the page's compression algorithm, following its content length.
+pub(crate) const FLAG_COMPRESSION: u64 = 0x04;
+/// Header flag indicating that every inode table entry records a SHA-256 checksum of the page's content, following its compression algorithm.
+pub(crate) const FLAG_CHECKSUM: u64 = 0x08;
+/// Header flag indicating that the database is sealed, and may no longer be modified until it's unsealed.
+pub(crate) const FLAG_SEALED: u64 = 0x10;
+/// Header flag indicating that a SHA-256 hash of the key required to unseal the database directly follows the metadata.
+pub(crate) const FLAG_SEAL_KEY: u64 = 0x20;
+/// Header flag indicating that every inode table entry ends with the nonce of each of its chunks, following its user data block.
+pub(crate) const FLAG_NONCES: u64 = 0x40;
+/// Header flag indicating that the header's CRC-32C is stored in the upper half of the flag field, and every inode table entry is followed by a CRC-32C of its bytes.
+pub(crate) const FLAG_CRC: u64 = 0x80;
+/// Header flag bits recording how the metadata object is serialised. See `MetadataFormat`.
+pub(crate) const FLAG_METADATA_FORMAT: u64 = 0x300;
+/// Header flag indicating that the history table is followed by the free list.
+pub(crate) const FLAG_FREE_LIST: u64 = 0x400;
+/// Header flag indicating that every inode table entry records when the page was created and last modified, following its nonces.
+pub(crate) const FLAG_TIMESTAMPS: u64 = 0x800;
+
+/// Configures which format versions `Database`s are opened with, similarly to `std::fs::OpenOptions`.
+/// ```rust
+/// # use datastore_provider::format::database::{Database, DatabaseOpenOptions};
+/// # #[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+/// # struct Metadata { friendly_name: String }
+/// # let file = tempfile::tempfile()?;
+/// # Database::create(file.try_clone()?, Metadata::default())?;
+/// let db = DatabaseOpenOptions::new()
+///     .accept_version(1u32)
+///     .accept_version(2u32)
+///     .open::<_, Metadata>(file)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseOpenOptions {
+    versions: Vec<u32>,
+    offset: u64,
+    verify_header: bool,
+}
+
+impl DatabaseOpenOptions {
+    /// Options accepting no versions at all. At least one must be added before opening.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept databases of the given format version.
+    pub fn accept_version<V: Into<u32>>(&mut self, version: V) -> &mut Self {
+        let version = version.into();
+        if !self.versions.contains(&version) {
+            self.versions.push(version);
+        }
+        self
+    }
+
+    /// Look for the header at `offset` rather than at the start of the backing buffer, such as for databases embedded within a larger file.
+    pub fn at_offset(&mut self, offset: u64) -> &mut Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Check the header against its CRC-32C before parsing anything it points to, failing with `Corrupted` if they don't match.
+    /// Headers written before checksums were introduced have no CRC, and are accepted as-is.
+    pub fn verify_header(&mut self, verify: bool) -> &mut Self {
+        self.verify_header = verify;
+        self
+    }
+
+    /// Parse the backing buffer into a Database object. Fails with `VersionMismatch` if the database's version isn't accepted, or isn't supported by this build.
+    pub fn open<Backing, Metadata>(&self, backing: Backing) -> Result<Database<Backing, Metadata>>
+    where Backing: Read + Write + Seek, Metadata: Serialize + DeserializeOwned + Clone {
+        Database::open_accepting(backing, &self.versions, self.offset, self.verify_header)
+    }
+}
+
+/// A checksum which doesn't match the bytes it covers, as reported by `Database::verify_integrity`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityError {
+    /// The header's CRC-32C doesn't match its fields
+    Header { expected: u32, found: u32 },
+    /// The CRC-32C following an inode table entry doesn't match the entry. The page's name is given if its string table index resolves.
+    InodeEntry { index: usize, name: Option<String>, expected: u32, found: u32 },
+}
+
+/// A structural violation found by `Database::verify`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseError {
+    /// A chunk extends past the end of the backing object
+    ChunkOutOfBounds { page: String, chunk: Array, backing_length: u64 },
+    /// Two distinct chunks share bytes of the backing object
+    OverlappingChunks { first: (String, Array), second: (String, Array) },
+    /// A chunk has length 0
+    EmptyChunk { page: String, chunk: Array },
+    /// A page's name or one of its access control entities isn't in the string table, so it has no index to be serialised under
+    MissingString { page: String, string: String },
+    /// The string count recorded in the string table's range doesn't match the number of strings in the table
+    StringCountMismatch { recorded: u64, actual: u64 },
+}
+
+/// A page write staged by `Database::two_phase_commit`.
+#[derive(Debug, Clone)]
+pub struct PageWrite {
+    /// The name of the page to write to. The page is created if it doesn't exist yet.
+    pub name: String,
+    /// The page's new content. It replaces the existing content entirely.
+    pub content: Vec<u8>,
+}
+
+/// How `Database::merge` resolves pages which exist in both databases with differing content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep this database's version of the page
+    KeepSelf,
+    /// Replace this database's version with the other's
+    KeepOther,
+    /// Keep whichever version was modified most recently
+    KeepNewer,
+    /// Abort the merge on the first conflict
+    Error,
+}
+
+/// What `Database::apply_migration` should do with a page once the migration function has seen it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationAction {
+    /// Keep the page, applying any changes made to its descriptor or content
+    Keep,
+    /// Delete the page
+    Delete,
+    /// Move the page to the given name, applying any changes made to its descriptor or content
+    Rename(String),
+    /// Leave the page exactly as it was, discarding any changes made to it
+    Skip,
+}
+
+/// A summary of the changes made by `Database::apply_migration`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MigrationResult {
+    pub kept: usize,
+    pub deleted: usize,
+    pub renamed: usize,
+    pub skipped: usize,
+    /// The number of content bytes written back for pages whose content was changed
+    pub bytes_written: u64,
+}
+
+/// How the backing object's space is divided between page content and free space, as reported by `Database::statistics`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DatabaseStats {
+    /// The length of the backing object
+    pub total_bytes: u64,
+    /// The bytes occupied by page content. Chunks shared by linked pages are counted once
+    pub live_bytes: u64,
+    /// The bytes used by neither the tables nor any page
+    pub free_bytes: u64,
+    /// The fraction of the space past the header and tables which doesn't hold page content, from 0 to 1
+    pub fragmentation_ratio: f64,
+    pub page_count: usize,
+    pub chunk_count: usize,
+    pub string_count: usize,
+    /// The length of the largest free region, which bounds the largest chunk that can be allocated without growing the backing object
+    pub largest_gap: u64,
+}
+
+/// A change to a page, as reported to the receivers returned by `Database::watch_all_pages` and `Database::watch_namespace`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEvent {
+    Created,
+    Modified,
+    Deleted,
+    ACLChanged,
+}
+
+/// Implemented by metadata objects which record the version of the application-defined schema a database follows, so that `Database::open_or_create_with_migration` can tell when it needs migrating.
+pub trait HasVersion {
+    fn version(&self) -> u32;
+    fn set_version(&mut self, version: u32);
+}
+
+/// What a region of the backing object is used for, as reported by `Database::get_total_layout`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutRegion {
+    /// The header, including the salt of version 2 databases
+    Header,
+    /// The serialised metadata object
+    Metadata,
+    InodeTable,
+    StringTable,
+    HistoryTable,
+    /// A chunk of the named page
+    PageChunk(String),
+    /// Space which isn't referenced by anything
+    Free,
+}
+
+/// How serious a `DiagnosticMessage` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Worth knowing, but harmless
+    Info,
+    /// Wasteful or suspicious, but the database is still sound
+    Warning,
+    /// The database is structurally broken
+    Error,
+}
+
+/// A single finding reported by `Database::diagnose`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticMessage {
+    pub severity: Severity,
+    /// A short, stable identifier of the kind of finding, such as `overlapping-chunks`
+    pub code: &'static str,
+    pub message: String,
+    /// The offset in the backing object the finding relates to, if any
+    pub location: Option<u64>,
+}
+
+impl std::fmt::Display for DiagnosticMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+
+        match self.location {
+            Some(location) => write!(f, "{}[{}] at {:#x}: {}", severity, self.code, location, self.message),
+            None => write!(f, "{}[{}]: {}", severity, self.code, self.message),
+        }
+    }
+}
+
+/// A chunk, along with the name of the page it belongs to.
+pub type NamedChunk<'a> = (&'a str, Array);
+
+/// Checks a page before `Database::open_page` hands it out. See `Database::with_page_validator`.
+type PageValidator = Box<dyn Fn(&PageDescriptor) -> Result<()> + Send>;
+
+/// A page's content and access control list, as recorded in the journal along with a change to it.
+type Journaled = (Vec<u8>, Vec<Access>);
+
 /// Contains information about the database, providing a clean interface to accessing it.
 /// This object represents the on-disk parseable format which can be transformed into a live Database object for consumption.
 pub struct Database<Buffer, Metadata> where Buffer: Read + Write + Seek, Metadata: Serialize + DeserializeOwned + Clone {
     /// The underlying data source. As long as it supports Read, Write and Seek operations, this can be anything.
-    pub(crate) backing: Rc<RefCell<Buffer>>,
+    pub(crate) backing: Arc<Mediator<Buffer>>,
     /// Number of elements in inode table + Offset
     pub(crate) inode_table_range: Array,
     /// Number of elements in string table + Offset
@@ -40,20 +323,72 @@ pub struct Database<Buffer, Metadata> where Buffer: Read + Write + Seek, Metadat
     
     inode_table: HashMap<String, PageDescriptor>,
     string_table: RefCell<Vec<String>>,
+    /// Every recorded change to a page, oldest first
+    history_table: Vec<HistoryEntry>,
+    /// The number of entries `history_table` is capped at, dropping the oldest first. See `set_history_limit`. Not persisted.
+    history_limit: Option<usize>,
     
     inode_table_size: u64,
     string_table_size: u64,
     history_table_size: u64,
     
     borrowed_slices: Arc<Mutex<Vec<Array>>>,
+    /// Chunks released by deleted or rewritten pages, which `allocate_chunks` hands out before searching for gaps. Adjacent chunks are merged. Persisted after the history table.
+    free_list: Vec<Array>,
+    
+    /// Callbacks notified of changes to pages
+    hooks: Hooks,
+    
+    /// For every page which shares its chunks with others, the names of those other pages. Rebuilt along with `PageDescriptor::link_count`.
+    linked_pages: HashMap<String, Vec<String>>,
+
+    /// Liveness tokens of the pages handed out by `open_page`. A page is open for as long as its token has a strong reference.
+    open_pages: HashMap<String, Vec<Weak<()>>>,
+    /// Liveness tokens of the pages handed out by `open_read_write_locked`. While one is alive, no other instance of the page may be opened.
+    exclusive_pages: HashMap<String, Weak<()>>,
+
+    /// Checked against every page before it's opened
+    page_validator: Option<PageValidator>,
+
+    /// How often each page has been opened during this session. Not persisted.
+    page_access_counts: HashMap<String, AtomicU64>,
+    /// When each page was last opened during this session. Not persisted.
+    page_access_times: HashMap<String, Instant>,
     
     raw_header: Vec<u8>,
+    /// Where the header starts within the backing buffer. Offsets stored in the file are relative to it.
+    base_offset: u64,
+    /// The salt used to derive the encryption key from a passphrase. Only version 2 headers carry one.
+    salt: Option<[u8; 16]>,
+    /// The SHA-256 hash of the key required by `unseal`, stored directly after the metadata.
+    seal_key_hash: Option<[u8; 32]>,
+    /// Called on the backing object after every header write, if syncing on write is enabled.
+    sync_on_write: Option<fn(&mut Buffer) -> Result<()>>,
+    /// Requests sent by open pages, such as the descriptors of flushed pages. Drained whenever the header is written.
+    page_requests: (Sender<PageRequest>, Receiver<PageRequest>),
+    /// The write-ahead log enabled with `enable_wal`, shared with the pages handed out since. Not persisted.
+    wal: Option<Arc<Mutex<Wal>>>,
+    /// The journal enabled with `enable_journal`, which changes to pages' content are recorded in. Not persisted.
+    journal: Option<Arc<Mutex<Journal>>>,
+    /// The length pages handed out from now on may not grow beyond. See `set_max_page_size`. Not persisted.
+    max_page_size: Option<u64>,
+    /// The `max_page_size` and `max_chunk_size` fields of `meta`, as found by `find_page_limits` when the database was opened or its header was last written. Not persisted.
+    page_limits: (Option<u64>, Option<u64>),
+    /// Every page name in order, so that prefix queries can be answered with a range scan. Built by the first query after `build_sorted_index` and cleared whenever pages are added, removed or renamed. Not persisted.
+    sorted_index: RefCell<Option<BTreeMap<String, ()>>>,
+    /// Whether `find_pages_by_prefix` keeps and uses `sorted_index`
+    sorted_index_enabled: bool,
+    /// The ends of the channels to the agent handed out by `agent`, if any. Not persisted.
+    agent: Option<(Receiver<AgentRequest>, Sender<AgentResponse>)>,
+    /// Chunks allocated for the agent which it hasn't installed into a page or given back yet. They're never handed out again or moved, not even by compaction. Not persisted.
+    agent_reservations: Vec<Array>,
     pub meta: Metadata
 }
 
 impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write + Seek, Metadata: Serialize + DeserializeOwned + Clone {
     /// Parse the backing buffer into a Database object.
-    /// ```rust
+    /// ```rust,no_run
+    /// #[derive(serde::Serialize, serde::Deserialize, Clone)]
     /// struct Metadata {
     ///     pub friendly_name: String,
     ///     pub max_chunk_size: u64,
@@ -69,73 +404,184 @@ impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write
     ///     .open("./test-file.db")?;
     ///
     /// Database::<std::fs::File, Metadata>::open(file)?;
+    /// # Ok::<(), std::io::Error>(())
     /// ```
     /// > **Note**: The `Metadata` structure is completely arbitrary, and the database does not interpret nor otherwise use its values in any way.
-    ///     It's designed to act as a preferences map for use by consumers or hooks of the database.
-    pub fn open(mut backing: Backing) -> Result<Self> {
+    /// > It's designed to act as a preferences map for use by consumers or hooks of the database.
+    pub fn open(backing: Backing) -> Result<Self> {
+        DatabaseOpenOptions::new()
+            .accept_version(1u32)
+            .accept_version(2u32)
+            .open(backing)
+    }
+
+    /// Parse the backing buffer into a Database object, and write its metadata in `format` from the next time the header is written.
+    /// The format the metadata is in is recorded in the header, so `open` reads databases in any of them; this is only needed to change it.
+    pub fn open_with_format(backing: Backing, format: MetadataFormat) -> Result<Self> {
+        let mut db = Self::open(backing)?;
+        db.set_metadata_format(format)?;
+
+        Ok(db)
+    }
+
+    /// Parse the backing buffer into a Database object, provided its format version is exactly `version`.
+    pub fn open_versioned<V: Into<u32>>(backing: Backing, version: V) -> Result<Self> {
+        DatabaseOpenOptions::new()
+            .accept_version(version)
+            .open(backing)
+    }
+
+    /// Parse a database whose header starts at `offset` within the backing buffer, rather than at its start.
+    /// Offsets stored in the embedded database are relative to its header, and are translated back when the header is written.
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use datastore_provider::format::database::Database;
+    /// # #[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+    /// # struct Metadata { friendly_name: String }
+    /// # let db = Database::<_, Metadata>::open(File::open("./container.bin")?)?;
+    /// for offset in db.scan_for_magic()? {
+    ///     let embedded = Database::<_, Metadata>::open_at_offset(File::open("./container.bin")?, offset)?;
+    /// }
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn open_at_offset(backing: Backing, offset: u64) -> Result<Self> {
+        DatabaseOpenOptions::new()
+            .accept_version(1u32)
+            .accept_version(2u32)
+            .at_offset(offset)
+            .open(backing)
+    }
+
+    /// Run `validator` over the entire raw content of the backing buffer before parsing it, such as to check a signature or checksum covering the whole file.
+    /// The database is only opened if the validator succeeds; otherwise its error is returned as-is.
+    /// ```rust
+    /// # use datastore_provider::format::database::Database;
+    /// # #[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+    /// # struct Metadata { friendly_name: String }
+    /// # fn verify_signature(bytes: &[u8], key: &[u8; 32]) -> std::io::Result<()> { Ok(()) }
+    /// # let public_key = [0u8; 32];
+    /// # let file = tempfile::tempfile()?;
+    /// # Database::create(file.try_clone()?, Metadata::default())?;
+    /// let db = Database::<_, Metadata>::open_with_pre_validation(file, |bytes| verify_signature(bytes, &public_key))?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn open_with_pre_validation<Validator: Fn(&[u8]) -> Result<()>>(mut backing: Backing, validator: Validator) -> Result<Self> {
+        let mut bytes = vec![];
+        backing.seek(SeekFrom::Start(0))?;
+        backing.read_to_end(&mut bytes)?;
+
+        validator(&bytes)?;
+
+        Self::open(backing)
+    }
+
+    /// Parse the backing buffer, failing with `VersionMismatch` unless its format version is among `versions`.
+    fn open_accepting(mut backing: Backing, versions: &[u32], base_offset: u64, verify_header: bool) -> Result<Self> {
         let mut reader = BufReader::new(&mut backing);
-        reader.seek(std::io::SeekFrom::Start(0))?;
+        reader.seek(std::io::SeekFrom::Start(base_offset))?;
 
         let mut buf = vec![0u8; 4 + 4 + 4 + 4 + (4 * (2 * 8))];
         reader.read_exact(&mut buf)?;
         if &buf[0..4] != b"FSDB" { return Err(Error::other("Invalid Magic Number")); }
-        if buf[4..8] != [0x01, 0, 0, 0] { return Err(Error::other("Unrecognised version")); }
+
+        let version = u32::from_le_bytes(buf[4..8]
+            .try_into()
+            .map_err(Error::other)?);
+
+        // Only versions 1 and 2 are understood by this parser
+        if !versions.contains(&version) || !matches!(version, 1 | 2) {
+            return Err(Error::new(std::io::ErrorKind::Unsupported, crate::error::Error::VersionMismatch {
+                found: version,
+                expected: versions.to_vec(),
+            }));
+        }
+
+        let salt = match version {
+            2 => Self::parse_v2(&mut reader)?,
+            _ => None
+        };
+
+        let flags = u64::from_le_bytes(buf[8..16]
+            .try_into()
+            .map_err(Error::other)?);
+
+        if verify_header && flags & FLAG_CRC != 0 && header_crc(&buf).to_le_bytes() != buf[HEADER_CRC] {
+            return Err(Error::new(std::io::ErrorKind::InvalidData, crate::error::Error::Corrupted));
+        }
 
         let inode_table_range = Array {
             length: u64::from_le_bytes(buf[16..24]
                 .try_into()
                 .map_err(Error::other)?),
-            offset: u64::from_le_bytes(buf[24..32]
+            offset: base_offset + u64::from_le_bytes(buf[24..32]
                 .try_into()
-                .map_err(Error::other)?)
+                .map_err(Error::other)?),
+                nonce: None,
         };
 
         let string_table_range = Array {
             length: u64::from_le_bytes(buf[32..40]
                 .try_into()
                 .map_err(Error::other)?),
-            offset: u64::from_le_bytes(buf[40..48]
+            offset: base_offset + u64::from_le_bytes(buf[40..48]
                 .try_into()
-                .map_err(Error::other)?)
+                .map_err(Error::other)?),
+                nonce: None,
         };
 
         let history_table_range = Array {
             length: u64::from_le_bytes(buf[48..56]
                 .try_into()
                 .map_err(Error::other)?),
-            offset: u64::from_le_bytes(buf[56..64]
+            offset: base_offset + u64::from_le_bytes(buf[56..64]
                 .try_into()
-                .map_err(Error::other)?)
+                .map_err(Error::other)?),
+                nonce: None,
         };
 
         let metadata_range = Array {
             length: u64::from_le_bytes(buf[64..72]
                     .try_into()
                     .map_err(Error::other)?),
-            offset: u64::from_le_bytes(buf[72..80]
+            offset: base_offset + u64::from_le_bytes(buf[72..80]
                     .try_into()
-                    .map_err(Error::other)?)
+                    .map_err(Error::other)?),
+                    nonce: None,
         };
 
-        let backing = Rc::new(RefCell::new(backing));
+        // Every table has to lie within the backing object, and each entry takes up at least one byte, so a damaged header can't lead to huge allocations
+        let backing_len = reader.seek(SeekFrom::End(0))?;
+        for table in [inode_table_range, string_table_range, history_table_range] {
+            Self::bounded_length(table.length, backing_len.saturating_sub(table.offset))?;
+        }
+        Self::bounded_length(metadata_range.offset.saturating_add(metadata_range.length), backing_len)?;
+
+        let backing = Arc::new(Mediator::new(backing));
+        backing.set_sealed(flags & FLAG_SEALED != 0);
 
-        let strtab = Self::parse_string_table(Rc::clone(&backing)
-            .try_borrow_mut()
+        let strtab = Self::parse_string_table(Arc::clone(&backing)
+            .lock_backing()
             .map_err(Error::other)?, string_table_range)?;
         let string_table_size = strtab.len() as u64;
         let strtab = RefCell::new(strtab);
 
-        let inodetab = Self::parse_inode_table(Rc::clone(&backing)
-            .try_borrow_mut()
-            .map_err(Error::other)?, strtab.borrow(), inode_table_range)?;
+        let (inodetab, _) = Self::parse_inode_table(Arc::clone(&backing)
+            .lock_backing()
+            .map_err(Error::other)?, strtab.borrow(), inode_table_range, flags, base_offset)?;
 
-        let x = Ok(Self {
+        let (history_table, free_list, history_table_size) = Self::parse_history_table(Arc::clone(&backing)
+            .lock_backing()
+            .map_err(Error::other)?, history_table_range, flags, base_offset)?;
+
+        let mut x = Ok(Self {
             inode_table_size: inodetab.len() as u64,
             string_table_size,
-            history_table_size: 0,
+            history_table_size,
+            history_limit: Some(DEFAULT_HISTORY_LIMIT),
 
             inode_table: inodetab,
             string_table: strtab,
+            history_table,
 
             inode_table_range,
             string_table_range,
@@ -143,39 +589,177 @@ impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write
             metadata_range,
 
             borrowed_slices: Arc::new(Mutex::new(vec![])),
+            free_list,
+
+            hooks: Hooks::default(),
+            page_validator: None,
+            linked_pages: HashMap::new(),
+            open_pages: HashMap::new(),
+            exclusive_pages: HashMap::new(),
+
+            page_access_counts: HashMap::new(),
+            page_access_times: HashMap::new(),
 
             raw_header: buf.clone(),
+            base_offset,
+            salt,
+            seal_key_hash: match flags & FLAG_SEAL_KEY {
+                0 => None,
+                _ => {
+                    let mut hash = [0u8; 32];
+                    let mut backing = backing
+                        .lock_backing()
+                        .map_err(Error::other)?;
+
+                    backing.seek(SeekFrom::Start(metadata_range.end()))?;
+                    backing.read_exact(&mut hash)?;
+
+                    Some(hash)
+                }
+            },
+            sync_on_write: None,
+            page_requests: channel(),
+            wal: None,
+            journal: None,
+            max_page_size: None,
+            page_limits: (None, None),
+            sorted_index: RefCell::new(None),
+            sorted_index_enabled: false,
+            agent: None,
+            agent_reservations: vec![],
             meta: {
                 let mut s = vec![0u8; metadata_range.length as usize];
-                let mut backing: RefMut<Backing> = backing
-                    .try_borrow_mut()
+                let mut backing = backing
+                    .lock_backing()
                     .map_err(Error::other)?;
 
                 backing.seek(SeekFrom::Start(metadata_range.offset))?;
                 backing.read_exact(&mut s)?;
 
-                ron::de::from_bytes::<Metadata>(&s)
-                    .map_err(Error::other)?
-                    .clone()
+                MetadataFormat::from_flags(flags)?
+                    .deserialise::<Metadata>(&s)?
             },
 
-            backing: Rc::clone(&backing),
+            backing: Arc::clone(&backing),
         });
 
-        return x;
+        // The tables' byte sizes aren't stored in the header, so measure them by serialising the parsed tables
+        if let Ok(db) = x.as_mut() {
+            db.page_limits = Self::find_page_limits(&db.meta);
+            db.rebuild_links();
+            db.serialise_inode_table()?;
+            db.serialise_string_table()?;
+            db.prune_free_list()?;
+        }
+
+        x
+    }
+
+    /// Version 2 headers are followed by the 16-byte passphrase salt.
+    fn parse_v2<Reader: Read>(reader: &mut Reader) -> Result<Option<[u8; 16]>> {
+        let mut salt = [0u8; 16];
+        reader.read_exact(&mut salt)?;
+        Ok(Some(salt))
+    }
+
+    /// Find the offsets of every FSDB header in the backing buffer, including this database's own, by looking for the magic number at each 0x10-byte boundary.
+    /// Any of the offsets can then be passed to `open_at_offset` to recover databases embedded in compound files or memory dumps.
+    pub fn scan_for_magic(&self) -> Result<Vec<u64>> {
+        let mut backing = self.backing
+            .lock_backing()
+            .map_err(Error::other)?;
+
+        backing.seek(SeekFrom::Start(0))?;
+
+        scan_reader_for_magic(backing.deref_mut())
     }
 
     /// Compute the offset of the allowable data region.
     fn data_offset(&self) -> u64 {
         (self.inode_table_range.offset + self.inode_table_size)
             .max(self.string_table_range.offset + self.string_table_size)
-            // .max(self.history_table_range.offset + self.history_table_size) // TODO: Include once History Table becomes relevant
+            .max(self.history_table_range.offset + self.history_table_size)
             .max(self.metadata_range.offset + self.metadata_range.length)
     }
 
+    /// Determine the length of the backing object in bytes.
+    fn backing_len(&self) -> Result<u64> {
+        self.backing.lock_backing()
+            .map_err(Error::other)?
+            .stream_len()
+    }
+
+    /// Estimate what percentage of the backing object is occupied by metadata (the header along with a version 2 database's salt, the metadata object and the inode, string and history tables) rather than page content.
+    /// If the length of the backing object can't be determined, the end of the table region is used in its place.
+    pub fn estimated_overhead(&self) -> f64 {
+        let overhead = self.used_regions()
+            .into_iter()
+            .filter(|(region, _)| !matches!(region, LayoutRegion::PageChunk(_)))
+            .map(|(_, region)| region.length)
+            .sum::<u64>();
+        let total = self.backing_len()
+            .unwrap_or_else(|_| self.data_offset());
+
+        if total == 0 { return 0.0; }
+
+        overhead as f64 / total as f64 * 100.0
+    }
+
+    /// The complement of `estimated_overhead` - the percentage of the backing object available to page content.
+    pub fn page_data_ratio(&self) -> f64 {
+        100.0 - self.estimated_overhead()
+    }
+
+    /// The average number of inode table bytes each page costs.
+    pub fn inode_overhead_per_page(&self) -> f64 {
+        if self.inode_table.is_empty() { return 0.0; }
+
+        self.inode_table_size as f64 / self.inode_table.len() as f64
+    }
+
+    /// Measure how the backing object's space is used. See `DatabaseStats`.
+    /// Free space is found the same way as by `get_free_regions`, so chunks released by deleted pages count towards it.
+    pub fn statistics(&self) -> Result<DatabaseStats> {
+        let total_bytes = self.backing_len()?;
+
+        let mut chunks = self.iter_chunks()
+            .map(|(_, chunk)| chunk)
+            .filter(|chunk| chunk.length > 0)
+            .collect::<Vec<_>>();
+        chunks.sort_unstable_by_key(|chunk| (chunk.offset, chunk.length));
+        chunks.dedup_by_key(|chunk| (chunk.offset, chunk.length));
+
+        let live_bytes = chunks.iter()
+            .map(|chunk| chunk.length)
+            .sum::<u64>();
+
+        let free = self.get_free_regions();
+        // The tables needn't precede page content, so their overhead is their combined length rather than where they end
+        let header_overhead = self.used_regions()
+            .into_iter()
+            .filter(|(region, _)| !matches!(region, LayoutRegion::PageChunk(_)))
+            .map(|(_, region)| region.length)
+            .sum::<u64>();
+        let data_bytes = total_bytes.saturating_sub(header_overhead);
+
+        Ok(DatabaseStats {
+            total_bytes,
+            live_bytes,
+            free_bytes: free.iter().map(|region| region.length).sum(),
+            fragmentation_ratio: if data_bytes == 0 { 0.0 } else { (1.0 - live_bytes as f64 / data_bytes as f64).clamp(0.0, 1.0) },
+            page_count: self.inode_table.len(),
+            chunk_count: chunks.len(),
+            string_count: self.string_table.try_borrow()
+                .map_err(Error::other)?
+                .len(),
+            largest_gap: free.iter().map(|region| region.length).max().unwrap_or(0),
+        })
+    }
+
     /// Fetch a string in the string table
     /// Strings are referenced by their index into the table, and can be easily fetched using the `str!` macro:
     /// ```rust
+    /// # use std::io::Error;
     /// fn get_string_by_index(index: u64, strtab: std::cell::Ref<Vec<String>>) -> Option<String> {
     ///     let str = datastore_provider::get_str!(strtab, index).ok()?.clone();
     ///     Some(str)
@@ -197,353 +781,4222 @@ impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write
         })
     }
 
-    /// Read the contents of the string table into a vector
-    fn parse_string_table(mut backing: RefMut<Backing>, arr: Array) -> Result<Vec<String>> {
-        let mut buf = Cursor::new(vec![0u8; 512]);
-        let mut strtab = vec![];
-        let offset = backing.seek(std::io::SeekFrom::Start(arr.offset))?;
-
-        // TODO: If an EOF is reached while attempting to fill the buffer, despite the potential validity of the descriptors, we will receive an error.
-        while strtab.len() < arr.length as usize {
-            let buffer = {
-                let mut buffer = Vec::new();
-                buffer.extend(buf.get_ref());
-                buffer.reserve(512);
-                buffer[buf.get_ref().len()..].fill(0x00);
-                backing.read_exact(&mut buffer[buf.get_ref().len()..])?;
-                buffer
-            };
+    /// The pages in the order their inode table entries are serialised in: by the offset of their first chunk, then by name, so that compacted databases list their pages back to back.
+    fn pages_in_table_order(&self) -> Vec<&PageDescriptor> {
+        let mut pages = self.inode_table.values().collect::<Vec<_>>();
+        pages.sort_unstable_by(|a, b| Ord::cmp(&(a.inodes.first().map(|chunk| chunk.offset), &a.name), &(b.inodes.first().map(|chunk| chunk.offset), &b.name)));
+
+        pages
+    }
 
-            let strlen = u16::from_le_bytes(buffer[0..2].try_into().map_err(Error::other)?);
+    /// Reorder the string table by first use, so that each page's name is directly followed by its ACL entities, in the order the inode table is serialised.
+    /// Strings no page refers to are kept, after all others. Returns the number of strings whose index changed.
+    /// > **Note**: Journal entries refer to pages by string table index, so journals recorded before defragmenting no longer resolve correctly.
+    pub fn defragment_string_table(&mut self) -> Result<usize> {
+        self.begin_write()?;
 
-            let total_space = 2 + strlen as usize;
+        let previous = self.string_table.try_borrow()
+            .map_err(Error::other)?
+            .clone();
 
-            strtab.push(String::from_utf8(buffer[2..total_space].to_owned()).map_err(Error::other)?);
+        let mut table: Vec<String> = vec![];
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        let mut push = |str: &str| if !indices.contains_key(str) {
+            indices.insert(str.to_owned(), table.len());
+            table.push(str.to_owned());
+        };
 
-            buf.seek(SeekFrom::Start(0))?;
-            buf.write_all(&buffer[total_space..])?;
+        for page in self.pages_in_table_order() {
+            push(&page.name);
+            page.access_control_list.iter()
+                .for_each(|acl| push(acl.entity()));
         }
+        previous.iter()
+            .for_each(|str| push(str));
 
-        Ok(strtab)
-    }
+        let repositioned = table.iter()
+            .zip(previous.iter())
+            .filter(|(new, old)| new != old)
+            .count();
 
-    /// Parse the string table.
-    pub(crate) fn get_string_table(&mut self) -> Result<Vec<String>> {
-        Self::parse_string_table(self.backing.try_borrow_mut()
-            .map_err(Error::other)?, self.string_table_range)
+        // History entries refer to pages by index, so they follow their strings to their new positions. Every previous string is in the new table.
+        let moved = previous.iter()
+            .map(|str| indices[str])
+            .collect::<Vec<_>>();
+        for entry in self.history_table.iter_mut() {
+            if let Some(index) = moved.get(entry.page_name_index as usize) {
+                entry.page_name_index = *index as u64;
+            }
+        }
+
+        *self.string_table.try_borrow_mut()
+            .map_err(Error::other)? = table;
+
+        self.write_header()?;
+
+        Ok(repositioned)
     }
 
-    /// Parse the inode table
-    fn parse_inode_table(mut backing: RefMut<Backing>, strtab: Ref<Vec<String>>, arr: Array) -> Result<HashMap<String, PageDescriptor>> {
-        let mut buf = BufReader::new(backing.deref_mut());
-        let mut map = HashMap::new();
+    /// Drop the strings nothing refers to from the string table, returning the number removed. Strings left behind by renamed or deleted pages and revoked access are dropped,
+    /// while page names, ACL entities and the names history entries are recorded under are kept, in their existing order. History entries follow their strings to their new positions.
+    /// > **Note**: Journal entries refer to pages by string table index, so journals recorded before collecting no longer resolve correctly.
+    pub fn gc_string_table(&mut self) -> Result<usize> {
+        self.begin_write()?;
 
-        let strtab = strtab.deref();
+        let previous = self.string_table.try_borrow()
+            .map_err(Error::other)?
+            .clone();
 
-        let offset = buf.seek(SeekFrom::Start(arr.offset))?;
+        let referenced = self.inode_table.values()
+            .flat_map(|page| iter::once(page.name.as_str()).chain(page.access_control_list.iter().map(|acl| acl.entity())))
+            .chain(self.history_table.iter().filter_map(|entry| previous.get(entry.page_name_index as usize).map(String::as_str)))
+            .collect::<std::collections::HashSet<_>>();
 
-        while (map.len() as u64) < arr.length {
-            // Read the necessary information first.
+        let table = previous.iter()
+            .filter(|str| referenced.contains(str.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
 
-            // u64 + u16
-            let mut page_header = [0u8; 8 + 2];
-            buf.read_exact(&mut page_header)?;
+        let removed = previous.len() - table.len();
+        if removed == 0 {
+            return Ok(0);
+        }
 
-            let page_name = u64::from_le_bytes(page_header[0..8].try_into().map_err(Error::other)?);
-            let acl_len = u16::from_le_bytes(page_header[8..10].try_into().map_err(Error::other)?) as u64;
+        for entry in self.history_table.iter_mut() {
+            if let Some(index) = previous.get(entry.page_name_index as usize).and_then(|name| table.iter().position(|i| i == name)) {
+                entry.page_name_index = index as u64;
+            }
+        }
 
-            // (u8 + u64) * acl_len + %0x10
-            let mut acl = vec![0u8; round((1 + 8) * acl_len as u64, 0x10) as usize - 2];
-            buf.read_exact(&mut acl)?;
+        *self.string_table.try_borrow_mut()
+            .map_err(Error::other)? = table;
 
-            // u64
-            let mut chunk_len = [0u8; 8];
-            buf.read_exact(&mut chunk_len)?;
+        self.write_header()?;
 
-            let chunk_len = u64::from_le_bytes(chunk_len);
+        Ok(removed)
+    }
 
-            // (u64 + u64) * chunk_len
-            let mut chunk_ranges = vec![0u8; 2 * 8 * chunk_len as usize];
-            buf.read_exact(&mut chunk_ranges)?;
+    /// Dump the inode table as JSON, sorted by page name. This is intended for debugging and tooling only, and isn't a persistence format.
+    /// ```json
+    /// [{"name": "test", "chunks": [{"offset": 4096, "length": 512}], "acl": ["*:rw"], "created": 1234567890, "modified": 1234567891}]
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn export_inode_table_as_json(&self) -> Result<String> {
+        let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH)
+            .map(|i| i.as_secs())
+            .unwrap_or(0);
 
-            let name: &String = get_str!(strtab, page_name)?;
+        let mut pages = self.inode_table.values().collect::<Vec<_>>();
+        pages.sort_unstable_by(|i, j| Ord::cmp(&i.name, &j.name));
 
-            map.insert(
-                name.clone(),
-                PageDescriptor {
-                    name: name.clone(),
-                    access_control_list: acl[0..(1 + 8) * acl_len as usize]
-                        .chunks(1 + 8) // u8 + u64
-                        .map(|i| Ok(match i[0] {
-                            0b000 => Access::None(get_str!(strtab, i[1])?.clone()),
-                            0b001 => Access::Read(get_str!(strtab, i[1])?.clone()),
-                            0b011 => Access::ReadWrite(get_str!(strtab, i[1])?.clone()),
-                            0b111 => Access::ReadWriteExecute(get_str!(strtab, i[1])?.clone()),
-                            0b101 => Access::ReadExecute(get_str!(strtab, i[1])?.clone()),
-                            perm => Access::Custom(get_str!(strtab, i[1])?.clone(), perm),
-                        }))
-                        .collect::<Result<Vec<Access>>>()?,
-                    inodes: chunk_ranges
-                        .chunks(8 + 8) // u64 + u64
-                        .map(|i| Ok(Array {
-                            length: u64::from_le_bytes(i[0..8].try_into().map_err(Error::other)?),
-                            offset: u64::from_le_bytes(i[8..16].try_into().map_err(Error::other)?)
-                        }))
-                        .collect::<Result<Vec<Array>>>()?,
-                    modified: SystemTime::now(),
-                    created: SystemTime::now(),
-                }
-            );
-        }
+        let pages = pages.into_iter()
+            .map(|page| serde_json::json!({
+                "name": page.name,
+                "chunks": page.inodes
+                    .iter()
+                    .map(|i| serde_json::json!({ "offset": i.offset, "length": i.length }))
+                    .collect::<Vec<_>>(),
+                "acl": page.access_control_list
+                    .iter()
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>(),
+                "created": secs(page.created),
+                "modified": secs(page.modified),
+            }))
+            .collect::<Vec<_>>();
 
-        Ok(map)
+        serde_json::to_string_pretty(&pages)
+            .map_err(Error::other)
     }
 
-    /// Serialise the header into the defined format and write it to the backing buffer.
-    /// Open pages will automatically synchronise their changes with the header and usually don't need manual flushing.
-    /// This method is mainly used internally, but can be additionally invoked for extra clarity or assurance.
-    pub fn write_header(&mut self) -> Result<()> {
-        let offset = {
-            let mut backing = self.backing
-                .try_borrow_mut()
-                .map_err(Error::other)?;
+    /// Dump the string table as a JSON array, in index order.
+    #[cfg(feature = "json")]
+    pub fn export_string_table_as_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self.string_table
+            .try_borrow()
+            .map_err(Error::other)?
+            .deref())
+            .map_err(Error::other)
+    }
 
-            backing.seek(SeekFrom::Start(0))?;
-            backing.write_all(&self.raw_header)?;
+    /// Dump the header fields, as well as the metadata object, as JSON.
+    #[cfg(feature = "json")]
+    pub fn export_header_as_json(&self) -> Result<String> {
+        let range = |i: Array| serde_json::json!({ "offset": i.offset, "length": i.length });
 
-            backing.seek(SeekFrom::Start(0x50))?;
-            let metadata = ron::ser::to_writer(backing.deref_mut(), &self.meta)
-                .map_err(Error::other)?;
+        serde_json::to_string_pretty(&serde_json::json!({
+            "magic": String::from_utf8_lossy(&self.raw_header[0..4]),
+            "version": u32::from_le_bytes(self.raw_header[4..8]
+                .try_into()
+                .map_err(Error::other)?),
+            "inode_table": range(self.inode_table_range),
+            "string_table": range(self.string_table_range),
+            "history_table": range(self.history_table_range),
+            "metadata": range(self.metadata_range),
+            "meta": serde_json::to_value(&self.meta)
+                .map_err(Error::other)?,
+        }))
+            .map_err(Error::other)
+    }
+
+    /// The MIME type recorded in the named page's user data, if any. See `Page::set_content_type`.
+    pub fn page_content_type(&self, name: &str) -> Result<Option<String>> {
+        self.inode_table.get(name)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))?
+            .content_type()
+            .map_err(Error::other)
+    }
 
-            let offset = backing.seek(SeekFrom::Current(0))?;
+    /// The names of all pages starting with `prefix`, sorted alphabetically. O(n) in the number of pages.
+    pub fn list_pages_with_prefix(&self, prefix: &str) -> Vec<&str> {
+        self.list_pages_where(|name, _| name.starts_with(prefix))
+    }
 
-            offset + (0x10 - offset % 0x10) // Align to next 0x10th byte
+    /// The paths one component deeper than `path`, treating `/` as the separator, sorted alphabetically. O(n) in the number of pages.
+    /// Components are listed even where only deeper pages exist, so `list_children("/a")` yields `/a/b` for a page named `/a/b/c`.
+    pub fn list_children(&self, path: &str) -> Vec<String> {
+        let prefix = match path.is_empty() || path.ends_with('/') {
+            true => path.to_owned(),
+            false => format!("{}/", path),
         };
 
-        // Write INode Table before writing offsets as it may alter the string table
+        let mut children = self.inode_table.keys()
+            .filter(|name| name.starts_with(&prefix))
+            .filter_map(|name| name[prefix.len()..].split('/').next())
+            .filter(|child| !child.is_empty())
+            .map(|child| format!("{}{}", prefix, child))
+            .collect::<Vec<_>>();
+        children.sort_unstable();
+        children.dedup();
 
-        let backing = Rc::clone(&self.backing);
-        let mut backing = backing.try_borrow_mut().map_err(Error::other)?;
+        children
+    }
 
-        // Write Header
-        backing.seek(SeekFrom::Start(0x10))?;
-        // ranges:
-        let inode_offset = offset;
-        let inode_length = self.inode_table.len() as u64;
+    /// The names of all pages starting with `prefix`, sorted alphabetically.
+    /// This is O(n) in the number of pages, unless the sorted index is enabled with `build_sorted_index`, in which case it's O(log n + k) in the number of matches, once the index has been built.
+    pub fn find_pages_by_prefix(&self, prefix: &str) -> Vec<String> {
+        if !self.sorted_index_enabled {
+            return self.list_pages_with_prefix(prefix)
+                .into_iter()
+                .map(str::to_owned)
+                .collect();
+        }
 
-        backing.seek(SeekFrom::Start(inode_offset))?;
-        let data = self.serialise_inode_table()?;
-        backing.write_all(&data)?;
+        let mut index = self.sorted_index.borrow_mut();
+        index.get_or_insert_with(|| self.sorted_page_names())
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .map(|(name, _)| name)
+            .take_while(|name| name.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
 
-        let string_offset = (inode_offset + data.len() as u64) + 0x100 & !0x100; // Align to next 0x100th byte
-        let string_length = self.string_table.borrow().len() as u64;
+    /// Keep a sorted copy of every page name for `find_pages_by_prefix` to search. The copy costs roughly as much memory as the names themselves, and is rebuilt by the first query after pages are added, removed or renamed,
+    /// so it pays off for databases which are queried by prefix far more often than their pages change.
+    pub fn build_sorted_index(&mut self) {
+        self.sorted_index_enabled = true;
+        *self.sorted_index.get_mut() = Some(self.sorted_page_names());
+    }
 
-        backing.seek(SeekFrom::Start(string_offset))?;
-        let data = self.serialise_string_table()?;
-        backing.write_all(&data)?;
+    /// Discard the sorted index, freeing its memory. `find_pages_by_prefix` scans the inode table from now on.
+    pub fn drop_sorted_index(&mut self) {
+        self.sorted_index_enabled = false;
+        *self.sorted_index.get_mut() = None;
+    }
 
-        let history_offset = string_offset + string_length + 0x100 - (string_offset + string_length) % 0x100; // Align to next 0x100th byte
-        let history_length = 0;
+    fn sorted_page_names(&self) -> BTreeMap<String, ()> {
+        self.inode_table.keys()
+            .map(|name| (name.clone(), ()))
+            .collect()
+    }
 
-        backing.write_all(&vec![inode_length, inode_offset, string_length, string_offset, history_length, history_offset]
-            .into_iter()
-            .map(|i| i.to_le_bytes())
-            .flatten()
-            .collect::<Vec<_>>())?;
+    /// Clear the sorted index, if there is one, as the set of page names is about to change. It's rebuilt by the next prefix query.
+    fn invalidate_sorted_index(&mut self) {
+        *self.sorted_index.get_mut() = None;
+    }
 
-        Ok(())
+    /// The names of all pages ending with `suffix`, sorted alphabetically. O(n) in the number of pages.
+    pub fn list_pages_with_suffix(&self, suffix: &str) -> Vec<&str> {
+        self.list_pages_where(|name, _| name.ends_with(suffix))
     }
 
-    /// Generate a byte buffer of the inode table
-    fn serialise_inode_table(&mut self) -> Result<Vec<u8>> {
-        let mut vec = vec![];
+    /// The names of all pages matching `glob`, where `*` matches any run of characters and `?` any single character. Sorted alphabetically. O(n) in the number of pages.
+    pub fn list_pages_matching(&self, glob: &str) -> Vec<&str> {
+        self.list_pages_where(|name, _| glob_match(glob, name))
+    }
 
-        for (name, page) in self.inode_table.iter().map(|i| (i.0.clone(), i.1.clone())) {
-            let strtab_index = self.get_strtab_index(&name)?;
+    /// The names of all pages for which `filter` holds, sorted alphabetically. O(n) in the number of pages.
+    pub fn list_pages_where<Filter: Fn(&str, &PageDescriptor) -> bool>(&self, filter: Filter) -> Vec<&str> {
+        let mut pages = self.inode_table.iter()
+            .filter(|(name, page)| filter(name, page))
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        pages.sort_unstable();
 
-            let acl_len = page.access_control_list.len() as u64;
-            let acls: Vec<_> = page.access_control_list
-                .iter()
-                .map(|i| Ok(match i {
-                    Access::None(entity) => (0b000u8, self.get_strtab_index(&entity)?),
-                    Access::Read(entity) => (0b001u8, self.get_strtab_index(&entity)?),
-                    Access::ReadWrite(entity) => (0b011u8, self.get_strtab_index(&entity)?),
-                    Access::ReadWriteExecute(entity) => (0b111u8, self.get_strtab_index(&entity)?),
-                    Access::ReadExecute(entity) => (0b101u8, self.get_strtab_index(&entity)?),
-                    Access::Custom(entity, perm) => (*perm, self.get_strtab_index(&entity)?)
-                }))
-                .collect::<Result<Vec<(u8, u64)>>>()?
-                .into_iter()
-                .map(|i| {
-                    let mut arr = [0u8; 1 + 8];
-                    arr[0] = i.0;
+        pages
+    }
 
-                    i.1
-                        .to_le_bytes()
-                        .into_iter()
-                        .enumerate()
-                        .for_each(|(a, i)| arr[a + 1] = i);
+    /// The names of all pages whose recorded MIME type is `mime`, sorted alphabetically.
+    pub fn pages_by_content_type(&self, mime: &str) -> Vec<&str> {
+        let mut pages = self.inode_table.values()
+            .filter(|page| matches!(page.content_type(), Ok(Some(content_type)) if content_type == mime))
+            .map(|page| page.name.as_str())
+            .collect::<Vec<_>>();
+        pages.sort_unstable();
 
-                    arr
-                })
-                .flatten()
-                .collect();
+        pages
+    }
 
-            vec.extend((&[
-                &u64::to_le_bytes(self.get_strtab_index(&page.name)?)[..],
-                &u16::to_le_bytes(page.access_control_list.len() as u16)[..],
-                &acls[..],
-                &vec![0x00; round(2 + (1 + 8) * acls.len() as u64, 0x10) as usize][..],
-                &u64::to_le_bytes(page.inodes.len() as u64)[..],
-            ][..])
-                .iter()
+    /// Every page `entity` has been granted access to, along with the ACL entries which apply to it, sorted by page name.
+    /// Entries apply if their entity matches `entity` as a glob pattern. Pages where all applicable entries are `None` are left out.
+    pub fn check_access_for_entity(&self, entity: &str) -> Vec<(String, Vec<Access>)> {
+        let mut pages = self.inode_table.values()
+            .map(|page| (page.name.clone(), page.access_control_list.iter()
+                .filter(|access| access.applies_to(entity))
                 .cloned()
-                .flatten());
+                .collect::<Vec<_>>()))
+            .filter(|(_, acl)| acl.iter().any(|access| access.bits() != 0))
+            .collect::<Vec<_>>();
+        pages.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
 
-            for i in page.inodes.iter().cloned() {
-                vec.extend_from_slice(&i.length.to_le_bytes()[..]);
-                vec.extend_from_slice(&i.offset.to_le_bytes()[..]);
-            }
-        }
+        pages
+    }
 
-        self.inode_table_size = vec.len() as u64;
-        Ok(vec)
+    /// For every distinct entity named in any ACL, the pages it has access to and the union of the permission bits granted to it there.
+    /// Each list is sorted by page name. See `check_access_for_entity`.
+    pub fn check_access_matrix(&self) -> HashMap<String, Vec<(String, u8)>> {
+        let entities = self.inode_table.values()
+            .flat_map(|page| page.access_control_list.iter())
+            .map(|access| access.entity().to_owned())
+            .collect::<std::collections::HashSet<_>>();
+
+        entities.into_iter()
+            .map(|entity| {
+                let pages = self.check_access_for_entity(&entity)
+                    .into_iter()
+                    .map(|(page, acl)| (page, acl.iter().fold(0u8, |bits, access| bits | access.bits())))
+                    .collect();
+
+                (entity, pages)
+            })
+            .collect()
     }
 
-    /// Generate a byte-buffer of the string table.
-    fn serialise_string_table(&mut self) -> Result<Vec<u8>> {
-        let mut vec = vec![];
+    /// Whether any instance of the named page handed out by `open_page` or `open_read_write_locked` is still open.
+    pub fn is_page_open(&self, name: &str) -> bool {
+        self.is_page_exclusively_open(name) || self.open_pages.get(name)
+            .is_some_and(|tokens| tokens.iter().any(|token| token.strong_count() > 0))
+    }
 
-        for i in self.string_table.try_borrow().map_err(Error::other)?.iter() {
-            vec.extend_from_slice(&[
-                &(i.len() as u64).to_le_bytes()[..],
-                i.as_bytes()
+    /// Whether the named page is held open by `open_read_write_locked`.
+    pub fn is_page_exclusively_open(&self, name: &str) -> bool {
+        self.exclusive_pages.get(name)
+            .is_some_and(|token| token.strong_count() > 0)
+    }
+
+    /// Exchange the descriptors of two pages, so that each name refers to the other's content and access control list. No page content is moved.
+    /// The swap is undone if the header can't be written. Fails with `ResourceBusy` if either page is open.
+    pub fn atomic_swap_pages(&mut self, a: &str, b: &str) -> Result<()> {
+        self.begin_write()?;
+
+        if self.is_page_open(a) || self.is_page_open(b) {
+            return Err(Error::new(std::io::ErrorKind::ResourceBusy, "Can't swap open pages"));
+        }
+
+        for name in [a, b] {
+            if !self.inode_table.contains_key(name) {
+                return Err(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)));
+            }
+        }
+
+        if a == b { return Ok(()); }
+
+        let journaled = self.journaled_contents([a.to_owned(), b.to_owned()].iter())?;
+        self.swap_descriptors(a, b);
+
+        if let Err(err) = self.write_header() {
+            self.swap_descriptors(a, b);
+            return Err(err);
+        }
+
+        self.journal_changes(journaled)
+    }
+
+    /// Exchange the descriptors of two existing pages in the inode table, keeping each descriptor's name in line with its key.
+    fn swap_descriptors(&mut self, a: &str, b: &str) {
+        if let (Some(mut page_a), Some(mut page_b)) = (self.inode_table.remove(a), self.inode_table.remove(b)) {
+            std::mem::swap(&mut page_a.name, &mut page_b.name);
+
+            self.inode_table.insert(page_a.name.clone(), page_a);
+            self.inode_table.insert(page_b.name.clone(), page_b);
+        }
+    }
+
+    /// Register a callback invoked whenever a page is created.
+    pub fn set_create_hook<Hook: Fn(&str, &PageDescriptor) + Send + 'static>(&mut self, hook: Hook) {
+        self.hooks.create.push(Box::new(hook));
+    }
+
+    /// Register a callback invoked whenever a page is written to, along with the number of bytes written.
+    pub fn set_write_hook<Hook: Fn(&str, u64) + Send + 'static>(&mut self, hook: Hook) {
+        self.hooks.write.push(Box::new(hook));
+    }
+
+    /// Register a callback invoked whenever a page is deleted.
+    pub fn set_delete_hook<Hook: Fn(&str) + Send + 'static>(&mut self, hook: Hook) {
+        self.hooks.delete.push(Box::new(hook));
+    }
+
+    /// Register a callback invoked whenever a page's access control list changes, along with the new list.
+    pub fn set_acl_change_hook<Hook: Fn(&str, &[Access]) + Send + 'static>(&mut self, hook: Hook) {
+        self.hooks.acl_change.push(Box::new(hook));
+    }
+
+    /// Receive an event for every change to any page, along with the page's name. Each call returns a new receiver, independent of any others.
+    /// Once the receiver is dropped, the database stops sending to it.
+    pub fn watch_all_pages(&mut self) -> Receiver<(String, WatchEvent)> {
+        self.watch_namespace("")
+    }
+
+    /// Like `watch_all_pages`, but only receive events of pages whose names start with `prefix`.
+    pub fn watch_namespace(&mut self, prefix: &str) -> Receiver<(String, WatchEvent)> {
+        let (tx, rx) = channel();
+        self.hooks.watch(prefix, tx);
+
+        rx
+    }
+
+    /// Remove all registered hooks, including the watchers created by `watch_all_pages` and `watch_namespace`.
+    pub fn clear_hooks(&mut self) {
+        self.hooks = Hooks::default();
+    }
+
+    /// Record that a page has been opened. Used by the access statistics below.
+    fn record_access(&mut self, name: &str) {
+        match self.page_access_counts.get(name) {
+            Some(count) => { count.fetch_add(1, AtomicOrdering::Relaxed); },
+            None => { self.page_access_counts.insert(name.to_owned(), AtomicU64::new(1)); }
+        }
+
+        self.page_access_times.insert(name.to_owned(), Instant::now());
+    }
+
+    /// The number of times the page has been opened during this session. Pages which haven't been opened yield 0.
+    pub fn page_access_count(&self, name: &str) -> u64 {
+        self.page_access_counts.get(name)
+            .map(|i| i.load(AtomicOrdering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    /// The `n` pages opened most often during this session, most accessed first.
+    pub fn most_accessed_pages(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut pages = self.page_access_counts.iter()
+            .filter(|(name, _)| self.inode_table.contains_key(*name))
+            .map(|(name, count)| (name.as_str(), count.load(AtomicOrdering::Relaxed)))
+            .collect::<Vec<_>>();
+
+        pages.sort_unstable_by(|i, j| Ord::cmp(&j.1, &i.1));
+        pages.truncate(n);
+
+        pages
+    }
+
+    /// The `n` pages which haven't been opened for the longest time. Pages which haven't been opened at all during this session come first.
+    pub fn least_recently_used_pages(&self, n: usize) -> Vec<&str> {
+        let mut pages = self.inode_table.keys()
+            .map(|name| (name.as_str(), self.page_access_times.get(name)))
+            .collect::<Vec<_>>();
+
+        // `None` orders before `Some`, so pages which were never accessed come first
+        pages.sort_unstable_by(|i, j| Ord::cmp(&i.1, &j.1));
+
+        pages.into_iter()
+            .take(n)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Fail with `Corrupted` unless `length` fits within `limit`, returning it as a `usize`. Lengths read from the backing object are checked against its size before anything is allocated for them.
+    fn bounded_length(length: u64, limit: u64) -> Result<usize> {
+        match length <= limit {
+            true => usize::try_from(length).map_err(Error::other),
+            false => Err(Error::new(std::io::ErrorKind::InvalidData, crate::error::Error::Corrupted)),
+        }
+    }
+
+    /// Read the contents of the string table into a vector. Each string is prefixed with its length in bytes as a `u16`.
+    fn parse_string_table(mut backing: MutexGuard<Backing>, arr: Array) -> Result<Vec<String>> {
+        let limit = backing.stream_len()?;
+        Self::bounded_length(arr.length, limit.saturating_sub(arr.offset))?;
+
+        backing.seek(SeekFrom::Start(arr.offset))?;
+        let mut buf = BufReader::new(backing.deref_mut());
+
+        (0..arr.length)
+            .map(|_| {
+                // u16
+                let mut strlen = [0u8; 2];
+                buf.read_exact(&mut strlen)?;
+
+                // u8 * strlen
+                let mut string = vec![0u8; u16::from_le_bytes(strlen) as usize];
+                buf.read_exact(&mut string)?;
+
+                String::from_utf8(string).map_err(|err| Error::new(std::io::ErrorKind::InvalidData, err))
+            })
+            .collect()
+    }
+
+    /// Parse the string table.
+    #[cfg(test)]
+    pub(crate) fn get_string_table(&mut self) -> Result<Vec<String>> {
+        Self::parse_string_table(self.backing.lock_backing()
+            .map_err(Error::other)?, self.string_table_range)
+    }
+
+    /// Parse the history table, returning its entries and the free list following them, along with their size in bytes.
+    /// Databases written without the free list yield an empty one.
+    fn parse_history_table(mut backing: MutexGuard<Backing>, arr: Array, flags: u64, base_offset: u64) -> Result<(Vec<HistoryEntry>, Vec<Array>, u64)> {
+        let limit = backing.stream_len()?;
+        Self::bounded_length(arr.length, limit.saturating_sub(arr.offset))?;
+
+        backing.seek(SeekFrom::Start(arr.offset))?;
+
+        let mut buf = BufReader::new(backing.deref_mut());
+        let entries = (0..arr.length)
+            .map(|_| HistoryEntry::read(&mut buf, base_offset))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut free_list = vec![];
+        if flags & FLAG_FREE_LIST != 0 {
+            let mut len = [0u8; 8];
+            buf.read_exact(&mut len)?;
+
+            let len = u64::from_le_bytes(len);
+            Self::bounded_length(len.saturating_mul(8 + 8), limit)?;
+
+            for _ in 0..len {
+                let mut chunk = [0u8; 8 + 8];
+                buf.read_exact(&mut chunk)?;
+
+                free_list.push(Array {
+                    length: u64::from_le_bytes(chunk[0..8].try_into().map_err(Error::other)?),
+                    offset: base_offset + u64::from_le_bytes(chunk[8..16].try_into().map_err(Error::other)?),
+                    nonce: None,
+                });
+            }
+        }
+
+        let size = buf.stream_position()? - arr.offset;
+
+        Ok((entries, free_list, size))
+    }
+
+    /// Parse the inode table. Chunk offsets are stored relative to the header, so `base_offset` is added to each of them.
+    /// Entries whose CRC-32C doesn't match are parsed regardless, and returned alongside the table.
+    fn parse_inode_table(mut backing: MutexGuard<Backing>, strtab: Ref<Vec<String>>, arr: Array, flags: u64, base_offset: u64) -> Result<(HashMap<String, PageDescriptor>, Vec<IntegrityError>)> {
+        let limit = backing.stream_len()?;
+        Self::bounded_length(arr.length, limit.saturating_sub(arr.offset))?;
+
+        let mut buf = BufReader::new(backing.deref_mut());
+        let mut map = HashMap::new();
+        let mut failures = vec![];
+
+        let strtab = strtab.deref();
+
+        buf.seek(SeekFrom::Start(arr.offset))?;
+        let mut buf = CrcReader::new(buf);
+
+        for index in 0..arr.length as usize {
+            buf.reset();
+
+            // Read the necessary information first.
+
+            // u64 + u16
+            let mut page_header = [0u8; 8 + 2];
+            buf.read_exact(&mut page_header)?;
+
+            let page_name = u64::from_le_bytes(page_header[0..8].try_into().map_err(Error::other)?);
+            let acl_len = u16::from_le_bytes(page_header[8..10].try_into().map_err(Error::other)?) as u64;
+
+            // (u8 + u64) * acl_len, aligning the entry so far to 0x10
+            let mut acl = vec![0u8; ((1 + 8) * acl_len + (0x10 - (2 + (1 + 8) * acl_len) % 0x10) % 0x10) as usize];
+            buf.read_exact(&mut acl)?;
+
+            // u64
+            let mut chunk_len = [0u8; 8];
+            buf.read_exact(&mut chunk_len)?;
+
+            let chunk_len = u64::from_le_bytes(chunk_len);
+
+            // (u64 + u64) * chunk_len
+            let mut chunk_ranges = vec![0u8; Self::bounded_length(chunk_len.saturating_mul(2 * 8), limit)?];
+            buf.read_exact(&mut chunk_ranges)?;
+
+            // u64
+            let content_length = match flags & FLAG_CONTENT_LENGTH != 0 {
+                true => {
+                    let mut content_length = [0u8; 8];
+                    buf.read_exact(&mut content_length)?;
+                    Some(u64::from_le_bytes(content_length))
+                },
+                false => None
+            };
+
+            // u8 + [u8; 3] + i32
+            let compression = match flags & FLAG_COMPRESSION != 0 {
+                true => {
+                    let mut compression = [0u8; 8];
+                    buf.read_exact(&mut compression)?;
+                    CompressionAlgorithm::from_parts(compression[0], i32::from_le_bytes(compression[4..8].try_into().map_err(Error::other)?))?
+                },
+                false => CompressionAlgorithm::None
+            };
+
+            // u8 + [u8; 32]
+            let checksum = match flags & FLAG_CHECKSUM != 0 {
+                true => {
+                    let mut checksum = [0u8; 1 + 32];
+                    buf.read_exact(&mut checksum)?;
+                    match checksum[0] {
+                        0 => None,
+                        _ => Some(checksum[1..].try_into().map_err(Error::other)?)
+                    }
+                },
+                false => None
+            };
+
+            // u64 + u8 * user_data_len + %0x10
+            let user_data = match flags & FLAG_USER_DATA != 0 {
+                true => {
+                    let mut user_data_len = [0u8; 8];
+                    buf.read_exact(&mut user_data_len)?;
+
+                    let user_data_len = Self::bounded_length(u64::from_le_bytes(user_data_len), limit)?;
+                    let mut user_data = vec![0u8; user_data_len + (0x10 - user_data_len % 0x10) % 0x10];
+                    buf.read_exact(&mut user_data)?;
+
+                    user_data.truncate(user_data_len);
+                    user_data
+                },
+                false => vec![]
+            };
+
+            // (u8 + [u8; 12] + [u8; 3]) * chunk_len
+            let mut nonces = vec![0u8; (1 + 12 + 3) * chunk_len as usize];
+            if flags & FLAG_NONCES != 0 {
+                buf.read_exact(&mut nonces)?;
+            }
+
+            // u64 + u64
+            let (created, modified) = match flags & FLAG_TIMESTAMPS != 0 {
+                true => {
+                    let mut timestamps = [0u8; 8 + 8];
+                    buf.read_exact(&mut timestamps)?;
+
+                    let timestamp = |i: &[u8]| -> Result<SystemTime> {
+                        Ok(UNIX_EPOCH + Duration::from_nanos(u64::from_le_bytes(i.try_into().map_err(Error::other)?)))
+                    };
+                    (timestamp(&timestamps[0..8])?, timestamp(&timestamps[8..16])?)
+                },
+                // Databases written before timestamps were recorded can't tell, so their pages count as just changed
+                false => (SystemTime::now(), SystemTime::now())
+            };
+
+            // u32
+            if flags & FLAG_CRC != 0 {
+                let found = buf.crc();
+
+                let mut expected = [0u8; 4];
+                buf.read_exact(&mut expected)?;
+                let expected = u32::from_le_bytes(expected);
+
+                if expected != found {
+                    failures.push(IntegrityError::InodeEntry { index, name: strtab.get(page_name as usize).cloned(), expected, found });
+                }
+            }
+
+            let name: &String = get_str!(strtab, page_name)?;
+
+            let inodes = chunk_ranges
+                .chunks(8 + 8) // u64 + u64
+                .zip(nonces.chunks(1 + 12 + 3))
+                .map(|(i, nonce)| Ok(Array {
+                    length: u64::from_le_bytes(i[0..8].try_into().map_err(Error::other)?),
+                    offset: base_offset + u64::from_le_bytes(i[8..16].try_into().map_err(Error::other)?),
+                    nonce: match nonce[0] {
+                        0 => None,
+                        _ => Some(nonce[1..13].try_into().map_err(Error::other)?),
+                    },
+                }))
+                .collect::<Result<Vec<Array>>>()?;
+
+            map.insert(
+                name.clone(),
+                PageDescriptor {
+                    name: name.clone(),
+                    access_control_list: acl[0..(1 + 8) * acl_len as usize]
+                        .chunks(1 + 8) // u8 + u64
+                        .map(|i| {
+                            let entity = get_str!(strtab, u64::from_le_bytes(i[1..9].try_into().map_err(Error::other)?))?.clone();
+
+                            Ok(Access::from_bits(entity, i[0]))
+                        })
+                        .collect::<Result<Vec<Access>>>()?,
+                    // Without a recorded length, assume the chunks are fully occupied
+                    content_length: content_length.unwrap_or(inodes.iter().map(|i| i.length).sum()),
+                    link_count: 1,
+                    inodes,
+                    compression,
+                    checksum,
+                    user_data,
+                    modified,
+                    created,
+                }
+            );
+        }
+
+        Ok((map, failures))
+    }
+
+    /// Serialise the header into the defined format and write it to the backing buffer.
+    /// Open pages will automatically synchronise their changes with the header and usually don't need manual flushing.
+    /// This method is mainly used internally, but can be additionally invoked for extra clarity or assurance.
+    /// Once the header is written, records of pages which aren't open are dropped from the write-ahead log, as their writes are recorded in it.
+    /// > **Note**: Fails with `PermissionDenied` while the database is sealed.
+    /// > **Note**: The header is only handed to the backing object, which may buffer it. Unless `fsync` is called or syncing on write is enabled, changes may be lost on power failure.
+    pub fn write_header(&mut self) -> Result<()> {
+        self.ensure_unsealed()?;
+        self.process_agent_requests()?;
+        self.flush_header()?;
+        self.page_limits = Self::find_page_limits(&self.meta);
+
+        // Writes of closed pages are recorded in the header now, so replaying them could only overwrite newer content
+        if let Some(wal) = self.wal.as_ref() {
+            wal.lock()
+                .map_err(|_| Error::other("PoisonError"))?
+                .retain(|record| self.is_page_open(&record.page_name))?;
+        }
+
+        Ok(())
+    }
+
+    /// Hand out an agent through which space can be allocated without holding the database, such as from another thread. Handing out another agent disconnects the previous one.
+    /// Chunks reserved through the previous agent stay reserved, and can be installed or given back through the new one.
+    pub fn agent(&mut self) -> DBAgent {
+        let (requests, request_receiver) = channel();
+        let (response_sender, responses) = channel();
+        self.agent = Some((request_receiver, response_sender));
+
+        DBAgent::new(requests, responses)
+    }
+
+    /// Answer the requests the agent has sent since they were last processed, returning the number answered. Called whenever the header is written.
+    /// Chunks allocated for the agent are reserved, so they aren't handed out again until the agent installs them into a page or gives them back.
+    pub fn process_agent_requests(&mut self) -> Result<usize> {
+        let Some((requests, _)) = self.agent.as_ref() else { return Ok(0); };
+        let requests = requests.try_iter().collect::<Vec<_>>();
+
+        for request in requests.iter() {
+            let response = match request {
+                AgentRequest::Allocate(min_space) => self.allocate_chunks(*min_space)
+                    .map(|chunks| {
+                        self.agent_reservations.extend(chunks.iter().cloned());
+                        AgentResponse::Allocated(chunks)
+                    }),
+                AgentRequest::Install(name, chunks) => match self.is_page_open(name) {
+                    true => Err(Error::from(crate::error::Error::Busy)),
+                    false => self.take_agent_reservations(chunks)
+                        .and_then(|_| self.journaled_content(name))
+                        .and_then(|before| {
+                            self.install_chunks(name, chunks.clone())?;
+                            self.journal_change(name, before)
+                        })
+                        .map(|_| AgentResponse::Done),
+                },
+                AgentRequest::Release(chunks) => self.take_agent_reservations(chunks)
+                    .and_then(|_| self.release_chunks(chunks.clone()))
+                    .map(|_| AgentResponse::Done),
+            };
+
+            if let Some((_, responses)) = self.agent.as_ref() {
+                // The agent may have been dropped without waiting for its answer
+                let _ = responses.send(response.unwrap_or_else(AgentResponse::Failed));
+            }
+        }
+
+        Ok(requests.len())
+    }
+
+    /// Stop reserving `chunks` for the agent. Fails with `InvalidInput`, leaving every reservation in place, unless each of them was allocated for the agent and is still reserved.
+    fn take_agent_reservations(&mut self, chunks: &[Array]) -> Result<()> {
+        let reserved = |chunk: &Array| self.agent_reservations.iter().any(|reserved| reserved.offset == chunk.offset && reserved.length == chunk.length);
+        if let Some(chunk) = chunks.iter().find(|chunk| !reserved(chunk)) {
+            return Err(Error::new(std::io::ErrorKind::InvalidInput, format!("{:#x}..{:#x} isn't reserved for the agent", chunk.offset, chunk.end())));
+        }
+
+        self.agent_reservations.retain(|reserved| !chunks.iter().any(|chunk| chunk.offset == reserved.offset && chunk.length == reserved.length));
+
+        Ok(())
+    }
+
+    /// The slices borrowed by pending writes, followed by the chunks reserved for the agent and those allocated by pages which haven't synchronised yet. None of them may be handed out or overwritten.
+    fn reserved_slices(&self) -> Result<Vec<Array>> {
+        let mut reserved = self.borrowed_slices.lock()
+            .map_err(|_| Error::other("PoisonError"))?
+            .clone();
+        reserved.extend(self.agent_reservations.iter().cloned());
+        reserved.extend(self.backing.pending_allocations());
+
+        Ok(reserved)
+    }
+
+    /// The first offset from `offset` on at which `length` bytes overlap neither a borrowed slice nor a chunk reserved for the agent.
+    fn skip_reserved(&self, mut offset: u64, length: u64) -> Result<u64> {
+        let reserved = self.reserved_slices()?;
+        while let Some(reserved) = reserved.iter().find(|reserved| reserved.overlaps(&Array { offset, length, nonce: None })) {
+            offset = reserved.end();
+        }
+
+        Ok(offset)
+    }
+
+    /// Record the changes of pages flushed since the header was last written, writing the header if there are any. Returns the number of pages synchronised.
+    /// Writing the header for any other reason picks up flushed pages too, so this is only needed when nothing else changes.
+    pub fn sync_pages(&mut self) -> Result<usize> {
+        let synced = self.apply_page_requests()?;
+        if synced > 0 {
+            self.write_header()?;
+        }
+
+        Ok(synced)
+    }
+
+    /// Apply the requests sent by open pages, returning the number of descriptors recorded.
+    /// Descriptors of pages which have since been deleted or renamed are discarded, as are those of pages whose chunks were replaced through the database after the page was opened or last flushed. The chunks such pages allocated are freed.
+    fn apply_page_requests(&mut self) -> Result<usize> {
+        let mut synced = 0;
+
+        while let Ok(request) = self.page_requests.1.try_recv() {
+            let PageRequest::Sync { descriptor, previous, allocated, before } = request else { continue; };
+            let descriptor = *descriptor;
+            // The page's new chunks are either taken into the inode table or released below, so they no longer need keeping clear
+            self.backing.settle_allocations(&allocated);
+
+            let Some(page) = self.inode_table.get_mut(&descriptor.name) else {
+                self.release_chunks(descriptor.inodes)?;
+                continue;
+            };
+
+            let unchanged = page.inodes.len() == previous.len() && page.inodes.iter()
+                .zip(previous.iter())
+                .all(|(a, b)| a.offset == b.offset && a.length == b.length);
+            if !unchanged {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(page = %descriptor.name, "Discarded the descriptor of a page whose chunks were replaced while it was open");
+
+                self.release_chunks(descriptor.inodes)?;
+                continue;
+            }
+
+            let previous = std::mem::replace(page, descriptor).inodes;
+            self.hooks.written(&page.name, page.content_length);
+
+            let (name, chunks, access_control_list) = (page.name.clone(), page.inodes.clone(), page.access_control_list.clone());
+            self.append_history_entry(HistoryOp::Write, &name, &chunks)?;
+            if let Some(before) = before {
+                self.journal_change(&name, Some((before, access_control_list)))?;
+            }
+
+            // Chunks the page has given up, such as when it was truncated
+            self.release_chunks(previous)?;
+            synced += 1;
+        }
+
+        Ok(synced)
+    }
+
+    /// The header flags of the optional inode table entry fields the table is serialised with.
+    /// Entries always record content lengths, compression, checksums, user data blocks, timestamps and CRCs, and the nonces of their chunks once anything is encrypted.
+    fn entry_flags(&self) -> u64 {
+        let encrypted = self.backing.is_encrypted() || self.iter_chunks().any(|(_, chunk)| chunk.nonce.is_some());
+
+        FLAG_CONTENT_LENGTH | FLAG_COMPRESSION | FLAG_CHECKSUM | FLAG_USER_DATA | FLAG_TIMESTAMPS | FLAG_CRC | if encrypted { FLAG_NONCES } else { 0 }
+    }
+
+    /// Rewrite the inode table of databases written before some of its optional entry fields were introduced, so that every entry carries all of them.
+    /// The new fields take their defaults: uncompressed, without a checksum, without nonces and timestamped now. Returns `false` without writing anything if the entries already carry every field.
+    /// Entry fields are announced by header flags rather than the format version, which only changes with the layout of the header itself (version 2 adds the key salt), so the version is left as it is. See `migrate_v1_to_v2` for that.
+    /// > **Note**: Any write of the header does the same. This is for databases which are otherwise only read.
+    pub fn add_missing_entry_fields(&mut self) -> Result<bool> {
+        let required = self.entry_flags();
+        let flags = u64::from_le_bytes(self.raw_header[8..16]
+            .try_into()
+            .map_err(Error::other)?);
+
+        if flags & required == required {
+            return Ok(false);
+        }
+
+        self.write_header()?;
+
+        Ok(true)
+    }
+
+    /// Upgrade a version 1 database to version 2 in place. Does nothing if the database already is version 2.
+    /// Version 2 headers are followed by the salt passphrases are derived with, which databases that weren't created with a passphrase leave zeroed. The metadata moves out of the salt's way, and is written along with the rest of the header,
+    /// whose inode table carries every optional entry field afterwards, as with `add_missing_entry_fields`. Pages' content stays where it is.
+    pub fn migrate_v1_to_v2(&mut self) -> Result<()> {
+        self.begin_write()?;
+
+        if self.salt.is_some() {
+            return Ok(());
+        }
+
+        // The metadata's previous space is freed, save for where the salt and the metadata itself end up
+        let previous = self.metadata_region();
+        self.raw_header[4..8].copy_from_slice(&u32::to_le_bytes(0x02));
+        self.salt = Some([0u8; 16]);
+        self.metadata_range.offset = self.base_offset + self.raw_header.len() as u64 + 16;
+        self.release_chunks(vec![previous])?;
+
+        self.write_header()
+    }
+
+    /// Serialise and write the header regardless of whether the database is sealed.
+    fn flush_header(&mut self) -> Result<()> {
+        self.apply_page_requests()?;
+        self.rebuild_links();
+
+        // The space the tables occupied so far, taken before they're re-serialised
+        let previous_tables = [
+            Array { offset: self.inode_table_range.offset, length: self.inode_table_size, nonce: None },
+            Array { offset: self.string_table_range.offset, length: self.string_table_size, nonce: None },
+            Array { offset: self.history_table_range.offset, length: self.history_table_size, nonce: None },
+        ].into_iter()
+            .filter(|table| table.length > 0)
+            .reduce(|a, b| Array { offset: a.offset.min(b.offset), length: a.end().max(b.end()) - a.offset.min(b.offset), nonce: None })
+            .unwrap_or(Array { offset: 0, length: 0, nonce: None });
+
+        let flags = u64::from_le_bytes(self.raw_header[8..16]
+            .try_into()
+            .map_err(Error::other)?) | self.entry_flags() | FLAG_FREE_LIST;
+        self.raw_header[8..16].copy_from_slice(&flags.to_le_bytes());
+
+        let metadata = MetadataFormat::from_flags(flags)?
+            .serialise(&self.meta)?;
+        self.metadata_range.length = metadata.len() as u64;
+
+        // Serialise the INode Table before the String Table as it may alter the string table
+        let inode_table = self.serialise_inode_table()?;
+        let string_table = self.serialise_string_table()?;
+        let history_table = self.serialise_history_table()?;
+
+        let align = |offset: u64| offset + (0x10 - offset % 0x10) % 0x10;
+        let header = Array { offset: self.base_offset, length: self.raw_header.len() as u64 + self.salt.map(|i| i.len() as u64).unwrap_or(0), nonce: None };
+        let borrowed = self.reserved_slices()?;
+        let mut obstacles = self.inode_table.values()
+            .flat_map(|page| page.inodes.iter())
+            .chain(borrowed.iter())
+            .filter(|chunk| chunk.length > 0)
+            .cloned()
+            .collect::<Vec<_>>();
+        let last = obstacles.iter()
+            .map(|chunk| chunk.end())
+            .max()
+            .unwrap_or(0);
+
+        // Metadata which has outgrown its space moves past the last chunk
+        if obstacles.iter().any(|chunk| chunk.overlaps(&self.metadata_region())) {
+            self.metadata_range.offset = align(last);
+        }
+
+        obstacles.push(header);
+        obstacles.push(self.metadata_region());
+        obstacles.sort_unstable_by_key(|chunk| chunk.offset);
+
+        // The tables go into the first gap large enough to hold them, or past the last chunk if there is none. Neither the space they occupied so far nor the free list count against them.
+        // The space they leave behind is freed and the space they now occupy taken off the free list, which is stored along with the history table. Should that have outgrown the gap, the search starts over.
+        let free_list = self.free_list.clone();
+        let mut history_length = history_table.len() as u64;
+        let (inode_offset, string_offset, history_offset, history_table) = loop {
+            let layout = |start: u64| {
+                let inode_offset = align(start);
+                let string_offset = align(inode_offset + inode_table.len() as u64);
+                let history_offset = align(string_offset + string_table.len() as u64);
+                (inode_offset, string_offset, history_offset, history_offset + history_length)
+            };
+
+            let mut cursor = self.base_offset;
+            let mut placement = None;
+            for chunk in obstacles.iter() {
+                if layout(cursor).3 <= chunk.offset {
+                    placement = Some(layout(cursor));
+                    break;
+                }
+                cursor = cursor.max(chunk.end());
+            }
+            let (inode_offset, string_offset, history_offset, end) = placement.unwrap_or_else(|| layout(cursor));
+
+            self.release_chunks(vec![previous_tables])?;
+            for region in [header, self.metadata_region(), Array { offset: inode_offset, length: end - inode_offset, nonce: None }] {
+                self.claim_free_space(region);
+            }
+
+            let history_table = self.serialise_history_table()?;
+            if history_table.len() as u64 <= history_length {
+                break (inode_offset, string_offset, history_offset, history_table);
+            }
+
+            history_length = history_table.len() as u64;
+            self.free_list = free_list.clone();
+        };
+
+        let mut backing = self.backing
+            .lock_backing()
+            .map_err(Error::other)?;
+
+        self.inode_table_range = Array { length: self.inode_table.len() as u64, offset: inode_offset, nonce: None };
+        self.string_table_range = Array { length: self.string_table.borrow().len() as u64, offset: string_offset, nonce: None };
+        self.history_table_range = Array { length: self.history_table.len() as u64, offset: history_offset, nonce: None };
+
+        // ranges, stored relative to the header:
+        let ranges = [self.inode_table_range, self.string_table_range, self.history_table_range, self.metadata_range]
+            .into_iter()
+            .flat_map(|i| [i.length, i.offset - self.base_offset])
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.raw_header[0x10..0x50].copy_from_slice(&ranges);
+        let crc = header_crc(&self.raw_header);
+        self.raw_header[HEADER_CRC].copy_from_slice(&crc.to_le_bytes());
+
+        // Write Header
+        backing.seek(SeekFrom::Start(self.base_offset))?;
+        backing.write_all(&self.raw_header)?;
+
+        if let Some(salt) = self.salt {
+            backing.write_all(&salt)?;
+        }
+
+        backing.seek(SeekFrom::Start(self.metadata_range.offset))?;
+        backing.write_all(&metadata)?;
+
+        if let Some(hash) = self.seal_key_hash {
+            backing.write_all(&hash)?;
+        }
+
+        backing.seek(SeekFrom::Start(inode_offset))?;
+        backing.write_all(&inode_table)?;
+
+        backing.seek(SeekFrom::Start(string_offset))?;
+        backing.write_all(&string_table)?;
+
+        backing.seek(SeekFrom::Start(history_offset))?;
+        backing.write_all(&history_table)?;
+
+        if let Some(sync) = self.sync_on_write {
+            sync(&mut backing)?;
+        }
+
+        Ok(())
+    }
+
+    /// Generate a byte buffer of the inode table
+    fn serialise_inode_table(&mut self) -> Result<Vec<u8>> {
+        let mut vec = vec![];
+        let flags = u64::from_le_bytes(self.raw_header[8..16]
+            .try_into()
+            .map_err(Error::other)?);
+        let nonces = flags & FLAG_NONCES != 0;
+        let crc = flags & FLAG_CRC != 0;
+
+        let pages = self.pages_in_table_order()
+            .into_iter()
+            .map(|page| (page.name.clone(), page.clone()))
+            .collect::<Vec<_>>();
+
+        for (name, page) in pages {
+            let entry_start = vec.len();
+            self.get_strtab_index(&name)?;
+
+            let acls: Vec<_> = page.access_control_list
+                .iter()
+                .map(|i| Ok(match i {
+                    Access::None(entity) => (0b000u8, self.get_strtab_index(entity)?),
+                    Access::Read(entity) => (0b001u8, self.get_strtab_index(entity)?),
+                    Access::ReadWrite(entity) => (0b011u8, self.get_strtab_index(entity)?),
+                    Access::ReadWriteExecute(entity) => (0b111u8, self.get_strtab_index(entity)?),
+                    Access::ReadExecute(entity) => (0b101u8, self.get_strtab_index(entity)?),
+                    Access::Custom(entity, perm) => (*perm, self.get_strtab_index(entity)?)
+                }))
+                .collect::<Result<Vec<(u8, u64)>>>()?
+                .into_iter()
+                .flat_map(|i| {
+                    let mut arr = [0u8; 1 + 8];
+                    arr[0] = i.0;
+
+                    i.1
+                        .to_le_bytes()
+                        .into_iter()
+                        .enumerate()
+                        .for_each(|(a, i)| arr[a + 1] = i);
+
+                    arr
+                })
+                .collect();
+
+            vec.extend([
+                &u64::to_le_bytes(self.get_strtab_index(&page.name)?)[..],
+                &u16::to_le_bytes(page.access_control_list.len() as u16)[..],
+                &acls[..],
+                &vec![0x00; (0x10 - (2 + acls.len()) % 0x10) % 0x10][..],
+                &u64::to_le_bytes(page.inodes.len() as u64)[..],
+            ][..]
+                .iter()
+                .cloned()
+                .flatten());
+
+            for i in page.inodes.iter().cloned() {
+                vec.extend_from_slice(&i.length.to_le_bytes()[..]);
+                vec.extend_from_slice(&(i.offset - self.base_offset).to_le_bytes()[..]);
+            }
+
+            vec.extend_from_slice(&page.content_length.to_le_bytes()[..]);
+            vec.extend_from_slice(&[page.compression.id(), 0, 0, 0]);
+            vec.extend_from_slice(&page.compression.level().to_le_bytes()[..]);
+            match page.checksum {
+                Some(checksum) => {
+                    vec.push(0x01);
+                    vec.extend_from_slice(&checksum);
+                },
+                None => vec.extend_from_slice(&[0x00; 1 + 32]),
+            }
+            vec.extend_from_slice(&(page.user_data.len() as u64).to_le_bytes()[..]);
+            vec.extend_from_slice(&page.user_data);
+            vec.extend(iter::repeat_n(0x00, (0x10 - page.user_data.len() % 0x10) % 0x10));
+
+            if nonces {
+                for chunk in page.inodes.iter() {
+                    match chunk.nonce {
+                        Some(nonce) => {
+                            vec.push(0x01);
+                            vec.extend_from_slice(&nonce);
+                            vec.extend_from_slice(&[0x00; 3]);
+                        },
+                        None => vec.extend_from_slice(&[0x00; 1 + 12 + 3]),
+                    }
+                }
+            }
+
+            for timestamp in [page.created, page.modified] {
+                let nanos = timestamp.duration_since(UNIX_EPOCH)
+                    .map(|i| i.as_nanos() as u64)
+                    .unwrap_or(0);
+                vec.extend_from_slice(&nanos.to_le_bytes());
+            }
+
+            if crc {
+                let crc = crc32c(&vec[entry_start..]);
+                vec.extend_from_slice(&crc.to_le_bytes());
+            }
+        }
+
+        self.inode_table_size = vec.len() as u64;
+        Ok(vec)
+    }
+
+    /// Generate a byte-buffer of the string table, in which every string is prefixed with its `u16` length.
+    /// Fails with `InvalidInput` if any string is longer than `u16::MAX` bytes.
+    fn serialise_string_table(&mut self) -> Result<Vec<u8>> {
+        let mut vec = vec![];
+
+        for i in self.string_table.try_borrow().map_err(Error::other)?.iter() {
+            let len = u16::try_from(i.len())
+                .map_err(|_| Error::new(std::io::ErrorKind::InvalidInput, format!("Strings may be at most {} bytes long, but one is {}", u16::MAX, i.len())))?;
+
+            vec.extend_from_slice(&len.to_le_bytes());
+            vec.extend_from_slice(i.as_bytes());
+        }
+
+        self.string_table_size = vec.len() as u64;
+        Ok(vec)
+    }
+
+    /// Generate a byte-buffer of the history table, followed by the free list
+    fn serialise_history_table(&mut self) -> Result<Vec<u8>> {
+        let mut vec = vec![];
+
+        for entry in self.history_table.iter() {
+            entry.write(&mut vec, self.base_offset)?;
+        }
+
+        vec.extend((self.free_list.len() as u64).to_le_bytes());
+        for chunk in self.free_list.iter() {
+            vec.extend(chunk.length.to_le_bytes());
+            vec.extend((chunk.offset - self.base_offset).to_le_bytes());
+        }
+
+        self.history_table_size = vec.len() as u64;
+        Ok(vec)
+    }
+
+    /// Record a change to the named page, along with the chunks it occupies afterwards. The name is added to the string table if necessary.
+    /// The entry is persisted the next time the header is written.
+    fn append_history_entry(&mut self, op: HistoryOp, name: &str, chunks: &[Array]) -> Result<()> {
+        let entry = HistoryEntry {
+            op,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|i| i.as_secs())
+                .unwrap_or(0),
+            page_name_index: self.get_strtab_index(&name.to_owned())?,
+            chunks_snapshot: chunks.to_vec(),
+        };
+
+        self.history_table.push(entry);
+        self.trim_history();
+
+        Ok(())
+    }
+
+    /// Drop the oldest history entries until there are no more than `history_limit`.
+    fn trim_history(&mut self) {
+        if let Some(excess) = self.history_limit.and_then(|limit| self.history_table.len().checked_sub(limit)) {
+            self.history_table.drain(..excess);
+        }
+    }
+
+    /// Keep no more than `limit` history entries from now on, dropping the oldest first, or all of them if `None`. Defaults to `DEFAULT_HISTORY_LIMIT`.
+    /// Entries beyond the limit are dropped straight away, and the table shrinks the next time the header is written. Page timestamps are kept in the inode table, so they aren't affected.
+    /// > **Note**: The setting isn't persisted.
+    pub fn set_history_limit(&mut self, limit: Option<usize>) {
+        self.history_limit = limit;
+        self.trim_history();
+    }
+
+    /// Every recorded change to the named page, oldest first. Changes made before the page was renamed are recorded under its previous name.
+    pub fn get_history(&self, page: &str) -> Result<Vec<HistoryEntry>> {
+        let strtab = self.string_table.try_borrow()
+            .map_err(Error::other)?;
+
+        Ok(self.history_table.iter()
+            .filter(|entry| strtab.get(entry.page_name_index as usize).is_some_and(|name| name == page))
+            .cloned()
+            .collect())
+    }
+
+    // TODO: Refactor to make returning multiple chunks which add up to `min_space` possible
+    /// Request the backing object grow by `min_space` bytes.
+    /// This is used before appending chunks to a page, and ensures that unused chunks are either reused, deleted or reallocated before being assigned to a page.
+    fn allocate_chunks(&mut self, min_space: u64) -> Result<Vec<Array>> {
+        let total_length: u64 = self.backing.lock_backing()
+            .map_err(Error::other)?
+            .deref_mut()
+            .stream_len()?;
+
+        self.prune_free_list()?;
+
+        // Chunks released by deleted pages are reused first, picking the smallest which fits
+        if let Some((index, _)) = self.free_list.iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.length >= min_space)
+            .min_by_key(|(_, chunk)| chunk.length) {
+            let chunk = self.free_list.swap_remove(index);
+            if chunk.length > min_space {
+                self.free_list.push(Array { offset: chunk.offset + min_space, length: chunk.length - min_space, nonce: None });
+            }
+
+            let chunk = Array { offset: chunk.offset, length: min_space, nonce: None };
+            debug_assert!(self.is_unallocated(&chunk), "{:?} was allocated twice", chunk);
+
+            return Ok(vec![chunk]);
+        }
+
+        let borrowed = self.reserved_slices()?;
+
+        let mut inodes = self.inode_table.values()
+            .flat_map(|i| i.inodes.iter())
+            .cloned()
+            .chain(borrowed)
+            .chain(iter::once(Array { length: 0, offset: self.data_offset(), nonce: None }))
+            .chain(iter::once(Array { length: 0, offset: total_length, nonce: None }))
+            .collect::<Vec<_>>();
+
+        inodes.sort_unstable_by(|i, j| Ord::cmp(&i.offset, &j.offset));
+
+        let mut inodes = inodes
+            .into_iter()
+            .scan(Array { length: 0u64, offset: self.data_offset(), nonce: None }, |a, i| {
+                // The gap is the furthest end seen so far => the start of the next. Chunks may share a start (such as the data offset), so they can't be assumed to be disjoint.
+                let out = Some(Array {
+                    length: i.offset.saturating_sub(a.end()),
+                    offset: a.end(),
+                    nonce: None,
+                });
+                if i.end() > a.end() {
+                    *a = i;
+                }
+                out
+            })
+            .collect::<Vec<_>>();
+        inodes.sort_unstable_by(|i, j| Ord::cmp(&i.length, &j.length));
+
+        if let Some(inode) = inodes.iter()
+            .find(|i| i.length >= min_space) {
+            let chunk = Array { offset: inode.offset, length: min_space, nonce: None };
+            debug_assert!(self.is_unallocated(&chunk), "{:?} was allocated twice", chunk);
+
+            Ok(vec![chunk])
+        } else {
+            // todo!("Expand file to make room for new chunk")
+            let mut backing = self.backing.lock_backing()
+                .map_err(Error::other)?;
+
+            let position = backing.seek(SeekFrom::End(0))?;
+            backing.write_all(&vec![0u8; (min_space + (0x1000 - min_space % 0x1000)) as usize])?;
+
+            Ok(vec![Array {offset: position, length: min_space, nonce: None }])
+        }
+    }
+
+    /// Whether `chunk` is clear of every page's chunks, the slices borrowed by pending writes, the agent's reservations and the tables. Only used to check the allocator's choices.
+    fn is_unallocated(&self, chunk: &Array) -> bool {
+        // The caller may already hold the lock, in which case the borrowed slices can't be checked
+        let borrowed = self.borrowed_slices.try_lock()
+            .map(|borrowed| borrowed.clone())
+            .unwrap_or_default();
+
+        self.used_regions()
+            .into_iter()
+            .map(|(_, region)| region)
+            .chain(borrowed)
+            .chain(self.agent_reservations.iter().cloned())
+            .all(|region| !chunk.overlaps(&region))
+    }
+
+    /// Drop the entries of the free list which have since been put to use by other means, such as `create_page_at_offset`, or which overlap the tables.
+    fn prune_free_list(&mut self) -> Result<()> {
+        let borrowed = self.reserved_slices()?;
+        let used = self.used_regions()
+            .into_iter()
+            .map(|(_, region)| region)
+            .chain(borrowed)
+            .collect::<Vec<_>>();
+
+        self.free_list.retain(|chunk| !used.iter().any(|region| chunk.overlaps(region)));
+
+        Ok(())
+    }
+
+    /// Add chunks a page no longer refers to to the free list, merging them with adjacent entries. Chunks still referenced by another page or borrowed are kept out of it.
+    fn release_chunks(&mut self, chunks: Vec<Array>) -> Result<()> {
+        let borrowed = self.reserved_slices()?;
+
+        let freed = chunks.into_iter()
+            .filter(|chunk| chunk.length > 0)
+            .filter(|chunk| !self.inode_table.values()
+                .flat_map(|page| page.inodes.iter())
+                .chain(borrowed.iter())
+                .any(|used| chunk.overlaps(used)))
+            .map(|chunk| Array { nonce: None, ..chunk });
+
+        let mut free_list = std::mem::take(&mut self.free_list);
+        free_list.extend(freed);
+        free_list.sort();
+
+        for chunk in free_list {
+            match self.free_list.last().and_then(|last| last.merge(&chunk)) {
+                Some(merged) => {
+                    self.free_list.pop();
+                    self.free_list.push(merged);
+                },
+                None => self.free_list.push(chunk),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove `region` from the free list, keeping whatever lies either side of it.
+    fn claim_free_space(&mut self, region: Array) {
+        self.free_list = std::mem::take(&mut self.free_list)
+            .into_iter()
+            .flat_map(|chunk| match chunk.overlaps(&region) {
+                true => vec![
+                    Array { offset: chunk.offset, length: region.offset.saturating_sub(chunk.offset), nonce: None },
+                    Array { offset: region.end(), length: chunk.end().saturating_sub(region.end()), nonce: None },
+                ],
+                false => vec![chunk],
+            })
+            .filter(|chunk| chunk.length > 0)
+            .collect();
+    }
+
+    /// Copy `content` into freshly allocated chunks without referencing them from the inode table.
+    /// The chunks are recorded as borrowed so that subsequent allocations don't hand them out again.
+    fn prepare_write(&mut self, content: &[u8]) -> Result<Vec<Array>> {
+        if content.is_empty() { return Ok(vec![]); }
+
+        // Encrypted chunks are followed by their authentication tag
+        let overhead = self.backing.stored_length(0);
+        let mut chunks = self.allocate_chunks(content.len() as u64 + overhead)?;
+
+        {
+            let mut backing = self.backing.lock_backing()
+                .map_err(Error::other)?;
+
+            let mut remaining = content;
+            for chunk in chunks.iter_mut() {
+                let (head, tail) = remaining.split_at((chunk.length - overhead) as usize);
+                let (stored, nonce) = self.backing.encrypt_chunk(head)?;
+
+                backing.seek(SeekFrom::Start(chunk.offset))?;
+                backing.write_all(&stored)?;
+                chunk.nonce = nonce;
+                remaining = tail;
+            }
+        }
+
+        self.borrowed_slices.lock()
+            .map_err(|_| Error::other("PoisonError"))?
+            .extend(chunks.iter().cloned());
+
+        Ok(chunks)
+    }
+
+    /// Return borrowed chunks, making them available for allocation again unless a page references them.
+    fn release_slices(&self, chunks: &[Array]) -> Result<()> {
+        self.borrowed_slices.lock()
+            .map_err(|_| Error::other("PoisonError"))?
+            .retain(|i| !chunks.contains(i));
+
+        Ok(())
+    }
+
+    /// Point a page at `inodes`, creating the page if it doesn't exist yet, and notify the relevant hooks. The page's previous chunks are added to the free list.
+    fn install_chunks(&mut self, name: &str, inodes: Vec<Array>) -> Result<()> {
+        let written = inodes.iter()
+            .map(|i| i.content_length())
+            .sum();
+
+        match self.inode_table.get_mut(name) {
+            Some(page) => {
+                let previous = std::mem::replace(&mut page.inodes, inodes);
+                page.content_length = written;
+                page.compression = CompressionAlgorithm::None;
+                page.checksum = None;
+                page.modified = SystemTime::now();
+
+                let chunks = page.inodes.clone();
+                self.append_history_entry(HistoryOp::Write, name, &chunks)?;
+                self.release_chunks(previous)?;
+            },
+            None => {
+                self.append_history_entry(HistoryOp::Create, name, &inodes)?;
+
+                let page = PageDescriptor {
+                    name: name.to_owned(),
+                    access_control_list: vec![],
+                    user_data: vec![],
+                    modified: SystemTime::now(),
+                    created: SystemTime::now(),
+                    content_length: written,
+                    link_count: 1,
+                    compression: CompressionAlgorithm::None,
+                    checksum: None,
+                    inodes,
+                };
+
+                self.hooks.created(name, &page);
+                self.invalidate_sorted_index();
+                self.inode_table.insert(name.to_owned(), page);
+            }
+        }
+        self.refresh_checksum(name)?;
+
+        self.hooks.written(name, written);
+
+        Ok(())
+    }
+
+    /// Record the SHA-256 hash of the named page's content as its checksum. The checksum covers the content as it's read back, so this has to happen once the page's chunks and compression are in place. See `hash_content`.
+    fn refresh_checksum(&mut self, name: &str) -> Result<()> {
+        let checksum = match self.inode_table.get(name) {
+            Some(page) => self.hash_content(page)?,
+            None => return Ok(()),
+        };
+
+        if let Some(page) = self.inode_table.get_mut(name) {
+            page.checksum = Some(checksum);
+        }
+
+        Ok(())
+    }
+
+    /// Replace the content of a page with `content`, creating the page if it doesn't exist yet. The page's previous chunks are added to the free list.
+    /// The header isn't written; it's up to the caller to do so.
+    pub(crate) fn write_page_content(&mut self, name: &str, content: &[u8]) -> Result<()> {
+        self.begin_write()?;
+
+        let inodes = self.prepare_write(content)?;
+        self.release_slices(&inodes)?;
+
+        let before = self.journaled_content(name)?;
+        self.install_chunks(name, inodes)?;
+        self.journal_change(name, before)?;
+
+        Ok(())
+    }
+
+    /// Recompress the named page's content with `algorithm`. The content is decompressed with the page's current algorithm, compressed with the new one and written to freshly allocated chunks.
+    pub fn set_compression_algorithm(&mut self, name: &str, algorithm: CompressionAlgorithm) -> Result<()> {
+        self.begin_write()?;
+
+        let page = self.inode_table.get(name)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))?;
+
+        if page.compression == algorithm {
+            return Ok(());
+        }
+
+        let content = page.compression.decompress(&self.read_chunks(&page.inodes)?)?;
+        let compressed = algorithm.compress(&content)?;
+
+        let inodes = self.prepare_write(&compressed)?;
+        self.release_slices(&inodes)?;
+
+        self.install_chunks(name, inodes)?;
+        if let Some(page) = self.inode_table.get_mut(name) {
+            page.compression = algorithm;
+        }
+        self.refresh_checksum(name)?;
+
+        self.write_header()
+    }
+
+    /// Compress the named page's content with LZ4, returning the number of bytes it now occupies. Pages opened afterwards decompress it transparently.
+    #[cfg(feature = "compression")]
+    pub fn compress_page(&mut self, name: &str) -> Result<u64> {
+        self.set_compression_algorithm(name, CompressionAlgorithm::Lz4)?;
+        self.stored_length(name)
+    }
+
+    /// Store the named page's content uncompressed again, returning the number of bytes it now occupies.
+    #[cfg(feature = "compression")]
+    pub fn decompress_page(&mut self, name: &str) -> Result<u64> {
+        self.set_compression_algorithm(name, CompressionAlgorithm::None)?;
+        self.stored_length(name)
+    }
+
+    #[cfg(feature = "compression")]
+    fn stored_length(&self, name: &str) -> Result<u64> {
+        self.inode_table.get(name)
+            .map(|page| page.inodes.iter().map(|chunk| chunk.length).sum())
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))
+    }
+
+    /// Create the named page with `content`, or replace its content if it already exists. The page's previous chunks are freed for reuse.
+    /// Holding the database mutably for the duration rules out interleaving operations, and the header is written once at the end.
+    pub fn create_or_replace_page(&mut self, name: &str, content: &[u8]) -> Result<()> {
+        self.begin_write()?;
+
+        self.write_page_content(name, content)?;
+        self.write_header()
+    }
+
+    /// Create an empty page holding fixed-size records of `record_size` bytes, which can be accessed with `Page::read_record` and `Page::write_record`.
+    /// The record size is kept in the page's user data. Fails if the page already exists.
+    pub fn create_record_page(&mut self, name: &str, record_size: u64) -> Result<()> {
+        self.begin_write()?;
+
+        if record_size == 0 {
+            return Err(Error::new(std::io::ErrorKind::InvalidInput, "Records must be at least one byte long"));
+        }
+
+        if self.inode_table.contains_key(name) {
+            return Err(Error::new(std::io::ErrorKind::AlreadyExists, format!("'{}' already exists", name)));
+        }
+
+        self.write_page_content(name, &[])?;
+        if let Some(page) = self.inode_table.get_mut(name) {
+            page.set_record_size(record_size);
+        }
+
+        self.write_header()
+    }
+
+    /// Rename the page `old` to `new`, updating its modification time. The header is written before returning.
+    /// Fails with `NotFound` if there's no page named `old`, `AlreadyExists` if `new` is taken and `ResourceBusy` if `old` is open. The old name stays in the string table until it's garbage-collected.
+    pub fn rename_page(&mut self, old: &str, new: &str) -> Result<()> {
+        self.begin_write()?;
+
+        if !self.inode_table.contains_key(old) {
+            return Err(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", old)));
+        }
+
+        if self.is_page_open(old) || self.is_page_exclusively_open(old) {
+            return Err(Error::new(std::io::ErrorKind::ResourceBusy, format!("'{}' is open", old)));
+        }
+
+        if old == new {
+            return Ok(());
+        }
+
+        if self.inode_table.contains_key(new) {
+            return Err(Error::new(std::io::ErrorKind::AlreadyExists, format!("A page named '{}' already exists", new)));
+        }
+
+        let journaled = self.journaled_contents([old.to_owned(), new.to_owned()].iter())?;
+
+        self.invalidate_sorted_index();
+        let page = self.inode_table.remove(old)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", old)))?;
+
+        self.insert_renamed(old, new, page)?;
+        self.journal_changes(journaled)?;
+
+        self.write_header()
+    }
+
+    /// Put `page`, just taken out of the inode table under the name `old`, back under `new`. Its modification time is updated and the rename recorded in the history.
+    /// Hooks are notified of the deletion of `old` and the creation of `new`, just as the rename is journaled.
+    fn insert_renamed(&mut self, old: &str, new: &str, page: PageDescriptor) -> Result<()> {
+        let page = PageDescriptor {
+            name: new.to_owned(),
+            modified: SystemTime::now(),
+            ..page
+        };
+        self.append_history_entry(HistoryOp::Rename, new, &page.inodes)?;
+        self.inode_table.insert(page.name.clone(), page);
+
+        self.hooks.deleted(old);
+        if let Some(page) = self.inode_table.get(new) {
+            self.hooks.created(new, page);
+        }
+
+        Ok(())
+    }
+
+    /// Rename every page starting with `old_prefix` by replacing that prefix with `new_prefix`, returning the number of pages renamed. Each page is renamed as by `rename_page`.
+    /// If any new name is already taken by a page which isn't itself being renamed, nothing is renamed, and neither is it if any of the pages is open. The header is written once at the end.
+    pub fn rename_all(&mut self, old_prefix: &str, new_prefix: &str) -> Result<usize> {
+        self.begin_write()?;
+
+        let mut renames = self.inode_table.keys()
+            .filter_map(|name| name.strip_prefix(old_prefix)
+                .map(|rest| (name.clone(), format!("{}{}", new_prefix, rest))))
+            .collect::<Vec<_>>();
+        renames.sort_unstable();
+
+        if let Some((open, _)) = renames.iter()
+            .find(|(old, _)| self.is_page_open(old) || self.is_page_exclusively_open(old)) {
+            return Err(Error::new(std::io::ErrorKind::ResourceBusy, format!("'{}' is open", open)));
+        }
+
+        if let Some((_, conflict)) = renames.iter()
+            .find(|(_, new)| self.inode_table.contains_key(new) && !new.starts_with(old_prefix)) {
+            return Err(Error::new(std::io::ErrorKind::AlreadyExists, format!("A page named '{}' already exists", conflict)));
+        }
+
+        let journaled = self.journaled_contents(renames.iter().flat_map(|(old, new)| [old, new]))?;
+
+        // Every page is taken out before any is put back, as new names may be old names of other pages
+        self.invalidate_sorted_index();
+        let pages = renames.iter()
+            .filter_map(|(old, new)| self.inode_table.remove(old)
+                .map(|page| (old, new, page)))
+            .collect::<Vec<_>>();
+
+        for (old, new, page) in pages {
+            self.insert_renamed(old, new, page)?;
+        }
+
+        self.journal_changes(journaled)?;
+        self.write_header()?;
+
+        Ok(renames.len())
+    }
+
+    /// Run `migration` over every page in alphabetical order, passing it the page's name, descriptor and full (decompressed) content to modify, and then apply the returned actions.
+    /// Changes to the ACL, user data and timestamps are carried over; the fields describing the page's storage are managed by the database and can't be migrated.
+    /// Changed content is written back with the page's existing compression. Renames are checked for conflicts before anything is applied, and the header is written once at the end.
+    /// ```rust
+    /// # use datastore_provider::format::database::MigrationAction;
+    /// # #[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+    /// # struct Metadata { friendly_name: String }
+    /// # let mut db = datastore_provider::format::blank::<Metadata>()?;
+    /// # db.create_or_replace_page("legacy/a", b"Hello")?;
+    /// db.apply_migration(|name, page, content| match name.strip_prefix("legacy/") {
+    ///     Some(rest) => MigrationAction::Rename(format!("v2/{}", rest)),
+    ///     None => MigrationAction::Keep
+    /// })?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn apply_migration<Migration: Fn(&str, &mut PageDescriptor, &mut Vec<u8>) -> MigrationAction>(&mut self, migration: Migration) -> Result<MigrationResult> {
+        self.begin_write()?;
+
+        let mut names = self.inode_table.keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+
+        let mut changes = vec![];
+        let mut result = MigrationResult::default();
+
+        for name in names {
+            let Some(page) = self.inode_table.get(&name) else { continue; };
+
+            let mut content = vec![];
+            self.pipe_content(page, 0, u64::MAX, &mut content)?;
+            let (mut descriptor, mut migrated) = (page.clone(), content.clone());
+
+            match migration(&name, &mut descriptor, &mut migrated) {
+                MigrationAction::Skip => result.skipped += 1,
+                MigrationAction::Delete => {
+                    result.deleted += 1;
+                    changes.push((name, None, descriptor, None));
+                },
+                action => {
+                    let target = match action {
+                        MigrationAction::Rename(target) if target != name => {
+                            result.renamed += 1;
+                            Some(target)
+                        },
+                        _ => {
+                            result.kept += 1;
+                            None
+                        }
+                    };
+
+                    let content = (migrated != content).then_some(migrated);
+                    changes.push((name, Some(target), descriptor, content));
+                }
+            }
+        }
+
+        // Names freed up by deletions and renames may be reused by other renames
+        let mut taken = self.inode_table.keys()
+            .cloned()
+            .collect::<std::collections::HashSet<_>>();
+        for (name, target, ..) in changes.iter() {
+            if !matches!(target, Some(None)) {
+                taken.remove(name);
+            }
+        }
+        for (_, target, ..) in changes.iter() {
+            if let Some(Some(target)) = target {
+                if !taken.insert(target.clone()) {
+                    return Err(Error::new(std::io::ErrorKind::AlreadyExists, format!("A page named '{}' already exists", target)));
+                }
+            }
+        }
+
+        let journaled = self.journaled_contents(changes.iter()
+            .flat_map(|(name, target, ..)| iter::once(name).chain(target.iter().flatten())))?;
+
+        self.invalidate_sorted_index();
+        for (name, target, descriptor, content) in changes {
+            let Some(mut page) = self.inode_table.remove(&name) else { continue; };
+
+            let Some(target) = target else {
+                self.hooks.deleted(&name);
+                continue;
+            };
+
+            let written = content.as_ref().map(|content| content.len() as u64);
+            if let Some(content) = content {
+                let stored = page.compression.compress(&content)?;
+                let inodes = self.prepare_write(&stored)?;
+                self.release_slices(&inodes)?;
+
+                result.bytes_written += content.len() as u64;
+                // Excludes the authentication tags of encrypted chunks, and matches the content's length unless it's compressed
+                page.content_length = stored.len() as u64;
+                page.inodes = inodes;
+                page.checksum = Some(self.hash_content(&page)?);
+                page.modified = SystemTime::now();
+            }
+
+            let name = target.unwrap_or(name);
+            if let Some(written) = written {
+                self.hooks.written(&name, written);
+            }
+            if page.access_control_list != descriptor.access_control_list {
+                self.hooks.acl_changed(&name, &descriptor.access_control_list);
+            }
+
+            self.inode_table.insert(name.clone(), PageDescriptor {
+                name,
+                access_control_list: descriptor.access_control_list,
+                user_data: descriptor.user_data,
+                modified: descriptor.modified.max(page.modified),
+                created: descriptor.created,
+                ..page
+            });
+        }
+
+        self.journal_changes(journaled)?;
+        self.write_header()?;
+
+        Ok(result)
+    }
+
+    /// Create the page `name` as an alias of `target`, sharing its chunks rather than copying them. Both pages keep their own ACLs and timestamps.
+    /// Writing to either page in place is visible through both, but replacing either page's content wholesale gives it chunks of its own, breaking the link.
+    pub fn create_linked_page(&mut self, name: &str, target: &str) -> Result<()> {
+        self.begin_write()?;
+
+        if self.inode_table.contains_key(name) {
+            return Err(Error::new(std::io::ErrorKind::AlreadyExists, format!("'{}' already exists", name)));
+        }
+
+        let target = self.inode_table.get(target)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", target)))?;
+
+        let page = PageDescriptor {
+            name: name.to_owned(),
+            access_control_list: vec![],
+            modified: SystemTime::now(),
+            created: SystemTime::now(),
+            ..target.clone()
+        };
+
+        self.hooks.created(name, &page);
+        self.invalidate_sorted_index();
+        self.inode_table.insert(name.to_owned(), page);
+        self.journal_change(name, None)?;
+
+        self.write_header()
+    }
+
+    /// Copy the page `src` to `dst`, giving the copy chunks of its own so that writes to either page don't affect the other. Fails if `dst` already exists.
+    /// The stored content is copied as-is, so both pages hash identically with `page_content_hash`. The copy keeps the ACL, user data and compression of `src`, but is timestamped afresh.
+    pub fn clone_page(&mut self, src: &str, dst: &str) -> Result<()> {
+        self.begin_write()?;
+
+        if self.inode_table.contains_key(dst) {
+            return Err(Error::new(std::io::ErrorKind::AlreadyExists, format!("'{}' already exists", dst)));
+        }
+
+        let source = self.inode_table.get(src)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", src)))?
+            .clone();
+
+        let content = self.read_chunks(&source.inodes)?;
+        let inodes = self.prepare_write(&content)?;
+        self.release_slices(&inodes)?;
+
+        let page = PageDescriptor {
+            name: dst.to_owned(),
+            modified: SystemTime::now(),
+            created: SystemTime::now(),
+            link_count: 1,
+            inodes,
+            ..source
+        };
+
+        self.hooks.created(dst, &page);
+        self.hooks.written(dst, content.len() as u64);
+        self.invalidate_sorted_index();
+        self.inode_table.insert(dst.to_owned(), page);
+        self.journal_change(dst, None)?;
+
+        self.write_header()
+    }
+
+    /// The names of the other pages sharing the named page's chunks, sorted alphabetically. Empty for pages which aren't linked.
+    pub fn linked_pages(&self, name: &str) -> Vec<&str> {
+        self.linked_pages.get(name)
+            .map(|names| names.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Delete the named page. Its chunks are freed for reuse, unless they're still shared with a linked page, in which case only that page's link count drops.
+    pub fn delete_page(&mut self, name: &str) -> Result<()> {
+        self.begin_write()?;
+
+        if self.is_page_open(name) || self.is_page_exclusively_open(name) {
+            return Err(Error::new(std::io::ErrorKind::ResourceBusy, format!("'{}' is open", name)));
+        }
+
+        let before = self.journaled_content(name)?;
+
+        self.invalidate_sorted_index();
+        let page = self.inode_table.remove(name)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))?;
+
+        self.append_history_entry(HistoryOp::Delete, name, &page.inodes)?;
+        self.journal_change(name, before)?;
+
+        // Chunks shared with linked pages stay in use
+        self.release_chunks(page.inodes)?;
+
+        self.hooks.deleted(name);
+
+        self.write_header()
+    }
+
+    /// Recompute every page's link count and the index of linked pages, grouping pages by identical chunk lists. Pages without chunks are never linked.
+    fn rebuild_links(&mut self) {
+        let mut groups: HashMap<Vec<(u64, u64)>, Vec<String>> = HashMap::new();
+        for page in self.inode_table.values().filter(|page| !page.inodes.is_empty()) {
+            groups.entry(page.inodes.iter().map(|chunk| (chunk.offset, chunk.length)).collect())
+                .or_default()
+                .push(page.name.clone());
+        }
+
+        self.linked_pages.clear();
+        for page in self.inode_table.values_mut() {
+            page.link_count = 1;
+        }
+
+        for mut names in groups.into_values().filter(|names| names.len() > 1) {
+            names.sort_unstable();
+
+            for name in names.iter() {
+                if let Some(page) = self.inode_table.get_mut(name) {
+                    page.link_count = names.len() as u64;
+                }
+
+                self.linked_pages.insert(name.clone(), names.iter()
+                    .filter(|other| *other != name)
+                    .cloned()
+                    .collect());
+            }
+        }
+    }
+
+    /// The database-wide metadata object.
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.meta
+    }
+
+    /// Replace the database-wide metadata object and write it out immediately. If it no longer fits where it was, it's moved along with the tables.
+    pub fn set_metadata(&mut self, meta: Metadata) -> Result<()> {
+        self.begin_write()?;
+
+        self.meta = meta;
+        self.write_header()
+    }
+
+    /// The `max_page_size` and `max_chunk_size` fields of `meta`, if it has them. The metadata's type is only known to be serialisable, so its fields are looked up in its RON representation.
+    /// That makes this too slow to run whenever a page is opened, so the result is kept in `page_limits` instead.
+    /// Fields which aren't non-negative integers, or are 0, are ignored.
+    fn find_page_limits(meta: &Metadata) -> (Option<u64>, Option<u64>) {
+        let Some(ron::Value::Map(meta)) = ron::ser::to_string(meta).ok()
+            .and_then(|meta| ron::de::from_str::<ron::Value>(&meta).ok()) else {
+            return (None, None);
+        };
+
+        let field = |name: &str| meta.iter()
+            .find(|(key, _)| matches!(key, ron::Value::String(key) if key == name))
+            .and_then(|(_, value)| match value {
+                ron::Value::Number(number) => number.as_i64(),
+                _ => None,
+            })
+            .and_then(|value| u64::try_from(value).ok())
+            .filter(|value| *value > 0);
+
+        (field("max_page_size"), field("max_chunk_size"))
+    }
+
+    /// The format the metadata object is serialised in.
+    pub fn metadata_format(&self) -> Result<MetadataFormat> {
+        MetadataFormat::from_flags(u64::from_le_bytes(self.raw_header[8..16]
+            .try_into()
+            .map_err(Error::other)?))
+    }
+
+    /// Serialise the metadata object in `format` from the next time the header is written.
+    pub fn set_metadata_format(&mut self, format: MetadataFormat) -> Result<()> {
+        self.begin_write()?;
+
+        let flags = u64::from_le_bytes(self.raw_header[8..16]
+            .try_into()
+            .map_err(Error::other)?);
+        self.raw_header[8..16].copy_from_slice(&format.to_flags(flags).to_le_bytes());
+
+        Ok(())
+    }
+
+    /// Register the key required to `unseal` the database once it's been sealed. Only a SHA-256 hash of the key is stored, directly after the metadata.
+    /// Registering a new key replaces the previous one.
+    pub fn register_seal_key(&mut self, key: [u8; 32]) -> Result<()> {
+        self.begin_write()?;
+
+        self.seal_key_hash = Some(Sha256::digest(key).into());
+        self.set_header_flag(FLAG_SEAL_KEY, true)?;
+        self.write_header()
+    }
+
+    /// Make the database read-only. The header is flushed and marked as sealed, after which every write - through the database or any of its pages - fails with `PermissionDenied`.
+    /// The seal is persisted, so the database remains sealed when it's reopened.
+    /// > **Note**: Without a registered seal key, a sealed database can't be unsealed.
+    pub fn seal(&mut self) -> Result<()> {
+        if self.is_sealed() { return Ok(()); }
+
+        self.set_header_flag(FLAG_SEALED, true)?;
+        self.flush_header()?;
+        self.backing.set_sealed(true);
+
+        Ok(())
+    }
+
+    /// Whether the database has been sealed, and can't be modified.
+    pub fn is_sealed(&self) -> bool {
+        self.backing.is_sealed()
+    }
+
+    /// Lift the seal, given the key previously passed to `register_seal_key`.
+    pub fn unseal(&mut self, key: [u8; 32]) -> Result<()> {
+        if !self.is_sealed() { return Ok(()); }
+
+        match self.seal_key_hash {
+            Some(hash) if hash == <[u8; 32]>::from(Sha256::digest(key)) => {},
+            Some(_) => return Err(Error::new(std::io::ErrorKind::PermissionDenied, "Incorrect seal key")),
+            None => return Err(Error::new(std::io::ErrorKind::PermissionDenied, "No seal key is registered")),
+        }
+
+        self.backing.set_sealed(false);
+        self.set_header_flag(FLAG_SEALED, false)?;
+        self.write_header()
+    }
+
+    /// Fail with `PermissionDenied` if the database is sealed.
+    pub(crate) fn ensure_unsealed(&self) -> Result<()> {
+        match self.is_sealed() {
+            true => Err(Error::new(std::io::ErrorKind::PermissionDenied, "Database is sealed")),
+            false => Ok(()),
+        }
+    }
+
+    /// Prepare for an operation which modifies the database: fail if it's sealed, and record the descriptors of pages flushed or closed since the header was last written.
+    /// Otherwise, those descriptors would only be applied at the next header write, after the operation, and overwrite its changes.
+    pub(crate) fn begin_write(&mut self) -> Result<()> {
+        self.ensure_unsealed()?;
+        self.apply_page_requests()?;
+
+        Ok(())
+    }
+
+    /// Set or clear a flag in the header's flag field. The header isn't written.
+    fn set_header_flag(&mut self, flag: u64, set: bool) -> Result<()> {
+        let flags = u64::from_le_bytes(self.raw_header[8..16]
+            .try_into()
+            .map_err(Error::other)?);
+
+        let flags = match set {
+            true => flags | flag,
+            false => flags & !flag,
+        };
+        self.raw_header[8..16].copy_from_slice(&flags.to_le_bytes());
+
+        Ok(())
+    }
+
+    /// The region occupied by the metadata, including the seal key hash which follows it.
+    fn metadata_region(&self) -> Array {
+        Array {
+            offset: self.metadata_range.offset,
+            length: self.metadata_range.length + self.seal_key_hash.map(|i| i.len() as u64).unwrap_or(0),
+            nonce: None,
+        }
+    }
+
+    /// Replace the access control list of an existing page, returning whether it changed.
+    /// The header isn't written; it's up to the caller to do so.
+    pub(crate) fn set_access_control_list(&mut self, name: &str, acl: Vec<Access>) -> Result<bool> {
+        let page = self.inode_table.get_mut(name)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))?;
+
+        if page.access_control_list == acl {
+            return Ok(false);
+        }
+
+        page.access_control_list = acl;
+        page.modified = SystemTime::now();
+        self.hooks.acl_changed(name, &page.access_control_list);
+
+        Ok(true)
+    }
+
+    /// Write a set of pages in two phases, leaving the decision of whether the writes take effect to an external coordinator.
+    ///
+    /// 1. **Prepare**: the content of each `PageWrite` is copied into newly allocated chunks, which aren't referenced by the inode table.
+    ///    If the write-ahead log is enabled, the content is logged too, so that the commit can be redone should the process die before the header reaches the backing object.
+    /// 2. **Commit**: `commit_fn` is invoked. If it returns `true`, the decision is logged, each page's chunks are replaced by the prepared ones (creating the page if necessary) and the header is written.
+    ///    The replaced chunks are only added to the free list once the header no longer refers to them.
+    ///    If it returns `false`, the prepared chunks are released again, the logged content is discarded and the in-memory state of the database is left as it was.
+    ///
+    /// Fails with `Busy` if any of the pages is open. Aborting isn't considered an error, as the caller already knows the outcome.
+    pub fn two_phase_commit<Commit: FnOnce() -> bool>(&mut self, prepare: Vec<PageWrite>, commit_fn: Commit) -> Result<()> {
+        self.begin_write()?;
+
+        if prepare.iter().any(|write| self.is_page_open(&write.name)) {
+            return Err(Error::from(crate::error::Error::Busy));
+        }
+
+        let mut prepared: Vec<(String, Vec<Array>)> = Vec::with_capacity(prepare.len());
+
+        for write in prepare {
+            let logged = self.prepare_write(&write.content)
+                .and_then(|chunks| {
+                    prepared.push((write.name.clone(), chunks));
+                    self.log_two_phase_commit(WalOp::Prepare, &write.name, write.content)
+                });
+
+            if let Err(err) = logged {
+                self.release_slices(&prepared.into_iter().flat_map(|i| i.1).collect::<Vec<_>>())?;
+                self.discard_prepared()?;
+                return Err(err);
+            }
+        }
+
+        let chunks = prepared.iter()
+            .flat_map(|i| i.1.iter().cloned())
+            .collect::<Vec<_>>();
+
+        if !commit_fn() {
+            self.release_slices(&chunks)?;
+            return self.discard_prepared();
+        }
+
+        self.log_two_phase_commit(WalOp::Commit, "", vec![])?;
+
+        // The header on the backing object still refers to the replaced chunks, so they're borrowed until it's been rewritten
+        let replaced = prepared.iter()
+            .filter_map(|(name, _)| self.inode_table.get(name))
+            .flat_map(|page| page.inodes.iter().cloned())
+            .collect::<Vec<_>>();
+        self.borrowed_slices.lock()
+            .map_err(|_| Error::other("PoisonError"))?
+            .extend(replaced.iter().cloned());
+
+        for (name, inodes) in prepared {
+            let before = self.journaled_content(&name)?;
+            self.install_chunks(&name, inodes)?;
+            self.journal_change(&name, before)?;
+        }
+
+        // The chunks are referenced by the inode table now, so there's no need to keep borrowing them.
+        self.release_slices(&chunks)?;
+
+        let written = self.write_header();
+
+        self.release_slices(&replaced)?;
+        written?;
+
+        self.release_chunks(replaced)
+    }
+
+    /// Append a record of a two-phase commit to the write-ahead log, if it's enabled.
+    fn log_two_phase_commit(&self, op: WalOp, name: &str, data: Vec<u8>) -> Result<()> {
+        let Some(wal) = self.wal.as_ref() else { return Ok(()); };
+
+        wal.lock()
+            .map_err(|_| Error::other("PoisonError"))?
+            .append(&WalRecord { op, page_name: name.to_owned(), offset: 0, data })
+    }
+
+    /// Drop the logged content of an aborted two-phase commit from the write-ahead log, if it's enabled.
+    fn discard_prepared(&self) -> Result<()> {
+        let Some(wal) = self.wal.as_ref() else { return Ok(()); };
+
+        wal.lock()
+            .map_err(|_| Error::other("PoisonError"))?
+            .retain(|record| record.op != WalOp::Prepare)
+    }
+
+    /// Start a transaction. Changes made through it only take effect once it's committed, all at once. See `Transaction`.
+    pub fn begin_transaction(&mut self) -> Transaction<'_, Backing, Metadata> {
+        let inode_table = self.inode_table.clone();
+        Transaction::new(self, inode_table)
+    }
+
+    /// Apply a committed transaction: copy the staged content into newly allocated chunks, then apply the changes to the pages in `inode_table` to the inode table, record `changes` in the history and write the header.
+    /// The chunks of deleted pages and those replaced by writes are added to the free list, unless a linked page still refers to them, and stay write-locked until the header is written.
+    /// Fails with `Busy` if any of the changed pages is open. If any content can't be written, the chunks written so far are released and the database is left as it was.
+    pub(crate) fn commit_transaction(&mut self, mut inode_table: HashMap<String, PageDescriptor>, writes: Vec<PageWrite>, changes: Vec<(HistoryOp, String)>) -> Result<()> {
+        self.begin_write()?;
+
+        // Renamed pages only appear in `changes` under their new name
+        let mut changed = changes.iter()
+            .map(|(_, name)| name.clone())
+            .chain(self.inode_table.keys().filter(|name| !inode_table.contains_key(*name)).cloned())
+            .collect::<Vec<_>>();
+        changed.sort_unstable();
+        changed.dedup();
+
+        if changed.iter().any(|name| self.is_page_open(name)) {
+            return Err(Error::from(crate::error::Error::Busy));
+        }
+
+        // Renames are journaled as the deletion of the old name and the creation of the new one
+        let journaled = self.journaled_contents(changed.iter())?;
+
+        let replaced = changed.iter()
+            .filter_map(|name| self.inode_table.get(name))
+            .flat_map(|page| page.inodes.iter().copied())
+            .collect::<Vec<_>>();
+        let _guards = self.backing.try_lock_write_ranges(&replaced)?;
+
+        let mut prepared: Vec<(String, Vec<Array>)> = Vec::with_capacity(writes.len());
+
+        for write in writes {
+            match self.prepare_write(&write.content) {
+                Ok(chunks) => prepared.push((write.name, chunks)),
+                Err(err) => {
+                    self.release_slices(&prepared.into_iter().flat_map(|i| i.1).collect::<Vec<_>>())?;
+                    return Err(err);
+                }
+            }
+        }
+
+        let chunks = prepared.iter()
+            .flat_map(|i| i.1.iter().cloned())
+            .collect::<Vec<_>>();
+
+        self.invalidate_sorted_index();
+
+        let mut removed = HashMap::new();
+        for name in changed {
+            let previous = match inode_table.remove(&name) {
+                Some(page) => self.inode_table.insert(name.clone(), page),
+                None => self.inode_table.remove(&name),
+            };
+
+            if let Some(previous) = previous {
+                removed.insert(name, previous);
+            }
+        }
+
+        for (op, name) in changes {
+            let page = match op {
+                HistoryOp::Write => continue,
+                HistoryOp::Delete => removed.get(&name),
+                _ => self.inode_table.get(&name),
+            };
+            let snapshot = page.map(|page| page.inodes.clone()).unwrap_or_default();
+
+            match (op, page) {
+                (HistoryOp::Create, Some(page)) => self.hooks.created(&name, page),
+                (HistoryOp::Delete, _) => self.hooks.deleted(&name),
+                _ => {}
+            }
+
+            self.append_history_entry(op, &name, &snapshot)?;
+        }
+
+        // Chunks still referred to, such as those of renamed pages, are kept out of the free list
+        self.release_chunks(removed.into_values().flat_map(|page| page.inodes).collect())?;
+
+        for (name, inodes) in prepared {
+            self.install_chunks(&name, inodes)?;
+        }
+
+        // The chunks are referenced by the inode table now, so there's no need to keep borrowing them.
+        self.release_slices(&chunks)?;
+
+        self.journal_changes(journaled)?;
+
+        self.write_header()
+    }
+
+    /// Import the pages of `other` into this database. Pages which only exist in `other` are copied over, including their ACLs and timestamps.
+    /// Pages which exist in both databases with differing content are resolved according to `conflict`.
+    /// With `ConflictPolicy::Error`, the merge stops at the first conflict, leaving any pages merged up to that point in place.
+    /// The header is written once all pages have been merged.
+    pub fn merge<OtherBacking, OtherMetadata>(&mut self, other: &Database<OtherBacking, OtherMetadata>, conflict: ConflictPolicy) -> Result<()>
+    where OtherBacking: Read + Write + Seek, OtherMetadata: Serialize + DeserializeOwned + Clone {
+        self.begin_write()?;
+
+        let mut incoming = other.leak_inode_table()
+            .into_values()
+            .collect::<Vec<_>>();
+        incoming.sort_unstable_by(|i, j| Ord::cmp(&i.name, &j.name));
+
+        for page in incoming {
+            if let Some(existing) = self.inode_table.get(&page.name) {
+                if self.hash_content(existing)? == other.hash_content(&page)? {
+                    continue;
+                }
+
+                let keep_other = match conflict {
+                    ConflictPolicy::KeepSelf => false,
+                    ConflictPolicy::KeepOther => true,
+                    ConflictPolicy::KeepNewer => page.modified > existing.modified,
+                    ConflictPolicy::Error => return Err(Error::new(std::io::ErrorKind::AlreadyExists, format!("Conflict on page {}", page.name))),
+                };
+
+                if !keep_other {
+                    continue;
+                }
+            }
+
+            let inodes = self.prepare_write(&other.read_chunks(&page.inodes)?)?;
+            self.release_slices(&inodes)?;
+
+            let page = PageDescriptor {
+                inodes,
+                ..page
+            };
+
+            if !self.inode_table.contains_key(&page.name) {
+                self.hooks.created(&page.name, &page);
+            }
+            self.hooks.written(&page.name, page.content_length);
+
+            let (name, before) = (page.name.clone(), self.journaled_content(&page.name)?);
+            self.invalidate_sorted_index();
+            self.inode_table.insert(page.name.clone(), page);
+            self.journal_change(&name, before)?;
+        }
+
+        self.write_header()
+    }
+
+    /// Import the pages of the database stored in `source`, whose metadata is a `SourceMetadata`, returning the number of pages merged. Each page's content is copied into newly allocated chunks, and its ACL and timestamps are kept.
+    /// The source's metadata needn't match this database's, but has to be named, as it can't be read without knowing its type in every format. See `MetadataFormat`.
+    /// Pages whose name is already taken are never overwritten. They're collected instead, and once every other page has been merged and the header written, reported together as a single `AlreadyExists` error.
+    /// Names and ACL entities the source shares with this database's string table are stored once.
+    pub fn merge_from<SourceMetadata, Source>(&mut self, mut source: Source) -> Result<usize> where SourceMetadata: Serialize + DeserializeOwned + Clone, Source: Read + Seek {
+        self.begin_write()?;
+
+        let mut bytes = vec![];
+        source.seek(SeekFrom::Start(0))?;
+        source.read_to_end(&mut bytes)?;
+
+        let other = Database::<_, SourceMetadata>::open(Cursor::new(bytes))?;
+
+        let mut incoming = other.leak_inode_table()
+            .into_values()
+            .collect::<Vec<_>>();
+        incoming.sort_unstable_by(|i, j| Ord::cmp(&i.name, &j.name));
+
+        let mut merged = 0;
+        let mut conflicts = vec![];
+        for page in incoming {
+            if self.inode_table.contains_key(&page.name) {
+                conflicts.push(page.name);
+                continue;
+            }
+
+            let inodes = self.prepare_write(&other.read_chunks(&page.inodes)?)?;
+            self.release_slices(&inodes)?;
+
+            let page = PageDescriptor {
+                inodes,
+                ..page
+            };
+
+            let name = page.name.clone();
+            self.hooks.created(&page.name, &page);
+            self.invalidate_sorted_index();
+            self.inode_table.insert(page.name.clone(), page);
+            self.journal_change(&name, None)?;
+            merged += 1;
+        }
+
+        self.write_header()?;
+
+        if !conflicts.is_empty() {
+            return Err(Error::new(std::io::ErrorKind::AlreadyExists, format!("{} pages merged, but the following already exist: {}", merged, conflicts.join(", "))));
+        }
+
+        Ok(merged)
+    }
+
+    /// Record every change to a page's content from now on in the journal at `journal_path`, which `point_in_time_restore` rolls the database back with. The journal is created if it doesn't exist, and appended to if it does.
+    /// Each record holds the page's entire content from before and after the change. Writes through open pages are recorded once per flush, and renames as the deletion of the old name and the creation of the new one.
+    /// > **Note**: Pages opened before the journal was enabled don't record their writes.
+    pub fn enable_journal<P: AsRef<Path>>(&mut self, journal_path: P) -> Result<()> {
+        self.journal = Some(Arc::new(Mutex::new(Journal::open(journal_path.as_ref())?)));
+
+        Ok(())
+    }
+
+    /// The named page's content and access control list, to be recorded in the journal along with a change to it. `None` if the journal isn't enabled or there's no such page.
+    fn journaled_content(&self, name: &str) -> Result<Option<Journaled>> {
+        let (Some(_), Some(page)) = (self.journal.as_ref(), self.inode_table.get(name)) else {
+            return Ok(None);
+        };
+
+        let mut content = vec![];
+        self.pipe_page(name, &mut content)?;
+
+        Ok(Some((content, page.access_control_list.clone())))
+    }
+
+    /// The content of each of the named pages, to be recorded in the journal by `journal_changes`. See `journaled_content`.
+    fn journaled_contents<'a, Names: Iterator<Item=&'a String>>(&self, names: Names) -> Result<Vec<(String, Option<Journaled>)>> {
+        names.map(|name| Ok((name.clone(), self.journaled_content(name)?)))
+            .collect()
+    }
+
+    /// Record a change to the named page in the journal, given its content from before the change as returned by `journaled_content`.
+    /// Whether the page was created, written or deleted is told by whether it existed before and after. Does nothing if the journal isn't enabled.
+    fn journal_change(&self, name: &str, before: Option<Journaled>) -> Result<()> {
+        let Some(journal) = self.journal.as_ref() else { return Ok(()); };
+
+        let after = self.journaled_content(name)?;
+        let operation = match (&before, &after) {
+            (None, Some(_)) => JournalEntry::CREATE,
+            (Some(_), Some(_)) => JournalEntry::WRITE,
+            (Some(_), None) => JournalEntry::DELETE,
+            (None, None) => return Ok(()),
+        };
+
+        let (before_data, access_control_list) = before.unzip();
+        let entry = JournalEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|i| i.as_nanos() as u64)
+                .unwrap_or(0),
+            page_name_idx: self.get_strtab_index(&name.to_owned())?,
+            operation,
+            access_control_list: access_control_list.unwrap_or_default(),
+            before_data,
+            after_data: after.map(|(content, _)| content),
+        };
+
+        journal.lock()
+            .map_err(|_| Error::other("PoisonError"))?
+            .append(&entry)
+    }
+
+    /// Record the changes to several pages in the journal. See `journal_change`.
+    fn journal_changes(&self, changes: Vec<(String, Option<Journaled>)>) -> Result<()> {
+        for (name, before) in changes {
+            self.journal_change(&name, before)?;
+        }
+
+        Ok(())
+    }
+
+    /// Roll the database back to its state at `target` by undoing, newest first, every journal record written after it.
+    /// Created pages are removed, overwritten pages regain their previous content and deleted pages are recreated along with their access control list. The changes are themselves recorded in the journal, if it's enabled.
+    /// The header is written once all records have been undone.
+    pub fn point_in_time_restore(&mut self, journal_path: &Path, target: SystemTime) -> Result<()> {
+        self.begin_write()?;
+
+        let target = target.duration_since(UNIX_EPOCH)
+            .map_err(Error::other)?
+            .as_nanos() as u64;
+
+        let mut journal = BufReader::new(std::fs::File::open(journal_path)?);
+        let mut entries = vec![];
+        while let Some(entry) = JournalEntry::read(&mut journal)? {
+            if entry.timestamp > target {
+                entries.push(entry);
+            }
+        }
+
+        self.invalidate_sorted_index();
+        for entry in entries.into_iter().rev() {
+            let name = get_str!(self.string_table.borrow(), entry.page_name_idx)?.clone();
+
+            match (entry.operation, entry.before_data) {
+                (JournalEntry::CREATE, _) => {
+                    let before = self.journaled_content(&name)?;
+
+                    if let Some(page) = self.inode_table.remove(&name) {
+                        self.release_chunks(page.inodes)?;
+                        self.journal_change(&name, before)?;
+                        self.hooks.deleted(&name);
+                    }
+                },
+                (JournalEntry::WRITE, Some(before)) => self.write_page_content(&name, &before)?,
+                (JournalEntry::DELETE, Some(before)) => {
+                    self.write_page_content(&name, &before)?;
+                    self.set_access_control_list(&name, entry.access_control_list)?;
+                },
+                (operation, _) => return Err(Error::new(std::io::ErrorKind::InvalidData, format!("Journal record of type {} on page {} can't be undone", operation, name))),
+            }
+        }
+
+        self.write_header()
+    }
+
+    /// Derive a 256-bit key from a passphrase and salt using Argon2.
+    #[cfg(feature = "encryption")]
+    fn derive_key(passphrase: &[u8], salt: &[u8; 16]) -> Result<[u8; 32]> {
+        let mut key = [0u8; 32];
+        argon2::Argon2::default()
+            .hash_password_into(passphrase, salt, &mut key)
+            .map_err(|e| Error::other(e.to_string()))?;
+
+        Ok(key)
+    }
+
+    /// Open a passphrase-protected database. The salt is read from the header, from which the content key is derived.
+    /// Fails with `Corrupted` if the database holds encrypted content which the derived key doesn't decrypt.
+    /// > **Note**: The passphrase can't be recovered from the database. Losing it means losing access to all data stored in the database.
+    #[cfg(feature = "encryption")]
+    pub fn open_with_passphrase<Passphrase: AsRef<[u8]>>(backing: Backing, passphrase: Passphrase) -> Result<Self> {
+        let db = Self::open(backing)?;
+
+        let salt = db.salt
+            .ok_or(Error::new(std::io::ErrorKind::InvalidData, "Database isn't passphrase-protected"))?;
+        db.backing.set_key(Some(Self::derive_key(passphrase.as_ref(), &salt)?));
+
+        if let Some((_, chunk)) = db.iter_chunks().find(|(_, chunk)| chunk.nonce.is_some()) {
+            db.read_chunks(&[chunk])?;
+        }
+
+        Ok(db)
+    }
+
+    /// Initialise a new passphrase-protected database on `backing`. A fresh random salt is generated and stored in the header.
+    /// Passphrase-protected databases use a version 2 header, which places the salt immediately after the fixed header fields, moving the metadata to 0x60.
+    /// > **Note**: The passphrase can't be recovered from the database. Losing it means losing access to all data stored in the database.
+    #[cfg(feature = "encryption")]
+    pub fn create_with_passphrase<Passphrase: AsRef<[u8]>>(backing: Backing, passphrase: Passphrase, meta: Metadata) -> Result<Self> {
+        let salt: [u8; 16] = rand::random();
+
+        let mut db = Self::initialise(backing, meta, Some(salt))?;
+        db.backing.set_key(Some(Self::derive_key(passphrase.as_ref(), &salt)?));
+
+        db.write_header()?;
+
+        Ok(db)
+    }
+
+    /// Open a database whose page content is encrypted with AES-256-GCM under `key`. Content written from now on is encrypted too.
+    /// Fails with `Corrupted` if the database holds encrypted content which `key` doesn't decrypt.
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted(backing: Backing, key: [u8; 32]) -> Result<Self> {
+        let db = Self::open(backing)?;
+        db.backing.set_key(Some(key));
+
+        if let Some((_, chunk)) = db.iter_chunks().find(|(_, chunk)| chunk.nonce.is_some()) {
+            db.read_chunks(&[chunk])?;
+        }
+
+        Ok(db)
+    }
+
+    /// Initialise a new, empty database on `backing` whose page content is encrypted with AES-256-GCM under `key`.
+    /// Every chunk is encrypted with its own random nonce, which is stored in the inode table, and followed by its authentication tag.
+    /// > **Note**: The key isn't stored in the database. Losing it means losing access to all content stored in the database.
+    #[cfg(feature = "encryption")]
+    pub fn create_encrypted(backing: Backing, key: [u8; 32], meta: Metadata) -> Result<Self> {
+        let mut db = Self::initialise(backing, meta, None)?;
+        db.backing.set_key(Some(key));
+
+        db.write_header()?;
+
+        Ok(db)
+    }
+
+    /// Initialise a new, empty database on `backing`, which is expected to be empty itself. The header is written straight away.
+    pub fn create(backing: Backing, meta: Metadata) -> Result<Self> {
+        let mut db = Self::initialise(backing, meta, None)?;
+
+        db.write_header()?;
+
+        Ok(db)
+    }
+
+    /// Construct an empty database without writing anything. Databases with a salt use a version 2 header, whose metadata follows the salt at 0x60.
+    fn initialise(backing: Backing, meta: Metadata, salt: Option<[u8; 16]>) -> Result<Self> {
+        let (version, meta_offset) = match salt {
+            Some(_) => (0x02u32, 0x60u64),
+            None => (0x01u32, 0x50u64),
+        };
+
+        let meta_len = MetadataFormat::Ron.serialise(&meta)?
+            .len() as u64;
+
+        Ok(Self {
+            backing: Arc::new(Mediator::new(backing)),
+            inode_table_range: Array { length: 0, offset: 0, nonce: None },
+            string_table_range: Array { length: 0, offset: 0, nonce: None },
+            history_table_range: Array { length: 0, offset: 0, nonce: None },
+            metadata_range: Array { length: meta_len, offset: meta_offset, nonce: None },
+            inode_table: HashMap::new(),
+            string_table: RefCell::new(vec![]),
+            history_table: vec![],
+            inode_table_size: 0,
+            string_table_size: 0,
+            history_table_size: 0,
+            history_limit: Some(DEFAULT_HISTORY_LIMIT),
+            borrowed_slices: Arc::new(Mutex::new(vec![])),
+            free_list: vec![],
+            hooks: Hooks::default(),
+            page_validator: None,
+            linked_pages: HashMap::new(),
+            open_pages: HashMap::new(),
+            exclusive_pages: HashMap::new(),
+            page_access_counts: HashMap::new(),
+            page_access_times: HashMap::new(),
+            raw_header: vec![
+                &b"FSDB"[..], &u32::to_le_bytes(version)[..], &u64::to_le_bytes(0x00)[..],
+                &[0u8; 6 * 8][..], // Table ranges are filled in by `write_header`
+                &u64::to_le_bytes(meta_len)[..], &u64::to_le_bytes(meta_offset)[..],
             ]
                 .into_iter()
-                .flatten()
-                .cloned()
-                .collect::<Vec<_>>());
+                .flatten()
+                .cloned()
+                .collect(),
+            base_offset: 0,
+            salt,
+            seal_key_hash: None,
+            sync_on_write: None,
+            page_requests: channel(),
+            wal: None,
+            journal: None,
+            max_page_size: None,
+            page_limits: Self::find_page_limits(&meta),
+            sorted_index: RefCell::new(None),
+            sorted_index_enabled: false,
+            agent: None,
+            agent_reservations: vec![],
+            meta,
+        })
+    }
+
+    /// Swap the backing object against any new container. Useful for cloning / duplicating parts or all of the database, or initialising new databases on blank containers.
+    /// ```rust,no_run
+    /// let container = std::fs::OpenOptions::new()
+    ///     .read(true)
+    ///     .write(true)
+    ///     .open("/tmp/db.db")?;
+    ///
+    ///
+    /// use datastore_provider::format::database::Database;
+    /// #[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+    /// struct Metadata {
+    ///     pub friendly_name: String,
+    ///     pub max_chunk_size: u64,
+    ///     pub chunk_alignment: u64,
+    ///     pub max_page_size: u64,
+    ///     pub page_alignment: u64,
+    /// }
+    ///
+    /// // initialise a new database with a backing vector (completely in-memory), wrapped in a Cursor for `Seek`ability.
+    /// let db: Database<std::io::Cursor<Vec<u8>>, Metadata> = datastore_provider::format::blank()?;
+    /// let db: Database<std::fs::File, Metadata> = db.change_buffer(container)?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn change_buffer<NewBuffer>(self, buffer: NewBuffer) -> Result<Database<NewBuffer, Metadata>> where NewBuffer: Read + Write + Seek {
+        let sealed = self.is_sealed();
+        let mut db = Database {
+            backing: Arc::new(Mediator::new(buffer).with_encryption_of(&self.backing)),
+            inode_table_range: self.inode_table_range,
+            string_table_range: self.string_table_range,
+            history_table_range: self.history_table_range,
+            inode_table_size: self.inode_table_size,
+            string_table_size: self.string_table_size,
+            history_table_size: self.history_table_size,
+            history_limit: self.history_limit,
+            metadata_range: self.metadata_range,
+            inode_table: self.inode_table,
+            string_table: self.string_table,
+            history_table: self.history_table,
+            raw_header: self.raw_header,
+            base_offset: self.base_offset,
+            salt: self.salt,
+            seal_key_hash: self.seal_key_hash,
+            // The sync function is specific to the old backing object's type
+            sync_on_write: None,
+            // Pages opened before the switch keep using the old backing object
+            page_requests: channel(),
+            wal: self.wal,
+            journal: self.journal,
+            max_page_size: self.max_page_size,
+            page_limits: self.page_limits,
+            sorted_index: RefCell::new(None),
+            sorted_index_enabled: self.sorted_index_enabled,
+            agent: None,
+            agent_reservations: vec![],
+            meta: self.meta,
+            borrowed_slices: Arc::new(Mutex::new(vec![])),
+            free_list: self.free_list,
+            hooks: self.hooks,
+            page_validator: self.page_validator,
+            linked_pages: self.linked_pages,
+            open_pages: self.open_pages,
+            exclusive_pages: self.exclusive_pages,
+            page_access_counts: self.page_access_counts,
+            page_access_times: self.page_access_times,
+        };
+
+        // flush the header to keep the new backing object in-sync
+        db.flush_header()?;
+        db.backing.set_sealed(sealed);
+
+        Ok(db)
+    }
+
+    /// Produce an independent, in-memory copy of the database. Useful for trying out destructive operations which can then be discarded.
+    /// The header is flushed beforehand, so the copy reflects the latest state. Changes made to either database aren't visible to the other.
+    pub fn clone_to_memory(&mut self) -> Result<Database<Cursor<Vec<u8>>, Metadata>> {
+        // Sealed databases were flushed when they were sealed
+        if !self.is_sealed() {
+            self.write_header()?;
+        }
+
+        let mut buffer = vec![];
+        {
+            let mut backing = self.backing
+                .lock_backing()
+                .map_err(Error::other)?;
+
+            backing.seek(SeekFrom::Start(0))?;
+            backing.read_to_end(&mut buffer)?;
+        }
+
+        Database::open_at_offset(Cursor::new(buffer), self.base_offset)
+    }
+
+    /// Write a self-contained copy of the database, as it is at the time of the call, to `writer`. Nothing is written to the backing object, and the copy can be opened with `open`.
+    /// The backing object is held exclusively throughout, so pages can't write to it until the copy is complete. Descriptors sent by flushed pages are recorded in the inode table beforehand.
+    /// The copy is laid out back to back: the header, metadata and tables, followed by every page's chunks in the order they appear in the backing object.
+    /// Space not used by any page is left out, and so history entries lose the chunks of deleted and rewritten pages from their snapshots.
+    pub fn snapshot<W: Write + Seek>(&mut self, writer: W) -> Result<()> {
+        self.apply_page_requests()?;
+
+        let mut backing = self.backing.lock_backing()
+            .map_err(Error::other)?;
+
+        let mut snapshot = Database {
+            backing: Arc::new(Mediator::new(WriteOnly(writer)).with_encryption_of(&self.backing)),
+            inode_table_range: self.inode_table_range,
+            string_table_range: self.string_table_range,
+            history_table_range: self.history_table_range,
+            metadata_range: Array {
+                offset: self.raw_header.len() as u64 + self.salt.map(|i| i.len() as u64).unwrap_or(0),
+                length: self.metadata_range.length,
+                nonce: None,
+            },
+            inode_table: self.inode_table.clone(),
+            string_table: self.string_table.clone(),
+            history_table: self.history_table.clone(),
+            inode_table_size: self.inode_table_size,
+            string_table_size: self.string_table_size,
+            history_table_size: self.history_table_size,
+            history_limit: self.history_limit,
+            borrowed_slices: Arc::new(Mutex::new(vec![])),
+            free_list: vec![],
+            hooks: Hooks::default(),
+            page_validator: None,
+            linked_pages: HashMap::new(),
+            open_pages: HashMap::new(),
+            exclusive_pages: HashMap::new(),
+            page_access_counts: HashMap::new(),
+            page_access_times: HashMap::new(),
+            raw_header: self.raw_header.clone(),
+            base_offset: 0,
+            salt: self.salt,
+            seal_key_hash: self.seal_key_hash,
+            sync_on_write: None,
+            page_requests: channel(),
+            wal: None,
+            journal: None,
+            max_page_size: self.max_page_size,
+            page_limits: self.page_limits,
+            sorted_index: RefCell::new(None),
+            sorted_index_enabled: self.sorted_index_enabled,
+            agent: None,
+            agent_reservations: vec![],
+            meta: self.meta.clone(),
+        };
+
+        // Chunks shared between linked pages are copied once. Where chunks start at the same offset, the longest one covers the others.
+        let mut chunks = self.inode_table.values()
+            .flat_map(|page| page.inodes.iter().copied())
+            .filter(|chunk| chunk.length > 0)
+            .collect::<Vec<_>>();
+        chunks.sort_by_key(|chunk| (chunk.offset, std::cmp::Reverse(chunk.length)));
+        chunks.dedup();
+
+        // Mirrors the layout `write_header` picks when the tables fit after the metadata
+        let align = |offset: u64| offset + (0x10 - offset % 0x10) % 0x10;
+        let inode_offset = align(snapshot.metadata_region().end());
+        let string_offset = align(inode_offset + snapshot.serialise_inode_table()?.len() as u64);
+        let history_offset = align(string_offset + snapshot.serialise_string_table()?.len() as u64);
+        let mut cursor = history_offset + snapshot.serialise_history_table()?.len() as u64;
+
+        // Previous offsets of copied chunks => their offsets in the snapshot
+        let mut moved: HashMap<u64, u64> = HashMap::new();
+        {
+            let mut writer = snapshot.backing.lock_backing()
+                .map_err(Error::other)?;
+
+            for chunk in chunks {
+                let mut content = vec![0u8; chunk.length as usize];
+                backing.seek(SeekFrom::Start(chunk.offset))?;
+                backing.read_exact(&mut content)?;
+
+                writer.seek(SeekFrom::Start(cursor))?;
+                writer.write_all(&content)?;
+
+                moved.insert(chunk.offset, cursor);
+                cursor += chunk.length;
+            }
+        }
+
+        for chunk in snapshot.inode_table.values_mut().flat_map(|page| page.inodes.iter_mut()) {
+            if let Some(offset) = moved.get(&chunk.offset) {
+                chunk.offset = *offset;
+            }
+        }
+
+        for entry in snapshot.history_table.iter_mut() {
+            entry.chunks_snapshot = entry.chunks_snapshot.iter()
+                .filter_map(|chunk| moved.get(&chunk.offset).map(|offset| Array { offset: *offset, ..*chunk }))
+                .collect();
+        }
+
+        snapshot.flush_header()
+    }
+
+    /// Read the concatenated content of the given chunks, decrypting encrypted ones. Fails with `Corrupted` if one of them fails authentication.
+    pub(crate) fn read_chunks(&self, chunks: &[Array]) -> Result<Vec<u8>> {
+        let mut backing = self.backing
+            .lock_backing()
+            .map_err(Error::other)?;
+
+        let mut content = Vec::with_capacity(chunks.iter().map(|i| i.content_length()).sum::<u64>() as usize);
+        for chunk in chunks {
+            let mut stored = vec![0u8; chunk.length as usize];
+            backing.seek(SeekFrom::Start(chunk.offset))?;
+            backing.read_exact(&mut stored)?;
+
+            content.extend(self.backing.decrypt_chunk(stored, chunk.nonce)?);
+        }
+
+        Ok(content)
+    }
+
+    /// Stream the named page's entire content into `dst` without buffering it, returning the number of bytes written. See `pipe_page_range`.
+    pub fn pipe_page<Dst: Write + ?Sized>(&self, name: &str, dst: &mut Dst) -> Result<u64> {
+        self.pipe_page_range(name, 0, u64::MAX, dst)
+    }
+
+    /// Stream up to `len` bytes of the named page's content, starting at `start`, into `dst`, returning the number of bytes written. Useful for serving range requests.
+    /// Each chunk is copied straight from the backing object with `std::io::copy`, so the page is never held in memory as a whole.
+    /// > **Note**: Compressed and encrypted pages can't be streamed chunk by chunk, so they're read, decrypted and decompressed in full first.
+    pub fn pipe_page_range<Dst: Write + ?Sized>(&self, name: &str, start: u64, len: u64, dst: &mut Dst) -> Result<u64> {
+        let page = self.inode_table.get(name)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))?;
+
+        self.pipe_content(page, start, len, dst)
+    }
+
+    /// Stream up to `len` bytes of `page`'s content, starting at `start`, into `dst`, returning the number of bytes written. See `pipe_page_range`.
+    pub(crate) fn pipe_content<Dst: Write + ?Sized>(&self, page: &PageDescriptor, start: u64, len: u64, dst: &mut Dst) -> Result<u64> {
+        if page.compression != CompressionAlgorithm::None || page.inodes.iter().any(|chunk| chunk.nonce.is_some()) {
+            let mut stored = self.read_chunks(&page.inodes)?;
+            stored.truncate(page.content_length as usize);
+
+            let content = page.compression.decompress(&stored)?;
+            let from = (start as usize).min(content.len());
+            let to = from.saturating_add(len.try_into().unwrap_or(usize::MAX)).min(content.len());
+
+            dst.write_all(&content[from..to])?;
+            return Ok((to - from) as u64);
+        }
+
+        let len = len.min(page.content_length.saturating_sub(start));
+
+        let mut backing = self.backing
+            .lock_backing()
+            .map_err(Error::other)?;
+
+        let mut written = 0;
+        for region in page.physical_regions(start, len) {
+            backing.seek(SeekFrom::Start(region.offset))?;
+            written += std::io::copy(&mut backing.deref_mut().take(region.length), dst)?;
+        }
+
+        Ok(written)
+    }
+
+    /// Compute the SHA-256 hash of `page`'s content, as `pipe_page` yields it: only the first `content_length` bytes of its chunks count, and compressed pages are hashed decompressed.
+    /// Pages with the same content therefore hash the same, however their content is stored. Plain pages are streamed into the hash through a fixed-size buffer, so not even a whole chunk is held in memory;
+    /// compressed and encrypted pages can't be streamed, and are read in full first.
+    pub(crate) fn hash_content(&self, page: &PageDescriptor) -> Result<[u8; 32]> {
+        let mut hasher = Sha256::new();
+        self.pipe_content(page, 0, u64::MAX, &mut hasher)?;
+
+        Ok(hasher.finalize().into())
+    }
+
+    /// A token which changes whenever the named page is written to, for ETag-style caching.
+    /// There's no generation counter, so the token is derived from a hash of the page's content and its modification time.
+    pub fn page_generation_token(&self, name: &str) -> Result<u64> {
+        let page = self.inode_table.get(name)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))?;
+
+        let modified = page.modified.duration_since(UNIX_EPOCH)
+            .map(|i| i.as_nanos())
+            .unwrap_or(0);
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.hash_content(page)?);
+        hasher.update(modified.to_le_bytes());
+
+        let hash: [u8; 32] = hasher.finalize().into();
+        Ok(u64::from_le_bytes(hash[0..8].try_into().map_err(Error::other)?))
+    }
+
+    /// Compute the SHA-256 hash of the named page's content. Plain pages are streamed into the hash, so their content is never held in memory, however large the page. See `hash_content`.
+    pub fn page_content_hash(&self, name: &str) -> Result<[u8; 32]> {
+        let page = self.inode_table.get(name)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))?;
+
+        self.hash_content(page)
+    }
+
+    /// Compare the checksum recorded for the named page against a freshly computed hash of its content, returning whether they match.
+    /// Fails if no checksum is recorded, as is the case for pages modified in place since their content was last replaced.
+    pub fn verify_page_integrity(&self, name: &str) -> Result<bool> {
+        let checksum = self.inode_table.get(name)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))?
+            .checksum
+            .ok_or(Error::new(std::io::ErrorKind::InvalidData, format!("No checksum is recorded for '{}'", name)))?;
+
+        Ok(self.page_content_hash(name)? == checksum)
+    }
+
+    /// A copy of the chunks the named page's content is stored in, in order, without opening the page.
+    pub fn get_page_inodes(&self, name: &str) -> Result<Vec<Array>> {
+        self.inode_table.get(name)
+            .map(|page| page.inodes.clone())
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))
+    }
+
+    /// Every region of the backing object which is in use, sorted by offset. Empty regions are left out.
+    fn used_regions(&self) -> Vec<(LayoutRegion, Array)> {
+        let tables = [
+            (LayoutRegion::Header, Array { offset: self.base_offset, length: self.raw_header.len() as u64 + self.salt.map(|i| i.len() as u64).unwrap_or(0), nonce: None }),
+            (LayoutRegion::Metadata, self.metadata_region()),
+            (LayoutRegion::InodeTable, Array { offset: self.inode_table_range.offset, length: self.inode_table_size, nonce: None }),
+            (LayoutRegion::StringTable, Array { offset: self.string_table_range.offset, length: self.string_table_size, nonce: None }),
+            (LayoutRegion::HistoryTable, Array { offset: self.history_table_range.offset, length: self.history_table_size, nonce: None }),
+        ];
+
+        let chunks = self.inode_table.values()
+            .flat_map(|page| page.inodes.iter().map(|chunk| (LayoutRegion::PageChunk(page.name.clone()), *chunk)));
+
+        let mut regions = tables.into_iter()
+            .chain(chunks)
+            .filter(|(_, region)| region.length > 0)
+            .collect::<Vec<_>>();
+        regions.sort_by_key(|(_, region)| region.offset);
+
+        regions
+    }
+
+    /// The regions of the backing object which aren't used by the header, the tables or any page, sorted by offset.
+    /// If the length of the backing object can't be determined, the space past the last used region isn't included.
+    pub fn get_free_regions(&self) -> Vec<Array> {
+        let regions = self.used_regions();
+        let end = self.backing_len()
+            .unwrap_or(0);
+
+        let mut free = vec![];
+        let mut cursor = 0u64;
+        for (_, region) in regions.iter() {
+            if region.offset > cursor {
+                free.push(Array { offset: cursor, length: region.offset - cursor, nonce: None });
+            }
+            cursor = cursor.max(region.end());
+        }
+
+        if end > cursor {
+            free.push(Array { offset: cursor, length: end - cursor, nonce: None });
+        }
+
+        free
+    }
+
+    /// Regenerate the free list from the inode table, returning the number of free regions found.
+    /// Every gap between the header, the tables, the pages' chunks and the chunks reserved for the agent becomes an entry, up to the end of the last chunk; whatever the free list held before is discarded.
+    /// Space past the last chunk is left out, so that the tables can keep growing in place.
+    /// Operations hold the database mutably for their duration, so any borrowed slices left over were leaked by one which failed midway, and are discarded as well.
+    pub fn rebuild_free_list(&mut self) -> Result<usize> {
+        self.borrowed_slices.lock()
+            .map_err(|_| Error::other("PoisonError"))?
+            .clear();
+
+        let last = self.inode_table.values()
+            .flat_map(|page| page.inodes.iter())
+            .chain(self.agent_reservations.iter())
+            .map(Array::end)
+            .max()
+            .unwrap_or(0);
+
+        let mut used = self.used_regions()
+            .into_iter()
+            .map(|(_, region)| region)
+            .chain(self.agent_reservations.iter().cloned())
+            .filter(|region| region.length > 0)
+            .collect::<Vec<_>>();
+        used.sort_by_key(|region| region.offset);
+
+        let mut cursor = self.base_offset;
+        self.free_list.clear();
+        for region in used.into_iter().filter(|region| region.offset < last) {
+            if region.offset > cursor {
+                self.free_list.push(Array { offset: cursor, length: region.offset - cursor, nonce: None });
+            }
+            cursor = cursor.max(region.end());
+        }
+
+        Ok(self.free_list.len())
+    }
+
+    /// The number of entries in the free list. Adjacent chunks are merged into one entry as they're released.
+    pub fn free_list_len(&self) -> usize {
+        self.free_list.len()
+    }
+
+    /// Check the free list, the borrowed slices and the agent's reservations against the inode table without modifying anything.
+    /// Returns `false` if any of them overlaps another, a table or a page's chunk, or extends past the end of the backing object.
+    pub fn validate_free_list(&self) -> bool {
+        let Ok(borrowed) = self.borrowed_slices.lock() else { return false; };
+        let slices = self.free_list.iter()
+            .chain(borrowed.iter())
+            .chain(self.agent_reservations.iter())
+            .filter(|slice| slice.length > 0)
+            .collect::<Vec<_>>();
+        let len = self.backing_len()
+            .unwrap_or(u64::MAX);
+
+        let used = self.used_regions();
+
+        slices.iter()
+            .enumerate()
+            .all(|(i, slice)| slice.end() <= len
+                && !used.iter().any(|(_, region)| slice.overlaps(region))
+                && !slices.iter().skip(i + 1).any(|other| slice.overlaps(other)))
+    }
+
+    /// A map of the entire backing object, listing what each region is used for, sorted by offset.
+    /// Overlapping regions are reported as-is, so this can be used to spot corruption as well. See also `verify_format`.
+    pub fn get_total_layout(&self) -> Vec<(LayoutRegion, Array)> {
+        let mut layout = self.used_regions()
+            .into_iter()
+            .chain(self.get_free_regions()
+                .into_iter()
+                .map(|region| (LayoutRegion::Free, region)))
+            .collect::<Vec<_>>();
+        layout.sort_by_key(|(_, region)| region.offset);
+
+        layout
+    }
+
+    /// Check the structural soundness of the database: no two chunks may overlap, and no chunk may overlap the tables or extend past the end of the backing object.
+    pub fn verify_format(&self) -> Result<()> {
+        let len = self.backing_len()?;
+        let tables = [
+            Array { offset: self.base_offset, length: self.raw_header.len() as u64 + self.salt.map(|i| i.len() as u64).unwrap_or(0), nonce: None },
+            self.metadata_region(),
+            Array { offset: self.inode_table_range.offset, length: self.inode_table_size, nonce: None },
+            Array { offset: self.string_table_range.offset, length: self.string_table_size, nonce: None },
+            Array { offset: self.history_table_range.offset, length: self.history_table_size, nonce: None },
+        ];
+
+        for (name, chunk) in self.iter_chunks().filter(|(_, chunk)| chunk.length > 0) {
+            if chunk.end() > len {
+                return Err(Error::new(std::io::ErrorKind::InvalidData, format!("Chunk {:?} of '{}' extends past the end of the backing object", chunk, name)));
+            }
+
+            if tables.iter().any(|table| chunk.offset < table.end() && table.offset < chunk.end()) {
+                return Err(Error::new(std::io::ErrorKind::InvalidData, format!("Chunk {:?} of '{}' overlaps the tables", chunk, name)));
+            }
+        }
+
+        if let Some(((a, chunk_a), (b, chunk_b))) = self.overlapping_chunks().first() {
+            return Err(Error::new(std::io::ErrorKind::InvalidData, format!("Chunk {:?} of '{}' overlaps chunk {:?} of '{}'", chunk_a, a, chunk_b, b)));
+        }
+
+        Ok(())
+    }
+
+    /// Check the header and every inode table entry in the backing object against their CRC-32Cs, returning every mismatch rather than stopping at the first.
+    /// The tables are read from where the header was last written. Databases whose header was never written with checksums have nothing to check.
+    pub fn verify_integrity(&self) -> Result<Vec<IntegrityError>> {
+        let flags = u64::from_le_bytes(self.raw_header[8..16]
+            .try_into()
+            .map_err(Error::other)?);
+
+        if flags & FLAG_CRC == 0 {
+            return Ok(vec![]);
+        }
+
+        let mut failures = vec![];
+
+        let mut header = vec![0u8; self.raw_header.len()];
+        {
+            let mut backing = self.backing.lock_backing()
+                .map_err(Error::other)?;
+
+            backing.seek(SeekFrom::Start(self.base_offset))?;
+            backing.read_exact(&mut header)?;
+        }
+
+        let expected = u32::from_le_bytes(header[HEADER_CRC].try_into().map_err(Error::other)?);
+        let found = header_crc(&header);
+        if expected != found {
+            failures.push(IntegrityError::Header { expected, found });
+        }
+
+        let (_, entries) = Self::parse_inode_table(self.backing
+            .lock_backing()
+            .map_err(Error::other)?, self.string_table.borrow(), self.inode_table_range, flags, self.base_offset)?;
+        failures.extend(entries);
+
+        Ok(failures)
+    }
+
+    /// Every chunk of every page, along with the name of the page it belongs to. Chunks shared by linked pages are yielded once for each page.
+    pub fn iter_chunks(&self) -> impl Iterator<Item = (&str, Array)> {
+        self.inode_table.values()
+            .flat_map(|page| page.inodes.iter().map(move |chunk| (page.name.as_str(), *chunk)))
+    }
+
+    /// Every pair of distinct chunks which overlap, sorted by the offset of the first. Chunks shared by linked pages are identical rather than overlapping, so aren't reported.
+    /// In a valid database, this is always empty.
+    pub fn overlapping_chunks(&self) -> Vec<(NamedChunk<'_>, NamedChunk<'_>)> {
+        let mut chunks = self.iter_chunks()
+            .filter(|(_, chunk)| chunk.length > 0)
+            .collect::<Vec<_>>();
+        chunks.sort_unstable_by_key(|(_, chunk)| (chunk.offset, chunk.length));
+        chunks.dedup_by_key(|(_, chunk)| (chunk.offset, chunk.length));
+
+        let mut pairs = vec![];
+        for (i, a) in chunks.iter().enumerate() {
+            pairs.extend(chunks[i + 1..].iter()
+                .take_while(|b| a.1.overlaps(&b.1))
+                .map(|b| (*a, *b)));
+        }
+
+        pairs
+    }
+
+    /// The pages whose chunks aren't listed in order of their offset, along with the index of the first chunk which lies before its predecessor, sorted by name.
+    /// Chunks may legitimately be listed out of order (such as after `Page::prepend`), but it can also indicate corruption.
+    pub fn unsorted_chunks(&self) -> Vec<(&str, usize)> {
+        let mut pages = self.inode_table.values()
+            .filter_map(|page| page.inodes.windows(2)
+                .position(|pair| pair[1].offset < pair[0].offset)
+                .map(|index| (page.name.as_str(), index + 1)))
+            .collect::<Vec<_>>();
+        pages.sort_unstable();
+
+        pages
+    }
+
+    /// Check the inode and string tables for structural violations, collecting all of them rather than stopping at the first. Succeeds only if there are none.
+    /// Unlike `diagnose`, only violations which make the database unsound are reported. If the length of the backing object can't be determined, chunks aren't checked against it.
+    pub fn verify(&self) -> std::result::Result<(), Vec<DatabaseError>> {
+        let mut errors = vec![];
+
+        let len = self.backing_len().ok();
+
+        let mut pages = self.inode_table.values().collect::<Vec<_>>();
+        pages.sort_unstable_by(|i, j| Ord::cmp(&i.name, &j.name));
+
+        for page in pages.iter() {
+            for chunk in page.inodes.iter() {
+                if chunk.length == 0 {
+                    errors.push(DatabaseError::EmptyChunk { page: page.name.clone(), chunk: *chunk });
+                }
+
+                if let Some(len) = len.filter(|len| chunk.end() > *len) {
+                    errors.push(DatabaseError::ChunkOutOfBounds { page: page.name.clone(), chunk: *chunk, backing_length: len });
+                }
+            }
+        }
+
+        errors.extend(self.overlapping_chunks()
+            .into_iter()
+            .map(|((a, chunk_a), (b, chunk_b))| DatabaseError::OverlappingChunks { first: (a.to_owned(), chunk_a), second: (b.to_owned(), chunk_b) }));
+
+        if let Ok(strings) = self.string_table.try_borrow() {
+            for page in pages.iter() {
+                let missing = iter::once(page.name.as_str())
+                    .chain(page.access_control_list.iter().map(|acl| acl.entity()))
+                    .filter(|str| !strings.iter().any(|i| i == str));
+
+                errors.extend(missing.map(|str| DatabaseError::MissingString { page: page.name.clone(), string: str.to_owned() }));
+            }
+
+            if self.string_table_range.length != strings.len() as u64 {
+                errors.push(DatabaseError::StringCountMismatch { recorded: self.string_table_range.length, actual: strings.len() as u64 });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Collect every problem and noteworthy property of the database which can be detected, sorted by descending severity. Unlike `verify_format`, this doesn't stop at the first error.
+    /// ```rust
+    /// # use datastore_provider::format::database::Severity;
+    /// # #[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+    /// # struct Metadata { friendly_name: String }
+    /// # let db = datastore_provider::format::blank::<Metadata>()?;
+    /// let errors = db.diagnose()
+    ///     .into_iter()
+    ///     .filter(|message| message.severity == Severity::Error)
+    ///     .collect::<Vec<_>>();
+    /// # assert!(errors.is_empty());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn diagnose(&self) -> Vec<DiagnosticMessage> {
+        let mut messages = vec![];
+        let mut report = |severity: Severity, code: &'static str, location: Option<u64>, message: String| messages.push(DiagnosticMessage { severity, code, message, location });
+
+        let len = self.backing_len().ok();
+        let tables = [
+            self.metadata_region(),
+            Array { offset: self.inode_table_range.offset, length: self.inode_table_size, nonce: None },
+            Array { offset: self.string_table_range.offset, length: self.string_table_size, nonce: None },
+            Array { offset: self.history_table_range.offset, length: self.history_table_size, nonce: None },
+        ];
+
+        let mut pages = self.inode_table.values().collect::<Vec<_>>();
+        pages.sort_unstable_by(|i, j| Ord::cmp(&i.name, &j.name));
+
+        let now = SystemTime::now();
+        for page in pages.iter() {
+            for chunk in page.inodes.iter() {
+                if chunk.length == 0 {
+                    report(Severity::Warning, "empty-chunk", Some(chunk.offset), format!("Chunk at offset {:#x} of page '{}' has length 0", chunk.offset, page.name));
+                } else if len.is_some_and(|len| chunk.end() > len) {
+                    report(Severity::Error, "chunk-out-of-bounds", Some(chunk.offset), format!("Chunk {:?} of page '{}' extends past the end of the backing object", chunk, page.name));
+                } else if tables.iter().any(|table| chunk.offset < table.end() && table.offset < chunk.end()) {
+                    report(Severity::Error, "chunk-overlaps-tables", Some(chunk.offset), format!("Chunk {:?} of page '{}' overlaps the tables", chunk, page.name));
+                }
+            }
+
+            if page.inodes.len() > 1 {
+                report(Severity::Info, "fragmented-page", page.inodes.first().map(|i| i.offset), format!("Page '{}' has {} chunks and fragmentation ratio {:.2}", page.name, page.inodes.len(), page.fragmentation_ratio()));
+            }
+
+            if page.modified > now || page.created > now {
+                report(Severity::Warning, "future-timestamp", None, format!("Modified or created timestamp of page '{}' is in the future", page.name));
+            }
+        }
+
+        for ((a, chunk_a), (b, chunk_b)) in self.overlapping_chunks() {
+            report(Severity::Error, "overlapping-chunks", Some(chunk_b.offset), format!("Chunk {:?} of '{}' overlaps chunk {:?} of '{}'", chunk_a, a, chunk_b, b));
+        }
+
+        for (name, index) in self.unsorted_chunks() {
+            report(Severity::Info, "unsorted-chunks", None, format!("Chunk {} of page '{}' lies before its predecessor", index, name));
+        }
+
+        if let Ok(strings) = self.string_table.try_borrow() {
+            let unreferenced = strings.iter()
+                .enumerate()
+                .filter(|(index, str)| !self.inode_table.values().any(|page| page.name == **str || page.access_control_list.iter().any(|acl| acl.entity() == str.as_str()))
+                    && !self.history_table.iter().any(|entry| entry.page_name_index == *index as u64))
+                .count();
+
+            if unreferenced > 0 {
+                report(Severity::Info, "unreferenced-strings", Some(self.string_table_range.offset), format!("String table contains {} unreferenced strings", unreferenced));
+            }
+        }
+
+        let free = self.get_free_regions();
+        if !free.is_empty() {
+            report(Severity::Info, "free-space", free.first().map(|i| i.offset), format!("{} free regions totalling {} bytes", free.len(), free.iter().map(|i| i.length).sum::<u64>()));
+        }
+
+        if !self.validate_free_list() {
+            report(Severity::Warning, "free-list", None, "Free or borrowed chunks overlap each other or data in use, or extend past the end of the backing object".to_owned());
+        }
+
+        messages.sort_by_key(|message| std::cmp::Reverse(message.severity));
+        messages
+    }
+
+    /// Describe the complete runtime state of the database for debugging: the header, every page, the string table, borrowed slices and range locks.
+    /// Parts which can't be inspected at the moment are reported as unavailable rather than causing a panic.
+    pub fn dump_state(&self) -> String {
+        let mut out = String::new();
+        let range = |i: Array| format!("{:#x}+{:#x}", i.offset, i.length);
+
+        out += &format!("Header: version {}, flags {:#x}\n",
+            self.raw_header.get(4..8).and_then(|i| i.try_into().ok()).map(u32::from_le_bytes).unwrap_or(0),
+            self.raw_header.get(8..HEADER_CRC.start).and_then(|i| i.try_into().ok()).map(u32::from_le_bytes).unwrap_or(0));
+        out += &format!("  inode table: {}, string table: {}, history table: {}, metadata: {}\n",
+            range(self.inode_table_range), range(self.string_table_range), range(self.history_table_range), range(self.metadata_range));
+
+        let mut pages = self.inode_table.values().collect::<Vec<_>>();
+        pages.sort_unstable_by(|i, j| Ord::cmp(&i.name, &j.name));
+
+        out += &format!("Pages: {}\n", pages.len());
+        for page in pages {
+            out += &format!("  {}: {} chunks, {} bytes\n", page.name, page.chunk_count(), page.total_allocated_size());
+        }
+
+        match self.string_table.try_borrow() {
+            Ok(strtab) => {
+                out += &format!("Strings: {}\n", strtab.len());
+                for (index, str) in strtab.iter().enumerate() {
+                    out += &format!("  {}: {:?}\n", index, str);
+                }
+            },
+            Err(_) => out += "Strings: unavailable\n",
+        }
+
+        match self.borrowed_slices.try_lock() {
+            Ok(borrowed) => out += &format!("Borrowed slices: [{}]\n", borrowed.iter()
+                .map(|i| range(*i))
+                .collect::<Vec<_>>()
+                .join(", ")),
+            Err(_) => out += "Borrowed slices: unavailable\n",
+        }
+
+        out += &format!("Agent reservations: [{}]\n", self.agent_reservations.iter()
+            .map(|i| range(*i))
+            .collect::<Vec<_>>()
+            .join(", "));
+
+        out += &self.backing.stats_summary();
+        out
+    }
+
+    /// Panic with the output of `dump_state` unless `verify_format` passes. Intended for use in tests.
+    pub fn assert_consistent(&self) {
+        if let Err(err) = self.verify_format() {
+            panic!("Database is inconsistent: {}\n{}", err, self.dump_state());
+        }
+    }
+
+    /// Gain a sneaky reference to the string table. Useful during parsing or serialisation
+    #[cfg(test)]
+    pub(crate) fn leak_string_table(&self) -> Ref<'_, Vec<String>> {
+        self.string_table.borrow()
+    }
+
+    /// Gain a sneaky reference to the inode table. Useful during parsing or seralisation
+    pub(crate) fn leak_inode_table(&self) -> HashMap<String, PageDescriptor> {
+        self.inode_table.clone()
+    }
+}
+
+impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write + Seek + 'static, Metadata: Serialize + DeserializeOwned + Clone {
+    /// Register a validator which every page must pass before `open_page` hands it out. Replaces any previously registered validator.
+    pub fn with_page_validator<Validator: Fn(&PageDescriptor) -> Result<()> + Send + 'static>(mut self, validator: Validator) -> Self {
+        self.page_validator = Some(Box::new(validator));
+        self
+    }
+
+    /// Open an existing page by name, subject to the registered page validator.
+    /// Access control lists aren't consulted - they're opt-in, and only enforced by `open_page_as` and `create_page_as`.
+    pub fn open_page(&mut self, name: &str) -> Result<Page<Backing>> {
+        self.open_page_checked(name, |_| Ok(()))
+    }
+
+    /// Open an existing page by name, provided its descriptor passes `guard` as well as the registered page validator.
+    /// The checks happen before the page is constructed, so rejected pages never touch the backing object. The first error raised is returned as-is.
+    /// Fails with `Busy` while the page is held by `open_read_write_locked`.
+    pub fn open_page_checked<Guard: Fn(&PageDescriptor) -> Result<()>>(&mut self, name: &str, guard: Guard) -> Result<Page<Backing>> {
+        // Pages flushed since the header was last written would otherwise be opened with their old chunks
+        self.apply_page_requests()?;
+
+        if self.is_page_exclusively_open(name) {
+            return Err(Error::from(crate::error::Error::Busy));
+        }
+
+        let descriptor = self.inode_table.get(name)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))?;
+
+        if let Some(validator) = self.page_validator.as_ref() {
+            validator(descriptor)?;
+        }
+        guard(descriptor)?;
+
+        let page = self.hand_out(descriptor.clone())?;
+        self.record_access(name);
+
+        Ok(page)
+    }
+
+    /// Open an existing page on behalf of `entity`, which one of the page's ACL entries must grant at least `level`. Fails with `PermissionDenied` otherwise.
+    /// The check is opt-in: every other way of reaching the page, including `open_page`, ignores its access control list, so callers which rely on ACLs must go through the `_as` methods.
+    pub fn open_page_as(&mut self, name: &str, entity: &str, level: AccessLevel) -> Result<Page<Backing>> {
+        self.open_page_checked(name, |page| Self::check_access(page, entity, level))
+    }
+
+    /// Fail with `PermissionDenied` unless one of the page's ACL entries grants `entity` at least `level`.
+    fn check_access(page: &PageDescriptor, entity: &str, level: AccessLevel) -> Result<()> {
+        match page.access_control_list.iter().any(|access| access.check(entity, level.bits())) {
+            true => Ok(()),
+            false => Err(Error::new(std::io::ErrorKind::PermissionDenied, format!("'{}' may not access '{}'", entity, page.name))),
+        }
+    }
+
+    /// Open an existing page by name for exclusive access. While the returned page is alive, no other instance of the page can be opened, so it has all of the page's chunks to itself.
+    /// Its chunks are locked exclusively for as long as it's alive, so no other access to them through the range locks goes through either.
+    /// Fails with `Busy` if the page is already open or any of its chunks are locked. The page validator is applied as with `open_page`.
+    pub fn open_read_write_locked(&mut self, name: &str) -> Result<ExclusivePage<Backing>> {
+        self.apply_page_requests()?;
+
+        if self.is_page_open(name) {
+            return Err(Error::from(crate::error::Error::Busy));
+        }
+
+        let descriptor = self.inode_table.get(name)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))?;
+
+        if let Some(validator) = self.page_validator.as_ref() {
+            validator(descriptor)?;
+        }
+
+        let token = Arc::new(());
+        let locks = self.backing.try_lock_exclusive_ranges(&descriptor.inodes, Arc::as_ptr(&token) as usize)?;
+
+        self.exclusive_pages.insert(name.to_owned(), Arc::downgrade(&token));
+        let page = Page::new(descriptor.clone(), Arc::clone(&self.backing), token, self.page_requests.0.clone())
+            .with_wal(self.wal.clone())
+            .with_journal(self.journal.is_some())
+            .load()?;
+        self.record_access(name);
+
+        Ok(ExclusivePage::new(page, locks))
+    }
+
+    /// Open the named page only if its generation token differs from `token`, i.e. it has changed since the token was obtained. See `page_generation_token`.
+    pub fn open_page_if_modified(&mut self, name: &str, token: u64) -> Result<Option<Page<Backing>>> {
+        if self.page_generation_token(name)? == token {
+            return Ok(None);
+        }
+
+        self.open_page(name).map(Some)
+    }
+
+    /// Create an empty page and open it. The page has no chunks and an empty access control list, and grows as it's written to.
+    /// No access checks are made; use `create_page_as` to create pages on behalf of an entity.
+    /// Fails with `AlreadyExists` if a page named `name` exists.
+    pub fn create_page(&mut self, name: &str) -> Result<Page<Backing>> {
+        self.create_page_with_acl(name, vec![])
+    }
+
+    /// Create the page at `path`, along with an empty page for each of its ancestors which doesn't exist yet, treating `/` as the separator. `/a/b/c` creates `/a` and `/a/b` if needed, but not the root page `/`.
+    /// Fails with `AlreadyExists` if the page at `path` exists, leaving any ancestors it created in place.
+    pub fn create_page_recursive(&mut self, path: &str) -> Result<Page<Backing>> {
+        let ancestors = path.match_indices('/')
+            .map(|(i, _)| &path[..i])
+            .filter(|ancestor| !ancestor.is_empty() && !ancestor.ends_with('/'))
+            .collect::<Vec<_>>();
+
+        for ancestor in ancestors {
+            if !self.inode_table.contains_key(ancestor) {
+                self.create_page(ancestor)?;
+            }
+        }
+
+        self.create_page(path)
+    }
+
+    /// Create an empty page on behalf of `entity`, which needs write access to the root page `/`. The new page grants `entity` full access.
+    /// Fails with `PermissionDenied` if `entity` may not write to the root page, or there is none.
+    /// Like `open_page_as`, this is opt-in; the page's ACL is only enforced when it's reopened through `open_page_as`.
+    pub fn create_page_as(&mut self, name: &str, entity: &str) -> Result<Page<Backing>> {
+        let root = self.inode_table.get("/")
+            .ok_or(Error::new(std::io::ErrorKind::PermissionDenied, "Pages can only be created by entities with access to the root page '/', of which there is none"))?;
+        Self::check_access(root, entity, AccessLevel::ReadWrite)?;
+
+        self.create_page_with_acl(name, vec![Access::ReadWriteExecute(entity.to_owned())])
+    }
+
+    /// Create a page holding everything `reader` yields, read `chunk_size` bytes at a time so that each read is written to a chunk of its own. The page is flushed before it's returned.
+    /// Fails with `InvalidInput` if `chunk_size` is 0, and otherwise as `create_page`.
+    pub fn import_page<R: Read + ?Sized>(&mut self, name: &str, reader: &mut R, chunk_size: usize) -> Result<Page<Backing>> {
+        if chunk_size == 0 {
+            return Err(Error::new(std::io::ErrorKind::InvalidInput, "Pages can't be imported in chunks of 0 bytes"));
+        }
+
+        let mut page = self.create_page(name)?;
+
+        let mut buffer = Vec::with_capacity(chunk_size);
+        loop {
+            buffer.clear();
+            if reader.take(chunk_size as u64).read_to_end(&mut buffer)? == 0 {
+                break;
+            }
+
+            page.extend_from_slice(&buffer)?;
+        }
+
+        page.flush()?;
+
+        Ok(page)
+    }
+
+    fn create_page_with_acl(&mut self, name: &str, access_control_list: Vec<Access>) -> Result<Page<Backing>> {
+        self.begin_write()?;
+
+        if self.inode_table.contains_key(name) {
+            return Err(Error::new(std::io::ErrorKind::AlreadyExists, format!("A page named '{}' already exists", name)));
+        }
+
+        let descriptor = PageDescriptor {
+            name: name.to_owned(),
+            access_control_list,
+            modified: SystemTime::now(),
+            created: SystemTime::now(),
+            inodes: vec![],
+            content_length: 0,
+            link_count: 1,
+            compression: CompressionAlgorithm::None,
+            checksum: None,
+            user_data: vec![],
+        };
+
+        self.append_history_entry(HistoryOp::Create, name, &[])?;
+        self.hooks.created(name, &descriptor);
+        self.invalidate_sorted_index();
+        self.inode_table.insert(name.to_owned(), descriptor.clone());
+        self.journal_change(name, None)?;
+        self.write_header()?;
+
+        self.hand_out(descriptor)
+    }
+
+    /// Create a page backed by exactly the region `len` bytes long at `offset`, bypassing the allocator. The backing object is grown if it doesn't reach the end of the region yet.
+    /// Fails if the region overlaps the database's tables or a chunk belonging to any page.
+    pub fn create_page_at_offset(&mut self, name: &str, offset: u64, len: u64) -> Result<Page<Backing>> {
+        self.begin_write()?;
+
+        if self.inode_table.contains_key(name) {
+            return Err(Error::new(std::io::ErrorKind::AlreadyExists, format!("A page named '{}' already exists", name)));
+        }
+
+        let region = Array { offset, length: len, nonce: None };
+
+        let borrowed = self.reserved_slices()?;
+
+        let in_use = offset < self.data_offset() || self.inode_table.values()
+            .flat_map(|page| page.inodes.iter())
+            .chain(borrowed.iter())
+            .any(|chunk| chunk.offset < region.end() && region.offset < chunk.end());
+
+        if in_use {
+            return Err(Error::other("Region already in use"));
         }
 
-        self.string_table_size = vec.len() as u64;
-        Ok(vec)
+        {
+            let mut backing = self.backing.lock_backing()
+                .map_err(Error::other)?;
+
+            let end = backing.stream_len()?;
+            if end < region.end() {
+                backing.seek(SeekFrom::End(0))?;
+                backing.write_all(&vec![0u8; (region.end() - end) as usize])?;
+            }
+        }
+
+        let mut descriptor = PageDescriptor {
+            name: name.to_owned(),
+            access_control_list: vec![],
+            modified: SystemTime::now(),
+            created: SystemTime::now(),
+            inodes: vec![region],
+            content_length: len,
+            link_count: 1,
+            compression: CompressionAlgorithm::None,
+            checksum: None,
+            user_data: vec![],
+        };
+        descriptor.checksum = Some(self.hash_content(&descriptor)?);
+
+        self.hooks.created(name, &descriptor);
+        self.invalidate_sorted_index();
+        self.inode_table.insert(name.to_owned(), descriptor.clone());
+        self.journal_change(name, None)?;
+        self.write_header()?;
+
+        self.hand_out(descriptor)
     }
 
-    /// Generate a byte-buffer of the history table
-    /// **!Not Implemented**
-    fn serialise_history_table(&mut self) -> Result<Vec<u8>> {
-        self.history_table_size = 0;
-        Ok(vec![])
+    /// Construct a page over `descriptor`, keeping track of it as open.
+    fn hand_out(&mut self, descriptor: PageDescriptor) -> Result<Page<Backing>> {
+        let token = Arc::new(());
+
+        let tokens = self.open_pages.entry(descriptor.name.clone()).or_default();
+        tokens.retain(|token| token.strong_count() > 0);
+        tokens.push(Arc::downgrade(&token));
+
+        let (max_page_size, max_chunk_size) = self.page_limits;
+
+        Ok(Page::new(descriptor, Arc::clone(&self.backing), token, self.page_requests.0.clone())
+            .with_wal(self.wal.clone())
+            .with_journal(self.journal.is_some())
+            .with_max_page_size(self.max_page_size.or(max_page_size))
+            .with_max_chunk_size(max_chunk_size)
+            .load()?)
     }
 
-    // TODO: Refactor to make returning multiple chunks which add up to `min_space` possible
-    /// Request the backing object grow by `min_space` bytes.
-    /// This is used before appending chunks to a page, and ensures that unused chunks are either reused, deleted or reallocated before being assigned to a page.
-    fn allocate_chunks(&mut self, min_space: u64) -> Result<Vec<Array>> {
-        let total_length: u64 = self.backing.try_borrow_mut()
+    /// Make the database usable again after a thread panicked while holding one of its locks, such as while writing a page, instead of failing on the poisoned lock from then on.
+    /// Range locks are released, as the panicking thread's would never be. Slices borrowed by pending writes are kept, so at worst the space the panicking thread had reserved isn't reused until the database is reopened.
+    /// The backing object isn't repaired, so a write interrupted by the panic may have been left incomplete. `verify` can be used to check the database afterwards.
+    pub fn recover_from_poison(&mut self) -> Result<()> {
+        let mut recovered = self.backing.recover_from_poison();
+
+        if self.borrowed_slices.is_poisoned() {
+            self.borrowed_slices.clear_poison();
+            recovered = true;
+        }
+        if let Some(wal) = self.wal.as_ref().filter(|wal| wal.is_poisoned()) {
+            wal.clear_poison();
+            recovered = true;
+        }
+
+        #[cfg(feature = "tracing")]
+        if recovered {
+            tracing::warn!("Recovered from a thread panicking while holding a lock. Range locks were released, but writes in progress may be incomplete");
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = recovered;
+
+        Ok(())
+    }
+
+    /// Log every write made through pages opened from now on to the write-ahead log at `wal_path`, before it reaches the backing object. The log is created if it doesn't exist.
+    /// Records left in an existing log, such as by a crash, are replayed straight away. See `checkpoint`.
+    /// > **Note**: Pages opened before the log was enabled don't log their writes.
+    pub fn enable_wal<P: AsRef<Path>>(&mut self, wal_path: P) -> Result<()> {
+        self.wal = Some(Arc::new(Mutex::new(Wal::open(wal_path.as_ref())?)));
+        self.checkpoint()?;
+
+        Ok(())
+    }
+
+    /// Replay every record of the write-ahead log into the backing object, write the header and empty the log, returning the number of records replayed.
+    /// Records whose writes already took place are harmless to replay, as they write the same content again. Records of pages which no longer exist are skipped.
+    /// Fails with `ResourceBusy` if any page is open, as its writes since it was last flushed would be lost along with the log. Does nothing if the log isn't enabled.
+    pub fn checkpoint(&mut self) -> Result<usize> {
+        let Some(wal) = self.wal.clone() else { return Ok(0); };
+
+        if let Some(name) = self.inode_table.keys().find(|name| self.is_page_open(name)) {
+            return Err(Error::new(std::io::ErrorKind::ResourceBusy, format!("'{}' is open", name)));
+        }
+
+        let records = wal.lock()
+            .map_err(|_| Error::other("PoisonError"))?
+            .records()?;
+        if records.is_empty() {
+            return Ok(0);
+        }
+
+        self.begin_write()?;
+
+        // Prepared content only takes effect if its commit was logged as well
+        let mut prepared = vec![];
+
+        for record in records.iter() {
+            match record.op {
+                WalOp::Write => {},
+                WalOp::Prepare => {
+                    prepared.push(record);
+                    continue;
+                },
+                WalOp::Commit => {
+                    for record in prepared.drain(..) {
+                        self.write_page_content(&record.page_name, &record.data)?;
+                    }
+                    continue;
+                }
+            }
+
+            let Some(descriptor) = self.inode_table.get(&record.page_name) else { continue; };
+
+            // The page isn't handed out, so its writes aren't logged again
+            let mut page = Page::new(descriptor.clone(), Arc::clone(&self.backing), Arc::new(()), self.page_requests.0.clone())
+                .with_journal(self.journal.is_some())
+                .load()?;
+            page.write_sparse(record.offset, &record.data)?;
+            page.close()?;
+
+            self.apply_page_requests()?;
+        }
+
+        // No page is open, so this empties the log
+        self.write_header()?;
+
+        Ok(records.len())
+    }
+}
+
+impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write + Seek + Durable, Metadata: Serialize + DeserializeOwned + Clone {
+    /// Write the header and block until it and all content written before it have reached stable storage, such as with `fsync(2)` for files.
+    /// Without syncing, changes only reach the operating system's buffers, and may be lost on power failure even after `write_header` has returned.
+    pub fn fsync(&mut self) -> Result<()> {
+        // Sealed databases were flushed when they were sealed
+        if !self.is_sealed() {
+            self.write_header()?;
+        }
+
+        self.backing.lock_backing()
             .map_err(Error::other)?
-            .deref_mut()
-            .stream_len()? as u64;
+            .sync()
+    }
 
-        let mut inodes = self.inode_table.values()
-            .map(|i| i.inodes.iter())
-            .flatten()
-            .cloned()
-            .chain(iter::once(Array { length: 0, offset: self.data_offset() }))
-            .chain(iter::once(Array { length: 0, offset: total_length }))
+    /// Sync the backing object to stable storage after every header write, making each write durable at the cost of throughput. Disabled by default.
+    /// Databases opened from files with `DatabaseBuilder` have this set by its `sync_on_write` option.
+    /// > **Note**: The setting isn't persisted, and is reset when the backing object is swapped with `change_buffer`.
+    pub fn set_sync_on_write(&mut self, sync_on_write: bool) {
+        self.sync_on_write = match sync_on_write {
+            true => Some(|backing| backing.sync()),
+            false => None,
+        };
+    }
+
+    /// Stop pages opened from now on from growing beyond `max_page_size` bytes. This takes precedence over a `max_page_size` field of the metadata object, and `None` falls back to it. Pages already open keep the limit they were opened with.
+    /// > **Note**: The setting isn't persisted.
+    pub fn set_max_page_size(&mut self, max_page_size: Option<u64>) {
+        self.max_page_size = max_page_size;
+    }
+}
+
+impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write + Seek + Truncatable, Metadata: Serialize + DeserializeOwned + Clone {
+    /// Truncate the backing object to the end of the last chunk or table, returning the number of bytes freed.
+    /// The header is flushed beforehand. Nothing is moved, so unused space between chunks remains in place; only the trailing space is given back.
+    pub fn shrink_to_fit(&mut self) -> Result<u64> {
+        self.begin_write()?;
+
+        self.write_header()?;
+
+        let borrowed = self.reserved_slices()?;
+
+        let end = self.inode_table.values()
+            .flat_map(|page| page.inodes.iter())
+            .chain(borrowed.iter())
+            .map(|chunk| chunk.end())
+            .chain([
+                self.data_offset(),
+                self.history_table_range.offset + self.history_table_size,
+            ])
+            .max()
+            .unwrap_or(0);
+
+        let mut backing = self.backing.lock_backing()
+            .map_err(Error::other)?;
+
+        let len = backing.stream_len()?;
+        if len <= end {
+            return Ok(0);
+        }
+
+        backing.set_len(end)?;
+        self.free_list.retain(|chunk| chunk.end() <= end);
+
+        Ok(len - end)
+    }
+
+    /// Close the gaps left between chunks by deleted or rewritten pages, then truncate the backing object, returning the number of bytes reclaimed.
+    /// Chunks are moved towards the start of the data region in order of their offset, so each page's chunks keep their sizes and order. Borrowed slices and chunks reserved for the agent stay where they are.
+    /// Chunks are first copied past the end of the backing object and the header written, so that it never refers to content which has been overwritten; only then are they copied into place, the header written again and the space reclaimed.
+    /// Unlike `compact_to_minimum`, content isn't merged into single chunks and the string table is left as it is, and only one chunk is held in memory at a time.
+    /// Fails with `Busy` if any page is open, as open pages refer to their chunks by position. The free list is cleared, as the space it tracked is given back.
+    pub fn compact(&mut self) -> Result<u64> {
+        self.begin_write()?;
+
+        if self.inode_table.keys().any(|name| self.is_page_open(name)) {
+            return Err(Error::from(crate::error::Error::Busy));
+        }
+
+        let before = self.backing_len()?;
+
+        // Pages sharing content share chunks, which only need to be moved once. Where chunks start at the same offset, the longest one covers the others.
+        let mut chunks = self.inode_table.values()
+            .flat_map(|page| page.inodes.iter().copied())
+            .filter(|chunk| chunk.length > 0)
             .collect::<Vec<_>>();
+        chunks.sort_by_key(|chunk| (chunk.offset, std::cmp::Reverse(chunk.length)));
+        chunks.dedup();
 
-        inodes.sort_unstable_by(|i, j| Ord::cmp(&i.offset, &j.offset));
+        // The gaps are about to be closed, and the free list is serialised along with the history table
+        self.free_list.clear();
 
-        let mut inodes = inodes
-            .into_iter()
-            .scan(Array { length: 0u64, offset: self.data_offset() }, |a, i| {
-                // The gap is the the start of the current + length => the start of the next
-                let out = Some(Array {
-                    length: i.offset - (a.offset + a.length),
-                    offset: a.offset + a.length
-                });
-                *a = i;
-                return out;
-            })
+        // Mirrors the layout `write_header` picks when the tables fit after the metadata. Content is only moved in front of the tables if they can't fit before the first chunk anyway.
+        let align = |offset: u64| offset + (0x10 - offset % 0x10) % 0x10;
+        let inode_offset = align(self.metadata_region().end());
+        let string_offset = align(inode_offset + self.serialise_inode_table()?.len() as u64);
+        let history_offset = align(string_offset + self.serialise_string_table()?.len() as u64);
+        let tables_end = history_offset + self.serialise_history_table()?.len() as u64;
+
+        let mut cursor = match chunks.first() {
+            Some(first) if first.offset < tables_end => first.offset,
+            _ => tables_end,
+        };
+
+        // Chunks which move => where they end up
+        let mut targets = vec![];
+        for chunk in chunks {
+            let target = Array { offset: self.skip_reserved(cursor, chunk.length)?, ..chunk };
+            cursor = target.end();
+
+            if target.offset != chunk.offset {
+                targets.push((chunk, target));
+            }
+        }
+
+        let mut scratch = self.skip_reserved(before.max(cursor), 0)?;
+        let mut staged = vec![];
+        for (chunk, target) in targets {
+            let copy = Array { offset: self.skip_reserved(scratch, chunk.length)?, ..chunk };
+            scratch = copy.end();
+
+            self.copy_chunk(chunk, copy.offset)?;
+            staged.push((chunk.offset, copy, target));
+        }
+
+        // The header now refers to the copies, so the chunks' previous positions can be overwritten
+        self.relocate_chunks(staged.iter().map(|(offset, copy, _)| (*offset, copy.offset)).collect());
+        self.write_header()?;
+
+        for (_, copy, target) in staged.iter() {
+            self.copy_chunk(*copy, target.offset)?;
+        }
+
+        self.relocate_chunks(staged.iter().map(|(_, copy, target)| (copy.offset, target.offset)).collect());
+        // The space the previous write freed has since been packed over, or is about to be truncated
+        self.free_list.clear();
+        self.write_header()?;
+
+        let end = cursor
+            .max(self.data_offset())
+            .max(self.metadata_region().end())
+            .max(self.reserved_slices()?.iter().map(Array::end).max().unwrap_or(0));
+        self.backing.lock_backing()
+            .map_err(Error::other)?
+            .set_len(end)?;
+
+        Ok(before.saturating_sub(end))
+    }
+
+    /// Copy the stored bytes of `chunk` to `offset`. Nothing refers to the copy until the caller records it.
+    fn copy_chunk(&self, chunk: Array, offset: u64) -> Result<()> {
+        let mut backing = self.backing.lock_backing()
+            .map_err(Error::other)?;
+
+        let mut content = vec![0u8; chunk.length as usize];
+        backing.seek(SeekFrom::Start(chunk.offset))?;
+        backing.read_exact(&mut content)?;
+        backing.seek(SeekFrom::Start(offset))?;
+        backing.write_all(&content)?;
+
+        Ok(())
+    }
+
+    /// Point every page's and history entry's chunk starting at a key of `moved` at the offset it maps to.
+    fn relocate_chunks(&mut self, moved: HashMap<u64, u64>) {
+        let relocate = |chunk: &mut Array| if let Some(offset) = moved.get(&chunk.offset) {
+            chunk.offset = *offset;
+        };
+
+        self.inode_table.values_mut()
+            .flat_map(|page| page.inodes.iter_mut())
+            .for_each(relocate);
+        self.history_table.iter_mut()
+            .flat_map(|entry| entry.chunks_snapshot.iter_mut())
+            .for_each(relocate);
+    }
+
+    /// Reduce the database to its minimum size, returning the number of bytes freed. Every page's content is moved into a single chunk, the string table is stripped of strings no page refers to,
+    /// and the metadata, tables and chunks are packed back to back directly after the header, with the inode table sorted by the pages' offsets, after which the backing object is truncated.
+    /// Borrowed slices and chunks reserved for the agent stay where they are. The header is written once, after all content has been moved.
+    /// `progress` is called with the number of pages moved so far and the total after each page. Fails with `Busy` if any page is open.
+    /// > **Note**: Every page's content is held in memory while it's moved. Linked pages stay linked.
+    /// > **Note**: The content is moved before the header referring to it is written, so if compacting is interrupted, the database can't be read back. Take a `snapshot` first if that's a concern.
+    /// > **Note**: Journal entries refer to pages by string table index, so journals recorded before compacting no longer resolve correctly.
+    pub fn compact_to_minimum<Progress: Fn(usize, usize)>(&mut self, progress: Option<Progress>) -> Result<u64> {
+        self.begin_write()?;
+
+        if self.inode_table.keys().any(|name| self.is_page_open(name)) {
+            return Err(Error::from(crate::error::Error::Busy));
+        }
+
+        let before = self.backing_len()?;
+
+        let mut names = self.inode_table.keys()
+            .cloned()
             .collect::<Vec<_>>();
-        inodes.sort_unstable_by(|i, j| Ord::cmp(&i.length, &j.length));
+        names.sort();
 
-        if let Some(inode) = inodes.iter()
-            .find(|i| i.length >= min_space) {
-            Ok(vec![Array { offset: inode.offset, length: min_space }])
-        } else {
-            // todo!("Expand file to make room for new chunk")
-            let mut backing = self.backing.try_borrow_mut()
-                .map_err(Error::other)?;
+        // The content has to be read up front, as it's about to be overwritten. Pages sharing a chunk list share their content.
+        let mut contents: Vec<Vec<u8>> = vec![];
+        let mut groups: HashMap<String, usize> = HashMap::new();
+        let mut seen: HashMap<Vec<(u64, u64)>, usize> = HashMap::new();
+        for name in names.iter() {
+            let page = &self.inode_table[name];
+            let group = match seen.entry(page.inodes.iter().map(|chunk| (chunk.offset, chunk.length)).collect()) {
+                std::collections::hash_map::Entry::Occupied(group) => *group.get(),
+                std::collections::hash_map::Entry::Vacant(group) => {
+                    let mut content = self.read_chunks(&page.inodes)?;
+                    content.truncate(page.content_length as usize);
+                    contents.push(content);
+                    *group.insert(contents.len() - 1)
+                }
+            };
+            groups.insert(name.clone(), group);
+        }
 
-            let position = backing.seek(SeekFrom::End(0))?;
-            backing.write_all(&vec![0u8; (min_space + (0x1000 - min_space % 0x1000)) as usize])?;
+        // Only keep the strings pages refer to
+        let mut table: Vec<String> = vec![];
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        let mut intern = |str: &str| *indices.entry(str.to_owned())
+            .or_insert_with(|| {
+                table.push(str.to_owned());
+                table.len() - 1
+            });
+        for name in names.iter() {
+            let page = &self.inode_table[name];
+            for str in iter::once(page.name.as_str()).chain(page.access_control_list.iter().map(|acl| acl.entity())) {
+                intern(str);
+            }
+        }
+        // as well as the names the history refers to
+        let previous = self.string_table.replace(vec![]);
+        for entry in self.history_table.iter_mut() {
+            let Some(name) = previous.get(entry.page_name_index as usize) else { continue; };
+            entry.page_name_index = intern(name) as u64;
+        }
+        *self.string_table.try_borrow_mut()
+            .map_err(Error::other)? = table;
+
+        // Give every page a placeholder chunk of its final size, so that the tables serialise to their final size too
+        let overhead = self.backing.stored_length(0);
+        for page in self.inode_table.values_mut() {
+            let len = contents[groups[&page.name]].len() as u64;
+            page.inodes = match len {
+                0 => vec![],
+                length => vec![Array { offset: 0, length: length + overhead, nonce: None }],
+            };
+            page.content_length = len;
+        }
+
+        self.metadata_range = Array {
+            offset: self.base_offset + self.raw_header.len() as u64 + self.salt.map(|i| i.len() as u64).unwrap_or(0),
+            length: self.metadata_format()?
+                .serialise(&self.meta)?
+                .len() as u64,
+                nonce: None,
+        };
+
+        // The gaps are about to be closed, and the free list is serialised along with the history table.
+        // The space the tables occupy now is about to be overwritten or truncated, so it isn't freed when they're written either.
+        self.free_list.clear();
+        self.inode_table_size = 0;
+        self.string_table_size = 0;
+        self.history_table_size = 0;
+
+        // Mirrors the layout `write_header` picks when the tables fit after the metadata
+        let align = |offset: u64| offset + (0x10 - offset % 0x10) % 0x10;
+        let inode_offset = align(self.metadata_region().end());
+        let string_offset = align(inode_offset + self.serialise_inode_table()?.len() as u64);
+        let history_offset = align(string_offset + self.serialise_string_table()?.len() as u64);
+        let mut cursor = history_offset + self.serialise_history_table()?.len() as u64;
+
+        // Each distinct content is packed after the tables, in the order of the first page holding it
+        let mut targets: Vec<Option<Array>> = vec![None; contents.len()];
+        for (index, name) in names.iter().enumerate() {
+            let group = groups[name];
+
+            if targets[group].is_none() && !contents[group].is_empty() {
+                let (stored, nonce) = self.backing.encrypt_chunk(&contents[group])?;
+                let target = Array { offset: self.skip_reserved(cursor, stored.len() as u64)?, length: stored.len() as u64, nonce };
+                cursor = target.end();
+
+                let mut backing = self.backing.lock_backing()
+                    .map_err(Error::other)?;
+                backing.seek(SeekFrom::Start(target.offset))?;
+                backing.write_all(&stored)?;
+
+                targets[group] = Some(target);
+            }
 
-            Ok(vec![Array {offset: position, length: min_space }])
+            if let Some(page) = self.inode_table.get_mut(name) {
+                page.inodes = targets[group].into_iter().collect();
+            }
+
+            if let Some(progress) = progress.as_ref() {
+                progress(index + 1, names.len());
+            }
         }
+
+        self.write_header()?;
+
+        let end = cursor
+            .max(self.data_offset())
+            .max(self.reserved_slices()?.iter().map(Array::end).max().unwrap_or(0));
+        self.backing.lock_backing()
+            .map_err(Error::other)?
+            .set_len(end)?;
+
+        Ok(before.saturating_sub(end))
     }
+}
 
-    /// Grow a page by at least `min_space` bytes. Usually involves appending a new chunk to the page, but can also cause the final chunk to grow.
-    fn grow(&mut self, page: &PageDescriptor, min_space: u64) -> Result<()> {
-        if let Some(page) = self.inode_table.get_mut(&page.name) {
+impl<Metadata> Database<File, Metadata> where Metadata: Serialize + DeserializeOwned + Clone + Send {
+    /// Open the database file at `path` for reading and writing. If a write-ahead log is found alongside it, it's replayed and kept enabled. See `wal::wal_path`.
+    fn open_path(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)?;
 
+        let mut db = Self::open(file)?;
+
+        let wal = wal_path(path);
+        if wal.exists() {
+            db.enable_wal(wal)?;
         }
 
-        Ok(())
+        Ok(db)
     }
 
-    /// Swap the backing object against any new container. Useful for cloning / duplicating parts or all of the database, or initialising new databases on blank containers.
-    /// ```rust
-    /// let container = std::fs::OpenOptions::new()
-    ///     .read(true)
-    ///     .write(true)
-    ///     .open("/tmp/db.db")?;
-    ///
-    ///
-    /// use datastore_provider::format::database::Database;#[derive(Default)]
-    /// struct Metadata {
-    ///     pub friendly_name: String,
-    ///     pub max_chunk_size: u64,
-    ///     pub chunk_alignment: u64,
-    ///     pub max_page_size: u64,
-    ///     pub page_alignment: u64,
-    /// }
-    ///
-    /// // initialise a new database with a backing vector (completely in-memory), wrapped in a Cursor for `Seek`ability.
-    /// let db: Database<std::io::Cursor<Vec<u8>>, Metadata> = Database::blank(Metadata::default());
-    /// let db: Database<std::fs::File, Metadata> = db.change_buffer(container)?;
+    /// Open the database file at `path`, migrating it if its metadata version is older than that of `default_meta`, or create it with `default_meta` if it doesn't exist.
+    /// `migrate` is given the opened database, after which its metadata version is brought up to date and the header is written.
+    /// ```rust,no_run
+    /// # use datastore_provider::format::database::{Database, HasVersion};
+    /// # #[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+    /// # struct Metadata { version: u32 }
+    /// # impl HasVersion for Metadata {
+    /// #     fn version(&self) -> u32 { self.version }
+    /// #     fn set_version(&mut self, version: u32) { self.version = version; }
+    /// # }
+    /// let db = Database::open_or_create_with_migration("app.db", Metadata { version: 3, ..Default::default() }, |db| {
+    ///     db.rename_all("legacy/", "v3/")?;
+    ///     Ok(())
+    /// })?;
+    /// # Ok::<(), std::io::Error>(())
     /// ```
-    pub fn change_buffer<NewBuffer>(self, buffer: NewBuffer) -> Result<Database<NewBuffer, Metadata>> where NewBuffer: Read + Write + Seek {
-        let mut db = Database {
-            backing: Rc::new(RefCell::new(buffer)),
+    pub fn open_or_create_with_migration<P, Migrate>(path: P, default_meta: Metadata, migrate: Migrate) -> Result<Self>
+    where P: AsRef<Path>, Metadata: HasVersion, Migrate: FnOnce(&mut Self) -> Result<()> {
+        let mut db = match Self::open_path(path.as_ref()) {
+            Ok(db) => db,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create_new(true)
+                    .open(path.as_ref())?;
+
+                return Self::create(file, default_meta);
+            },
+            Err(err) => return Err(err),
+        };
+
+        let expected = default_meta.version();
+        if db.meta.version() < expected {
+            migrate(&mut db)?;
+
+            db.meta.set_version(expected);
+            db.write_header()?;
+        }
+
+        Ok(db)
+    }
+
+    /// Read the entire content of each named page, returning it alongside the page's name in the order the names were given. Fails if any page doesn't exist.
+    /// The pages are read in parallel on Rayon's thread pool with positioned reads (`pread(2)`), which leave the file's cursor alone and so don't have to be serialised through the backing object's lock.
+    /// Their chunks are read-locked throughout instead, and each page is decrypted, truncated and decompressed just as `pipe_page_range` would.
+    /// > **Note**: Every page is held in memory at once.
+    #[cfg(all(feature = "rayon", unix))]
+    pub fn parallel_read_pages<'a>(&self, names: &[&'a str]) -> Result<Vec<(&'a str, Vec<u8>)>> {
+        use rayon::prelude::*;
+        use std::os::unix::fs::FileExt;
+
+        let pages = names.iter()
+            .map(|name| self.inode_table.get(*name)
+                .map(|page| (*name, page.inodes.clone(), page.content_length, page.compression))
+                .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name))))
+            .collect::<Result<Vec<_>>>()?;
+
+        let chunks = pages.iter()
+            .flat_map(|(_, inodes, _, _)| inodes.iter().copied())
+            .collect::<Vec<_>>();
+        let _guards = self.backing.try_lock_read_ranges(&chunks)
+            .map_err(Error::other)?;
+
+        let file = self.backing.lock_backing()
+            .map_err(Error::other)?
+            .try_clone()?;
+
+        let backing = &*self.backing;
+        pages.into_par_iter()
+            .map(|(name, inodes, content_length, compression)| {
+                let mut content = Vec::with_capacity(inodes.iter().map(|i| i.content_length()).sum::<u64>() as usize);
+                for chunk in inodes {
+                    let mut stored = vec![0u8; chunk.length as usize];
+                    file.read_exact_at(&mut stored, chunk.offset)?;
+                    content.extend(backing.decrypt_chunk(stored, chunk.nonce)?);
+                }
+                content.truncate(content_length as usize);
+
+                match compression {
+                    CompressionAlgorithm::None => Ok((name, content)),
+                    compression => Ok((name, compression.decompress(&content)?)),
+                }
+            })
+            .collect()
+    }
+
+    /// Stream the named page's entire content into the file descriptor `dst` using `sendfile(2)`, so the content never passes through userspace. See `sendfile_page_range`.
+    #[cfg(all(feature = "sendfile", target_os = "linux"))]
+    pub fn sendfile_page<Dst: AsFd + Write>(&self, name: &str, dst: &mut Dst) -> Result<u64> {
+        self.sendfile_page_range(name, 0, u64::MAX, dst)
+    }
+
+    /// The zero-copy counterpart of `pipe_page_range` for file-backed databases, streaming with `sendfile(2)` into any file descriptor, such as a socket or another file.
+    /// Compressed and encrypted pages fall back to `pipe_page_range`, as they have to be decompressed or decrypted first.
+    #[cfg(all(feature = "sendfile", target_os = "linux"))]
+    pub fn sendfile_page_range<Dst: AsFd + Write>(&self, name: &str, start: u64, len: u64, dst: &mut Dst) -> Result<u64> {
+        let page = self.inode_table.get(name)
+            .ok_or(Error::new(std::io::ErrorKind::NotFound, format!("No page named '{}'", name)))?;
+
+        if page.compression != CompressionAlgorithm::None || self.backing.is_encrypted() || page.inodes.iter().any(|chunk| chunk.nonce.is_some()) {
+            return self.pipe_page_range(name, start, len, dst);
+        }
+
+        let len = len.min(page.content_length.saturating_sub(start));
+
+        let backing = self.backing
+            .lock_backing()
+            .map_err(Error::other)?;
+
+        let mut written = 0;
+        for region in page.physical_regions(start, len) {
+            let mut offset = region.offset as nix::libc::off_t;
+            let mut remaining = region.length as usize;
+
+            while remaining > 0 {
+                match nix::sys::sendfile::sendfile(&*dst, backing.deref(), Some(&mut offset), remaining).map_err(Error::from)? {
+                    0 => return Err(Error::new(std::io::ErrorKind::UnexpectedEof, format!("Chunk {:?} of '{}' extends past the end of the file", region, name))),
+                    sent => remaining -= sent,
+                }
+            }
+
+            written += region.length;
+        }
+
+        Ok(written)
+    }
+
+    /// Open several database files in parallel, returning them in the same order as `paths`.
+    /// If any of them fails to open, the ones which did are closed again and the first error is returned.
+    pub fn open_many<P: AsRef<Path> + Sync>(paths: &[P]) -> Result<Vec<Self>> {
+        Self::open_many_best_effort(paths)
+            .into_iter()
+            .collect()
+    }
+
+    /// Open several database files in parallel, returning the outcome for each path in the same order as `paths`.
+    /// Unlike `open_many`, one file failing to open doesn't affect the others.
+    pub fn open_many_best_effort<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<Result<Self>> {
+        std::thread::scope(|scope| {
+            let handles = paths.iter()
+                .map(|path| scope.spawn(move || Self::open_path(path.as_ref())))
+                .collect::<Vec<_>>();
+
+            handles.into_iter()
+                .zip(paths)
+                .map(|(handle, path)| {
+                    let db = handle.join()
+                        .unwrap_or_else(|_| Err(Error::other("Thread panicked while opening database")));
+
+                    #[cfg(feature = "tracing")]
+                    if let Err(err) = &db {
+                        tracing::warn!(path = %path.as_ref().display(), error = %err, "Failed to open database");
+                    }
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = path;
+
+                    db
+                })
+                .collect()
+        })
+    }
+}
+
+/// Lets a writer stand in for a backing object where nothing is read back, such as the destination of `Database::snapshot`. Reading fails with `Unsupported`.
+struct WriteOnly<W>(W);
+
+impl<W: Write> Read for WriteOnly<W> {
+    fn read(&mut self, _: &mut [u8]) -> Result<usize> {
+        Err(Error::new(std::io::ErrorKind::Unsupported, "The destination can't be read from"))
+    }
+}
+
+impl<W: Write> Write for WriteOnly<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Seek> Seek for WriteOnly<W> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+/// Cloning the database clones the backing object alongside the in-memory tables, rather than re-parsing it.
+/// This way, changes which haven't been flushed yet are carried over to the copy. Nothing is shared with the original: the copy wraps its own backing object,
+/// and neither the pages opened on the original nor the slices they've borrowed carry over, so writes to either database are never seen by the other.
+impl<Backing, Metadata> Clone for Database<Backing, Metadata> where Backing: Read + Write + Seek + Clone, Metadata: Serialize + DeserializeOwned + Clone {
+    fn clone(&self) -> Self {
+        let db = Self {
+            backing: Arc::new(Mediator::new(self.backing.clone_backing())
+                .with_encryption_of(&self.backing)),
             inode_table_range: self.inode_table_range,
             string_table_range: self.string_table_range,
             history_table_range: self.history_table_range,
+            metadata_range: self.metadata_range,
+            inode_table: self.inode_table.clone(),
+            string_table: self.string_table.clone(),
+            history_table: self.history_table.clone(),
             inode_table_size: self.inode_table_size,
             string_table_size: self.string_table_size,
             history_table_size: self.history_table_size,
-            metadata_range: self.metadata_range,
-            inode_table: self.inode_table,
-            string_table: self.string_table,
-            raw_header: self.raw_header,
-            meta: self.meta,
+            history_limit: self.history_limit,
             borrowed_slices: Arc::new(Mutex::new(vec![])),
+            free_list: self.free_list.clone(),
+            // Hooks and validators can't be cloned, so the copy starts without any
+            hooks: Hooks::default(),
+            page_validator: None,
+            linked_pages: self.linked_pages.clone(),
+            open_pages: HashMap::new(),
+            exclusive_pages: HashMap::new(),
+            page_access_counts: HashMap::new(),
+            page_access_times: HashMap::new(),
+            raw_header: self.raw_header.clone(),
+            base_offset: self.base_offset,
+            salt: self.salt,
+            seal_key_hash: self.seal_key_hash,
+            sync_on_write: self.sync_on_write,
+            page_requests: channel(),
+            // Records logged by the copy would be replayed into the original
+            wal: None,
+            journal: None,
+            max_page_size: self.max_page_size,
+            page_limits: self.page_limits,
+            sorted_index: RefCell::new(None),
+            sorted_index_enabled: self.sorted_index_enabled,
+            agent: None,
+            agent_reservations: vec![],
+            meta: self.meta.clone(),
         };
 
-        // flush the header to keep the new backing object in-sync
-        db.write_header()?;
-
-        Ok(db)
-    }
-
-    /// Gain a sneaky reference to the string table. Useful during parsing or serialisation
-    pub(crate) fn leak_string_table(&self) -> Ref<Vec<String>> {
-        self.string_table.borrow()
-    }
+        db.backing.set_sealed(self.is_sealed());
 
-    /// Gain a sneaky reference to the inode table. Useful during parsing or seralisation
-    pub(crate) fn leak_inode_table(&self) -> HashMap<String, PageDescriptor> {
-        self.inode_table.clone()
+        db
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,95 @@
+use std::io::Read;
+use std::io::Result;
+use std::io::Seek;
+use std::io::Write;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::format::database::Database;
+
+/// The differences between the pages of two databases, as produced by `Database::diff`. All lists are sorted by page name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatabaseDiff {
+    /// Pages present only in the other database
+    pub added: Vec<String>,
+    /// Pages present only in this database
+    pub removed: Vec<String>,
+    /// Pages present in both databases, whose content differs
+    pub modified: Vec<String>,
+    /// Pages present in both databases with identical content
+    pub unchanged: Vec<String>,
+}
+
+/// A single step in transforming one database into another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchOp {
+    /// Remove the named page
+    Delete(String),
+    /// Create the named page with the given content
+    Create(String, Vec<u8>),
+    /// Overwrite the content of the named page
+    Replace(String, Vec<u8>),
+}
+
+impl DatabaseDiff {
+    /// Produce the ordered list of operations transforming the diff's source database into `dst` - the database it was compared against.
+    /// Deletions come first, followed by creations and replacements. The content of each page is read from `dst` as it reads back, so compressed pages are decompressed and encrypted pages decrypted.
+    pub fn to_patch<Backing, Metadata>(&self, dst: &Database<Backing, Metadata>) -> Result<Vec<PatchOp>>
+    where Backing: Read + Write + Seek, Metadata: Serialize + DeserializeOwned + Clone {
+        let pages = dst.leak_inode_table();
+        let content = |name: &String| {
+            let page = pages.get(name)
+                .ok_or(std::io::Error::new(std::io::ErrorKind::NotFound, format!("Page '{}' not found in destination", name)))?;
+
+            let mut content = vec![];
+            dst.pipe_content(page, 0, u64::MAX, &mut content)?;
+            Ok::<_, std::io::Error>(content)
+        };
+
+        let mut patch = self.removed.iter()
+            .map(|name| PatchOp::Delete(name.clone()))
+            .collect::<Vec<_>>();
+
+        for name in self.added.iter() {
+            patch.push(PatchOp::Create(name.clone(), content(name)?));
+        }
+
+        for name in self.modified.iter() {
+            patch.push(PatchOp::Replace(name.clone(), content(name)?));
+        }
+
+        Ok(patch)
+    }
+}
+
+impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write + Seek, Metadata: Serialize + DeserializeOwned + Clone {
+    /// Compare the pages of this database against `other` by name and SHA-256 hash of their content.
+    /// Pages are hashed one chunk at a time, so the content of a page is never held in memory in its entirety.
+    pub fn diff<OtherBacking, OtherMetadata>(&self, other: &Database<OtherBacking, OtherMetadata>) -> Result<DatabaseDiff>
+    where OtherBacking: Read + Write + Seek, OtherMetadata: Serialize + DeserializeOwned + Clone {
+        let pages = self.leak_inode_table();
+        let other_pages = other.leak_inode_table();
+
+        let mut diff = DatabaseDiff::default();
+
+        for (name, page) in pages.iter() {
+            match other_pages.get(name) {
+                None => diff.removed.push(name.clone()),
+                Some(other_page) if self.hash_content(page)? == other.hash_content(other_page)? => diff.unchanged.push(name.clone()),
+                Some(_) => diff.modified.push(name.clone()),
+            }
+        }
+
+        diff.added.extend(other_pages.keys()
+            .filter(|name| !pages.contains_key(*name))
+            .cloned());
+
+        diff.added.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.modified.sort_unstable();
+        diff.unchanged.sort_unstable();
+
+        Ok(diff)
+    }
+}
@@ -0,0 +1,21 @@
+use std::fs::File;
+use std::io::Cursor;
+
+/// Backing objects which can be flushed to stable storage, allowing the database to guarantee its changes survive a crash or power failure.
+pub trait Durable {
+    /// Block until everything written so far has reached stable storage.
+    fn sync(&mut self) -> std::io::Result<()>;
+}
+
+impl Durable for File {
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.sync_all()
+    }
+}
+
+/// In-memory buffers have no stable storage to sync to, so syncing them does nothing.
+impl Durable for Cursor<Vec<u8>> {
+    fn sync(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
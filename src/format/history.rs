@@ -0,0 +1,104 @@
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result;
+use std::io::Write;
+
+use crate::format::Array;
+
+/// The number of entries a database's history table is capped at unless changed with `Database::set_history_limit`. Every write adds an entry, so without a cap the table would grow for as long as the database is used.
+pub const DEFAULT_HISTORY_LIMIT: usize = 0x400;
+
+/// The kind of change a history entry records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryOp {
+    Create,
+    Write,
+    Delete,
+    Rename,
+}
+
+impl HistoryOp {
+    fn id(self) -> u8 {
+        match self {
+            Self::Create => 0x00,
+            Self::Write => 0x01,
+            Self::Delete => 0x02,
+            Self::Rename => 0x03,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self> {
+        Ok(match id {
+            0x00 => Self::Create,
+            0x01 => Self::Write,
+            0x02 => Self::Delete,
+            0x03 => Self::Rename,
+            id => return Err(std::io::Error::new(ErrorKind::InvalidData, format!("Unknown history operation {:#x}", id))),
+        })
+    }
+}
+
+/// A single record of the history table, describing a change to a page and the chunks the page occupied afterwards.
+/// Unlike journal entries, history entries don't hold content, so they're cheap enough to be kept for every change.
+///
+/// |key|length/type|meaning|
+/// |---|-----------|-------|
+/// |timestamp|`u64`|When the change took place, in seconds since the Unix epoch|
+/// |page_name_index|`u64`|An index into the string table. Renames are recorded under the new name|
+/// |op|`u8` + `[u8; 7]`|0: create, 1: write, 2: delete, 3: rename, followed by padding|
+/// |chunks_len|`u64`|The number of chunks in the snapshot|
+/// |[chunks_snapshot]|(`u64` + `u64`) * _chunks_len_|The page's chunks following the change, as length;offset pairs. For deletions, the chunks the page held|
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub op: HistoryOp,
+    pub timestamp: u64,
+    pub page_name_index: u64,
+    pub chunks_snapshot: Vec<Array>,
+}
+
+impl HistoryEntry {
+    /// Serialise the entry into `w`. Chunk offsets are written relative to `base_offset`, like those of the inode table.
+    pub fn write<W: Write>(&self, w: &mut W, base_offset: u64) -> Result<()> {
+        w.write_all(&self.timestamp.to_le_bytes())?;
+        w.write_all(&self.page_name_index.to_le_bytes())?;
+        w.write_all(&[self.op.id(), 0, 0, 0, 0, 0, 0, 0])?;
+        w.write_all(&(self.chunks_snapshot.len() as u64).to_le_bytes())?;
+
+        for chunk in self.chunks_snapshot.iter() {
+            w.write_all(&chunk.length.to_le_bytes())?;
+            w.write_all(&(chunk.offset - base_offset).to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Parse the next entry from `r`, adding `base_offset` to each chunk's offset.
+    pub fn read<R: Read>(r: &mut R, base_offset: u64) -> Result<Self> {
+        let mut header = [0u8; 8 + 8 + 8 + 8];
+        r.read_exact(&mut header)?;
+
+        let u64_at = |i: usize| header[i..i + 8].try_into()
+            .map(u64::from_le_bytes)
+            .map_err(std::io::Error::other);
+
+        let chunks_snapshot = (0..u64_at(24)?)
+            .map(|_| {
+                let mut chunk = [0u8; 8 + 8];
+                r.read_exact(&mut chunk)?;
+
+                Ok(Array {
+                    length: u64::from_le_bytes(chunk[0..8].try_into().map_err(std::io::Error::other)?),
+                    offset: base_offset + u64::from_le_bytes(chunk[8..16].try_into().map_err(std::io::Error::other)?),
+                    nonce: None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            op: HistoryOp::from_id(header[16])?,
+            timestamp: u64_at(0)?,
+            page_name_index: u64_at(8)?,
+            chunks_snapshot,
+        })
+    }
+}
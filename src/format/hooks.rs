@@ -0,0 +1,59 @@
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+
+use crate::access::Access;
+use crate::format::database::WatchEvent;
+use crate::page::PageDescriptor;
+
+pub(crate) type CreateHook = Box<dyn Fn(&str, &PageDescriptor) + Send>;
+pub(crate) type WriteHook = Box<dyn Fn(&str, u64) + Send>;
+pub(crate) type DeleteHook = Box<dyn Fn(&str) + Send>;
+pub(crate) type AclChangeHook = Box<dyn Fn(&str, &[Access]) + Send>;
+/// A prefix, and the channel notified of changes to pages whose names start with it
+type Watcher = (String, Sender<(String, WatchEvent)>);
+
+/// The callbacks registered on a database. Each is invoked synchronously, in order of registration, once the triggering operation has succeeded.
+#[derive(Default)]
+pub(crate) struct Hooks {
+    pub(crate) create: Vec<CreateHook>,
+    pub(crate) write: Vec<WriteHook>,
+    pub(crate) delete: Vec<DeleteHook>,
+    pub(crate) acl_change: Vec<AclChangeHook>,
+    /// Channels notified of changes to pages whose names start with the associated prefix
+    watchers: Mutex<Vec<Watcher>>,
+}
+
+impl Hooks {
+    pub(crate) fn watch(&mut self, prefix: &str, sender: Sender<(String, WatchEvent)>) {
+        self.watchers.get_mut()
+            .unwrap_or_else(|err| err.into_inner())
+            .push((prefix.to_owned(), sender));
+    }
+
+    /// Send `event` to every watcher whose prefix `name` matches, dropping those whose receiver has gone away.
+    fn notify(&self, name: &str, event: WatchEvent) {
+        self.watchers.lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .retain(|(prefix, sender)| !name.starts_with(prefix.as_str()) || sender.send((name.to_owned(), event)).is_ok());
+    }
+
+    pub(crate) fn created(&self, name: &str, page: &PageDescriptor) {
+        self.create.iter().for_each(|hook| hook(name, page));
+        self.notify(name, WatchEvent::Created);
+    }
+
+    pub(crate) fn written(&self, name: &str, bytes: u64) {
+        self.write.iter().for_each(|hook| hook(name, bytes));
+        self.notify(name, WatchEvent::Modified);
+    }
+
+    pub(crate) fn deleted(&self, name: &str) {
+        self.delete.iter().for_each(|hook| hook(name));
+        self.notify(name, WatchEvent::Deleted);
+    }
+
+    pub(crate) fn acl_changed(&self, name: &str, acl: &[Access]) {
+        self.acl_change.iter().for_each(|hook| hook(name, acl));
+        self.notify(name, WatchEvent::ACLChanged);
+    }
+}
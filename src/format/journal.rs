@@ -0,0 +1,158 @@
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+
+use crate::access::Access;
+
+/// A single record of the write-ahead journal, describing one operation on a page.
+/// Records store the page's content before and after the operation, which allows them to be replayed in either direction.
+///
+/// |key|length/type|meaning|
+/// |---|-----------|-------|
+/// |timestamp|`u64`|When the operation took place, in nanoseconds since the Unix epoch|
+/// |page_name_idx|`u64`|An index into the string table|
+/// |operation|`u8`|One of `JournalEntry::CREATE`, `WRITE` or `DELETE`|
+/// |access_control_list|`u16` + [`u8` + `u64` + [`u8`]]|The number of entries, followed by each entry's permission bits and length-prefixed entity|
+/// |before_data|`u8` + (`u64` + [`u8`])|A presence flag, followed by a length-prefixed byte array if present|
+/// |after_data|`u8` + (`u64` + [`u8`])|A presence flag, followed by a length-prefixed byte array if present|
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub timestamp: u64,
+    pub page_name_idx: u64,
+    pub operation: u8,
+    /// The page's access control list prior to the operation. Empty for `CREATE`.
+    pub access_control_list: Vec<Access>,
+    /// The page's content prior to the operation. Absent for `CREATE`.
+    pub before_data: Option<Vec<u8>>,
+    /// The page's content following the operation. Absent for `DELETE`.
+    pub after_data: Option<Vec<u8>>,
+}
+
+impl JournalEntry {
+    pub const CREATE: u8 = 0x00;
+    pub const WRITE: u8 = 0x01;
+    pub const DELETE: u8 = 0x02;
+
+    /// Serialise the record into `w`.
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        w.write_all(&self.timestamp.to_le_bytes())?;
+        w.write_all(&self.page_name_idx.to_le_bytes())?;
+        w.write_all(&[self.operation])?;
+
+        w.write_all(&(self.access_control_list.len() as u16).to_le_bytes())?;
+        for access in self.access_control_list.iter() {
+            w.write_all(&[access.bits()])?;
+            w.write_all(&(access.entity().len() as u64).to_le_bytes())?;
+            w.write_all(access.entity().as_bytes())?;
+        }
+
+        for data in [&self.before_data, &self.after_data] {
+            match data {
+                Some(data) => {
+                    w.write_all(&[0x01])?;
+                    w.write_all(&(data.len() as u64).to_le_bytes())?;
+                    w.write_all(data)?;
+                },
+                None => w.write_all(&[0x00])?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse the next record from `r`. Yields `None` if `r` is exhausted before the record begins.
+    /// Byte arrays are only read as far as `r` reaches, so a corrupted length can't cause more memory to be allocated than `r` holds.
+    pub fn read<R: Read>(r: &mut R) -> Result<Option<Self>> {
+        let mut timestamp = [0u8; 8];
+        match r.read_exact(&mut timestamp) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            res => res?
+        };
+
+        let mut header = [0u8; 8 + 1 + 2];
+        r.read_exact(&mut header)?;
+
+        let access_control_list = (0..u16::from_le_bytes([header[9], header[10]]))
+            .map(|_| {
+                let mut bits = [0u8; 1];
+                r.read_exact(&mut bits)?;
+
+                let entity = String::from_utf8(read_prefixed(r)?)
+                    .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+
+                Ok(Access::from_bits(entity, bits[0]))
+            })
+            .collect::<Result<Vec<Access>>>()?;
+
+        let mut read_data = || -> Result<Option<Vec<u8>>> {
+            let mut present = [0u8; 1];
+            r.read_exact(&mut present)?;
+
+            if present[0] == 0x00 {
+                return Ok(None);
+            }
+
+            read_prefixed(r).map(Some)
+        };
+
+        Ok(Some(Self {
+            timestamp: u64::from_le_bytes(timestamp),
+            page_name_idx: u64::from_le_bytes(header[0..8].try_into().map_err(std::io::Error::other)?),
+            operation: header[8],
+            access_control_list,
+            before_data: read_data()?,
+            after_data: read_data()?,
+        }))
+    }
+}
+
+/// Read a length-prefixed (`u64`) byte array, failing with `UnexpectedEof` if `r` ends before it does.
+fn read_prefixed<R: Read>(r: &mut R) -> Result<Vec<u8>> {
+    let mut len = [0u8; 8];
+    r.read_exact(&mut len)?;
+
+    let len = u64::from_le_bytes(len);
+    let mut data = vec![];
+    r.take(len).read_to_end(&mut data)?;
+
+    if data.len() as u64 != len {
+        return Err(std::io::Error::new(ErrorKind::UnexpectedEof, "The journal ends mid-record"));
+    }
+
+    Ok(data)
+}
+
+/// The file journal records are appended to, shared between a database and the pages it hands out. See `Database::enable_journal`.
+pub(crate) struct Journal {
+    file: File,
+}
+
+impl Journal {
+    /// Open the journal at `path`, creating it if it doesn't exist. Existing records are kept.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        Ok(Self { file })
+    }
+
+    /// Append `entry` to the end of the journal, and wait for it to reach stable storage.
+    pub fn append(&mut self, entry: &JournalEntry) -> Result<()> {
+        let mut buffer = vec![];
+        entry.write(&mut buffer)?;
+
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&buffer)?;
+        self.file.sync_data()
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::format::database::FLAG_METADATA_FORMAT;
+
+/// How the database's metadata object is serialised. Each format other than `Ron` requires its feature flag.
+/// The format is recorded in the header flags, so databases written before it could be chosen are read as RON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetadataFormat {
+    /// Rusty Object Notation, as metadata has always been written
+    #[default]
+    Ron,
+    /// JSON. Requires the `json` feature.
+    Json,
+    /// bincode, the most compact, though it can't be read without knowing the metadata's type. Requires the `bincode` feature.
+    Bincode,
+}
+
+impl MetadataFormat {
+    /// The format's discriminant as stored in the header flags.
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::Ron => 0,
+            Self::Json => 1,
+            Self::Bincode => 2,
+        }
+    }
+
+    /// Reconstruct a format from its discriminant.
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(Self::Ron),
+            1 => Ok(Self::Json),
+            2 => Ok(Self::Bincode),
+            id => Err(Error::new(ErrorKind::InvalidData, format!("Unrecognised metadata format {}", id))),
+        }
+    }
+
+    /// The format recorded in the header flags `flags`.
+    pub fn from_flags(flags: u64) -> Result<Self> {
+        Self::from_id(((flags & FLAG_METADATA_FORMAT) >> FLAG_METADATA_FORMAT.trailing_zeros()) as u8)
+    }
+
+    /// `flags` with this format recorded in place of the one they held.
+    pub fn to_flags(&self, flags: u64) -> u64 {
+        flags & !FLAG_METADATA_FORMAT | (self.id() as u64) << FLAG_METADATA_FORMAT.trailing_zeros()
+    }
+
+    /// Serialise `meta` in this format.
+    pub fn serialise<Metadata: Serialize>(&self, meta: &Metadata) -> Result<Vec<u8>> {
+        match self {
+            Self::Ron => ron::ser::to_string(meta)
+                .map(String::into_bytes)
+                .map_err(Error::other),
+            #[cfg(feature = "json")]
+            Self::Json => serde_json::to_vec(meta)
+                .map_err(Error::other),
+            #[cfg(feature = "bincode")]
+            Self::Bincode => bincode::serialize(meta)
+                .map_err(Error::other),
+            #[allow(unreachable_patterns)]
+            format => Err(format.unsupported()),
+        }
+    }
+
+    /// Deserialise metadata which was serialised in this format.
+    pub fn deserialise<Metadata: DeserializeOwned>(&self, data: &[u8]) -> Result<Metadata> {
+        match self {
+            Self::Ron => ron::de::from_bytes(data)
+                .map_err(Error::other),
+            #[cfg(feature = "json")]
+            Self::Json => serde_json::from_slice(data)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err)),
+            #[cfg(feature = "bincode")]
+            Self::Bincode => bincode::deserialize(data)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err)),
+            #[allow(unreachable_patterns)]
+            format => Err(format.unsupported()),
+        }
+    }
+
+    #[allow(dead_code)]
+    fn unsupported(&self) -> Error {
+        Error::new(ErrorKind::Unsupported, format!("{:?} metadata isn't enabled in this build", self))
+    }
+}
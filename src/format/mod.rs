@@ -1,73 +1,56 @@
 pub mod parse;
 pub mod serialise;
 pub mod database;
+pub mod diff;
+pub mod backup;
+pub mod journal;
+pub mod wal;
+pub mod history;
+pub mod transaction;
+pub mod schema;
+pub mod compression;
+pub mod metadata;
+pub mod recovery;
+mod hooks;
+mod checksum;
 mod array;
+mod truncatable;
+mod durable;
+
+pub use array::Array;
+pub use array::TAG_LENGTH;
+pub use truncatable::Truncatable;
+pub use durable::Durable;
 
-use std::io::Error;
 use std::io::Cursor;
 use std::io::Result;
-use std::io::Read;
 
-pub use parse::*;
 use serde::{Serialize, de::DeserializeOwned};
-pub use serialise::*;
-use crate::database::Database;
-use crate::format::array::round;
+use crate::access::Access;
+use crate::format::database::Database;
+
+/// Construct an empty database in memory using the default metadata. It holds only the empty root page `/`, to which everyone (`*`) has full access.
+/// Use `Database::change_buffer` to move it into a permanent home.
+pub fn blank<Meta>() -> Result<Database<Cursor<Vec<u8>>, Meta>> where Meta: Serialize + DeserializeOwned + Clone + Default {
+    blank_with(Meta::default())
+}
+
+/// Construct an empty database in memory like `blank`, with the given metadata.
+pub fn blank_with<Meta>(meta: Meta) -> Result<Database<Cursor<Vec<u8>>, Meta>> where Meta: Serialize + DeserializeOwned + Clone {
+    with_root(Database::create(Cursor::new(vec![]), meta)?)
+}
+
+/// Construct an empty database in memory like `blank`, whose page content is encrypted under `key`. See `Database::create_encrypted`.
+#[cfg(feature = "encryption")]
+pub fn blank_encrypted<Meta>(key: [u8; 32]) -> Result<Database<Cursor<Vec<u8>>, Meta>> where Meta: Serialize + DeserializeOwned + Clone + Default {
+    with_root(Database::create_encrypted(Cursor::new(vec![]), key, Meta::default())?)
+}
 
+/// Add the empty root page `/` to a freshly created database, to which everyone (`*`) has full access.
+fn with_root<Meta>(mut db: Database<Cursor<Vec<u8>>, Meta>) -> Result<Database<Cursor<Vec<u8>>, Meta>> where Meta: Serialize + DeserializeOwned + Clone {
+    db.write_page_content("/", &[])?;
+    db.set_access_control_list("/", vec![Access::ReadWriteExecute("*".to_owned())])?;
+    db.write_header()?;
 
-pub fn blank<Meta>() -> Result<Database<Cursor<Vec<u8>>>> where Meta: Serialize + DeserializeOwned + Clone + Default {
-    let metadata = Meta::default();
-    let meta = ron::ser::to_string(&metadata)
-        .map_err(Error::other)?
-        .into_bytes();
-        
-    let data_offset = round((0x50u64 + meta.len() as u64)
-        .max(0x80), 0x10);
-    
-    let mut header: Cursor<Vec<u8>> = Cursor::new(vec![
-        &b"FSDB"[..], &u32::to_le_bytes(0x01)[..], &u64::to_le_bytes(0x00)[..],
-        &u64::to_le_bytes(0x01)[..], &u64::to_le_bytes(data_offset)[..], // INode Table
-        &u64::to_le_bytes(0x02)[..], &u64::to_le_bytes(data_offset + 0x100)[..], // String Table
-        &u64::to_le_bytes(0x01)[..], &u64::to_le_bytes(data_offset + 0x200)[..], // History Table
-        &u64::to_le_bytes(meta.len() as u64)[..], &u64::to_le_bytes(0x50)[..],
-        &meta[..]
-    ]
-        .into_iter()
-        .flatten()
-        .cloned()
-        .collect()
-    );
-    
-    let mut raw_header = vec![0u8; 0x50];
-    header.read_exact(&mut raw_header)?;
-    
-    todo!()
-    
-//     Ok(crate::Database {
-//         raw_header: raw_header.to_vec(),
-//         backing: Rc::new(RefCell::new(header)),
-//         inode_table_range: crate::Array { length: 1, offset: data_offset },
-//         string_table_range: crate::Array { length: 2, offset: data_offset + 0x100 },
-//         history_table_range: crate::Array { length: 1, offset: data_offset + 0x200 },
-//         metadata_range: crate::Array { length: meta.len() as u64, offset: 0x50 },
-//         
-//         inode_table_size: 0x20, // this value is found by ... just measuring... not elegant, but simpler/faster than computing it.
-//         string_table_size: 0x12, // this value is also looked up.
-//         history_table_size: 0, // history tab not defined yet
-//         
-//         borrowed_slices: Arc::new(Mutex::new(vec![])),
-//         
-//         inode_table: vec![("/".to_string(), crate::PageDescriptor {
-//             name: "/".to_string(),
-//             access_control_list: vec![crate::Access::ReadWriteExecute("*".to_string())],
-//             modified: SystemTime::now(),
-//             created: SystemTime::now(),
-//             inodes: vec![]
-//         })]
-//             .into_iter()
-//             .collect(),
-//         // Upon serialisation, the missing strings will be inserted into the string table, but for completeness' sake, include them here.
-//         string_table: RefCell::new(vec!["/".to_string(), "*".to_string()]),
-//         meta: metadata
-//     })
-} 
+    Ok(db)
+}
@@ -0,0 +1,250 @@
+use std::io::Read;
+use std::io::Result;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::format::database::Database;
+use crate::format::database::FLAG_CHECKSUM;
+use crate::format::database::FLAG_COMPRESSION;
+use crate::format::database::FLAG_CONTENT_LENGTH;
+use crate::format::database::FLAG_CRC;
+use crate::format::database::FLAG_NONCES;
+use crate::format::database::FLAG_TIMESTAMPS;
+use crate::format::database::FLAG_USER_DATA;
+
+/// Find the offsets of every FSDB magic number in `reader` which lies on a 0x10-byte boundary, reading it 64KiB at a time.
+pub(crate) fn scan_reader_for_magic<R: Read + ?Sized>(reader: &mut R) -> Result<Vec<u64>> {
+    let mut offsets = vec![];
+    let mut offset = 0u64;
+    // A multiple of the boundary size, so the magic never straddles two reads
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match reader.read(&mut buf[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+
+        offsets.extend(buf[..filled]
+            .chunks(0x10)
+            .enumerate()
+            .filter(|(_, block)| block.starts_with(b"FSDB"))
+            .map(|(i, _)| offset + i as u64 * 0x10));
+
+        offset += filled as u64;
+
+        if filled < buf.len() {
+            break;
+        }
+    }
+
+    Ok(offsets)
+}
+
+/// A bounds-checked cursor over a byte slice. Every read returns `None` instead of panicking once the data runs out.
+#[derive(Clone)]
+struct Scanner<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn at(data: &'a [u8], pos: u64) -> Self {
+        Self { data, pos: usize::try_from(pos).unwrap_or(usize::MAX) }
+    }
+
+    fn take(&mut self, len: u64) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(usize::try_from(len).ok()?)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8)?.try_into().ok().map(u64::from_le_bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)?.try_into().ok().map(u16::from_le_bytes)
+    }
+}
+
+/// A page salvaged by `emergency_dump`.
+struct SalvagedPage {
+    name: String,
+    content: Vec<u8>,
+}
+
+/// Parse as many strings of the string table as possible. Strings are prefixed with a `u16` length, but older files were written with a `u64` one, so both are tried.
+fn salvage_strings(data: &[u8], offset: u64, count: u64) -> Vec<String> {
+    let parse = |wide: bool| {
+        let mut scanner = Scanner::at(data, offset);
+        let mut strings = vec![];
+
+        while (strings.len() as u64) < count {
+            let len = match wide {
+                true => scanner.u64(),
+                false => scanner.u16().map(u64::from),
+            };
+
+            match len.and_then(|len| scanner.take(len)).and_then(|bytes| String::from_utf8(bytes.to_vec()).ok()) {
+                Some(string) => strings.push(string),
+                None => break,
+            }
+        }
+
+        strings
+    };
+
+    let (wide, narrow) = (parse(true), parse(false));
+    match wide.len() >= narrow.len() {
+        true => wide,
+        false => narrow,
+    }
+}
+
+/// Parse as many inode table entries as possible, stopping at the first one which doesn't fit in `data`, and read their raw content.
+fn salvage_pages(data: &[u8], base: u64) -> Vec<SalvagedPage> {
+    let mut header = Scanner::at(data, base.saturating_add(8));
+    let Some(flags) = header.u64() else { return vec![]; };
+    let ranges = (0..4)
+        .map(|_| (header.u64().unwrap_or_default(), header.u64().unwrap_or_default()))
+        .collect::<Vec<_>>();
+
+    let (inode_count, inode_offset) = ranges[0];
+    let (string_count, string_offset) = ranges[1];
+
+    let strings = salvage_strings(data, base.saturating_add(string_offset), string_count);
+
+    let mut scanner = Scanner::at(data, base.saturating_add(inode_offset));
+    let mut pages = vec![];
+
+    for index in 0..inode_count {
+        let entry = (|| {
+            let mut scanner = scanner.clone();
+
+            let name = scanner.u64()?;
+            // With an intact string table, every name must refer to one of its strings
+            if strings.len() as u64 == string_count && name >= string_count { return None; }
+
+            // (u8 + u64) * acl_len, aligning the entry so far to 0x10
+            let acl_len = scanner.u16()? as u64;
+            scanner.take((1 + 8) * acl_len + (0x10 - (2 + (1 + 8) * acl_len) % 0x10) % 0x10)?;
+
+            let chunk_count = scanner.u64()?;
+            let chunks = (0..chunk_count.min(data.len() as u64))
+                .map(|_| Some((scanner.u64()?, scanner.u64()?)))
+                .collect::<Option<Vec<_>>>()?;
+
+            if chunks.iter().any(|(length, offset)| base.checked_add(*offset).and_then(|i| i.checked_add(*length)).is_none_or(|end| end > data.len() as u64)) {
+                return None;
+            }
+
+            let content_length = match flags & FLAG_CONTENT_LENGTH != 0 {
+                true => match scanner.u64()? {
+                    len if len > chunks.iter().map(|(length, _)| *length).sum() => return None,
+                    len => Some(len),
+                },
+                false => None,
+            };
+            if flags & FLAG_COMPRESSION != 0 {
+                let compression = scanner.take(8)?;
+                if compression[0] > 3 || compression[1..4] != [0; 3] { return None; }
+            }
+            if flags & FLAG_CHECKSUM != 0 && scanner.take(1 + 32)?[0] > 1 {
+                return None;
+            }
+            if flags & FLAG_USER_DATA != 0 {
+                let len = scanner.u64()?;
+                scanner.take(len.checked_add((0x10 - len % 0x10) % 0x10)?)?;
+            }
+            if flags & FLAG_NONCES != 0 {
+                scanner.take(chunk_count.checked_mul(1 + 12 + 3)?)?;
+            }
+            if flags & FLAG_TIMESTAMPS != 0 {
+                scanner.take(8 + 8)?;
+            }
+            if flags & FLAG_CRC != 0 {
+                scanner.take(4)?;
+            }
+
+            Some((name, chunks, content_length, scanner))
+        })();
+
+        let Some((name, chunks, content_length, next)) = entry else { break; };
+        scanner = next;
+
+        let mut content = chunks.into_iter()
+            .flat_map(|(length, offset)| {
+                let from = usize::try_from(base.saturating_add(offset)).unwrap_or(usize::MAX).min(data.len());
+                let to = from.saturating_add(usize::try_from(length).unwrap_or(usize::MAX)).min(data.len());
+                data[from..to].iter().copied()
+            })
+            .collect::<Vec<_>>();
+        content.truncate(usize::try_from(content_length.unwrap_or(u64::MAX)).unwrap_or(usize::MAX));
+
+        pages.push(SalvagedPage {
+            name: strings.get(name as usize)
+                .cloned()
+                .unwrap_or_else(|| format!("page-{}", index)),
+            content,
+        });
+    }
+
+    pages
+}
+
+/// Turn a page name (typically a path) into something usable as a single file name.
+fn file_name(name: &str) -> String {
+    let name = name.chars()
+        .map(|c| match c.is_alphanumeric() || matches!(c, '.' | '-' | '_') {
+            true => c,
+            false => '_',
+        })
+        .collect::<String>();
+
+    match name.trim_matches('.').is_empty() {
+        true => format!("_{}", name),
+        false => name,
+    }
+}
+
+impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write + Seek, Metadata: Serialize + DeserializeOwned + Clone {
+    /// Salvage whatever pages can be found in `backing` without opening it as a database, writing each one's raw (possibly compressed) content to `output_dir/{page_name}.bin`.
+    /// Every FSDB header found by scanning for the magic number is tried, and every inode table entry which can be parsed is dumped, even if the rest of the database is corrupted.
+    /// Pages of databases other than the first found are prefixed with their header's offset. Returns the number of files written.
+    /// > **Note**: This is a forensic last resort. Salvaged content isn't verified, and may be truncated or contain garbage.
+    pub fn emergency_dump(mut backing: Backing, output_dir: &Path) -> Result<usize> {
+        let mut data = vec![];
+        backing.seek(SeekFrom::Start(0))?;
+        backing.read_to_end(&mut data)?;
+
+        let headers = scan_reader_for_magic(&mut &data[..])?;
+
+        std::fs::create_dir_all(output_dir)?;
+
+        let mut written = 0;
+        for (i, base) in headers.iter().enumerate() {
+            for page in salvage_pages(&data, *base) {
+                let name = match i {
+                    0 => file_name(&page.name),
+                    _ => format!("{:x}-{}", base, file_name(&page.name)),
+                };
+
+                if std::fs::write(output_dir.join(format!("{}.bin", name)), &page.content).is_ok() {
+                    written += 1;
+                }
+            }
+        }
+
+        Ok(written)
+    }
+}
@@ -0,0 +1,88 @@
+use std::io::Error;
+use std::io::Read;
+use std::io::Result;
+use std::io::Seek;
+use std::io::Write;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use serde::Deserialize;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::access::Access;
+use crate::format::database::Database;
+
+/// The structure of a single page, as listed by `Database::export_schema`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageSchema {
+    /// The name of the page
+    pub name: String,
+    /// The page's access control list
+    pub access_control_list: Vec<Access>,
+    /// The number of chunks the page's content is spread across
+    pub chunks: usize,
+    /// The length of the page's content in bytes
+    pub size: u64,
+    /// When the page was created, in seconds since the Unix epoch
+    pub created: u64,
+    /// When the page was last modified, in seconds since the Unix epoch
+    pub modified: u64,
+    /// The page's user data, if it has any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub user_data: Vec<u8>,
+}
+
+impl<Backing, Metadata> Database<Backing, Metadata> where Backing: Read + Write + Seek, Metadata: Serialize + DeserializeOwned + Clone {
+    /// Describe the structure of the database as a RON list of `PageSchema`s, sorted by page name. Page content isn't included.
+    pub fn export_schema(&self) -> Result<String> {
+        let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH)
+            .map(|i| i.as_secs())
+            .unwrap_or(0);
+
+        let mut pages = self.leak_inode_table()
+            .into_values()
+            .map(|page| PageSchema {
+                chunks: page.inodes.len(),
+                size: page.inodes.iter().map(|i| i.length).sum(),
+                created: secs(page.created),
+                modified: secs(page.modified),
+                name: page.name,
+                access_control_list: page.access_control_list,
+                user_data: page.user_data,
+            })
+            .collect::<Vec<_>>();
+        pages.sort_unstable_by(|i, j| Ord::cmp(&i.name, &j.name));
+
+        ron::ser::to_string_pretty(&pages, ron::ser::PrettyConfig::default())
+            .map_err(Error::other)
+    }
+
+    /// The inverse of `export_schema`: create every page listed in `schema` which doesn't exist yet as an empty page, and bring the access control lists of existing pages in line with it.
+    /// Pages absent from the schema are left alone, as are sizes, timestamps and user data. Returns the number of pages created or updated.
+    pub fn apply_schema(&mut self, schema: &str) -> Result<usize> {
+        self.ensure_unsealed()?;
+
+        let schema = ron::de::from_str::<Vec<PageSchema>>(schema)
+            .map_err(Error::other)?;
+
+        let pages = self.leak_inode_table();
+        let mut changed = 0;
+
+        for page in schema {
+            if !pages.contains_key(&page.name) {
+                self.write_page_content(&page.name, &[])?;
+                self.set_access_control_list(&page.name, page.access_control_list)?;
+                changed += 1;
+            } else if self.set_access_control_list(&page.name, page.access_control_list)? {
+                changed += 1;
+            }
+        }
+
+        if changed > 0 {
+            self.write_header()?;
+        }
+
+        Ok(changed)
+    }
+}
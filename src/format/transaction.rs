@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result;
+use std::io::Seek;
+use std::io::Write;
+use std::time::SystemTime;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::format::compression::CompressionAlgorithm;
+use crate::format::database::Database;
+use crate::format::database::PageWrite;
+use crate::format::history::HistoryOp;
+use crate::page::PageDescriptor;
+
+/// A set of changes to a database's pages which are applied all at once, or not at all. Started with `Database::begin_transaction`.
+/// Changes are made to a copy of the inode table, and written content is staged in memory, so the backing object isn't touched until the transaction is committed.
+/// Dropping a transaction without committing it rolls it back.
+pub struct Transaction<'a, Backing, Metadata> where Backing: Read + Write + Seek, Metadata: Serialize + DeserializeOwned + Clone {
+    db: &'a mut Database<Backing, Metadata>,
+    /// The inode table as it will be once the transaction is committed
+    inode_table: HashMap<String, PageDescriptor>,
+    /// Content replacing that of pages, at most one per page
+    writes: Vec<PageWrite>,
+    /// The changes made, in order, to be recorded in the history once committed
+    changes: Vec<(HistoryOp, String)>,
+}
+
+impl<'a, Backing, Metadata> Transaction<'a, Backing, Metadata> where Backing: Read + Write + Seek, Metadata: Serialize + DeserializeOwned + Clone {
+    pub(crate) fn new(db: &'a mut Database<Backing, Metadata>, inode_table: HashMap<String, PageDescriptor>) -> Self {
+        Self {
+            db,
+            inode_table,
+            writes: vec![],
+            changes: vec![],
+        }
+    }
+
+    fn not_found(name: &str) -> Error {
+        Error::new(ErrorKind::NotFound, format!("No page named '{}'", name))
+    }
+
+    fn already_exists(name: &str) -> Error {
+        Error::new(ErrorKind::AlreadyExists, format!("A page named '{}' already exists", name))
+    }
+
+    /// Whether a page named `name` exists, taking the changes made so far into account.
+    pub fn contains_page(&self, name: &str) -> bool {
+        self.inode_table.contains_key(name)
+    }
+
+    /// Create an empty page. Fails with `AlreadyExists` if a page of the same name exists.
+    pub fn create_page(&mut self, name: &str) -> Result<()> {
+        if self.inode_table.contains_key(name) {
+            return Err(Self::already_exists(name));
+        }
+
+        self.inode_table.insert(name.to_owned(), PageDescriptor {
+            name: name.to_owned(),
+            access_control_list: vec![],
+            modified: SystemTime::now(),
+            created: SystemTime::now(),
+            inodes: vec![],
+            content_length: 0,
+            link_count: 1,
+            compression: CompressionAlgorithm::None,
+            checksum: None,
+            user_data: vec![],
+        });
+        self.changes.push((HistoryOp::Create, name.to_owned()));
+
+        Ok(())
+    }
+
+    /// Delete a page, discarding any content written to it during the transaction.
+    pub fn delete_page(&mut self, name: &str) -> Result<()> {
+        self.inode_table.remove(name)
+            .ok_or(Self::not_found(name))?;
+
+        self.writes.retain(|write| write.name != name);
+        self.changes.push((HistoryOp::Delete, name.to_owned()));
+
+        Ok(())
+    }
+
+    /// Rename the page `old` to `new`, carrying over any content written to it during the transaction.
+    /// Fails with `NotFound` if there's no page named `old`, and `AlreadyExists` if `new` is taken.
+    pub fn rename_page(&mut self, old: &str, new: &str) -> Result<()> {
+        if !self.inode_table.contains_key(old) {
+            return Err(Self::not_found(old));
+        }
+
+        if old == new {
+            return Ok(());
+        }
+
+        if self.inode_table.contains_key(new) {
+            return Err(Self::already_exists(new));
+        }
+
+        let page = self.inode_table.remove(old)
+            .ok_or(Self::not_found(old))?;
+
+        self.inode_table.insert(new.to_owned(), PageDescriptor {
+            name: new.to_owned(),
+            modified: SystemTime::now(),
+            ..page
+        });
+
+        self.writes.iter_mut()
+            .filter(|write| write.name == old)
+            .for_each(|write| write.name = new.to_owned());
+        self.changes.push((HistoryOp::Rename, new.to_owned()));
+
+        Ok(())
+    }
+
+    /// Replace the content of an existing page, superseding anything written to it earlier in the transaction.
+    pub fn write(&mut self, name: &str, content: &[u8]) -> Result<()> {
+        if !self.inode_table.contains_key(name) {
+            return Err(Self::not_found(name));
+        }
+
+        self.writes.retain(|write| write.name != name);
+        self.writes.push(PageWrite {
+            name: name.to_owned(),
+            content: content.to_vec(),
+        });
+        self.changes.push((HistoryOp::Write, name.to_owned()));
+
+        Ok(())
+    }
+
+    /// Apply the transaction: write the staged content into newly allocated chunks, then apply the changed pages to the database's inode table and write the header.
+    /// The chunks the changes replace are freed. Fails with `Busy` if any of the changed pages is open. If the content can't be written, the database is left as it was before the transaction.
+    pub fn commit(self) -> Result<()> {
+        self.db.commit_transaction(self.inode_table, self.writes, self.changes)
+    }
+
+    /// Discard the transaction. The database is left as it was before the transaction. Equivalent to dropping it.
+    pub fn rollback(self) {}
+}
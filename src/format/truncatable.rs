@@ -0,0 +1,21 @@
+use std::fs::File;
+use std::io::Cursor;
+
+/// Backing objects whose length can be set explicitly, allowing the database to give unused space back.
+pub trait Truncatable {
+    /// Truncate or extend the object to exactly `len` bytes. Extending fills the new space with zeros.
+    fn set_len(&mut self, len: u64) -> std::io::Result<()>;
+}
+
+impl Truncatable for File {
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        File::set_len(self, len)
+    }
+}
+
+impl Truncatable for Cursor<Vec<u8>> {
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        self.get_mut().resize(len as usize, 0x00);
+        Ok(())
+    }
+}
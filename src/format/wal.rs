@@ -0,0 +1,200 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufReader;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The kind of change a write-ahead log record describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalOp {
+    /// `data` was written at `offset` of the page's content
+    Write,
+    /// The page's content is to be replaced by `data`, creating the page if it doesn't exist, once a `Commit` record follows. Logged by `Database::two_phase_commit` before the coordinator is asked.
+    Prepare,
+    /// The `Prepare` records preceding it were committed. Names no page.
+    Commit,
+}
+
+impl WalOp {
+    fn id(self) -> u8 {
+        match self {
+            Self::Write => 0x00,
+            Self::Prepare => 0x01,
+            Self::Commit => 0x02,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self> {
+        Ok(match id {
+            0x00 => Self::Write,
+            0x01 => Self::Prepare,
+            0x02 => Self::Commit,
+            id => return Err(std::io::Error::new(ErrorKind::InvalidData, format!("Unknown write-ahead log operation {:#x}", id))),
+        })
+    }
+}
+
+/// A single record of the write-ahead log, describing a write to a page's content. Records are appended before the write reaches the backing object, so that it can be redone if it doesn't.
+/// Unlike journal entries, records name their page directly, as the log outlives changes to the string table.
+///
+/// |key|length/type|meaning|
+/// |---|-----------|-------|
+/// |op|`u8`|0: write, 1: prepare, 2: commit|
+/// |page_name_len|`u64`|The length of the page's name in bytes|
+/// |page_name|[`u8`]|The page's name as UTF-8|
+/// |offset|`u64`|Where in the page's content the data was written|
+/// |data_len|`u64`|The number of bytes written|
+/// |data|[`u8`]|The bytes written|
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalRecord {
+    pub op: WalOp,
+    pub page_name: String,
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+impl WalRecord {
+    /// Serialise the record into `w`.
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        w.write_all(&[self.op.id()])?;
+        w.write_all(&(self.page_name.len() as u64).to_le_bytes())?;
+        w.write_all(self.page_name.as_bytes())?;
+        w.write_all(&self.offset.to_le_bytes())?;
+        w.write_all(&(self.data.len() as u64).to_le_bytes())?;
+        w.write_all(&self.data)?;
+
+        Ok(())
+    }
+
+    /// The number of bytes the record occupies once serialised.
+    pub fn stored_length(&self) -> u64 {
+        1 + 8 + self.page_name.len() as u64 + 8 + 8 + self.data.len() as u64
+    }
+
+    /// Parse the next record from `r`, which holds no more than `remaining` bytes. Yields `None` if `r` is exhausted before the record begins.
+    /// Fails with `UnexpectedEof` if the record claims to be longer than `remaining`, rather than allocating for it.
+    pub fn read<R: Read>(r: &mut R, remaining: u64) -> Result<Option<Self>> {
+        let mut op = [0u8; 1];
+        match r.read_exact(&mut op) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            res => res?
+        };
+
+        let mut len = [0u8; 8];
+        r.read_exact(&mut len)?;
+
+        let mut remaining = remaining.saturating_sub(1 + 8);
+        let page_name_len = Self::bounded(u64::from_le_bytes(len), remaining)?;
+        remaining -= page_name_len;
+
+        let mut page_name = vec![0u8; page_name_len as usize];
+        r.read_exact(&mut page_name)?;
+
+        let mut header = [0u8; 8 + 8];
+        r.read_exact(&mut header)?;
+        remaining = remaining.saturating_sub(header.len() as u64);
+
+        let data_len = Self::bounded(u64::from_le_bytes(header[8..16].try_into().map_err(std::io::Error::other)?), remaining)?;
+        let mut data = vec![0u8; data_len as usize];
+        r.read_exact(&mut data)?;
+
+        Ok(Some(Self {
+            op: WalOp::from_id(op[0])?,
+            page_name: String::from_utf8(page_name)
+                .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?,
+            offset: u64::from_le_bytes(header[0..8].try_into().map_err(std::io::Error::other)?),
+            data,
+        }))
+    }
+
+    /// Fail with `UnexpectedEof` if `len` exceeds the `remaining` bytes of the log.
+    fn bounded(len: u64, remaining: u64) -> Result<u64> {
+        match len <= remaining {
+            true => Ok(len),
+            false => Err(std::io::Error::new(ErrorKind::UnexpectedEof, "Record extends beyond the end of the log")),
+        }
+    }
+}
+
+/// Where the write-ahead log of the database file at `database` is kept: alongside it, with `.wal` appended to its name.
+pub fn wal_path(database: &Path) -> PathBuf {
+    let mut path = OsString::from(database.as_os_str());
+    path.push(".wal");
+
+    PathBuf::from(path)
+}
+
+/// The file write-ahead log records are appended to, shared between a database and the pages it hands out.
+pub(crate) struct Wal {
+    file: File,
+}
+
+impl Wal {
+    /// Open the log at `path`, creating it if it doesn't exist. Existing records are kept.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        Ok(Self { file })
+    }
+
+    /// Append `record` to the end of the log, and wait for it to reach stable storage.
+    pub fn append(&mut self, record: &WalRecord) -> Result<()> {
+        let mut buffer = vec![];
+        record.write(&mut buffer)?;
+
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&buffer)?;
+        self.file.sync_data()
+    }
+
+    /// Every complete record in the log, oldest first. A record cut short, such as by a crash while it was being appended, ends the log, as its write never took place.
+    pub fn records(&mut self) -> Result<Vec<WalRecord>> {
+        let mut remaining = self.file.seek(SeekFrom::End(0))?;
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&mut self.file);
+
+        let mut records = vec![];
+        loop {
+            match WalRecord::read(&mut reader, remaining) {
+                Ok(Some(record)) => {
+                    remaining -= record.stored_length();
+                    records.push(record);
+                },
+                Ok(None) => break,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Discard every record `keep` rejects, rewriting the log with the rest. Does nothing if the log is empty.
+    pub fn retain<Keep: Fn(&WalRecord) -> bool>(&mut self, keep: Keep) -> Result<()> {
+        if self.file.metadata()?.len() == 0 {
+            return Ok(());
+        }
+
+        let mut buffer = vec![];
+        for record in self.records()?.into_iter().filter(|record| keep(record)) {
+            record.write(&mut buffer)?;
+        }
+
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&buffer)?;
+        self.file.sync_data()
+    }
+}
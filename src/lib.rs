@@ -1,14 +1,14 @@
-#![feature(io_error_other)]
 #![feature(io_error_more)]
-#![feature(write_all_vectored)]
 #![feature(seek_stream_len)]
-#![feature(if_let_guard)]
 
 pub mod database;
 pub mod page;
 pub mod access;
 pub mod agent;
 pub mod format;
+pub mod backing;
+#[cfg(feature = "async")]
+pub mod async_db;
 pub mod error;
 pub(crate) mod mediator;
 
@@ -46,70 +46,3178 @@ pub mod test {
     
     #[test]
     pub fn blank() -> Result<()> {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open("/tmp/test.db")?;
-            
-        let mut blank = Database::blank::<Metadata>()?
-            .change_backing(file);
-            
+        let file = tempfile::NamedTempFile::new()?;
+        drop(crate::format::blank::<Metadata>()?
+            .change_buffer(file.reopen()?)?);
+        
+        let db = crate::format::database::Database::<_, Metadata>::open(file.reopen()?)?;
+        assert_eq!(db.list_pages_with_prefix(""), vec!["/"]);
+        
         Ok(())
     }
     
     #[test]
     pub fn create_page() -> Result<()> {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open("/tmp/test.db")?;
+        let file = tempfile::NamedTempFile::new()?;
+        let mut db = Database::create(file.reopen()?, Metadata::default())
+            .map_err(Error::other)?;
         
-        let mut blank = Database::blank::<Metadata>()?
-            .change_backing(file);
-            
-        let page = blank.create_page("test")?;
+        drop(db.create_page("test").map_err(Error::other)?);
+        drop(db);
+        
+        let mut db = Database::<_, Metadata>::open(file.reopen()?)
+            .map_err(Error::other)?;
+        assert!(db.open_page("test").is_ok());
         
         Ok(())
     }
     
+    #[cfg(feature = "rwpage")]
     #[test]
     pub fn read_write() -> Result<()> {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open("/tmp/test.db")?;
+        use std::io::{Read, Seek, Write};
         
-        let mut blank = Database::blank::<Metadata>()?
-            .change_buffer(file)?;
-            
-        let mut page = blank.create_page("test")?;
+        let mut db = Database::create(Cursor::new(vec![]), Metadata::default())
+            .map_err(Error::other)?;
+        let mut page = db.create_page("test")
+            .map_err(Error::other)?;
         
         let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_err(Error::other)?.as_millis();
         write!(&mut page, "{:?}", millis)?;
+        
         let mut str = String::new();
         page.rewind()?;
         page.read_to_string(&mut str)?;
-        println!("{}: {}", format!("{:?}", millis).len(), str.len());
+        assert_eq!(str, format!("{:?}", millis));
         
-        println!("{:#?}", &page.page_descriptor);
+        Ok(())
+    }
+    
+    #[test]
+    pub fn read() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
         
-        // assert!(str.trim().len() == format!("{:?}", millis).len());
+        let parsed = db.get_string_table()?;
+        assert_eq!(*db.leak_string_table(), parsed);
         
         Ok(())
     }
     
     #[test]
-    pub fn read() -> Result<()> {
-        let mut file = OpenOptions::new()
-            .read(true)
-            .open("/tmp/test.db")?;
+    pub fn clone_to_memory() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        let mut clone = db.clone_to_memory()?;
+        clone.two_phase_commit(vec![crate::format::database::PageWrite {
+            name: "scratch".to_owned(),
+            content: b"Hello".to_vec()
+        }], || true)?;
+        
+        assert!(clone.leak_inode_table().contains_key("scratch"));
+        assert!(!db.leak_inode_table().contains_key("scratch"));
+        
+        // The replaced chunks are free again once the header has been written
+        let replaced = clone.leak_inode_table()["scratch"].inodes.clone();
+        clone.two_phase_commit(vec![crate::format::database::PageWrite {
+            name: "scratch".to_owned(),
+            content: b"World".to_vec()
+        }], || true)?;
+        assert!(replaced.iter().all(|chunk| clone.get_free_regions().iter().any(|free| free.overlaps(chunk))));
+        
+        let page = clone.open_page("scratch")?;
+        let busy = clone.two_phase_commit(vec![crate::format::database::PageWrite {
+            name: "scratch".to_owned(),
+            content: b"Busy".to_vec()
+        }], || true).unwrap_err();
+        assert!(matches!(crate::error::Error::from(busy), crate::error::Error::Busy));
+        drop(page);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn clone() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", b"Hello")?;
+        
+        // The open page has borrowed space for its second chunk, which the copy knows nothing of
+        let mut page = db.open_page("a")?;
+        page.extend_from_slice(b", World").map_err(Error::other)?;
+        
+        let mut copy = db.clone();
+        copy.create_or_replace_page("b", b"Copied")?;
+        copy.create_or_replace_page("c", &[3u8; 0x100])?;
+        
+        drop(page);
+        db.write_header()?;
+        
+        let mut out = vec![];
+        db.pipe_page("a", &mut out)?;
+        assert_eq!(out, b"Hello, World");
+        assert!(!db.leak_inode_table().contains_key("b"));
+        db.verify_format()?;
+        
+        for (name, content) in [("a", b"Hello".to_vec()), ("b", b"Copied".to_vec()), ("c", vec![3u8; 0x100])] {
+            let mut out = vec![];
+            copy.pipe_page(name, &mut out)?;
+            assert_eq!(out, content);
+        }
+        copy.verify_format()?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn page_validator() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?
+            .with_page_validator(|page| match page.access_control_list.is_empty() {
+                true => Err(Error::new(std::io::ErrorKind::PermissionDenied, "Pages must carry an ACL")),
+                false => Ok(())
+            });
+        
+        db.two_phase_commit(vec![crate::format::database::PageWrite {
+            name: "scratch".to_owned(),
+            content: b"Hello".to_vec()
+        }], || true)?;
+        
+        assert!(db.open_page("scratch").is_err());
+        assert!(db.open_page_checked("missing", |_| Ok(())).is_err());
+        assert_eq!(db.page_access_count("scratch"), 0);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn hooks() -> Result<()> {
+        use std::sync::Arc;
+        use std::sync::Mutex;
+        use crate::format::database::WatchEvent;
+        
+        let mut db = crate::format::blank::<Metadata>()?;
+        let events = Arc::new(Mutex::new(vec![]));
+        
+        let log = Arc::clone(&events);
+        db.set_create_hook(move |name, page| log.lock().unwrap().push(format!("create {} {}", name, page.content_length)));
+        let log = Arc::clone(&events);
+        db.set_write_hook(move |name, bytes| log.lock().unwrap().push(format!("write {} {}", name, bytes)));
+        let log = Arc::clone(&events);
+        db.set_delete_hook(move |name| log.lock().unwrap().push(format!("delete {}", name)));
+        let log = Arc::clone(&events);
+        db.set_acl_change_hook(move |name, acl| log.lock().unwrap().push(format!("acl {} {}", name, acl.len())));
+        let watcher = db.watch_namespace("a");
+        
+        db.create_or_replace_page("a", b"Hello")?;
+        db.create_or_replace_page("a", b"Hello, World")?;
+        db.set_access_control_list("a", vec![crate::access::Access::Read("guest".to_owned())])?;
+        db.create_or_replace_page("b", b"World")?;
+        db.delete_page("a")?;
+        
+        assert_eq!(*events.lock().unwrap(), ["create a 5", "write a 5", "write a 12", "acl a 1", "create b 5", "write b 5", "delete a"]);
+        // Only changes to pages within the namespace are sent
+        assert_eq!(watcher.try_iter().collect::<Vec<_>>(), [
+            ("a".to_owned(), WatchEvent::Created),
+            ("a".to_owned(), WatchEvent::Modified),
+            ("a".to_owned(), WatchEvent::Modified),
+            ("a".to_owned(), WatchEvent::ACLChanged),
+            ("a".to_owned(), WatchEvent::Deleted),
+        ]);
+        
+        // Once cleared, neither hooks nor watchers are notified
+        db.clear_hooks();
+        db.create_or_replace_page("a", b"Hello")?;
+        assert_eq!(events.lock().unwrap().len(), 7);
+        assert_eq!(watcher.try_recv(), Err(std::sync::mpsc::TryRecvError::Disconnected));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn page_access_statistics() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        for name in ["a", "b", "c", "d"] {
+            db.create_or_replace_page(name, b"Hello")?;
+        }
+        
+        drop(db.open_page("d")?);
+        drop(db.open_page("b")?);
+        for _ in 0..3 {
+            drop(db.open_page("a")?);
+        }
+        
+        assert_eq!(db.page_access_count("a"), 3);
+        assert_eq!(db.page_access_count("b"), 1);
+        assert_eq!(db.page_access_count("c"), 0);
+        assert_eq!(db.page_access_count("missing"), 0);
+        
+        // Deleted pages are no longer listed
+        db.delete_page("d")?;
+        assert_eq!(db.most_accessed_pages(2), vec![("a", 3), ("b", 1)]);
+        assert_eq!(db.most_accessed_pages(10).len(), 2);
+        
+        // Pages which were never opened come first, followed by the one opened longest ago
+        let mut unused = db.least_recently_used_pages(4);
+        assert_eq!(unused.split_off(2), vec!["b", "a"]);
+        unused.sort();
+        assert_eq!(unused, vec!["/", "c"]);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn apply_schema() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        let schema = ron::ser::to_string(&vec![crate::format::schema::PageSchema {
+            name: "scratch".to_owned(),
+            access_control_list: vec![crate::access::Access::ReadWrite("admin".to_owned())],
+            chunks: 0,
+            size: 0,
+            created: 0,
+            modified: 0,
+            user_data: vec![],
+        }]).map_err(Error::other)?;
+        
+        assert_eq!(db.apply_schema(&schema)?, 1);
+        assert_eq!(db.apply_schema(&schema)?, 0);
+        assert!(db.export_schema()?.contains("entity: \"admin\""));
+        
+        let mut page = db.open_page("scratch")?;
+        page.set_content_type("text/plain").map_err(Error::other)?;
+        drop(page);
+        db.write_header()?;
+        
+        let schema = ron::de::from_str::<Vec<crate::format::schema::PageSchema>>(&db.export_schema()?).map_err(Error::other)?;
+        let scratch = schema.iter().find(|page| page.name == "scratch").ok_or(Error::other("scratch is missing"))?;
+        assert_eq!(scratch.user_data, [&[10][..], b"text/plain"].concat());
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn content_type() -> Result<()> {
+        let mut page = crate::page::PageDescriptor {
+            name: "scratch".to_owned(),
+            access_control_list: vec![],
+            modified: SystemTime::now(),
+            created: SystemTime::now(),
+            inodes: vec![],
+            content_length: 0,
+            link_count: 1,
+            compression: Default::default(),
+            checksum: None,
+            user_data: vec![],
+        };
+        
+        assert_eq!(page.content_type().map_err(Error::other)?, None);
+        
+        page.user_data = [&[16u8][..], b"application/json", b"trailing"].concat();
+        assert_eq!(page.content_type().map_err(Error::other)?.as_deref(), Some("application/json"));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn open_many() -> Result<()> {
+        std::fs::write("/tmp/open_many.db", include_bytes!("../test/blank_generated.db"))?;
+        
+        type Db = crate::format::database::Database<File, Metadata>;
+        
+        assert_eq!(Db::open_many(&["/tmp/open_many.db", "/tmp/open_many.db"])?.len(), 2);
+        assert!(Db::open_many(&["/tmp/open_many.db", "/tmp/open_many-missing.db"]).is_err());
+        
+        let results = Db::open_many_best_effort(&["/tmp/open_many.db", "/tmp/open_many-missing.db"]);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn atomic_swap_pages() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.two_phase_commit(vec![
+            crate::format::database::PageWrite { name: "current".to_owned(), content: b"blue".to_vec() },
+            crate::format::database::PageWrite { name: "next".to_owned(), content: b"green".to_vec() },
+        ], || true)?;
+        
+        db.atomic_swap_pages("current", "next")?;
+        
+        let pages = db.leak_inode_table();
+        assert_eq!(db.read_chunks(&pages["current"].inodes)?, b"green");
+        assert_eq!(db.read_chunks(&pages["next"].inodes)?, b"blue");
+        assert_eq!(pages["current"].name, "current");
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn fragmentation_ratio() -> Result<()> {
+        let page = crate::page::PageDescriptor {
+            name: "scratch".to_owned(),
+            access_control_list: vec![],
+            modified: SystemTime::now(),
+            created: SystemTime::now(),
+            inodes: vec![
+                crate::format::Array { offset: 0x1000, length: 0x100, nonce: None },
+                crate::format::Array { offset: 0x2000, length: 0x100, nonce: None },
+            ],
+            content_length: 0x100,
+            link_count: 1,
+            compression: Default::default(),
+            checksum: None,
+            user_data: vec![],
+        };
+        
+        assert_eq!(page.chunk_count(), 2);
+        assert_eq!(page.total_allocated_size(), 0x200);
+        assert_eq!(page.fragmentation_ratio(), 0.5);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn create_page_at_offset_in_use() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.two_phase_commit(vec![crate::format::database::PageWrite {
+            name: "scratch".to_owned(),
+            content: b"Hello".to_vec()
+        }], || true)?;
+        
+        let chunk = db.leak_inode_table()["scratch"].inodes[0];
+        
+        assert!(db.create_page_at_offset("overlapping", chunk.offset + 1, 0x10).is_err());
+        assert!(db.create_page_at_offset("header", 0, 0x10).is_err());
+        assert!(!db.leak_inode_table().contains_key("overlapping"));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn shrink_to_fit() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.two_phase_commit(vec![crate::format::database::PageWrite {
+            name: "scratch".to_owned(),
+            content: vec![0xAA; 0x2000]
+        }], || true)?;
+        
+        assert!(db.shrink_to_fit()? > 0);
+        assert_eq!(db.shrink_to_fit()?, 0);
+        
+        let pages = db.leak_inode_table();
+        assert_eq!(db.read_chunks(&pages["scratch"].inodes)?, vec![0xAA; 0x2000]);
+        
+        Ok(())
+    }
+    
+    #[test]
+    #[cfg(feature = "zstd")]
+    pub fn set_compression_algorithm() -> Result<()> {
+        use crate::format::compression::CompressionAlgorithm;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.two_phase_commit(vec![crate::format::database::PageWrite {
+            name: "scratch".to_owned(),
+            content: vec![0xAA; 0x2000]
+        }], || true)?;
+        
+        db.set_compression_algorithm("scratch", CompressionAlgorithm::Zstd(3))?;
+        
+        let page = db.leak_inode_table().remove("scratch").unwrap();
+        assert!(page.total_allocated_size() < 0x2000);
+        assert_eq!(page.compression.decompress(&db.read_chunks(&page.inodes)?)?, vec![0xAA; 0x2000]);
+        
+        // The checksum covers the decompressed content, so it's unaffected by the compression
+        assert_eq!(db.page_content_hash("scratch")?, <[u8; 32]>::from(<sha2::Sha256 as sha2::Digest>::digest(vec![0xAA; 0x2000])));
+        assert!(db.verify_page_integrity("scratch")?);
+        assert_eq!(db.open_page("scratch")?.content_hash().map_err(Error::other)?, db.page_content_hash("scratch")?);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn open_versioned() -> Result<()> {
+        type Db = crate::format::database::Database<Cursor<Vec<u8>>, Metadata>;
+        let backing = || Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        
+        Db::open_versioned(backing(), 1u32)?;
+        
+        let err = Db::open_versioned(backing(), 2u32).err().unwrap();
+        assert!(matches!(err.get_ref().and_then(|i| i.downcast_ref()), Some(crate::error::Error::VersionMismatch { found: 1, .. })));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn page_generation_token() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        let write = |content: &[u8]| vec![crate::format::database::PageWrite {
+            name: "scratch".to_owned(),
+            content: content.to_vec()
+        }];
+        
+        db.two_phase_commit(write(b"Hello"), || true)?;
+        let token = db.page_generation_token("scratch")?;
+        
+        assert_eq!(db.page_generation_token("scratch")?, token);
+        assert!(db.open_page_if_modified("scratch", token)?.is_none());
+        
+        db.two_phase_commit(write(b"World"), || true)?;
+        assert_ne!(db.page_generation_token("scratch")?, token);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn glob_match() -> Result<()> {
+        use crate::access::glob_match;
+        
+        assert!(glob_match("/users/*/profile", "/users/alice/profile"));
+        assert!(glob_match("/users/?ob", "/users/bob"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*a*b", "xxaxxab"));
+        assert!(!glob_match("/users/?", "/users/bob"));
+        assert!(!glob_match("/users/*/profile", "/users/alice/settings"));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn rename_all() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.two_phase_commit((0..1000)
+            .map(|i| crate::format::database::PageWrite {
+                name: format!("/v1/{}", i),
+                content: i.to_string().into_bytes()
+            })
+            .chain(std::iter::once(crate::format::database::PageWrite {
+                name: "/v2/0".to_owned(),
+                content: vec![]
+            }))
+            .collect(), || true)?;
+        
+        // "/v2/0" is taken, so nothing may be renamed
+        assert!(db.rename_all("/v1/", "/v2/").is_err());
+        assert_eq!(db.list_pages_with_prefix("/v1/").len(), 1000);
+        
+        // Open pages can't be renamed, so nothing is
+        let page = db.open_page("/v1/0")?;
+        assert!(db.rename_all("/v1/", "/v3/").is_err_and(|err| err.kind() == std::io::ErrorKind::ResourceBusy));
+        assert_eq!(db.list_pages_with_prefix("/v1/").len(), 1000);
+        drop(page);
+        
+        let deleted = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let hook = std::sync::Arc::clone(&deleted);
+        db.set_delete_hook(move |name| hook.lock().unwrap().push(name.to_owned()));
+        let before = db.leak_inode_table()["/v1/999"].modified;
+        
+        assert_eq!(db.rename_all("/v1/", "/v3/")?, 1000);
+        assert!(db.list_pages_with_prefix("/v1/").is_empty());
+        assert_eq!(db.read_chunks(&db.leak_inode_table()["/v3/999"].inodes)?, b"999");
+        
+        // Each page is renamed as by `rename_page`
+        assert!(db.leak_inode_table()["/v3/999"].modified >= before);
+        assert_eq!(db.get_history("/v3/999")?.last().map(|entry| entry.op), Some(crate::format::history::HistoryOp::Rename));
+        assert_eq!(deleted.lock().unwrap().len(), 1000);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn verify_page_integrity() -> Result<()> {
+        use std::io::Seek;
+        use std::io::SeekFrom;
+        use std::io::Write;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.create_or_replace_page("scratch", b"Hello")?;
+        assert!(db.verify_page_integrity("scratch")?);
+        
+        let chunk = db.leak_inode_table()["scratch"].inodes[0];
+        {
+            let mut backing = db.backing.lock_backing().map_err(Error::other)?;
+            backing.seek(SeekFrom::Start(chunk.offset))?;
+            backing.write_all(b"J")?;
+        }
+        
+        assert!(!db.verify_page_integrity("scratch")?);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn dump_state() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.create_or_replace_page("scratch", b"Hello")?;
+        db.assert_consistent();
+        
+        let state = db.dump_state();
+        assert!(state.contains("scratch: 1 chunks, 5 bytes"));
+        assert!(state.contains("Locks: 0"));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn open_at_offset() -> Result<()> {
+        let mut container = vec![0xAAu8; 0x40];
+        container.extend(include_bytes!("../test/blank_generated.db"));
+        
+        let mut db = crate::format::database::Database::<_, Metadata>::open_at_offset(Cursor::new(container), 0x40)?;
+        assert_eq!(db.scan_for_magic()?, vec![0x40]);
+        assert_eq!(db.inode_table_range.offset, 0x40 + 0xc0);
+        
+        db.create_or_replace_page("scratch", b"Hello")?;
+        db.write_header()?;
+        assert!(db.verify_page_integrity("scratch")?);
+        db.assert_consistent();
+        
+        let container = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        assert!(container[..0x40].iter().all(|i| *i == 0xAA));
+        assert_eq!(&container[0x40..0x44], b"FSDB");
+        // Stored offsets stay relative to the embedded header
+        assert_eq!(u64::from_le_bytes(container[0x40 + 0x48..0x40 + 0x50].try_into().map_err(Error::other)?), 0x50);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn create_record_page() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.create_record_page("records", 24)?;
+        assert!(db.create_record_page("records", 24).is_err());
+        
+        let mut page = db.leak_inode_table()["records"].clone();
+        assert_eq!(page.record_size(), Some(24));
+        assert_eq!(page.content_length, 0);
+        
+        page.user_data = vec![];
+        page.set_record_size(8);
+        assert_eq!(page.content_type().map_err(Error::other)?, None);
+        assert_eq!(page.record_size(), Some(8));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn pipe_page() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.create_or_replace_page("scratch", b"Hello World")?;
+        
+        let mut out = vec![];
+        assert_eq!(db.pipe_page("scratch", &mut out)?, 11);
+        assert_eq!(out, b"Hello World");
+        
+        let mut out = vec![];
+        assert_eq!(db.pipe_page_range("scratch", 6, 100, &mut out)?, 5);
+        assert_eq!(out, b"World");
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn read_stream_chunked() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        let mut page = db.create_page("a")?;
+        page.extend_from_slice(&[0x01; 0x30]).map_err(Error::other)?;
+        page.extend_from_slice(&[0x02; 0x30]).map_err(Error::other)?;
+        drop(page);
+        db.sync_pages()?;
+        
+        // Chunks can hold more than the page's content, which the stream stops at
+        let mut descriptor = db.leak_inode_table().remove("a").unwrap();
+        descriptor.content_length = 0x40;
+        let page = crate::page::Page::new(descriptor, std::sync::Arc::clone(&db.backing), std::sync::Arc::new(()), std::sync::mpsc::channel().0);
+        
+        let pieces = page.read_stream_chunked(0x20).collect::<Vec<_>>();
+        assert_eq!(pieces.iter().map(Vec::len).collect::<Vec<_>>(), vec![0x20, 0x10, 0x10]);
+        assert_eq!(pieces.concat(), [&[0x01; 0x30][..], &[0x02; 0x10]].concat());
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn check_access_for_entity() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.create_or_replace_page("b", b"")?;
+        db.create_or_replace_page("a", b"")?;
+        db.create_or_replace_page("c", b"")?;
+        db.set_access_control_list("a", vec![crate::access::Access::Read("user:*".to_owned())])?;
+        db.set_access_control_list("b", vec![crate::access::Access::ReadWrite("user:jake".to_owned()), crate::access::Access::Custom("*".to_owned(), 0b100)])?;
+        db.set_access_control_list("c", vec![crate::access::Access::None("user:jake".to_owned())])?;
+        
+        let pages = db.check_access_for_entity("user:jake");
+        assert_eq!(pages.iter().map(|(page, _)| page.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(pages[1].1.len(), 2);
+        
+        let matrix = db.check_access_matrix();
+        assert_eq!(matrix["user:jake"], vec![("a".to_owned(), 0b001), ("b".to_owned(), 0b111)]);
+        assert_eq!(matrix["*"], vec![("b".to_owned(), 0b100)]);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn apply_migration() -> Result<()> {
+        use crate::format::database::MigrationAction;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.create_or_replace_page("legacy/a", b"Hello")?;
+        db.create_or_replace_page("legacy/b", b"World")?;
+        db.create_or_replace_page("tmp", b"")?;
+        db.create_or_replace_page("v2/c", b"Unchanged")?;
+        
+        let result = db.apply_migration(|name, page, content| match name {
+            "tmp" => MigrationAction::Delete,
+            "v2/c" => {
+                page.user_data = vec![0xFF];
+                MigrationAction::Skip
+            },
+            name => match name.strip_prefix("legacy/") {
+                Some(rest) => {
+                    content.extend_from_slice(b"!");
+                    MigrationAction::Rename(format!("v2/{}", rest))
+                },
+                None => MigrationAction::Keep
+            }
+        })?;
+        
+        assert_eq!(result, crate::format::database::MigrationResult { kept: 1, deleted: 1, renamed: 2, skipped: 1, bytes_written: 12 });
+        assert_eq!(db.list_pages_with_prefix("v2/"), vec!["v2/a", "v2/b", "v2/c"]);
+        assert!(db.leak_inode_table()["v2/c"].user_data.is_empty());
+        assert!(db.verify_page_integrity("v2/a")?);
+        
+        let mut out = vec![];
+        db.pipe_page("v2/b", &mut out)?;
+        assert_eq!(out, b"World!");
+        
+        assert!(db.apply_migration(|_, _, _| MigrationAction::Rename("v2/c".to_owned())).is_err());
+        
+        // The recorded length is that of the migrated content, not of the chunks holding it
+        #[cfg(feature = "encryption")]
+        {
+            let mut db = crate::format::blank_encrypted::<Metadata>([7u8; 32])?;
+            db.create_or_replace_page("a", b"Hello")?;
+            db.apply_migration(|_, _, content| {
+                content.extend_from_slice(b", World");
+                MigrationAction::Keep
+            })?;
+            
+            assert_eq!(db.leak_inode_table()["a"].content_length, 12);
+            assert_eq!(db.open_page("a")?.into_vec().map_err(Error::other)?, b"Hello, World");
+            assert!(db.verify_page_integrity("a")?);
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn estimated_overhead() -> Result<()> {
+        use crate::format::database::LayoutRegion;
+        
+        let check = |mut db: crate::format::database::Database<Cursor<Vec<u8>>, Metadata>| -> Result<()> {
+            for i in 0..0x20 {
+                db.create_or_replace_page(&format!("page-{}", i), &[i as u8; 0x100])?;
+            }
+            db.delete_page("page-0")?;
+            db.write_header()?;
+            
+            // Everything but the pages' chunks and free space, including the history table and the salt of version 2 headers
+            let layout = db.get_total_layout();
+            assert!(layout.iter().any(|(region, _)| *region == LayoutRegion::HistoryTable));
+            let overhead = layout.iter()
+                .filter(|(region, _)| !matches!(region, LayoutRegion::PageChunk(_) | LayoutRegion::Free))
+                .map(|(_, region)| region.length)
+                .sum::<u64>();
+            let total = db.backing.lock_backing().map_err(Error::other)?.get_ref().len() as u64;
             
-        let mut db = Database::open(file)?;
+            assert!((db.estimated_overhead() - overhead as f64 / total as f64 * 100.0).abs() < 1e-9);
+            assert!((db.estimated_overhead() + db.page_data_ratio() - 100.0).abs() < 1e-9);
+            
+            Ok(())
+        };
+        
+        check(crate::format::blank::<Metadata>()?)?;
+        #[cfg(feature = "encryption")]
+        check(crate::format::database::Database::create_with_passphrase(Cursor::new(vec![]), "passphrase", Metadata::default())?)?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn get_total_layout() -> Result<()> {
+        use crate::format::database::LayoutRegion;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.create_or_replace_page("scratch", b"Hello")?;
+        let inodes = db.get_page_inodes("scratch")?;
+        assert!(db.get_page_inodes("missing").is_err());
+        
+        let layout = db.get_total_layout();
+        assert_eq!(layout[0].0, LayoutRegion::Header);
+        assert!(layout.contains(&(LayoutRegion::PageChunk("scratch".to_owned()), inodes[0])));
+        
+        // The regions tile the backing object exactly
+        let mut cursor = 0;
+        for (_, region) in layout.iter() {
+            assert_eq!(region.offset, cursor);
+            cursor = region.end();
+        }
+        
+        let free = db.get_free_regions();
+        assert!(free.iter().all(|region| layout.contains(&(LayoutRegion::Free, *region))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    #[cfg(feature = "json")]
+    pub fn serialise_descriptors() -> Result<()> {
+        let access = crate::access::Access::ReadWrite("alice".to_owned());
+        assert_eq!(serde_json::to_value(&access).map_err(Error::other)?, serde_json::json!({ "type": "ReadWrite", "entity": "alice" }));
+        
+        let custom = crate::access::Access::Custom("bob".to_owned(), 0b1001);
+        let json = serde_json::to_string(&custom).map_err(Error::other)?;
+        assert_eq!(serde_json::from_str::<crate::access::Access>(&json).map_err(Error::other)?, custom);
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        
+        let page = serde_json::to_value(&db.leak_inode_table()["scratch"]).map_err(Error::other)?;
+        assert_eq!(page["name"], "scratch");
+        assert!(page.get("inodes").is_none());
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn create_linked_page() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        db.create_or_replace_page("target", b"Hello")?;
+        db.create_linked_page("alias", "target")?;
+        assert!(db.create_linked_page("alias", "target").is_err());
+        
+        assert_eq!(db.get_page_inodes("alias")?, db.get_page_inodes("target")?);
+        assert_eq!(db.leak_inode_table()["target"].link_count, 2);
+        assert_eq!(db.linked_pages("target"), vec!["alias"]);
+        db.assert_consistent();
+        
+        db.delete_page("target")?;
+        assert_eq!(db.leak_inode_table()["alias"].link_count, 1);
+        assert!(db.linked_pages("alias").is_empty());
+        
+        let mut out = vec![];
+        db.pipe_page("alias", &mut out)?;
+        assert_eq!(out, b"Hello");
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn emergency_dump() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("dir/scratch", b"Hello")?;
+        
+        let mut data = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        // Wreck the metadata so the database can no longer be opened
+        data[0x50..0x60].fill(0xFF);
+        assert!(crate::format::database::Database::<_, Metadata>::open(Cursor::new(data.clone())).is_err());
+        
+        let dir = std::env::temp_dir().join(format!("fsdb-emergency-dump-{}", std::process::id()));
+        let written = crate::format::database::Database::<_, Metadata>::emergency_dump(Cursor::new(data), &dir)?;
+        
+        assert_eq!(written, 2);
+        assert_eq!(std::fs::read(dir.join("dir_scratch.bin"))?, b"Hello");
+        
+        std::fs::remove_dir_all(&dir)?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn set_metadata() -> Result<()> {
+        use std::io::Read;
+        use std::io::Seek;
+        use std::io::SeekFrom;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, ron::Value>::open(backing)?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        
+        // Far larger than the space between the header and the first chunk
+        let meta = ron::Value::String("x".repeat(0x400));
+        db.set_metadata(meta.clone())?;
+        db.assert_consistent();
+        assert_eq!(db.get_metadata(), &meta);
+        
+        let chunk = db.get_page_inodes("scratch")?[0];
+        assert!(db.metadata_range.offset >= chunk.end());
+        
+        let mut stored = vec![0u8; db.metadata_range.length as usize];
+        {
+            let mut backing = db.backing.lock_backing().map_err(Error::other)?;
+            backing.seek(SeekFrom::Start(db.metadata_range.offset))?;
+            backing.read_exact(&mut stored)?;
+        }
+        assert_eq!(ron::de::from_bytes::<ron::Value>(&stored).map_err(Error::other)?, meta);
+        
+        let mut out = vec![];
+        db.pipe_page("scratch", &mut out)?;
+        assert_eq!(out, b"Hello");
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn open_with_pre_validation() -> Result<()> {
+        let bytes = include_bytes!("../test/blank_generated.db").to_vec();
+        let expected = bytes.len();
+        let validator = |bytes: &[u8]| match bytes.starts_with(b"FSDB") && bytes.len() == expected {
+            true => Ok(()),
+            false => Err(Error::new(std::io::ErrorKind::InvalidData, "Bad signature")),
+        };
+        
+        crate::format::database::Database::<_, Metadata>::open_with_pre_validation(Cursor::new(bytes.clone()), validator)?;
+        
+        let mut truncated = bytes;
+        truncated.pop();
+        let err = crate::format::database::Database::<_, Metadata>::open_with_pre_validation(Cursor::new(truncated), validator).err();
+        assert_eq!(err.map(|err| err.kind()), Some(std::io::ErrorKind::InvalidData));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn seal() -> Result<()> {
+        use std::io::Read;
+        use std::io::Seek;
+        use std::io::SeekFrom;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        
+        let key = [7u8; 32];
+        db.register_seal_key(key)?;
+        db.seal()?;
+        assert!(db.is_sealed());
+        
+        let flags = {
+            let mut flags = [0u8; 8];
+            let mut backing = db.backing.lock_backing().map_err(Error::other)?;
+            backing.seek(SeekFrom::Start(8))?;
+            backing.read_exact(&mut flags)?;
+            u64::from_le_bytes(flags)
+        };
+        assert_eq!(flags & 0x30, 0x30);
+        
+        let denied = |res: Result<()>| res.err().map(|err| err.kind()) == Some(std::io::ErrorKind::PermissionDenied);
+        assert!(denied(db.create_or_replace_page("scratch", b"World")));
+        assert!(denied(db.delete_page("scratch")));
+        assert!(denied(db.write_header()));
+        assert!(denied(db.unseal([0u8; 32])));
+        
+        // Reading is unaffected
+        let mut out = vec![];
+        db.pipe_page("scratch", &mut out)?;
+        assert_eq!(out, b"Hello");
+        
+        db.unseal(key)?;
+        assert!(!db.is_sealed());
+        db.create_or_replace_page("scratch", b"World")?;
+        
+        let mut out = vec![];
+        db.pipe_page("scratch", &mut out)?;
+        assert_eq!(out, b"World");
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn fill() -> Result<()> {
+        let mediator = crate::mediator::Mediator::new(Cursor::new(vec![1u8; 0x10]));
+        
+        // Larger than a single block, so the fill is written in several parts
+        let chunk = mediator.allocate_filled(0x18000, 0xAB).map_err(Error::other)?;
+        assert_eq!(chunk, crate::format::Array { offset: 0x10, length: 0x18000, nonce: None });
+        
+        mediator.try_fill_range(crate::format::Array { offset: 0x08, length: 0x10, nonce: None }, 0x00, None).map_err(Error::other)?;
+        
+        let backing = mediator.lock_backing().map_err(Error::other)?;
+        let bytes = backing.get_ref();
+        assert_eq!(bytes.len(), 0x18010);
+        assert!(bytes[..0x08].iter().all(|i| *i == 1));
+        assert!(bytes[0x08..0x18].iter().all(|i| *i == 0));
+        assert!(bytes[0x18..].iter().all(|i| *i == 0xAB));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn watch_all_pages() -> Result<()> {
+        use crate::format::database::WatchEvent;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        
+        let all = db.watch_all_pages();
+        let logs = db.watch_namespace("logs/");
+        drop(db.watch_all_pages());
+        
+        db.create_or_replace_page("logs/today", b"Hello")?;
+        db.create_or_replace_page("scratch", b"World")?;
+        db.delete_page("logs/today")?;
+        
+        let events = all.try_iter().collect::<Vec<_>>();
+        assert!(events.contains(&("logs/today".to_owned(), WatchEvent::Created)));
+        assert!(events.contains(&("scratch".to_owned(), WatchEvent::Modified)));
+        assert_eq!(events.last(), Some(&("logs/today".to_owned(), WatchEvent::Deleted)));
+        
+        let events = logs.try_iter().collect::<Vec<_>>();
+        assert!(events.iter().all(|(name, _)| name == "logs/today"));
+        assert_eq!(events.last(), Some(&("logs/today".to_owned(), WatchEvent::Deleted)));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn clone_page() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("original", b"Hello")?;
+        
+        db.clone_page("original", "copy")?;
+        db.assert_consistent();
+        assert!(db.clone_page("original", "copy").is_err());
+        assert!(db.clone_page("missing", "other").is_err());
+        
+        assert_eq!(db.page_content_hash("original")?, db.page_content_hash("copy")?);
+        assert_ne!(db.get_page_inodes("original")?, db.get_page_inodes("copy")?);
+        assert!(db.linked_pages("copy").is_empty());
+        
+        db.create_or_replace_page("copy", b"World")?;
+        
+        let mut out = vec![];
+        db.pipe_page("original", &mut out)?;
+        assert_eq!(out, b"Hello");
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn rebuild_free_list() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        db.create_or_replace_page("other", b"World")?;
+        db.delete_page("scratch")?;
+        
+        assert!(db.validate_free_list());
+        let freed = db.rebuild_free_list()?;
+        assert!(freed > 0);
+        assert_eq!(db.free_list_len(), freed);
+        assert!(db.validate_free_list());
+        
+        // The gaps are handed out again, and leaked borrowed slices are forgotten
+        let free = db.get_free_regions();
+        db.create_or_replace_page("scratch", b"Hello")?;
+        assert!(db.get_page_inodes("scratch")?.iter().all(|chunk| free.iter().any(|region| region.offset <= chunk.offset && chunk.end() <= region.end())));
+        assert!(db.validate_free_list());
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn open_or_create_with_migration() -> Result<()> {
+        use crate::format::database::HasVersion;
+        
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct VersionedMetadata {
+            version: u32,
+        }
+        
+        impl HasVersion for VersionedMetadata {
+            fn version(&self) -> u32 { self.version }
+            fn set_version(&mut self, version: u32) { self.version = version; }
+        }
+        
+        let path = std::env::temp_dir().join(format!("fsdb-migration-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        
+        type Db = crate::format::database::Database<File, VersionedMetadata>;
+        
+        let db = Db::open_or_create_with_migration(&path, VersionedMetadata { version: 1 }, |_| panic!("A new database doesn't need migrating"))?;
+        assert_eq!(db.meta.version, 1);
+        drop(db);
+        
+        let db = Db::open_or_create_with_migration(&path, VersionedMetadata { version: 1 }, |_| panic!("The database is up to date"))?;
+        drop(db);
+        
+        let mut migrated = false;
+        let db = Db::open_or_create_with_migration(&path, VersionedMetadata { version: 2 }, |db| {
+            assert_eq!(db.meta.version, 1);
+            migrated = true;
+            Ok(())
+        })?;
+        assert!(migrated);
+        assert_eq!(db.meta.version, 2);
+        drop(db);
+        
+        let db = Db::open_or_create_with_migration(&path, VersionedMetadata { version: 2 }, |_| panic!("The database was already migrated"))?;
+        assert_eq!(db.meta.version, 2);
+        drop(db);
+        
+        std::fs::remove_file(&path)?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn compact_to_minimum() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("a", &[1u8; 0x2000])?;
+        db.create_or_replace_page("b", b"Hello")?;
+        db.create_or_replace_page("c", &[3u8; 0x3000])?;
+        db.create_linked_page("d", "b")?;
+        db.delete_page("a")?;
+        
+        let page = db.open_page("c")?;
+        assert!(matches!(crate::error::Error::from(db.compact_to_minimum(None::<fn(usize, usize)>).unwrap_err()), crate::error::Error::Busy));
+        drop(page);
+        
+        let pages = std::cell::Cell::new(0);
+        let freed = db.compact_to_minimum(Some(|current, total| {
+            assert_eq!(current, pages.get() + 1);
+            pages.set(current);
+            assert_eq!(total, 4);
+        }))?;
+        assert_eq!(pages.get(), 4);
+        assert!(freed > 0x2000);
+        
+        db.assert_consistent();
+        db.verify_format()?;
+        assert_eq!(db.linked_pages("d"), vec!["b"]);
+        assert!(db.get_free_regions().iter().all(|region| region.length < 0x10));
+        
+        // The chunks are packed back to back and the file ends where the last one does
+        let mut chunks = db.leak_inode_table()
+            .into_values()
+            .flat_map(|page| page.inodes)
+            .collect::<Vec<_>>();
+        chunks.sort();
+        chunks.dedup();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].end(), chunks[1].offset);
+        assert_eq!(db.backing.lock_backing().map_err(Error::other)?.get_ref().len() as u64, chunks[1].end());
+        
+        for (name, content) in [("b", b"Hello".to_vec()), ("c", vec![3u8; 0x3000]), ("d", b"Hello".to_vec())] {
+            let mut out = vec![];
+            db.pipe_page(name, &mut out)?;
+            assert_eq!(out, content);
+            assert!(db.verify_page_integrity(name)?);
+        }
+        
+        assert_eq!(db.compact_to_minimum(None::<fn(usize, usize)>)?, 0);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn iter_chunks() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("a", b"Hello")?;
+        db.create_or_replace_page("b", b"World")?;
+        db.create_linked_page("c", "a")?;
+        
+        let chunks = db.iter_chunks()
+            .filter(|(name, _)| ["a", "b", "c"].contains(name))
+            .collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.contains(&("c", db.get_page_inodes("a")?[0])));
+        
+        assert!(db.overlapping_chunks().is_empty());
+        assert!(db.unsorted_chunks().is_empty());
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn diagnose() -> Result<()> {
+        use crate::format::database::Severity;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        db.delete_page("scratch")?;
+        // The deleted page's name is still referred to by its history, unlike a revoked ACL entity
+        db.create_or_replace_page("acl", b"")?;
+        db.set_access_control_list("acl", vec![crate::access::Access::Read("guest".to_owned())])?;
+        db.write_header()?;
+        db.set_access_control_list("acl", vec![])?;
+        
+        let messages = db.diagnose();
+        assert!(messages.iter().all(|message| message.severity != Severity::Error));
+        assert!(messages.iter().any(|message| message.code == "unreferenced-strings"));
+        assert!(messages.iter().any(|message| message.code == "free-space"));
+        assert!(messages.windows(2).all(|pair| pair[0].severity >= pair[1].severity));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn fsync() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("fsdb-fsync-{}.db", std::process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        
+        let mut db = crate::format::database::Database::<File, Metadata>::create(file, Metadata::default())?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        db.fsync()?;
+        
+        db.set_sync_on_write(true);
+        db.create_or_replace_page("scratch", b"World")?;
+        
+        db.seal()?;
+        db.fsync()?;
+        
+        drop(db);
+        std::fs::remove_file(&path)?;
+        
+        Ok(())
+    }
+    
+    #[cfg(all(feature = "rayon", unix))]
+    #[test]
+    pub fn parallel_read_pages() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("fsdb-parallel-{}.db", std::process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        
+        let mut db = crate::format::database::Database::<File, Metadata>::create(file, Metadata::default())?;
+        let names = ["a", "b", "c", "d"];
+        for (i, name) in names.iter().enumerate() {
+            db.create_or_replace_page(name, &vec![i as u8; 0x1000 * (i + 1)])?;
+        }
+        
+        let pages = db.parallel_read_pages(&["c", "a", "d", "b"])?;
+        assert_eq!(pages.iter().map(|(name, _)| *name).collect::<Vec<_>>(), vec!["c", "a", "d", "b"]);
+        for (name, content) in pages {
+            let i = names.iter().position(|i| *i == name).unwrap();
+            assert_eq!(content, vec![i as u8; 0x1000 * (i + 1)]);
+        }
+        
+        assert!(db.parallel_read_pages(&["a", "missing"]).is_err());
+        
+        // Compressed pages are decompressed rather than returned as stored
+        #[cfg(feature = "compression")]
+        {
+            db.compress_page("d")?;
+            assert_eq!(db.parallel_read_pages(&["d"])?[0].1, vec![3u8; 0x4000]);
+        }
+        
+        // Encrypted pages are decrypted, and their authentication tags dropped
+        #[cfg(feature = "encryption")]
+        {
+            db.backing.set_key(Some([7u8; 32]));
+            db.create_or_replace_page("e", b"Secret")?;
+            assert_eq!(db.parallel_read_pages(&["e"])?[0].1, b"Secret");
+        }
+        
+        // Pages are read-locked, so writes in progress make the read fail
+        let chunk = db.get_page_inodes("a")?[0];
+        let stored = db.read_chunks(&[chunk])?;
+        let guard = db.backing.try_write_range(&stored, chunk.offset, None).map_err(Error::other)?;
+        assert!(db.parallel_read_pages(&["a"]).is_err());
+        drop(guard);
+        assert_eq!(db.parallel_read_pages(&["a"])?[0].1, vec![0u8; 0x1000]);
+        
+        drop(db);
+        std::fs::remove_file(&path)?;
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "rwpage")]
+    #[test]
+    pub fn page_read() -> Result<()> {
+        use std::io::Read;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        
+        let mut page = db.open_page("scratch")?;
+        // The appended content lands in a second chunk
+        page.extend_from_slice(b", World").map_err(Error::other)?;
+        
+        let mut content = vec![];
+        let mut buf = [0u8; 4];
+        loop {
+            match page.read(&mut buf)? {
+                0 => break,
+                read => content.extend_from_slice(&buf[..read]),
+            }
+        }
+        assert_eq!(content, b"Hello, World");
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn page_compare_and_swap() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        
+        let mut page = db.open_page("scratch")?;
+        // The appended content lands in a second chunk
+        page.extend_from_slice(b", World").map_err(Error::other)?;
+        
+        assert!(!page.compare_and_swap(0, b"Jello", b"World").map_err(Error::other)?);
+        assert!(page.compare_and_swap(0, b"Hello", b"Jello").map_err(Error::other)?);
+        assert!(page.compare_and_swap(0, b"Jello", b"Jelly").is_ok());
+        assert!(page.compare_and_swap(0, b"Jelly", b"Hi").is_err());
+        assert!(page.compare_and_swap(10, b"World", b"Earth").is_err());
+        
+        // The range spans both chunks
+        let previous = page.fetch_and_update(3, 4, |current| current.to_ascii_uppercase()).map_err(Error::other)?;
+        assert_eq!(previous, b"ly, ");
+        assert!(page.fetch_and_update(8, 8, |current| current.to_vec()).is_err());
+        drop(page);
+        db.write_header()?;
+        
+        let mut out = vec![];
+        db.pipe_page("scratch", &mut out)?;
+        assert_eq!(out, b"JelLY, World");
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "rwpage")]
+    #[test]
+    pub fn page_write() -> Result<()> {
+        use std::io::Read;
+        use std::io::Write;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        
+        let mut page = db.open_page("scratch")?;
+        // Overwrites the existing chunk, then grows the page for the rest
+        page.write_all(b"Jelly, World")?;
+        assert_eq!(page.len(), 12);
+        
+        // Until the page is synchronised, the database only knows of the original chunk, which was overwritten in place
+        let mut reader = db.open_page("scratch")?;
+        let mut content = vec![];
+        reader.read_to_end(&mut content)?;
+        assert_eq!(content, b"Jelly");
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "rwpage")]
+    #[test]
+    pub fn page_typed_io() -> Result<()> {
+        use std::io::Seek;
+        use std::io::SeekFrom;
+        
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("scratch", b"")?;
+        
+        let mut page = db.open_page("scratch")?;
+        page.write_u8(0x01).map_err(Error::other)?;
+        page.write_u16_le(0x0203).map_err(Error::other)?;
+        page.write_u32_le(0x04050607).map_err(Error::other)?;
+        page.write_u64_le(0x08090a0b0c0d0e0f).map_err(Error::other)?;
+        page.write_u16_be(0x0203).map_err(Error::other)?;
+        page.write_u32_be(0x04050607).map_err(Error::other)?;
+        page.write_u64_be(0x08090a0b0c0d0e0f).map_err(Error::other)?;
+        assert_eq!(page.len(), 2 * (1 + 2 + 4 + 8) - 1);
+        
+        page.seek(SeekFrom::Start(0))?;
+        assert_eq!(page.read_u8().map_err(Error::other)?, 0x01);
+        assert_eq!(page.read_u16_le().map_err(Error::other)?, 0x0203);
+        assert_eq!(page.read_u32_le().map_err(Error::other)?, 0x04050607);
+        assert_eq!(page.read_u64_le().map_err(Error::other)?, 0x08090a0b0c0d0e0f);
+        // The same bytes, in the other order
+        assert_eq!(page.read_u16_be().map_err(Error::other)?, 0x0203);
+        assert_eq!(page.read_u32_be().map_err(Error::other)?, 0x04050607);
+        assert_eq!(page.read_u64_be().map_err(Error::other)?, 0x08090a0b0c0d0e0f);
+        assert!(page.read_u8().is_err());
+        
+        page.seek(SeekFrom::Start(1))?;
+        assert_eq!(page.read_u16_be().map_err(Error::other)?, 0x0302);
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "rwpage")]
+    #[test]
+    pub fn page_read_until() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("log", b"first\nsec")?;
+        
+        let mut page = db.open_page("log")?;
+        // The second line spans both chunks
+        page.extend_from_slice(b"ond\n\0third").map_err(Error::other)?;
+        
+        let mut buf = vec![];
+        assert_eq!(page.read_until(b'\n', &mut buf).map_err(Error::other)?, 6);
+        assert_eq!(buf, b"first\n");
+        
+        let mut line = "> ".to_owned();
+        assert_eq!(page.read_line(&mut line).map_err(Error::other)?, 7);
+        assert_eq!(line, "> second\n");
+        
+        let mut buf = vec![];
+        assert_eq!(page.read_until(0, &mut buf).map_err(Error::other)?, 1);
+        assert_eq!(buf, b"\0");
+        
+        // Without a delimiter, the rest of the page is read
+        let mut line = String::new();
+        assert_eq!(page.read_line(&mut line).map_err(Error::other)?, 5);
+        assert_eq!(line, "third");
+        assert_eq!(page.read_line(&mut line).map_err(Error::other)?, 0);
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "rwpage")]
+    #[test]
+    pub fn page_seek() -> Result<()> {
+        use std::io::Read;
+        use std::io::Seek;
+        use std::io::SeekFrom;
+        use std::io::Write;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        
+        let mut page = db.open_page("scratch")?;
+        page.extend_from_slice(b", World").map_err(Error::other)?;
+        
+        // Reads spanning both chunks
+        let mut buf = [0u8; 4];
+        page.seek(SeekFrom::Start(3))?;
+        page.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"lo, ");
+        
+        page.seek(SeekFrom::Current(-4))?;
+        page.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"lo, ");
+        
+        assert_eq!(page.seek(SeekFrom::End(-5))?, 7);
+        page.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"Worl");
+        assert!(page.seek(SeekFrom::Current(-100)).is_err());
+        
+        // Seeking past the end doesn't allocate until written to
+        let len = page.len();
+        page.seek(SeekFrom::End(4))?;
+        assert_eq!(page.len(), len);
+        page.write_all(b"!")?;
+        page.seek(SeekFrom::Start(12))?;
+        let mut rest = vec![];
+        page.read_to_end(&mut rest)?;
+        assert_eq!(rest, b"\0\0\0\0!");
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "rwpage")]
+    #[test]
+    pub fn page_flush() -> Result<()> {
+        use std::io::Read;
+        use std::io::Write;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        
+        let mut page = db.open_page("scratch")?;
+        page.write_all(b"Jelly, World")?;
+        
+        // Nothing reaches the inode table until the page is flushed
+        assert_eq!(db.sync_pages()?, 0);
+        
+        page.flush()?;
+        assert_eq!(db.sync_pages()?, 1);
+        
+        // An unchanged page isn't sent again
+        page.flush()?;
+        assert_eq!(db.sync_pages()?, 0);
+        
+        let mut content = vec![];
+        let mut reader = db.open_page("scratch")?;
+        reader.read_to_end(&mut content)?;
+        assert_eq!(content, b"Jelly, World");
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "rwpage")]
+    #[test]
+    pub fn page_close() -> Result<()> {
+        use std::io::Write;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("scratch", b"Hello")?;
+        
+        let mut page = db.open_page("scratch")?;
+        page.write_all(b", World")?;
+        assert!(db.is_page_open("scratch"));
+        
+        page.close()?;
+        assert!(!db.is_page_open("scratch"));
+        // Closing again does nothing
+        page.close()?;
+        assert_eq!(db.sync_pages()?, 1);
+        
+        // Dropping a page closes it too
+        let mut page = db.open_page("scratch")?;
+        page.extend_from_slice(b"!").map_err(Error::other)?;
+        drop(page);
+        assert_eq!(db.sync_pages()?, 1);
+        
+        assert_eq!(db.open_page("scratch")?.into_vec().map_err(Error::other)?, b", World!");
+        
+        // Closing a page leaves locks held by other operations alone
+        let chunk = db.get_page_inodes("scratch")?[0];
+        let stored = db.read_chunks(&[chunk])?;
+        let mediator = db.backing.clone();
+        let guard = mediator.try_write_range(&stored, chunk.offset, None).map_err(Error::other)?;
+        db.open_page("scratch")?.close()?;
+        assert!(matches!(mediator.try_read_chunk(chunk, None), Err(crate::error::Error::Busy)));
+        drop(guard);
+        assert!(mediator.try_read_chunk(chunk, None).is_ok());
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn blank_database() -> Result<()> {
+        let db = crate::format::blank::<Metadata>()?;
+        
+        assert_eq!(db.list_pages_with_prefix(""), vec!["/"]);
+        assert_eq!(db.get_page_inodes("/")?.iter().map(|i| i.length).sum::<u64>(), 0);
+        #[cfg(feature = "json")]
+        assert_eq!(db.export_string_table_as_json()?.replace(char::is_whitespace, ""), r#"["/","*"]"#);
+        
+        db.verify_format()?;
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "rwpage")]
+    #[test]
+    pub fn database_create_page() -> Result<()> {
+        use std::io::Write;
+        
+        let blank = crate::format::blank::<Metadata>()?;
+        let mut db = crate::database::Database::from(blank);
+        
+        let mut page = db.create_page("test")?;
+        page.write_all(b"Hello")?;
+        drop(page);
+        
+        assert!(matches!(db.create_page("test"), Err(crate::error::Error::AlreadyExists)));
+        
+        // The page is recorded in the tables as soon as it's created
+        let mut db = crate::format::blank::<Metadata>()?;
+        drop(db.create_page("test")?);
+        assert_eq!(db.list_pages_with_prefix("test"), vec!["test"]);
+        #[cfg(feature = "json")]
+        assert!(db.export_string_table_as_json()?.contains("\"test\""));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn database_delete_page() -> Result<()> {
+        let mut db = crate::database::Database::from(crate::format::blank::<Metadata>()?);
+        
+        let page = db.create_page("test")?;
+        assert!(matches!(db.delete_page("test"), Err(crate::error::Error::Busy)));
+        drop(page);
+        
+        db.delete_page("test")?;
+        assert!(matches!(db.delete_page("test"), Err(crate::error::Error::NotFound)));
+        
+        // The chunks of deleted pages are handed out before the backing object grows
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", &[1u8; 0x100])?;
+        db.create_or_replace_page("b", &[2u8; 0x100])?;
+        db.create_or_replace_page("c", &[3u8; 0x100])?;
+        db.delete_page("b")?;
+        let free = db.get_free_regions();
+        let size = db.backing.lock_backing().map_err(Error::other)?.get_ref().len();
+        db.create_or_replace_page("d", &[4u8; 0x80])?;
+        let chunk = db.get_page_inodes("d")?[0];
+        assert!(free.iter().any(|region| region.intersect(&chunk) == Some(chunk)));
+        assert_eq!(db.backing.lock_backing().map_err(Error::other)?.get_ref().len(), size);
+        
+        db.verify_format()?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn database_rename_page() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", b"Hello")?;
+        db.create_or_replace_page("b", b"World")?;
+        
+        assert!(db.rename_page("missing", "c").is_err_and(|err| err.kind() == std::io::ErrorKind::NotFound));
+        assert!(db.rename_page("a", "b").is_err_and(|err| err.kind() == std::io::ErrorKind::AlreadyExists));
+        
+        // Open pages can't be renamed, just as they can't be deleted
+        let page = db.open_page("a")?;
+        assert!(db.rename_page("a", "c").is_err_and(|err| err.kind() == std::io::ErrorKind::ResourceBusy));
+        drop(page);
+        
+        db.rename_page("a", "c")?;
+        assert_eq!(db.list_pages_with_prefix("a"), Vec::<&str>::new());
+        assert_eq!(db.list_pages_with_prefix("c"), vec!["c"]);
+        
+        let mut content = vec![];
+        db.pipe_page("c", &mut content)?;
+        assert_eq!(content, b"Hello");
+        
+        let mut db = crate::database::Database::from(db);
+        assert!(matches!(db.rename_page("c", "b"), Err(crate::error::Error::AlreadyExists)));
+        db.rename_page("c", "d")?;
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "rwpage")]
+    #[test]
+    pub fn database_open_page() -> Result<()> {
+        use std::io::Read;
+        use std::io::Write;
+        
+        let mut db = crate::database::Database::from(crate::format::blank::<Metadata>()?);
+        assert!(matches!(db.open_page("test"), Err(crate::error::Error::NotFound)));
+        
+        let mut page = db.create_page("test")?;
+        page.write_all(b"Hello")?;
+        page.close()?;
+        
+        let mut content = String::new();
+        db.open_page("test")?.read_to_string(&mut content)?;
+        assert_eq!(content, "Hello");
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "json")]
+    #[test]
+    pub fn defragment_string_table() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("x", b"Scratch")?;
+        db.create_or_replace_page("b", b"Hello")?;
+        db.create_or_replace_page("a", b"World")?;
+        db.set_access_control_list("a", vec![crate::access::Access::Read("guest".to_owned())])?;
+        db.delete_page("x")?;
+        db.write_header()?;
+        
+        // Pages follow the order of their chunks, the root page having none, while the deleted page's name is kept for its history
+        db.defragment_string_table()?;
+        let strings = serde_json::from_str::<Vec<String>>(&db.export_string_table_as_json()?).map_err(Error::other)?;
+        assert_eq!(strings, ["/", "*", "b", "a", "guest", "x"]);
+        assert_eq!(db.get_history("x")?.len(), 2);
+        assert_eq!(db.get_history("a")?.len(), 1);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn get_history() -> Result<()> {
+        use crate::format::history::HistoryOp;
+        
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", b"Hello")?;
+        db.create_or_replace_page("a", b"Goodbye")?;
+        let chunks = db.get_page_inodes("a")?;
+        
+        db.rename_page("a", "b")?;
+        db.delete_page("b")?;
+        
+        let ops = |history: Vec<crate::format::history::HistoryEntry>| history.into_iter()
+            .map(|entry| entry.op)
+            .collect::<Vec<_>>();
+        
+        assert_eq!(ops(db.get_history("a")?), vec![HistoryOp::Create, HistoryOp::Write]);
+        assert_eq!(ops(db.get_history("b")?), vec![HistoryOp::Rename, HistoryOp::Delete]);
+        assert_eq!(db.get_history("b")?[1].chunks_snapshot, chunks);
+        assert!(db.get_history("c")?.is_empty());
+        
+        // History entries keep following their page's name when the string table is reordered
+        db.defragment_string_table()?;
+        assert_eq!(ops(db.get_history("a")?), vec![HistoryOp::Create, HistoryOp::Write]);
+        
+        // The table is capped, dropping the oldest entries first
+        db.set_history_limit(Some(3));
+        assert_eq!(ops(db.get_history("a")?), vec![HistoryOp::Write]);
+        db.create_or_replace_page("c", b"Hello")?;
+        db.create_or_replace_page("c", b"Goodbye")?;
+        assert!(db.get_history("a")?.is_empty());
+        assert_eq!(ops(db.get_history("b")?), vec![HistoryOp::Delete]);
+        assert_eq!(ops(db.get_history("c")?), vec![HistoryOp::Create, HistoryOp::Write]);
+        
+        db.write_header()?;
+        assert_eq!(db.history_table_range.length, 3);
+        
+        db.verify_format()?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn transaction() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", b"Hello")?;
+        let chunks = db.get_page_inodes("a")?;
+        
+        // Rolled back transactions leave the tables as they were
+        let mut transaction = db.begin_transaction();
+        transaction.create_page("b")?;
+        transaction.write("b", b"World")?;
+        transaction.delete_page("a")?;
+        assert!(!transaction.contains_page("a"));
+        transaction.rollback();
+        
+        let mut pages = db.list_pages_with_prefix("");
+        pages.sort();
+        assert_eq!(pages, vec!["/", "a"]);
+        assert_eq!(db.get_page_inodes("a")?, chunks);
+        
+        let mut transaction = db.begin_transaction();
+        transaction.create_page("b")?;
+        transaction.write("b", b"World")?;
+        transaction.rename_page("b", "c")?;
+        transaction.delete_page("a")?;
+        assert!(transaction.write("a", b"Goodbye").is_err());
+        transaction.commit()?;
+        
+        let mut pages = db.list_pages_with_prefix("");
+        pages.sort();
+        assert_eq!(pages, vec!["/", "c"]);
+        
+        let mut content = vec![];
+        db.pipe_page("c", &mut content)?;
+        assert_eq!(content, b"World");
+        
+        // Transactions can't change open pages
+        let page = db.open_page("c")?;
+        let mut transaction = db.begin_transaction();
+        transaction.write("c", b"Goodbye")?;
+        assert!(matches!(crate::error::Error::from(transaction.commit().err().unwrap()), crate::error::Error::Busy));
+        drop(page);
+        
+        // Deleted and replaced chunks are freed, unless a linked page still refers to them
+        db.create_or_replace_page("d", b"Linked")?;
+        db.create_linked_page("e", "d")?;
+        let replaced = db.get_page_inodes("c")?;
+        
+        let mut transaction = db.begin_transaction();
+        transaction.write("c", b"Goodbye")?;
+        transaction.write("d", b"Unlinked")?;
+        transaction.commit()?;
+        
+        let free = db.get_free_regions();
+        let linked = db.get_page_inodes("e")?;
+        assert!(chunks.iter().chain(replaced.iter()).all(|chunk| free.iter().any(|region| region.intersect(chunk) == Some(*chunk))));
+        db.create_or_replace_page("f", b"Hello")?;
+        db.create_or_replace_page("g", b"World")?;
+        for chunk in db.get_page_inodes("f")?.into_iter().chain(db.get_page_inodes("g")?) {
+            assert!(free.iter().any(|region| region.intersect(&chunk) == Some(chunk)));
+            assert!(!linked.iter().any(|used| used.overlaps(&chunk)));
+        }
+        assert_eq!(db.open_page("d")?.into_vec().map_err(Error::other)?, b"Unlinked");
+        assert_eq!(db.open_page("e")?.into_vec().map_err(Error::other)?, b"Linked");
+        
+        db.verify_format()?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn compact() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", &[1u8; 0x2000])?;
+        db.create_or_replace_page("b", b"Hello")?;
+        db.create_or_replace_page("c", &[3u8; 0x3000])?;
+        db.create_linked_page("d", "b")?;
+        db.delete_page("a")?;
+        
+        let page = db.open_page("c")?;
+        assert!(matches!(crate::error::Error::from(db.compact().unwrap_err()), crate::error::Error::Busy));
+        drop(page);
+        
+        assert!(db.compact()? >= 0x2000);
+        assert_eq!(db.compact()?, 0);
+        
+        db.verify_format()?;
+        
+        // Nothing but the header refers to where the content ended up
+        let data = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        let reopened = crate::format::database::Database::<_, Metadata>::open(Cursor::new(data))?;
+        let mut out = vec![];
+        reopened.pipe_page("c", &mut out)?;
+        assert_eq!(out, vec![3u8; 0x3000]);
+        assert_eq!(db.linked_pages("d"), vec!["b"]);
+        
+        for (name, content) in [("b", b"Hello".to_vec()), ("c", vec![3u8; 0x3000]), ("d", b"Hello".to_vec())] {
+            let mut out = vec![];
+            db.pipe_page(name, &mut out)?;
+            assert_eq!(out, content);
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn gc_string_table() -> Result<()> {
+        use crate::format::history::HistoryOp;
+        
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", b"Hello")?;
+        db.set_access_control_list("a", vec![crate::access::Access::Read("someone".to_owned())])?;
+        db.write_header()?;
+        db.set_access_control_list("a", vec![])?;
+        db.rename_page("a", "b")?;
+        
+        // Only the revoked entity is unreferenced, as the history still refers to 'a'
+        assert_eq!(db.gc_string_table()?, 1);
+        assert!(!db.leak_string_table().iter().any(|str| str == "someone"));
+        assert_eq!(db.gc_string_table()?, 0);
+        
+        let ops = db.get_history("a")?
+            .into_iter()
+            .map(|entry| entry.op)
+            .collect::<Vec<_>>();
+        assert_eq!(ops, vec![HistoryOp::Create]);
+        
+        db.verify_format()?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn range_lock_overlap() -> Result<()> {
+        use crate::format::Array;
+        use crate::mediator::RangeLock;
+        
+        let lock = RangeLock::Write(Array { offset: 0x10, length: 0x10, nonce: None });
+        
+        // The previous check, `range.offset >= offset && range.end() < offset`, can't hold for any range, so overlapping access was never refused
+        let (offset, length) = (0x18u64, 0x10u64);
+        let previous = |start: u64, end: u64| start >= offset && end < offset;
+        assert!(!previous(0x10, 0x20));
+        assert!(lock.overlaps(offset, length));
+        assert!(lock.overlaps(0x00, 0x11));
+        assert!(lock.overlaps(0x12, 0x04));
+        
+        // Adjacent ranges don't share a byte
+        assert!(!lock.overlaps(0x00, 0x10));
+        assert!(!lock.overlaps(0x20, 0x10));
+        
+        // Locks are dropped once each operation finishes, so adjacent and repeated access goes through
+        let mediator = crate::mediator::Mediator::new(Cursor::new(vec![0u8; 0x40]));
+        mediator.try_write_range([1u8; 0x10], 0x10, None).map_err(Error::other)?;
+        mediator.try_write_range([2u8; 0x10], 0x20, None).map_err(Error::other)?;
+        
+        let mut buf = [0u8; 0x20];
+        mediator.try_read_range(&mut buf, 0x10, None).map_err(Error::other)?;
+        assert_eq!(&buf[..0x10], &[1u8; 0x10]);
+        assert_eq!(&buf[0x10..], &[2u8; 0x10]);
+        assert_eq!(mediator.stats_summary(), "Locks: 0 (read: 0 at [], write: 0 at [])");
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn range_lock_guard() -> Result<()> {
+        let mediator = crate::mediator::Mediator::new(Cursor::new(vec![0u8; 0x40]));
+        
+        let guard = mediator.try_write_range([1u8; 0x10], 0x10, None).map_err(Error::other)?;
+        assert!(matches!(mediator.try_read_range([0u8; 0x08], 0x18, None), Err(crate::error::Error::Busy)));
+        assert!(matches!(mediator.try_write_range([2u8; 0x10], 0x08, None), Err(crate::error::Error::Busy)));
+        
+        // Reads may overlap each other, and adjacent ranges are independent
+        let read = mediator.try_read_range([0u8; 0x10], 0x20, None).map_err(Error::other)?;
+        drop(mediator.try_read_range([0u8; 0x08], 0x24, None).map_err(Error::other)?);
+        assert_eq!(mediator.stats_summary(), "Locks: 2 (read: 1 at [0x20..0x30], write: 1 at [0x10..0x20])");
+        
+        drop((guard, read));
+        assert_eq!(mediator.stats_summary(), "Locks: 0 (read: 0 at [], write: 0 at [])");
+        
+        let mut buf = [0u8; 0x10];
+        mediator.try_read_range(&mut buf, 0x10, None).map_err(Error::other)?;
+        assert_eq!(buf, [1u8; 0x10]);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn range_lock_guard_lengths() -> Result<()> {
+        let mediator = crate::mediator::Mediator::new(Cursor::new(vec![0u8; 0x40]));
+        
+        // Guards release their own lock, even where another starts at the same offset
+        let short = mediator.try_read_range([0u8; 0x08], 0x10, None).map_err(Error::other)?;
+        let long = mediator.try_read_range([0u8; 0x20], 0x10, None).map_err(Error::other)?;
+        drop(short);
+        assert_eq!(mediator.stats_summary(), "Locks: 1 (read: 1 at [0x10..0x30], write: 0 at [])");
+        drop(long);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn access_check() -> Result<()> {
+        use crate::access::Access;
+        use crate::access::AccessLevel;
+        
+        assert!(Access::ReadWrite("alice".to_owned()).check("alice", AccessLevel::Read.bits()));
+        assert!(!Access::Read("alice".to_owned()).check("alice", AccessLevel::ReadWrite.bits()));
+        assert!(!Access::ReadWrite("alice".to_owned()).check("bob", AccessLevel::Read.bits()));
+        assert!(Access::ReadExecute("*".to_owned()).check("bob", AccessLevel::ReadExecute.bits()));
+        
+        let mut db = crate::database::Database::from(crate::format::blank::<Metadata>()?);
+        db.create_page_as("notes", "alice")?;
+        assert!(matches!(db.open_page_as("notes", "bob", AccessLevel::Read), Err(crate::error::Error::NotPermitted)));
+        db.open_page_as("notes", "alice", AccessLevel::ReadWriteExecute)?;
+        
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.set_access_control_list("/", vec![Access::Read("*".to_owned()), Access::ReadWrite("admin".to_owned())])?;
+        assert_eq!(db.create_page_as("a", "guest").err().map(|err| err.kind()), Some(std::io::ErrorKind::PermissionDenied));
+        db.create_page_as("a", "admin")?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn write_ahead_log() -> Result<()> {
+        use std::io::Write;
+        use crate::format::wal::{WalOp, WalRecord};
+        
+        let wal = std::env::temp_dir().join(format!("fsdb-wal-{}.db.wal", std::process::id()));
+        let _ = std::fs::remove_file(&wal);
+        
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", b"Hello")?;
+        db.enable_wal(&wal)?;
+        
+        let mut page = db.open_page("a")?;
+        page.write_sparse(0, b"J").map_err(Error::other)?;
+        assert!(std::fs::metadata(&wal)?.len() > 0);
+        assert!(db.checkpoint().is_err());
+        drop(page);
+        
+        assert_eq!(db.checkpoint()?, 1);
+        assert_eq!(std::fs::metadata(&wal)?.len(), 0);
+        
+        // A write which was logged but never took place is redone once the log is enabled again
+        let mut log = File::create(&wal)?;
+        WalRecord { op: WalOp::Write, page_name: "a".to_owned(), offset: 5, data: b", World".to_vec() }.write(&mut log)?;
+        // followed by a record cut short by a crash
+        log.write_all(&[0x01, 0x00])?;
+        drop(log);
+        
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", b"Jello")?;
+        db.enable_wal(&wal)?;
+        
+        let mut content = vec![];
+        db.pipe_page("a", &mut content)?;
+        assert_eq!(content, b"Jello, World");
+        assert_eq!(std::fs::metadata(&wal)?.len(), 0);
+        
+        // Writing the header drops the records of closed pages, so they can't be replayed over newer content
+        let mut page = db.open_page("a")?;
+        page.write_sparse(0, b"H").map_err(Error::other)?;
+        db.write_header()?;
+        assert!(std::fs::metadata(&wal)?.len() > 0);
+        drop(page);
+        db.write_header()?;
+        assert_eq!(std::fs::metadata(&wal)?.len(), 0);
+        
+        db.create_or_replace_page("a", b"Newer")?;
+        db.enable_wal(&wal)?;
+        assert_eq!(db.open_page("a")?.into_vec().map_err(Error::other)?, b"Newer");
+        
+        // Lengths beyond the end of the log end it rather than being allocated
+        let mut log = File::create(&wal)?;
+        log.write_all(&[0x00])?;
+        log.write_all(&u64::MAX.to_le_bytes())?;
+        drop(log);
+        assert_eq!(db.checkpoint()?, 0);
+        
+        // Prepared content is logged, and dropped again once the commit or abort is settled
+        let write = || vec![crate::format::database::PageWrite { name: "b".to_owned(), content: b"Prepared".to_vec() }];
+        db.two_phase_commit(write(), || {
+            assert!(std::fs::metadata(&wal).map(|wal| wal.len() > 0).unwrap_or(false));
+            false
+        })?;
+        assert_eq!(std::fs::metadata(&wal)?.len(), 0);
+        assert!(!db.leak_inode_table().contains_key("b"));
+        
+        // A commit whose header never reached the backing object is redone, unlike one which was only prepared
+        let mut log = File::create(&wal)?;
+        WalRecord { op: WalOp::Prepare, page_name: "b".to_owned(), offset: 0, data: b"Committed".to_vec() }.write(&mut log)?;
+        WalRecord { op: WalOp::Commit, page_name: String::new(), offset: 0, data: vec![] }.write(&mut log)?;
+        WalRecord { op: WalOp::Prepare, page_name: "c".to_owned(), offset: 0, data: b"Aborted".to_vec() }.write(&mut log)?;
+        drop(log);
+        assert_eq!(db.checkpoint()?, 3);
+        
+        let mut content = vec![];
+        db.pipe_page("b", &mut content)?;
+        assert_eq!(content, b"Committed");
+        assert!(!db.leak_inode_table().contains_key("c"));
+        
+        db.two_phase_commit(write(), || true)?;
+        assert_eq!(std::fs::metadata(&wal)?.len(), 0);
+        
+        std::fs::remove_file(&wal)?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn point_in_time_restore() -> Result<()> {
+        let journal = std::env::temp_dir().join(format!("fsdb-journal-{}.db.journal", std::process::id()));
+        let _ = std::fs::remove_file(&journal);
+        
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", b"Hello")?;
+        db.create_or_replace_page("b", b"World")?;
+        let acl = vec![crate::access::Access::Read("guest".to_owned())];
+        db.set_access_control_list("b", acl.clone())?;
+        db.enable_journal(&journal)?;
+        
+        // Writes through open pages, the database and transactions are all recorded
+        let mut page = db.open_page("a")?;
+        page.write_sparse(0, b"J").map_err(Error::other)?;
+        drop(page);
+        db.write_header()?;
+        let target = std::time::SystemTime::now();
+        db.create_or_replace_page("c", b"Created")?;
+        db.delete_page("b")?;
+        let mut transaction = db.begin_transaction();
+        transaction.rename_page("c", "d")?;
+        transaction.commit()?;
+        assert!(std::fs::metadata(&journal)?.len() > 0);
+        
+        // Records are told apart to the nanosecond, so changes made within the same second as the target are kept
+        db.point_in_time_restore(&journal, target)?;
+        let mut names = db.leak_inode_table().into_keys().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["/", "a", "b"]);
+        let mut out = vec![];
+        db.pipe_page("a", &mut out)?;
+        assert_eq!(out, b"Jello");
+        assert_eq!(db.leak_inode_table()["b"].access_control_list, acl);
+        
+        db.point_in_time_restore(&journal, std::time::UNIX_EPOCH)?;
+        
+        let mut names = db.leak_inode_table().into_keys().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["/", "a", "b"]);
+        for (name, content) in [("a", b"Hello"), ("b", b"World")] {
+            let mut out = vec![];
+            db.pipe_page(name, &mut out)?;
+            assert_eq!(out, content);
+        }
+        assert_eq!(db.leak_inode_table()["b"].access_control_list, acl);
+        db.verify_format()?;
+        
+        std::fs::remove_file(&journal)?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn journal_entry() -> Result<()> {
+        use crate::format::journal::JournalEntry;
+        
+        let entry = JournalEntry {
+            timestamp: 1,
+            page_name_idx: 2,
+            operation: JournalEntry::DELETE,
+            access_control_list: vec![crate::access::Access::ReadWrite("alice".to_owned()), crate::access::Access::Custom("bob".to_owned(), 0b1000)],
+            before_data: Some(b"Hello".to_vec()),
+            after_data: None,
+        };
+        let mut buffer = vec![];
+        entry.write(&mut buffer)?;
+        assert_eq!(JournalEntry::read(&mut Cursor::new(&buffer))?, Some(entry));
+        
+        // A length reaching past the end of the journal fails rather than allocating it
+        let mut buffer = buffer[..8 + 8 + 1].to_vec();
+        buffer.extend_from_slice(&[0x00, 0x00, 0x01]);
+        buffer.extend_from_slice(&u64::MAX.to_le_bytes());
+        buffer.extend_from_slice(b"Hello");
+        assert_eq!(JournalEntry::read(&mut Cursor::new(&buffer)).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn snapshot() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("a", &[1u8; 0x2000])?;
+        db.create_or_replace_page("b", b"Hello")?;
+        db.create_linked_page("c", "b")?;
+        db.delete_page("a")?;
+        
+        let before = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        
+        let mut snapshot = Cursor::new(vec![]);
+        db.snapshot(&mut snapshot)?;
+        let snapshot = snapshot.into_inner();
+        
+        // The original is left as it was, while the copy leaves out the deleted page's content
+        assert_eq!(db.backing.lock_backing().map_err(Error::other)?.get_ref(), &before);
+        assert!(snapshot.len() < before.len());
+        assert_eq!(snapshot.windows(5).filter(|window| *window == b"Hello").count(), 1);
+        assert!(!snapshot.windows(0x10).any(|window| window == [1u8; 0x10]));
+        
+        // Databases without pages can be opened again as-is
+        let mut db = crate::format::database::Database::create(Cursor::new(vec![]), Metadata::default())?;
+        let mut snapshot = Cursor::new(vec![]);
+        db.snapshot(&mut snapshot)?;
+        let copy = crate::format::database::Database::<_, Metadata>::open(Cursor::new(snapshot.into_inner()))?;
+        copy.verify_format()?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    #[cfg(feature = "compression")]
+    pub fn compress_page() -> Result<()> {
+        use std::io::Read;
+        
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open(backing)?;
+        db.create_or_replace_page("a", &[0xAA; 0x2000])?;
+        
+        assert!(db.compress_page("a")? < 0x2000);
+        
+        // Pages read and write their uncompressed content
+        let mut page = db.open_page("a")?;
+        assert_eq!(page.len(), 0x2000);
+        let mut content = vec![];
+        page.read_to_end(&mut content)?;
+        assert_eq!(content, vec![0xAA; 0x2000]);
+        
+        page.write_sparse(0x2000, b"Hello").map_err(Error::other)?;
+        assert!(page.fill(0x00, 1).is_err());
+        drop(page);
+        db.write_header()?;
+        
+        let mut content = db.open_page("a")?.into_vec().map_err(Error::other)?;
+        assert_eq!(content.split_off(0x2000), b"Hello");
+        assert_eq!(content, vec![0xAA; 0x2000]);
+        
+        assert_eq!(db.decompress_page("a")?, 0x2005);
+        assert_eq!(db.open_page("a")?.into_vec().map_err(Error::other)?.len(), 0x2005);
+        
+        Ok(())
+    }
+    
+    #[test]
+    #[cfg(feature = "compression")]
+    pub fn buffered_writes() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", &[0xAA; 0x2000])?;
+        db.compress_page("a")?;
+        let compressed = db.get_page_inodes("a")?;
+        let len = db.backing.lock_backing().map_err(Error::other)?.get_ref().len();
+        
+        // Compressed pages are only recompressed once they're flushed
+        let mut page = db.open_page("a")?;
+        for i in 0..0x100u64 {
+            page.write_sparse(i * 0x10, b"Hello").map_err(Error::other)?;
+        }
+        assert_eq!(db.backing.lock_backing().map_err(Error::other)?.get_ref().len(), len);
+        drop(page);
+        db.write_header()?;
+        
+        // The chunks the page gave up are freed
+        assert!(db.free_list_len() > 0);
+        let free = db.get_free_regions();
+        db.create_or_replace_page("b", &vec![0u8; compressed[0].length as usize])?;
+        let chunk = db.get_page_inodes("b")?[0];
+        assert!(free.iter().any(|region| region.intersect(&chunk) == Some(chunk)));
+        
+        let content = db.open_page("a")?.into_vec().map_err(Error::other)?;
+        assert_eq!(content.len(), 0x2000);
+        assert_eq!(&content[0x10..0x15], b"Hello");
+        assert_eq!(content[0x1FFF], 0xAA);
+        
+        Ok(())
+    }
+    
+    #[test]
+    #[cfg(feature = "encryption")]
+    pub fn encryption() -> Result<()> {
+        let mut db = crate::format::blank_encrypted::<Metadata>([7u8; 32])?;
+        db.create_or_replace_page("a", b"Hello, World")?;
+        
+        let mut page = db.create_page("b")?;
+        page.write_sparse(0, b"Secret").map_err(Error::other)?;
+        drop(page);
+        db.write_header()?;
+        
+        // Neither page's content is stored in plain text
+        let stored = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        assert!(!stored.windows(5).any(|window| window == b"Hello"));
+        assert!(!stored.windows(6).any(|window| window == b"Secret"));
+        assert!(db.get_page_inodes("a")?.iter().all(|chunk| chunk.nonce.is_some()));
+        
+        let mut content = vec![];
+        db.pipe_page("a", &mut content)?;
+        assert_eq!(content, b"Hello, World");
+        assert_eq!(db.open_page("b")?.into_vec().map_err(Error::other)?, b"Secret");
+        
+        // Content fails authentication under any other key, and can't be read without one
+        db.backing.set_key(Some([8u8; 32]));
+        let err = db.open_page("a").err().unwrap();
+        assert!(matches!(err.get_ref().and_then(|i| i.downcast_ref()), Some(crate::error::Error::Corrupted)));
+        
+        db.backing.set_key(None);
+        assert_eq!(db.pipe_page("b", &mut vec![]).err().unwrap().kind(), std::io::ErrorKind::PermissionDenied);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn incremental_backup() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", b"Hello")?;
+        db.create_or_replace_page("b", &[0xAA; 0x2000])?;
+        #[cfg(feature = "compression")]
+        db.compress_page("b")?;
+        
+        // Backups hold the content as it reads back rather than as it's stored
+        let mut backup = vec![];
+        assert_eq!(db.incremental_backup(&mut backup, UNIX_EPOCH)?, 3);
+        
+        let mut restored = crate::format::blank::<Metadata>()?;
+        assert_eq!(restored.restore_incremental(&backup[..], true)?, 3);
+        for (name, content) in [("a", b"Hello".to_vec()), ("b", vec![0xAA; 0x2000])] {
+            let mut out = vec![];
+            restored.pipe_page(name, &mut out)?;
+            assert_eq!(out, content);
+        }
+        
+        // Truncated backups are rejected before any page is restored
+        let mut restored = crate::format::blank::<Metadata>()?;
+        restored.create_or_replace_page("a", b"Untouched")?;
+        assert!(restored.restore_incremental(&backup[..backup.len() - 1], true).is_err_and(|err| err.kind() == std::io::ErrorKind::UnexpectedEof));
+        let mut out = vec![];
+        restored.pipe_page("a", &mut out)?;
+        assert_eq!(out, b"Untouched");
+        assert!(restored.pipe_page("b", &mut out).is_err());
+        
+        // Encrypted content is decrypted, so it can be restored under a different key
+        #[cfg(feature = "encryption")]
+        {
+            let mut db = crate::format::blank_encrypted::<Metadata>([7u8; 32])?;
+            db.create_or_replace_page("a", b"Hello, World")?;
+            let mut backup = vec![];
+            db.incremental_backup(&mut backup, UNIX_EPOCH)?;
+            
+            let mut restored = crate::format::blank_encrypted::<Metadata>([9u8; 32])?;
+            restored.restore_incremental(&backup[..], true)?;
+            let mut out = vec![];
+            restored.pipe_page("a", &mut out)?;
+            assert_eq!(out, b"Hello, World");
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    #[cfg(all(feature = "sendfile", target_os = "linux"))]
+    pub fn sendfile_page_range() -> Result<()> {
+        use std::io::Read;
+        use std::io::Seek;
+        
+        let send = |db: &crate::format::database::Database<File, Metadata>, name: &str| -> Result<Vec<u8>> {
+            let mut dst = tempfile::tempfile()?;
+            let written = db.sendfile_page_range(name, 0x10, 0x100, &mut dst)?;
+            let mut out = vec![];
+            dst.rewind()?;
+            dst.read_to_end(&mut out)?;
+            assert_eq!(written, out.len() as u64);
+            Ok(out)
+        };
+        
+        let content = (0..0x200).map(|i| i as u8).collect::<Vec<_>>();
+        let mut db = crate::format::database::Database::create(tempfile::tempfile()?, Metadata::default())?;
+        db.create_or_replace_page("plain", &content)?;
+        assert_eq!(send(&db, "plain")?, &content[0x10..0x110]);
+        
+        #[cfg(feature = "compression")]
+        {
+            db.create_or_replace_page("compressed", &content)?;
+            db.compress_page("compressed")?;
+            assert_eq!(send(&db, "compressed")?, &content[0x10..0x110]);
+        }
+        
+        // Encrypted chunks are decrypted rather than sent as they're stored
+        #[cfg(feature = "encryption")]
+        {
+            let mut db = crate::format::database::Database::create_encrypted(tempfile::tempfile()?, [7u8; 32], Metadata::default())?;
+            db.create_or_replace_page("encrypted", &content)?;
+            assert_eq!(send(&db, "encrypted")?, &content[0x10..0x110]);
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn diff_to_patch() -> Result<()> {
+        use crate::format::diff::PatchOp;
+        
+        let mut src = crate::format::blank::<Metadata>()?;
+        src.create_or_replace_page("a", b"Hello")?;
+        src.create_or_replace_page("b", b"World")?;
+        src.create_or_replace_page("c", b"Removed")?;
+        
+        let mut dst = crate::format::blank::<Metadata>()?;
+        dst.create_or_replace_page("a", b"Hello")?;
+        dst.create_or_replace_page("b", &[0xAA; 0x2000])?;
+        dst.create_or_replace_page("d", b"Added")?;
+        #[cfg(feature = "compression")]
+        dst.compress_page("b")?;
+        
+        let diff = src.diff(&dst)?;
+        assert_eq!(diff.unchanged, vec!["/", "a"]);
+        
+        // Patches carry the content as it reads back rather than as it's stored
+        assert_eq!(diff.to_patch(&dst)?, vec![
+            PatchOp::Delete("c".to_owned()),
+            PatchOp::Create("d".to_owned(), b"Added".to_vec()),
+            PatchOp::Replace("b".to_owned(), vec![0xAA; 0x2000]),
+        ]);
+        
+        #[cfg(feature = "encryption")]
+        {
+            let mut dst = crate::format::blank_encrypted::<Metadata>([7u8; 32])?;
+            dst.create_or_replace_page("a", b"Secret")?;
+            assert_eq!(src.diff(&dst)?.to_patch(&dst)?[2], PatchOp::Replace("a".to_owned(), b"Secret".to_vec()));
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    #[cfg(feature = "encryption")]
+    pub fn passphrase() -> Result<()> {
+        let mut db = crate::format::database::Database::create_with_passphrase(Cursor::new(vec![]), "correct horse", Metadata::default())?;
+        db.create_or_replace_page("a", b"TOPSECRET")?;
+        db.write_header()?;
+        
+        // Content is encrypted under the derived key
+        let stored = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        assert!(!stored.windows(9).any(|window| window == b"TOPSECRET"));
+        
+        let mut db = crate::format::database::Database::<_, Metadata>::open_with_passphrase(Cursor::new(stored.clone()), "correct horse")?;
+        assert_eq!(db.open_page("a")?.into_vec().map_err(Error::other)?, b"TOPSECRET");
+        
+        // Any other passphrase derives a key which fails authentication
+        let err = crate::format::database::Database::<_, Metadata>::open_with_passphrase(Cursor::new(stored), "battery staple").err().unwrap();
+        assert!(matches!(err.get_ref().and_then(|i| i.downcast_ref()), Some(crate::error::Error::Corrupted)));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn verify_integrity() -> Result<()> {
+        use crate::format::database::DatabaseOpenOptions;
+        use crate::format::database::IntegrityError;
+        
+        let db = crate::format::database::Database::create(Cursor::new(vec![]), Metadata::default())?;
+        assert_eq!(db.verify_integrity()?, vec![]);
+        
+        // Damage the header's metadata length
+        db.backing.lock_backing().map_err(Error::other)?.get_mut()[0x40] ^= 0xFF;
+        assert!(matches!(db.verify_integrity()?[..], [IntegrityError::Header { .. }]));
+        
+        // The header is only checked on opening if asked to
+        let db = crate::format::database::Database::create(Cursor::new(vec![]), Metadata::default())?;
+        let mut bytes = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        DatabaseOpenOptions::new().accept_version(1u32).verify_header(true).open::<_, Metadata>(Cursor::new(bytes.clone()))?;
+        
+        bytes[0x0C] ^= 0xFF;
+        DatabaseOpenOptions::new().accept_version(1u32).open::<_, Metadata>(Cursor::new(bytes.clone()))?;
+        let err = DatabaseOpenOptions::new().accept_version(1u32).verify_header(true).open::<_, Metadata>(Cursor::new(bytes)).err().unwrap();
+        assert!(matches!(err.get_ref().and_then(|i| i.downcast_ref()), Some(crate::error::Error::Corrupted)));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn page_content_hash() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        let mut page = db.create_page("a")?;
+        page.write_sparse(0, b"Hello").map_err(Error::other)?;
+        
+        // Pages modified in place have no checksum until their hash is computed, after which it's recorded
+        let hash = page.content_hash().map_err(Error::other)?;
+        drop(page);
+        db.write_header()?;
+        assert_eq!(db.page_content_hash("a")?, hash);
+        
+        // Writing invalidates the hash
+        let mut page = db.open_page("a")?;
+        page.write_sparse(0, b"J").map_err(Error::other)?;
+        assert_ne!(page.content_hash().map_err(Error::other)?, hash);
+        drop(page);
+        db.write_header()?;
+        
+        assert!(crate::database::Database::from(db.clone()).verify_page_hash("a").map_err(Error::other)?);
+        
+        // Corrupted content no longer matches its hash
+        let chunk = db.get_page_inodes("a")?[0];
+        db.backing.lock_backing().map_err(Error::other)?.get_mut()[chunk.offset as usize] ^= 0xFF;
+        assert!(!crate::database::Database::from(db.clone()).verify_page_hash("a").map_err(Error::other)?);
+        
+        // Chunks larger than the copy buffer are hashed in several steps, to the same result
+        let content = (0..0x5000u32).map(|i| i as u8).collect::<Vec<_>>();
+        db.create_or_replace_page("b", &content)?;
+        assert_eq!(db.page_content_hash("b")?, <[u8; 32]>::from(<sha2::Sha256 as sha2::Digest>::digest(&content)));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn statistics() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        let empty = db.statistics()?;
+        for name in ["a", "b", "c"] {
+            let mut page = db.create_page(name)?;
+            page.write_sparse(0, &[0xAB; 0x100]).map_err(Error::other)?;
+        }
+        db.write_header()?;
+        
+        let stats = db.statistics()?;
+        assert_eq!(stats.page_count, empty.page_count + 3);
+        assert_eq!(stats.chunk_count, empty.chunk_count + 3);
+        assert_eq!(stats.string_count, empty.string_count + 3);
+        assert_eq!(stats.live_bytes, db.iter_chunks().map(|(_, chunk)| chunk.length).sum::<u64>());
+        assert!(stats.live_bytes + stats.free_bytes <= stats.total_bytes);
+        assert!((0.0..=1.0).contains(&stats.fragmentation_ratio));
+        
+        // Deleting a page leaves a gap at least as large as its content
+        let length = db.get_page_inodes("b")?[0].length;
+        db.delete_page("b")?;
+        
+        let after = crate::database::Database::from(db).statistics().map_err(Error::other)?;
+        assert_eq!(after.page_count, empty.page_count + 2);
+        assert_eq!(after.live_bytes, stats.live_bytes - length);
+        assert!(after.free_bytes >= stats.free_bytes + length);
+        assert!(after.largest_gap >= length);
+        assert!(after.fragmentation_ratio > stats.fragmentation_ratio);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn verify() -> Result<()> {
+        use crate::format::database::DatabaseError;
+        
+        let mut db = crate::format::blank::<Metadata>()?;
+        for name in ["a", "b"] {
+            let mut page = db.create_page(name)?;
+            page.write_sparse(0, &[0xAB; 0x100]).map_err(Error::other)?;
+        }
+        db.write_header()?;
+        assert_eq!(db.verify(), Ok(()));
+        assert!(crate::database::Database::from(db.clone()).verify().is_ok());
+        
+        // An empty chunk and a truncated backing object are reported together
+        let len = db.backing.lock_backing().map_err(Error::other)?.get_ref().len() as u64;
+        db.create_page_at_offset("empty", len + 0x100, 0)?;
+        let chunk = db.get_page_inodes("b")?[0];
+        db.backing.lock_backing().map_err(Error::other)?.get_mut().truncate(chunk.offset as usize + 1);
+        
+        let errors = db.verify().err().unwrap();
+        assert!(errors.iter().any(|err| matches!(err, DatabaseError::EmptyChunk { page, .. } if page == "empty")));
+        assert!(errors.iter().any(|err| matches!(err, DatabaseError::ChunkOutOfBounds { page, .. } if page == "b")));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn merge() -> Result<()> {
+        use std::sync::Arc;
+        use std::sync::Mutex;
+        use crate::format::database::ConflictPolicy;
+        
+        let mut other = crate::format::blank::<Metadata>()?;
+        other.create_or_replace_page("a", b"Hello")?;
+        
+        #[cfg(not(feature = "encryption"))]
+        let mut db = crate::format::blank::<Metadata>()?;
+        // Encrypted chunks are longer than the content they hold
+        #[cfg(feature = "encryption")]
+        let mut db = crate::format::blank_encrypted::<Metadata>([7u8; 32])?;
+        let written = Arc::new(Mutex::new(vec![]));
+        let hook = Arc::clone(&written);
+        db.set_write_hook(move |name, bytes| hook.lock().unwrap().push((name.to_owned(), bytes)));
+        
+        // Write hooks are passed the length of the merged content
+        db.merge(&other, ConflictPolicy::Error)?;
+        assert_eq!(*written.lock().unwrap(), vec![("a".to_owned(), 5)]);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn merge_from() -> Result<()> {
+        #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+        struct Other {
+            tag: Vec<u32>,
+        }
+        
+        // The source's metadata needn't match this database's, nor be stored in the same format
+        let mut source = crate::format::database::Database::create(Cursor::new(vec![]), Other { tag: vec![1, 2] })?;
+        #[cfg(feature = "bincode")]
+        source.set_metadata_format(crate::format::metadata::MetadataFormat::Bincode)?;
+        source.create_or_replace_page("a", b"Hello")?;
+        let bytes = source.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        
+        let mut db = crate::format::blank::<Metadata>()?;
+        let pages = db.list_pages_with_prefix("").len();
+        assert_eq!(db.merge_from::<Other, _>(Cursor::new(bytes))?, 1);
+        assert_eq!(db.list_pages_with_prefix("").len(), pages + 1);
+        let mut out = vec![];
+        db.pipe_page("a", &mut out)?;
+        assert_eq!(out, b"Hello");
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn import_export_page() -> Result<()> {
+        let content = (0..0x2800u32).map(|i| i as u8).collect::<Vec<_>>();
+        
+        let mut db = crate::database::Database::from(crate::format::blank::<Metadata>()?);
+        let page = db.import_page("imported", &mut Cursor::new(&content), 0x1000).map_err(Error::other)?;
+        
+        let mut out = vec![];
+        assert_eq!(page.export(&mut out).map_err(Error::other)?, content.len() as u64);
+        assert_eq!(out, content);
+        drop(page);
+        
+        // The chunks written by the import are what the page is reopened with
+        let mut out = vec![];
+        db.open_page("imported").map_err(Error::other)?.export(&mut out).map_err(Error::other)?;
+        assert_eq!(out, content);
+        
+        assert_eq!(db.import_page("empty", &mut std::io::empty(), 0x1000).map_err(Error::other)?.export(vec![]).map_err(Error::other)?, 0);
+        assert!(db.import_page("zero", &mut std::io::empty(), 0).is_err());
+        
+        Ok(())
+    }
+    
+    #[test]
+    #[cfg(feature = "mmap")]
+    pub fn mmap_backing() -> Result<()> {
+        use crate::backing::MmapBacking;
+        
+        let path = std::env::temp_dir().join(format!("fsdb-mmap-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        
+        // The file starts out empty, and is grown as the database is written
+        let mut db = crate::format::database::Database::<MmapBacking, Metadata>::create(MmapBacking::open(&path)?, Metadata::default())?;
+        db.create_or_replace_page("a", &[0xAB; 0x2000])?;
+        
+        let mut content = vec![];
+        db.pipe_page("a", &mut content)?;
+        assert_eq!(content, vec![0xAB; 0x2000]);
+        
+        db.backing.lock_backing().map_err(Error::other)?.flush()?;
+        assert_eq!(std::fs::metadata(&path)?.len(), db.statistics()?.total_bytes);
+        drop(db);
+        
+        let mut db = crate::format::database::Database::<MmapBacking, Metadata>::create(MmapBacking::open(&path)?, Metadata::default())?;
+        db.set_metadata(Metadata { max_page_size: 42, ..Metadata::default() })?;
+        drop(db);
+        
+        let db = crate::format::database::Database::<MmapBacking, Metadata>::open(MmapBacking::open(&path)?)?;
+        assert_eq!(db.get_metadata().max_page_size, 42);
+        drop(db);
+        
+        // The file doubles in size as it's appended to, and is trimmed to its content once flushed or dropped
+        {
+            use std::io::Seek;
+            use std::io::SeekFrom;
+            use std::io::Write;
+            
+            std::fs::remove_file(&path)?;
+            let mut backing = MmapBacking::open(&path)?;
+            for _ in 0..100 {
+                backing.write_all(&[0xAB])?;
+            }
+            assert_eq!(std::fs::metadata(&path)?.len(), 128);
+            assert_eq!(backing.seek(SeekFrom::End(0))?, 100);
+            
+            backing.flush()?;
+            assert_eq!(std::fs::metadata(&path)?.len(), 100);
+            
+            backing.write_all(&[0xCD])?;
+        }
+        assert_eq!(std::fs::read(&path)?, [&[0xAB; 100][..], &[0xCD]].concat());
+        
+        std::fs::remove_file(&path)?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    #[cfg(feature = "async")]
+    pub fn async_database() -> Result<()> {
+        use crate::async_db::AsyncDatabase;
+        
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        runtime.block_on(async {
+            let mut db = AsyncDatabase::create(Cursor::new(vec![]), Metadata::default()).await.map_err(Error::other)?;
+            let mut page = db.create_page("a").await.map_err(Error::other)?;
+            page.write_sparse(0, b"Hello").map_err(Error::other)?;
+            page.flush().map_err(Error::other)?;
+            drop(page);
+            db.flush().await.map_err(Error::other)?;
+            assert!(db.backing.get_ref().windows(5).any(|i| i == b"Hello"));
+            
+            assert_eq!(db.open_page("a").await.map_err(Error::other)?.into_vec().map_err(Error::other)?, b"Hello");
+            assert!(db.create_page("a").await.is_err());
+            
+            // Page-less databases can be read back from what was written, even over longer stale content
+            let db = AsyncDatabase::<_, Metadata>::create(Cursor::new(vec![0xFF; 0x10000]), Metadata::default()).await.map_err(Error::other)?;
+            assert!(db.backing.get_ref().len() < 0x10000);
+            let backing = Cursor::new(db.backing.get_ref().clone());
+            let mut db = AsyncDatabase::<_, Metadata>::open(backing).await.map_err(Error::other)?;
+            
+            // Blocks which didn't change aren't written again, so bytes changed behind the database's back survive
+            let mut page = db.create_page("a").await.map_err(Error::other)?;
+            page.write_sparse(0, &[0xAA; 0x4000]).map_err(Error::other)?;
+            page.flush().map_err(Error::other)?;
+            drop(page);
+            db.flush().await.map_err(Error::other)?;
+            
+            let tampered = db.backing.get_ref().windows(0x2000).position(|i| i == [0xAA; 0x2000]).ok_or(Error::other("The content is missing"))? + 0x1000;
+            db.backing.get_mut()[tampered] = 0xBB;
+            db.create_page("b").await.map_err(Error::other)?;
+            assert_eq!(db.backing.get_ref()[tampered], 0xBB);
+            
+            Ok(())
+        })
+    }
+    
+    #[test]
+    pub fn write_stream() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        let mut page = db.create_page("a")?;
+        page.write_sparse(0, b"Hello").map_err(Error::other)?;
+        
+        // Items are packed into shared chunks rather than allocated one at a time
+        page.write_stream((0..0x300u32).map(|i| [i as u8; 0x100])).map_err(Error::other)?;
+        assert_eq!(page.len(), 5 + 0x30000);
+        
+        // The page was flushed, so the database only needs to pick up its chunks
+        db.sync_pages()?;
+        assert_eq!(db.get_page_inodes("a")?.len(), 1 + 3);
+        
+        let content = db.open_page("a")?.into_vec().map_err(Error::other)?;
+        assert_eq!(&content[..5], b"Hello");
+        assert!(content[5..].chunks(0x100).enumerate().all(|(i, item)| item == [i as u8; 0x100]));
+        
+        // Items past the maximum size are rejected, but those before them are kept
+        page.set_max_page_size(Some(page.len() as u64 + 0x10));
+        let err = page.write_stream([b"World".as_slice(), &[0; 0x10]].into_iter()).err().unwrap();
+        assert!(matches!(err, crate::error::Error::TooLarge));
+        drop(page);
+        db.sync_pages()?;
+        
+        let content = db.open_page("a")?.into_vec().map_err(Error::other)?;
+        assert_eq!(&content[5 + 0x30000..], b"World");
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn database_builder() -> Result<()> {
+        use crate::database::DatabaseBuilder;
+        
+        let path = std::env::temp_dir().join(format!("fsdb-builder-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        
+        // Missing databases are only created if asked to, and given metadata to create them with
+        assert!(DatabaseBuilder::<Metadata>::new().open(&path).is_err());
+        assert!(DatabaseBuilder::<Metadata>::new().create_if_missing().open(&path).is_err());
+        
+        let mut db = DatabaseBuilder::new()
+            .create_if_missing()
+            .with_metadata(Metadata::default())
+            .max_page_size(0x10)
+            .sync_on_write(true)
+            .open(&path)
+            .map_err(Error::other)?;
+        
+        let mut page = db.open_page("/").map_err(Error::other)?;
+        assert!(matches!(page.write_stream([[0u8; 0x20]].into_iter()), Err(crate::error::Error::TooLarge)));
+        drop(page);
+        
+        // Writes go through as usual with syncing enabled
+        db.create_page("a").map_err(Error::other)?;
+        assert_eq!(db.find_pages_by_prefix("a"), vec!["a".to_owned()]);
+        drop(db);
+        
+        // Read-only databases can be opened and verified, but not written to
+        let mut db = DatabaseBuilder::<Metadata>::new()
+            .read_only()
+            .verify_on_open(true)
+            .lock_timeout(std::time::Duration::from_millis(10))
+            .open(&path)
+            .map_err(Error::other)?;
+        assert!(db.create_page("b").is_err());
+        
+        std::fs::remove_file(&path)?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn add_missing_entry_fields() -> Result<()> {
+        let db = crate::format::database::Database::create(Cursor::new(vec![]), Metadata::default())?;
+        let mut bytes = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        
+        // A database written before any optional entry fields existed, without a header CRC
+        bytes[0x08..0x10].fill(0);
+        
+        let mut db = crate::format::database::Database::<_, Metadata>::open(Cursor::new(bytes))?;
+        assert!(db.add_missing_entry_fields()?);
+        assert!(!db.add_missing_entry_fields()?);
+        
+        let bytes = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        assert_eq!(bytes[0x08], 0x01 | 0x02 | 0x04 | 0x08 | 0x80);
+        crate::format::database::DatabaseOpenOptions::new().accept_version(1u32).verify_header(true).open::<_, Metadata>(Cursor::new(bytes))?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn migrate_v1_to_v2() -> Result<()> {
+        let backing = Cursor::new(include_bytes!("../test/blank_generated.db").to_vec());
+        let mut db = crate::format::database::Database::<_, Metadata>::open_versioned(backing, 1u32)?;
+        db.create_or_replace_page("a", b"Hello")?;
+        db.create_or_replace_page("b", &[2u8; 0x2000])?;
+        db.set_access_control_list("a", vec![crate::access::Access::Read("guest".to_owned())])?;
+        db.write_header()?;
+        
+        db.migrate_v1_to_v2()?;
+        db.migrate_v1_to_v2()?;
+        db.verify_format()?;
+        assert!(db.validate_free_list());
+        
+        let bytes = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        assert_eq!(bytes[0x04..0x08], [0x02, 0x00, 0x00, 0x00]);
+        assert_eq!(bytes[0x50..0x60], [0u8; 16]);
+        
+        let db = crate::format::database::Database::<_, Metadata>::open_versioned(Cursor::new(bytes), 2u32)?;
+        db.verify_format()?;
+        assert_eq!(db.leak_inode_table()["a"].access_control_list, vec![crate::access::Access::Read("guest".to_owned())]);
+        for (name, content) in [("a", b"Hello".to_vec()), ("b", vec![2u8; 0x2000])] {
+            let mut out = vec![];
+            db.pipe_page(name, &mut out)?;
+            assert_eq!(out, content);
+        }
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "json")]
+    #[test]
+    pub fn export_as_json() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("test", b"Hello")?;
+        db.set_access_control_list("test", vec![crate::access::Access::ReadWrite("*".to_owned())])?;
+        db.write_header()?;
+        
+        // Pages are listed by name, so the root page comes first
+        let pages = serde_json::from_str::<serde_json::Value>(&db.export_inode_table_as_json()?).map_err(Error::other)?;
+        let chunk = db.get_page_inodes("test")?[0];
+        assert_eq!(pages.as_array().map(Vec::len), Some(2));
+        assert_eq!(pages[1]["name"], "test");
+        assert_eq!(pages[1]["chunks"], serde_json::json!([{ "offset": chunk.offset, "length": chunk.length }]));
+        assert_eq!(pages[1]["acl"], serde_json::json!(["*:rw"]));
+        assert!(pages[1]["modified"].as_u64() >= pages[1]["created"].as_u64());
+        
+        let strings = serde_json::from_str::<Vec<String>>(&db.export_string_table_as_json()?).map_err(Error::other)?;
+        assert!(strings.iter().any(|i| i == "test"));
+        
+        let header = serde_json::from_str::<serde_json::Value>(&db.export_header_as_json()?).map_err(Error::other)?;
+        assert_eq!(header["magic"], "FSDB");
+        assert_eq!(header["version"], 1);
+        assert_eq!(header["meta"]["max_journal_size"], 100);
+        assert!(header["inode_table"]["offset"].as_u64() > Some(0));
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "json")]
+    #[test]
+    pub fn json_metadata() -> Result<()> {
+        use crate::format::metadata::MetadataFormat;
+        
+        let mut db = crate::format::database::Database::create(Cursor::new(vec![]), Metadata { max_journal_size: 7, ..Metadata::default() })?;
+        assert_eq!(db.metadata_format()?, MetadataFormat::Ron);
+        
+        db.set_metadata_format(MetadataFormat::Json)?;
+        db.write_header()?;
+        
+        let bytes = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        assert_eq!(bytes[0x50], b'{');
+        
+        let db = crate::format::database::Database::<_, Metadata>::open(Cursor::new(bytes))?;
+        assert_eq!(db.metadata_format()?, MetadataFormat::Json);
+        assert_eq!(db.get_metadata().max_journal_size, 7);
+        assert_eq!(db.get_metadata().max_chunk_size, Metadata::default().max_chunk_size);
+        
+        // Changing the format again takes effect the next time the header is written
+        #[cfg(feature = "bincode")] {
+            let mut db = db;
+            db.set_metadata_format(MetadataFormat::Bincode)?;
+            db.write_header()?;
+            
+            let bytes = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+            let db = crate::format::database::Database::<_, Metadata>::open_with_format(Cursor::new(bytes), MetadataFormat::Ron)?;
+            assert_eq!(db.get_metadata().max_journal_size, 7);
+            assert_eq!(db.metadata_format()?, MetadataFormat::Ron);
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn hierarchical_paths() -> Result<()> {
+        let mut db = crate::database::Database::create(Cursor::new(vec![]), Metadata::default())
+            .map_err(Error::other)?;
+        
+        db.create_page_recursive("/users/alice/profile").map_err(Error::other)?;
+        db.create_page_recursive("/users/bob/profile").map_err(Error::other)?;
+        db.create_page_recursive("/users/bob-2").map_err(Error::other)?;
+        db.create_page_recursive("/users/carol/settings/theme").map_err(Error::other)?;
+        
+        // Existing ancestors are reused, but the page itself must be new
+        assert!(matches!(db.create_page_recursive("/users/alice/profile"), Err(crate::error::Error::AlreadyExists)));
+        db.open_page("/users/alice").map_err(Error::other)?;
+        
+        assert_eq!(db.list_children("/users"), vec!["/users/alice", "/users/bob", "/users/bob-2", "/users/carol"]);
+        assert_eq!(db.list_children("/users/"), db.list_children("/users"));
+        assert_eq!(db.list_children("/users/carol"), vec!["/users/carol/settings"]);
+        assert_eq!(db.list_children("/"), vec!["/users"]);
+        assert!(db.list_children("/users/alice/profile").is_empty());
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn find_pages_by_prefix() -> Result<()> {
+        let mut db = crate::database::Database::create(Cursor::new(vec![]), Metadata::default())
+            .map_err(Error::other)?;
+        
+        for name in ["/b", "/a/2", "/a/1", "/ab", "/c"] {
+            db.create_page(name).map_err(Error::other)?;
+        }
+        
+        let scanned = db.find_pages_by_prefix("/a");
+        assert_eq!(scanned, vec!["/a/1", "/a/2", "/ab"]);
+        
+        db.build_sorted_index();
+        assert_eq!(db.find_pages_by_prefix("/a"), scanned);
+        assert_eq!(db.find_pages_by_prefix("/a/"), vec!["/a/1", "/a/2"]);
+        assert!(db.find_pages_by_prefix("/d").is_empty());
+        
+        // Changes to the set of pages invalidate the index
+        db.create_page("/a/0").map_err(Error::other)?;
+        db.delete_page("/ab").map_err(Error::other)?;
+        db.rename_page("/b", "/a/3").map_err(Error::other)?;
+        assert_eq!(db.find_pages_by_prefix("/a"), vec!["/a/0", "/a/1", "/a/2", "/a/3"]);
+        
+        db.drop_sorted_index();
+        assert_eq!(db.find_pages_by_prefix("/a"), vec!["/a/0", "/a/1", "/a/2", "/a/3"]);
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn array_overlaps() -> Result<()> {
+        use crate::format::Array;
+        
+        let a = Array { offset: 0x10, length: 0x10, nonce: None };
+        let b = Array { offset: 0x18, length: 0x10, nonce: None };
+        let c = Array { offset: 0x20, length: 0x10, nonce: None };
+        let empty = Array { offset: 0x14, length: 0, nonce: None };
+        
+        assert!(a.overlaps(&b) && b.overlaps(&a));
+        assert_eq!(a.intersect(&b), Some(Array { offset: 0x18, length: 0x8, nonce: None }));
+        assert_eq!(a.intersect(&b).map(|i| i.length), Some(0x8));
+        
+        // Adjacent arrays don't share any bytes, and neither do empty ones
+        assert!(!a.overlaps(&c));
+        assert_eq!(a.intersect(&c), None);
+        assert!(!a.overlaps(&empty));
+        
+        assert_eq!(b.intersect(&Array { offset: 0, length: 0x100, nonce: None }).map(|i| (i.offset, i.length)), Some((0x18, 0x10)));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn recover_from_poison() -> Result<()> {
+        let mut db = crate::format::database::Database::create(Cursor::new(vec![]), Metadata::default())?;
+        db.create_or_replace_page("a", b"Hello")?;
+        
+        let mediator = std::sync::Arc::clone(&db.backing);
+        let panicked = std::thread::spawn(move || {
+            let _backing = mediator.lock_backing().unwrap();
+            panic!("Panicking while holding the backing object");
+        }).join();
+        assert!(panicked.is_err());
+        
+        let mut content = vec![];
+        assert!(db.pipe_page("a", &mut content).is_err());
+        
+        // Cloning doesn't panic on the poisoned backing object
+        let copy = db.clone();
+        copy.pipe_page("a", &mut content)?;
+        assert_eq!(content, b"Hello");
+        content.clear();
+        
+        db.recover_from_poison()?;
+        db.pipe_page("a", &mut content)?;
+        assert_eq!(content, b"Hello");
+        
+        db.create_or_replace_page("b", b"World")?;
+        content.clear();
+        db.pipe_page("b", &mut content)?;
+        assert_eq!(content, b"World");
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn agent_allocate_chunks() -> Result<()> {
+        let mut db = crate::format::database::Database::create(Cursor::new(vec![]), Metadata::default())?;
+        db.create_or_replace_page("a", b"Hello")?;
+        
+        // Requests are answered when the header is written, so the agent waits for the database
+        let agent = db.agent();
+        let allocation = std::thread::spawn(move || {
+            let first = agent.allocate_chunks(0x100)?;
+            let second = agent.allocate_chunks(0x100)?;
+            Ok::<_, crate::error::Error>((agent, first, second))
+        });
+        
+        while !allocation.is_finished() {
+            db.write_header()?;
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        let (agent, first, second) = allocation.join()
+            .map_err(|_| Error::other("Agent panicked"))?
+            .map_err(Error::other)?;
+        
+        let chunks = first.iter().chain(second.iter()).collect::<Vec<_>>();
+        assert!(first.iter().map(|i| i.length).sum::<u64>() >= 0x100);
+        assert!(second.iter().map(|i| i.length).sum::<u64>() >= 0x100);
+        
+        // Chunks handed to the agent stay reserved, so they overlap neither each other nor any page
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert!(chunks[i + 1..].iter().all(|other| !chunk.overlaps(other)));
+            assert!(db.iter_chunks().all(|(_, used)| !chunk.overlaps(&used)));
+        }
+        db.create_or_replace_page("b", &[0xAA; 0x200])?;
+        assert!(db.get_page_inodes("b")?.iter().all(|used| chunks.iter().all(|chunk| !chunk.overlaps(used))));
+        
+        // Neither rebuilding the free list nor compacting moves or hands out reserved chunks
+        db.delete_page("a")?;
+        db.rebuild_free_list()?;
+        db.compact()?;
+        assert!(db.validate_free_list());
+        assert!(db.iter_chunks().all(|(_, used)| chunks.iter().all(|chunk| !chunk.overlaps(&used))));
+        
+        // Reserved chunks can be installed into a page or given back, but only once
+        let requests = std::thread::spawn(move || {
+            let installed = agent.install_chunks("c", first.clone());
+            let released = agent.release_chunks(second.clone());
+            let released_again = agent.release_chunks(second.clone());
+            (agent, first, second, installed, released, released_again)
+        });
+        
+        while !requests.is_finished() {
+            db.write_header()?;
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        let (agent, first, second, installed, released, released_again) = requests.join()
+            .map_err(|_| Error::other("Agent panicked"))?;
+        
+        installed.map_err(Error::other)?;
+        released.map_err(Error::other)?;
+        assert!(released_again.is_err());
+        
+        assert_eq!(db.get_page_inodes("c")?.iter().map(|i| (i.offset, i.length)).collect::<Vec<_>>(), first.iter().map(|i| (i.offset, i.length)).collect::<Vec<_>>());
+        assert!(db.dump_state().contains("Agent reservations: []"));
+        db.create_or_replace_page("d", &[0xBB; 0x100])?;
+        assert!(db.get_page_inodes("d")?.iter().any(|used| second.iter().any(|chunk| chunk.overlaps(used))));
+        
+        // Handing out another agent disconnects the first
+        let _replacement = db.agent();
+        assert!(agent.allocate_chunks(0x10).is_err());
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "rwpage")]
+    #[test]
+    pub fn metadata_page_limits() -> Result<()> {
+        use std::io::Read;
+        use std::io::Seek;
+        use std::io::Write;
+        
+        let meta = Metadata { max_page_size: 0x100, max_chunk_size: 0x10, ..Metadata::default() };
+        let mut db = crate::format::database::Database::create(Cursor::new(vec![]), meta)?;
+        
+        // Growth is spread across chunks no larger than the maximum chunk size
+        let mut page = db.create_page("a")?;
+        page.write_all(&[0xAA; 0x38])?;
+        page.flush()?;
+        assert_eq!(page.len(), 0x38);
+        drop(page);
+        db.sync_pages()?;
+        assert_eq!(db.get_page_inodes("a")?.iter().map(|i| i.length).collect::<Vec<_>>(), vec![0x10, 0x10, 0x10, 0x08]);
+        
+        // Writes past the end leave a zeroed gap, which counts towards the chunks too
+        let mut page = db.open_page("a")?;
+        page.write_sparse(0x48, &[0xBB; 0x10])?;
+        page.seek(std::io::SeekFrom::Start(0))?;
+        let mut content = vec![];
+        page.read_to_end(&mut content)?;
+        assert_eq!(content, [&[0xAA; 0x38][..], &[0x00; 0x10], &[0xBB; 0x10]].concat());
+        
+        // Writes which would grow the page beyond its maximum size are refused, but it can be overwritten up to it
+        assert!(matches!(page.write_sparse(0xF8, &[0xCC; 0x10]), Err(crate::error::Error::TooLarge)));
+        page.write_sparse(0xF0, &[0xCC; 0x10])?;
+        assert_eq!(page.len(), 0x100);
+        drop(page);
+        
+        // Limits set on the database take precedence over the metadata's
+        db.set_max_page_size(Some(0x200));
+        let mut page = db.open_page("a")?;
+        page.write_sparse(0x100, &[0xDD; 0x100])?;
+        assert_eq!(page.len(), 0x200);
+        drop(page);
+        
+        // Replacing the metadata changes the limits of pages opened afterwards
+        db.set_max_page_size(None);
+        db.set_metadata(Metadata { max_page_size: 0x300, max_chunk_size: 0x100, ..Metadata::default() })?;
+        let mut page = db.open_page("a")?;
+        page.write_sparse(0x200, &[0xEE; 0x100])?;
+        assert_eq!(page.len(), 0x300);
+        assert!(matches!(page.write_sparse(0x300, &[0xEE]), Err(crate::error::Error::TooLarge)));
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn free_list() -> Result<()> {
+        use crate::format::Array;
+        
+        let a = Array { offset: 0x10, length: 0x10, nonce: None };
+        assert_eq!(a.merge(&Array { offset: 0x20, length: 0x10, nonce: None }).map(|i| (i.offset, i.length)), Some((0x10, 0x20)));
+        assert_eq!(a.merge(&Array { offset: 0x18, length: 0x20, nonce: None }).map(|i| (i.offset, i.length)), Some((0x10, 0x28)));
+        assert!(a.merge(&Array { offset: 0x30, length: 0x10, nonce: None }).is_none());
+        
+        let mut db = crate::format::database::Database::create(Cursor::new(vec![]), Metadata::default())?;
+        db.create_or_replace_page("a", b"Hello")?;
+        db.create_or_replace_page("b", b"World")?;
+        db.create_or_replace_page("c", b"!")?;
+        
+        let released = db.get_history("a")?[0].chunks_snapshot.clone();
+        db.delete_page("a")?;
+        db.delete_page("b")?;
+        let len = db.free_list_len();
+        assert!(len > 0);
+        assert!(db.validate_free_list());
+        
+        // The free list is persisted along with the history table
+        let container = db.backing.lock_backing().map_err(Error::other)?.get_ref().clone();
+        let mut db = crate::format::database::Database::<_, Metadata>::open(Cursor::new(container))?;
+        assert_eq!(db.free_list_len(), len);
+        
+        // Released chunks are reused before the backing object grows
+        let free = db.get_free_regions();
+        let size = db.backing.lock_backing().map_err(Error::other)?.get_ref().len();
+        assert!(free.iter().any(|region| region.intersect(&released[0]) == Some(released[0])));
+        db.create_or_replace_page("d", b"Hello")?;
+        let chunk = db.get_history("d")?[0].chunks_snapshot[0];
+        assert!(free.iter().any(|region| region.intersect(&chunk) == Some(chunk)));
+        assert_eq!(db.backing.lock_backing().map_err(Error::other)?.get_ref().len(), size);
+        assert_eq!(db.open_page("d")?.into_vec().map_err(Error::other)?, b"Hello");
+        db.assert_consistent();
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn tables_reuse_space() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        
+        // The tables grow with every page, but the space they leave behind when they move is freed, so the file grows with the pages rather than with every copy of the tables
+        for i in 0..400 {
+            db.create_or_replace_page(&format!("page{}", i), &[i as u8])?;
+        }
+        
+        let size = db.backing.lock_backing().map_err(Error::other)?.get_ref().len();
+        assert!(size < 400 * 0x200, "{} bytes for 400 single-byte pages", size);
+        assert!(db.validate_free_list());
+        db.verify_format()?;
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "rwpage")]
+    #[test]
+    pub fn unsynced_chunks_survive_header_writes() -> Result<()> {
+        use std::io::Read;
+        use std::io::Seek;
+        
+        let mut db = crate::format::blank::<Metadata>()?;
+        let mut page = db.create_page("a")?;
+        page.write_sparse(0x10, &[0xAB; 0x100]).map_err(Error::other)?;
+        
+        // The page's new chunk isn't in the inode table yet, but the tables mustn't be written over it
+        db.create_or_replace_page("b", &[0xCD; 0x40])?;
+        db.write_header()?;
+        
+        let expected = [&[0x00; 0x10][..], &[0xAB; 0x100]].concat();
+        let mut content = vec![];
+        page.seek(std::io::SeekFrom::Start(0))?;
+        page.read_to_end(&mut content)?;
+        assert_eq!(content, expected);
+        
+        drop(page);
+        db.write_header()?;
+        
+        let mut content = vec![];
+        db.pipe_page("a", &mut content)?;
+        assert_eq!(content, expected);
+        assert!(db.backing.pending_allocations().is_empty());
+        db.verify_format()?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn closed_pages_sync_before_writes() -> Result<()> {
+        let mut db = crate::format::database::Database::create(Cursor::new(vec![]), Metadata::default())?;
+        db.create_or_replace_page("a", b"Hello")?;
+        
+        // The closed page's descriptor is recorded before the page is replaced, rather than overwriting it at the next header write
+        let mut page = db.open_page("a")?;
+        page.write_sparse(0, b"J").map_err(Error::other)?;
+        drop(page);
+        db.create_or_replace_page("a", b"World")?;
+        db.write_header()?;
+        assert_eq!(db.open_page("a")?.into_vec().map_err(Error::other)?, b"World");
+        
+        // A page whose chunks were replaced while it was open is discarded when it's flushed
+        let mut page = db.open_page("a")?;
+        db.create_or_replace_page("a", b"Again")?;
+        page.write_sparse(0, b"X").map_err(Error::other)?;
+        drop(page);
+        db.write_header()?;
+        assert_eq!(db.open_page("a")?.into_vec().map_err(Error::other)?, b"Again");
+        db.assert_consistent();
+        
+        Ok(())
+    }
+    
+    #[test]
+    pub fn exclusive_page() -> Result<()> {
+        let mut db = crate::format::blank::<Metadata>()?;
+        db.create_or_replace_page("a", b"Hello, World")?;
+        let chunk = db.get_page_inodes("a")?[0];
+        let mediator = db.backing.clone();
+        
+        let mut page = db.open_read_write_locked("a")?;
+        page.write_sparse(0, b"J").map_err(Error::other)?;
+        
+        // Neither other instances of the page nor anything else going through the range locks get at its chunks
+        assert!(matches!(crate::error::Error::from(db.open_page("a").err().unwrap()), crate::error::Error::Busy));
+        assert!(matches!(crate::error::Error::from(db.open_read_write_locked("a").err().unwrap()), crate::error::Error::Busy));
+        assert!(matches!(mediator.try_read_chunk(chunk, None), Err(crate::error::Error::Busy)));
+        
+        drop(page);
+        assert!(mediator.try_read_chunk(chunk, None).is_ok());
+        assert_eq!(db.open_page("a")?.into_vec().map_err(Error::other)?, b"Jello, World");
         
-        assert!(db.leak_string_table().len() as u64 == db.string_table_range.length);
+        // Pages can't be opened exclusively while their chunks are locked
+        let guard = mediator.try_write_range(b"J", chunk.offset, None).map_err(Error::other)?;
+        assert!(matches!(crate::error::Error::from(db.open_read_write_locked("a").err().unwrap()), crate::error::Error::Busy));
+        drop(guard);
+        assert!(!db.is_page_open("a"));
         
         Ok(())
     }
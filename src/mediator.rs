@@ -1,67 +1,449 @@
-use std::io::{Read, Write, Seek};
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::sync::TryLockError;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::error::Error;
 use crate::format::Array;
+use crate::format::TAG_LENGTH;
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum RangeLock {
     Read(Array),
-    Write(Array)
+    Write(Array),
+    /// Held for the lifetime of an `ExclusivePage`, admitting access to the range only on behalf of the owner it names. See `Page::lock_owner`.
+    Exclusive(Array, usize),
 }
 
 impl RangeLock {
     fn get_range(&self) -> Array {
         match self {
             Self::Read(range) => *range,
-            Self::Write(range) => *range
+            Self::Write(range) => *range,
+            Self::Exclusive(range, _) => *range,
+        }
+    }
+
+    /// Whether the locked range shares at least one byte with the `length` bytes starting at `offset`. Adjacent ranges don't overlap.
+    pub(crate) fn overlaps(&self, offset: u64, length: u64) -> bool {
+        let range = self.get_range();
+        range.offset < offset + length && range.end() > offset
+    }
+
+    /// Whether this lock, while it's held, stands in the way of acquiring `lock` on behalf of `owner`. Only overlapping locks can conflict.
+    /// Reads may overlap other reads, and exclusive locks admit any access on behalf of their owner.
+    pub(crate) fn blocks(&self, lock: &RangeLock, owner: Option<usize>) -> bool {
+        let range = lock.get_range();
+        if !self.overlaps(range.offset, range.length) { return false; }
+
+        match self {
+            Self::Read(_) => !matches!(lock, Self::Read(_)),
+            Self::Write(_) => true,
+            Self::Exclusive(_, held) => owner != Some(*held),
+        }
+    }
+}
+
+/// A range lock held in a `Mediator`'s lock list, which is removed from it when the guard is dropped.
+/// Guards share ownership of the lock list, so they can be kept for as long as needed, such as for the lifetime of an `ExclusivePage`.
+pub(crate) struct RangeLockGuard {
+    locks: Arc<Mutex<Vec<RangeLock>>>,
+    lock: RangeLock,
+}
+
+impl RangeLockGuard {
+    /// Add a lock to `locks`, which is held until the returned guard is dropped. The caller is expected to have checked the lock doesn't conflict with any held already.
+    fn acquire(locks: &Arc<Mutex<Vec<RangeLock>>>, held: &mut Vec<RangeLock>, lock: RangeLock) -> Self {
+        held.push(lock);
+
+        Self {
+            locks: Arc::clone(locks),
+            lock,
+        }
+    }
+}
+
+impl Drop for RangeLockGuard {
+    /// Remove this guard's entry, matched by kind, owner, offset and length. Identical locks are interchangeable, so only the first match is removed.
+    /// Poisoning is ignored, as the lock list is only ever pushed to and removed from.
+    fn drop(&mut self) {
+        let mut locks = self.locks.lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        // Arrays compare by offset alone
+        if let Some(index) = locks.iter().position(|i| *i == self.lock && i.get_range().length == self.lock.get_range().length) {
+            locks.swap_remove(index);
         }
     }
 }
 
-pub(crate) struct Mediator<Backing> where Backing: Read + Write + Seek + 'static {
-    locks: Mutex<Vec<RangeLock>>,
-    backing: Mutex<Backing>
+pub(crate) struct Mediator<Backing> where Backing: Read + Write + Seek {
+    locks: Arc<Mutex<Vec<RangeLock>>>,
+    backing: Mutex<Backing>,
+    /// Set while the database is sealed. Writes through the range locks are refused until it's cleared.
+    sealed: AtomicBool,
+    /// The AES-256-GCM key chunks written with `try_write_chunk` are encrypted with. Never persisted.
+    #[cfg(feature = "encryption")]
+    key: Mutex<Option<[u8; 32]>>,
+    /// Regions handed out by `allocate` whose pages haven't been synchronised with the database yet, so that no descriptor it knows of refers to them. The database keeps its tables clear of them.
+    allocations: Mutex<Vec<Array>>,
 }
 
-impl<Backing> Mediator<Backing> where Backing: Read + Write + Seek + 'static {
-    pub fn try_read_range<Buffer>(&self, mut buffer: Buffer, offset: u64) -> Result<(), Error> where Buffer: AsMut<[u8]> {
+impl<Backing> Mediator<Backing> where Backing: Read + Write + Seek {
+    pub fn new(backing: Backing) -> Self {
+        Self {
+            locks: Arc::new(Mutex::new(vec![])),
+            backing: Mutex::new(backing),
+            sealed: AtomicBool::new(false),
+            #[cfg(feature = "encryption")]
+            key: Mutex::new(None),
+            allocations: Mutex::new(vec![]),
+        }
+    }
+
+    /// Use the same encryption key as `other`, so that chunks copied from it can be read.
+    pub fn with_encryption_of<Other: Read + Write + Seek>(self, other: &Mediator<Other>) -> Self {
+        #[cfg(feature = "encryption")]
+        self.set_key(other.key());
+        #[cfg(not(feature = "encryption"))]
+        let _ = other;
+
+        self
+    }
+
+    /// Encrypt chunks written from now on with `key`, and decrypt encrypted chunks with it. `None` stores new chunks as-is.
+    #[cfg(feature = "encryption")]
+    pub fn set_key(&self, key: Option<[u8; 32]>) {
+        *self.key.lock().unwrap_or_else(PoisonError::into_inner) = key;
+    }
+
+    #[cfg(feature = "encryption")]
+    fn key(&self) -> Option<[u8; 32]> {
+        *self.key.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether chunks are encrypted as they're written.
+    pub fn is_encrypted(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        return self.key().is_some();
+        #[cfg(not(feature = "encryption"))]
+        return false;
+    }
+
+    /// The number of bytes a chunk needs to hold `length` bytes of content. Encrypted chunks also hold an authentication tag.
+    pub fn stored_length(&self, length: u64) -> u64 {
+        match self.is_encrypted() {
+            true => length + TAG_LENGTH,
+            false => length,
+        }
+    }
+
+    /// Prepare `content` for storage in a chunk, returning the bytes to store and the nonce they were encrypted with.
+    /// With a key set, `content` is encrypted under a fresh random nonce and followed by its authentication tag. Otherwise it's stored as-is.
+    pub fn encrypt_chunk(&self, content: &[u8]) -> Result<(Vec<u8>, Option<[u8; 12]>), Error> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = self.key() {
+            use aes_gcm::aead::Aead;
+            use aes_gcm::KeyInit;
+
+            let nonce: [u8; 12] = rand::random();
+            let stored = aes_gcm::Aes256Gcm::new(&key.into())
+                .encrypt(&nonce.into(), content)
+                .map_err(|_| Error::misc("Encryption failed"))?;
+
+            return Ok((stored, Some(nonce)));
+        }
+
+        Ok((content.to_vec(), None))
+    }
+
+    /// Recover the content of a chunk from its stored bytes. Chunks without a nonce are returned as-is.
+    /// Fails with `Corrupted` if an encrypted chunk fails authentication, such as when the key is wrong, and `NotPermitted` if no key is set.
+    pub fn decrypt_chunk(&self, stored: Vec<u8>, nonce: Option<[u8; 12]>) -> Result<Vec<u8>, Error> {
+        let Some(nonce) = nonce else { return Ok(stored); };
+
+        #[cfg(feature = "encryption")]
+        {
+            use aes_gcm::aead::Aead;
+            use aes_gcm::KeyInit;
+
+            let key = self.key()
+                .ok_or(Error::NotPermitted)?;
+
+            aes_gcm::Aes256Gcm::new(&key.into())
+                .decrypt(&nonce.into(), stored.as_slice())
+                .map_err(|_| Error::Corrupted)
+        }
+
+        #[cfg(not(feature = "encryption"))]
         {
-            let mut locks = self.locks.try_lock()?;
-            if let None = locks.iter().find(|i| matches!(i, RangeLock::Write(range) if range.offset >= offset && range.end() < offset)) {
-                locks.push(RangeLock::Read(Array {
-                    offset,
-                    length: buffer.as_mut().len() as u64,
-                }));
-            } else {
-                return Err(Error::Busy);
+            let _ = nonce;
+            Err(Error::misc("Encrypted chunks require the `encryption` feature"))
+        }
+    }
+
+    pub fn set_sealed(&self, sealed: bool) {
+        self.sealed.store(sealed, Ordering::SeqCst);
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.sealed.load(Ordering::SeqCst)
+    }
+
+    /// Take the list of range locks, failing with `Busy` if it's held elsewhere.
+    /// If a thread panicked while holding it, the locks that thread registered would never be released, so the list is emptied and its poison lifted.
+    fn try_lock_ranges(&self) -> Result<MutexGuard<'_, Vec<RangeLock>>, Error> {
+        match self.locks.try_lock() {
+            Ok(locks) => Ok(locks),
+            Err(TryLockError::WouldBlock) => Err(Error::Busy),
+            Err(TryLockError::Poisoned(poisoned)) => {
+                let mut locks = poisoned.into_inner();
+                locks.clear();
+                self.locks.clear_poison();
+
+                Ok(locks)
             }
         }
+    }
+
+    /// Add `lock` to the lock list on behalf of `owner`, failing with `Busy` if a lock held already conflicts with it. See `RangeLock::blocks`.
+    fn try_acquire(&self, lock: RangeLock, owner: Option<usize>) -> Result<RangeLockGuard, Error> {
+        let mut locks = self.try_lock_ranges()?;
+        if locks.iter().any(|held| held.blocks(&lock, owner)) {
+            return Err(Error::Busy);
+        }
+
+        Ok(RangeLockGuard::acquire(&self.locks, &mut locks, lock))
+    }
+
+    /// Add all of `requested` to the lock list at once on behalf of `owner`, failing with `Busy` and adding none of them if any conflicts with a lock held already.
+    fn try_acquire_all(&self, requested: Vec<RangeLock>, owner: Option<usize>) -> Result<Vec<RangeLockGuard>, Error> {
+        let mut locks = self.try_lock_ranges()?;
+        if locks.iter().any(|held| requested.iter().any(|lock| held.blocks(lock, owner))) {
+            return Err(Error::Busy);
+        }
+
+        Ok(requested.into_iter()
+            .map(|lock| RangeLockGuard::acquire(&self.locks, &mut locks, lock))
+            .collect())
+    }
+
+    /// Lift the poison left on the range locks and the backing object by threads which panicked while holding them, returning whether any were poisoned.
+    /// The range locks are released, as in `try_read_range`. The backing object is kept as it is, so a write interrupted by the panic may have been left incomplete.
+    pub fn recover_from_poison(&self) -> bool {
+        let poisoned = self.locks.is_poisoned() || self.backing.is_poisoned();
+
+        if self.locks.is_poisoned() {
+            self.locks.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clear();
+            self.locks.clear_poison();
+        }
+        self.backing.clear_poison();
+
+        poisoned
+    }
+
+    /// Gain exclusive access to the backing object, bypassing the range locks. Used by the database to manage its tables.
+    pub fn lock_backing(&self) -> Result<MutexGuard<'_, Backing>, Error> {
+        Ok(self.backing.lock()?)
+    }
+
+    /// A copy of the backing object. A thread which panicked while holding it doesn't stop it being copied, although a write interrupted by the panic may have been left incomplete. See `recover_from_poison`.
+    pub(crate) fn clone_backing(&self) -> Backing where Backing: Clone {
+        self.backing.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Read `buffer.len()` bytes at `offset` on behalf of `owner`, failing with `Busy` if any part of the range is write-locked.
+    /// The range stays read-locked until the returned guard is dropped.
+    pub fn try_read_range<Buffer>(&self, mut buffer: Buffer, offset: u64, owner: Option<usize>) -> Result<RangeLockGuard, Error> where Buffer: AsMut<[u8]> {
+        let range = Array {
+            offset,
+            length: buffer.as_mut().len() as u64,
+            nonce: None,
+        };
+
+        let guard = self.try_acquire(RangeLock::Read(range), owner)?;
 
         // I was hoping to avoid mutexes as they only allow a synchronised read/write operation.as
         // However, coordinating read/writes does exactly the same thing, and adds lots of code.
         // Plus the OS will synchronise read/writes across threads, so we ultimately gain nothing.
-        self.backing.try_lock()?.read_exact(buffer.as_mut())?;
+        let mut backing = self.backing.try_lock()?;
+        backing.seek(SeekFrom::Start(offset))?;
+        backing.read_exact(buffer.as_mut())?;
 
-        Ok(())
+        Ok(guard)
     }
 
-    pub fn try_write_range<Buffer>(&self, buffer: Buffer, offset: u64) -> Result<(), Error> where Buffer: AsRef<[u8]> {
-        {
-            let mut locks = self.locks.try_lock()?;
-            if let None = locks.iter().find(|i| i.get_range().offset >= offset && i.get_range().end() < offset) {
-                locks.push(RangeLock::Write(Array {
-                    offset,
-                    length: buffer.as_mut().len() as u64,
-                }));
-            } else {
-                return Err(Error::Busy);
-            }
-        }
+    /// Read-lock each of `regions` without reading them, failing with `Busy` if any part of them is write-locked.
+    /// The regions stay read-locked until the returned guards are dropped. Used where the reads themselves bypass the backing object's lock.
+    #[cfg(feature = "rayon")]
+    pub fn try_lock_read_ranges(&self, regions: &[Array]) -> Result<Vec<RangeLockGuard>, Error> {
+        self.try_acquire_all(regions.iter().copied().map(RangeLock::Read).collect(), None)
+    }
+
+    /// Write-lock each of `regions` without writing to them, failing with `Busy` if any part of them is locked already.
+    /// The regions stay write-locked until the returned guards are dropped. Used where the writes themselves bypass the backing object's lock.
+    pub fn try_lock_write_ranges(&self, regions: &[Array]) -> Result<Vec<RangeLockGuard>, Error> {
+        self.try_acquire_all(regions.iter().copied().map(RangeLock::Write).collect(), None)
+    }
+
+    /// Lock each of `regions` for `owner`'s exclusive use, failing with `Busy` if any part of them is locked already.
+    /// Until the returned guards are dropped, only access on behalf of `owner` is admitted to the regions.
+    pub fn try_lock_exclusive_ranges(&self, regions: &[Array], owner: usize) -> Result<Vec<RangeLockGuard>, Error> {
+        self.try_acquire_all(regions.iter().map(|region| RangeLock::Exclusive(*region, owner)).collect(), Some(owner))
+    }
+
+    /// Write `buffer` at `offset` on behalf of `owner`, failing with `Busy` if any part of the range is locked.
+    /// The range stays write-locked until the returned guard is dropped.
+    pub fn try_write_range<Buffer>(&self, buffer: Buffer, offset: u64, owner: Option<usize>) -> Result<RangeLockGuard, Error> where Buffer: AsRef<[u8]> {
+        if self.is_sealed() { return Err(Error::NotPermitted); }
+
+        let range = Array {
+            offset,
+            length: buffer.as_ref().len() as u64,
+            nonce: None,
+        };
+
+        let guard = self.try_acquire(RangeLock::Write(range), owner)?;
 
         // I was hoping to avoid mutexes as they only allow a synchronised read/write operation.as
         // However, coordinating read/writes does exactly the same thing, and adds lots of code.
         // Plus the OS will synchronise read/writes across threads, so we ultimately gain nothing.
-        self.backing.try_lock()?.write_all(buffer.as_mut())?;
+        let mut backing = self.backing.try_lock()?;
+        backing.seek(SeekFrom::Start(offset))?;
+        backing.write_all(buffer.as_ref())?;
+
+        Ok(guard)
+    }
+
+    /// Write `content` to `chunk` with `try_write_range`, encrypting it first if a key is set. `chunk` must be `stored_length(content.len())` bytes long.
+    /// Returns `chunk` with the nonce its content was encrypted with, which is needed to read it back.
+    pub fn try_write_chunk(&self, content: &[u8], chunk: Array, owner: Option<usize>) -> Result<Array, Error> {
+        let (stored, nonce) = self.encrypt_chunk(content)?;
+        if stored.len() as u64 != chunk.length {
+            return Err(Error::misc("Content doesn't fit the chunk"));
+        }
+
+        self.try_write_range(&stored, chunk.offset, owner)?;
+
+        Ok(Array { nonce, ..chunk })
+    }
+
+    /// Read the content of `chunk` with `try_read_range`, decrypting it if it's encrypted. See `decrypt_chunk`.
+    pub fn try_read_chunk(&self, chunk: Array, owner: Option<usize>) -> Result<Vec<u8>, Error> {
+        let mut stored = vec![0u8; chunk.length as usize];
+        self.try_read_range(&mut stored, chunk.offset, owner)?;
+
+        self.decrypt_chunk(stored, chunk.nonce)
+    }
+
+    /// Read the given regions into a single buffer and replace their content with the result of `update`, returning the previous content.
+    /// The regions are write-locked and the backing object is held throughout, so no other access can interleave between the read and the write.
+    pub fn try_update_ranges<Update>(&self, regions: &[Array], update: Update, owner: Option<usize>) -> Result<Vec<u8>, Error> where Update: FnOnce(&[u8]) -> Vec<u8> {
+        if self.is_sealed() { return Err(Error::NotPermitted); }
+
+        let _guards = self.try_acquire_all(regions.iter().copied().map(RangeLock::Write).collect(), owner)?;
+
+        let mut backing = self.backing.try_lock()?;
+
+        let mut previous = vec![];
+        for region in regions {
+            let mut chunk = vec![0u8; region.length as usize];
+            backing.seek(SeekFrom::Start(region.offset))?;
+            backing.read_exact(&mut chunk)?;
+            previous.extend(chunk);
+        }
+
+        let new = update(&previous);
+        if new.len() != previous.len() {
+            return Err(Error::misc("Updated content must be the same length as the original"));
+        }
+
+        let mut remaining = &new[..];
+        for region in regions {
+            let (head, tail) = remaining.split_at(region.length as usize);
+            backing.seek(SeekFrom::Start(region.offset))?;
+            backing.write_all(head)?;
+            remaining = tail;
+        }
+
+        Ok(previous)
+    }
+
+    /// Describe the currently held range locks, such as `Locks: 3 (read: 2 at [0x1000..0x2000, 0x5000..0x6000], write: 1 at [0x3000..0x4000])`.
+    pub fn stats_summary(&self) -> String {
+        let Ok(locks) = self.locks.try_lock() else {
+            return "Locks: unavailable".to_owned();
+        };
+
+        let ranges = |write: bool| locks.iter()
+            .filter(|i| !matches!(i, RangeLock::Read(_)) == write)
+            .map(|i| i.get_range())
+            .map(|i| format!("{:#x}..{:#x}", i.offset, i.end()))
+            .collect::<Vec<_>>();
+        let (read, write) = (ranges(false), ranges(true));
+
+        format!("Locks: {} (read: {} at [{}], write: {} at [{}])", locks.len(), read.len(), read.join(", "), write.len(), write.join(", "))
+    }
+
+    /// Grow the backing object by `length` bytes set to `byte`, returning the newly appended region.
+    pub fn allocate_filled(&self, length: u64, byte: u8) -> Result<Array, Error> {
+        if self.is_sealed() { return Err(Error::NotPermitted); }
+
+        let mut backing = self.backing.try_lock()?;
+
+        let offset = backing.seek(SeekFrom::End(0))?;
+        Self::write_repeated(&mut *backing, byte, length)?;
+
+        let region = Array { offset, length, nonce: None };
+        self.allocations.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(region);
+
+        Ok(region)
+    }
+
+    /// The regions handed out by `allocate` which haven't been settled yet. See `settle_allocations`.
+    pub(crate) fn pending_allocations(&self) -> Vec<Array> {
+        self.allocations.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Forget about regions handed out by `allocate`, once the database has either taken them into its inode table or let them go.
+    pub(crate) fn settle_allocations(&self, regions: &[Array]) {
+        self.allocations.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|region| !regions.contains(region));
+    }
+
+    /// Set every byte of `region` to `byte`, without buffering the whole region in memory.
+    pub fn try_fill_range(&self, region: Array, byte: u8, owner: Option<usize>) -> Result<(), Error> {
+        if self.is_sealed() { return Err(Error::NotPermitted); }
+
+        let _guard = self.try_acquire(RangeLock::Write(region), owner)?;
+
+        let mut backing = self.backing.try_lock()?;
+        backing.seek(SeekFrom::Start(region.offset))?;
+        Self::write_repeated(&mut *backing, byte, region.length)
+    }
+
+    /// Write `byte` `length` times at the backing object's cursor, reusing a single 64KiB block.
+    fn write_repeated(backing: &mut Backing, byte: u8, length: u64) -> Result<(), Error> {
+        let block = vec![byte; length.min(0x10000) as usize];
+
+        let mut remaining = length;
+        while remaining > 0 {
+            let len = remaining.min(block.len() as u64);
+            backing.write_all(&block[..len as usize])?;
+            remaining -= len;
+        }
 
         Ok(())
     }
@@ -1,31 +1,143 @@
-use std::cell::Cell;
 use std::io::Read;
 use std::io::Seek;
 use std::io::Write;
 use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ops::DerefMut;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::SystemTime;
 
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+
 use crate::access::Access;
 use crate::error::Error;
 use crate::format::Array;
+use crate::format::compression::CompressionAlgorithm;
+use crate::format::wal::Wal;
+use crate::format::wal::{WalOp, WalRecord};
 use crate::mediator::Mediator;
+use crate::mediator::RangeLockGuard;
 
 /// Metadata about the page it describes.
-#[derive(Debug, Clone)]
-pub(crate) struct PageDescriptor {
+/// The chunk list is left out when serialised, as chunks are physical addresses which mean nothing outside of the database they belong to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageDescriptor {
     /// The name of the page (typically a path)
-    pub(crate) name: String,
+    pub name: String,
     /// A list of generically-defined access lists. It is up to the caller to interpret these.
-    pub(crate) access_control_list: Vec<Access>,
-    /// When the page was last modified - determined by querying the journal
-    pub(crate) modified: SystemTime,
-    /// When the page was created - determined by querying the journal
-    pub(crate) created: SystemTime,
+    pub access_control_list: Vec<Access>,
+    /// When the page was last modified
+    pub modified: SystemTime,
+    /// When the page was created
+    pub created: SystemTime,
     /// A list of chunks ((start, length)) in order
-    pub(crate) inodes: Vec<Array>,
+    #[serde(skip)]
+    pub inodes: Vec<Array>,
+    /// The number of bytes of the chunks which hold content. The remainder is allocated but unused.
+    pub content_length: u64,
+    /// How the content stored in the chunks is compressed. Replacing a page's content wholesale stores it uncompressed.
+    pub compression: CompressionAlgorithm,
+    /// A SHA-256 hash of the content, decrypted and decompressed, recorded whenever the content is replaced wholesale. Modifying the page in place clears it.
+    pub checksum: Option<[u8; 32]>,
+    /// The number of pages (including this one) whose chunk lists are identical, i.e. which were linked with `Database::create_linked_page`.
+    /// Derived from the chunk lists whenever the database's header is written, and never persisted.
+    #[serde(skip)]
+    pub link_count: u64,
+    /// Application-defined bytes stored alongside the page in the inode table.
+    /// By convention, it starts with the page's MIME type, prefixed by its length as a single byte, optionally followed by the size of the page's records as a u64.
+    pub user_data: Vec<u8>,
+}
+
+impl PageDescriptor {
+    /// The total number of bytes reserved across all of the page's chunks.
+    #[inline]
+    pub fn total_allocated_size(&self) -> u64 {
+        self.inodes.iter().map(|a| a.length).sum::<u64>()
+    }
+
+    /// The proportion of the allocated space which doesn't hold content. 0.0 means perfectly packed, approaching 1.0 means almost entirely wasted.
+    #[inline]
+    pub fn fragmentation_ratio(&self) -> f64 {
+        match self.total_allocated_size() {
+            0 => 0.0,
+            total => 1.0 - (self.content_length as f64 / total as f64),
+        }
+    }
+
+    /// The number of chunks the page's content is spread across.
+    #[inline]
+    pub fn chunk_count(&self) -> usize {
+        self.inodes.len()
+    }
+
+    /// Translate a page-relative range into the regions of the backing object it occupies, in order.
+    /// The part of the range extending beyond the end of the chunks is ignored.
+    pub(crate) fn physical_regions(&self, offset: u64, length: u64) -> Vec<Array> {
+        let end = offset.saturating_add(length);
+        let mut regions = vec![];
+
+        let mut chunk_start = 0u64;
+        for chunk in self.inodes.iter() {
+            let chunk_end = chunk_start + chunk.length;
+
+            if chunk_end > offset && chunk_start < end {
+                let from = offset.max(chunk_start);
+                let to = end.min(chunk_end);
+
+                regions.push(Array {
+                    offset: chunk.offset + (from - chunk_start),
+                    length: to - from,
+                    nonce: None,
+                });
+            }
+
+            chunk_start = chunk_end;
+        }
+
+        regions
+    }
+
+    /// The MIME type stored at the start of `user_data`, or `None` if there isn't one.
+    pub fn content_type(&self) -> Result<Option<String>, Error> {
+        let Some(&len) = self.user_data.first() else { return Ok(None); };
+        if len == 0 { return Ok(None); }
+
+        let mime = self.user_data.get(1..1 + len as usize)
+            .ok_or(Error::ParseError)?;
+
+        String::from_utf8(mime.to_vec())
+            .map(Some)
+            .map_err(Error::other)
+    }
+
+    /// The size of the fixed-size records the page holds, stored in `user_data` after the MIME type. `None` if the page isn't a record page.
+    pub fn record_size(&self) -> Option<u64> {
+        let start = 1 + *self.user_data.first()? as usize;
+
+        self.user_data.get(start..start + 8)
+            .and_then(|size| size.try_into().ok())
+            .map(u64::from_le_bytes)
+            .filter(|size| *size > 0)
+    }
+
+    /// Record the size of the page's records in `user_data`, keeping the MIME type in front of it. Anything previously stored after the MIME type is replaced.
+    pub fn set_record_size(&mut self, size: u64) {
+        let start = match self.user_data.first() {
+            Some(&len) => (1 + len as usize).min(self.user_data.len()),
+            None => {
+                self.user_data.push(0);
+                1
+            }
+        };
+
+        self.user_data.truncate(start);
+        self.user_data.extend_from_slice(&size.to_le_bytes());
+    }
 }
 
 pub enum SpaceRequirements {
@@ -43,32 +155,63 @@ pub enum PageRequest {
     RefreshChunks,
     AllocateSpace(SpaceRequirements),
     ChangeACL(ACLOperation),
+    /// The page was flushed. The database should record its descriptor in the inode table, unless the page's chunks were changed through the database since `previous`, the chunks the page had when it was opened or last flushed.
+    /// `allocated` lists the chunks the page allocated since then, which the database keeps clear of its tables until now. If the database keeps a journal, `before` holds the page's content from before the first write since then.
+    Sync { descriptor: Box<PageDescriptor>, previous: Vec<Array>, allocated: Vec<Array>, before: Option<Vec<u8>> },
     Close,
 }
 
-enum Response {
-    Ok,
-    Busy,
-    NotPermitted,
-}
-
-pub struct PageResponse {
-    request: PageRequest,
-    response: Response,
-}
-
-pub struct ReadStream<Data: AsRef<[u8]>> {
+/// Yields a page's content a piece at a time, reading each piece from the backing object only once it's asked for. Created by `Page::read_stream_chunked`.
+/// Pieces are at most `chunk_size` bytes long, and never span more than one of the page's chunks. The stream ends with the page's content, even if its last chunk extends further. A failed read ends the stream.
+pub struct ReadStream<Data: AsRef<[u8]>, Backing> where Backing: Read + Write + Seek + 'static {
     chunk_size: usize,
-    buffer: Vec<u8>,
+    /// The content of compressed and encrypted pages, which is streamed from memory instead of the chunks
+    buffered: Option<Vec<u8>>,
     inodes: Vec<Array>,
+    mediator: Arc<Mediator<Backing>>,
+    /// The lock owner of the page the stream was created from. See `Page::lock_owner`.
+    owner: Option<usize>,
+    /// The index of the chunk the next piece is read from
+    chunk_index: usize,
+    /// Where in the current chunk (or the buffered content) the next piece begins
+    intra_chunk_offset: u64,
+    /// The number of bytes of content left to yield. The page's chunks may hold more than its content
+    remaining: u64,
     data: PhantomData<Data>
 }
 
-impl<Data: AsRef<[u8]>> Iterator for ReadStream<Data> {
+impl<Data: AsRef<[u8]> + From<Vec<u8>>, Backing> Iterator for ReadStream<Data, Backing> where Backing: Read + Write + Seek + 'static {
     type Item = Data;
     
     fn next(&mut self) -> Option<Self::Item> {
-        todo!()
+        if self.chunk_size == 0 { return None; }
+
+        if let Some(content) = self.buffered.as_ref() {
+            let start = self.intra_chunk_offset as usize;
+            if start >= content.len() { return None; }
+
+            let end = (start + self.chunk_size).min(content.len());
+            self.intra_chunk_offset = end as u64;
+
+            return Some(Data::from(content[start..end].to_vec()));
+        }
+
+        while self.inodes.get(self.chunk_index).is_some_and(|chunk| self.intra_chunk_offset >= chunk.length) {
+            self.chunk_index += 1;
+            self.intra_chunk_offset = 0;
+        }
+
+        let chunk = self.inodes.get(self.chunk_index).filter(|_| self.remaining > 0)?;
+        let len = (chunk.length - self.intra_chunk_offset)
+            .min(self.chunk_size as u64)
+            .min(self.remaining);
+
+        let mut piece = vec![0u8; len as usize];
+        self.mediator.try_read_range(&mut piece[..], chunk.offset + self.intra_chunk_offset, self.owner).ok()?;
+        self.intra_chunk_offset += len;
+        self.remaining -= len;
+
+        Some(Data::from(piece))
     }
 }
 
@@ -79,89 +222,876 @@ pub struct Page<Backing> where Backing: Read + Write + Seek + 'static {
     /// It includes information about the page's access permissions, it's journal as well as the list of chunks the page is to consume.
     descriptor: PageDescriptor,
     
-    /// Pages are expected to buffer their content for faster read/write. 
-    /// The buffer may be size-constrained by the database's configuration object (metadata), or contain the entire page
-    large_buffer: Mutex<Cell<Vec<u8>>>,
-
     /// The structure which regulates and manages read/write access to various chunks of the backing object.
     /// It uses atomic primitives internally to ensure synchronous locking, and can therefore be passed around immutably.
-    mediator: Arc<Mediator<Backing>>
+    mediator: Arc<Mediator<Backing>>,
+
+    /// The regions of the backing object which were written to since the page was last synchronised with the database.
+    dirty: Vec<Array>,
+
+    /// The chunks allocated since the page was last synchronised with the database, which the database is told to stop keeping clear of its tables once the page's descriptor reaches it.
+    allocated: Vec<Array>,
+
+    /// Held for as long as the page is open, letting the database tell whether it is. Released when the page is closed.
+    token: Option<Arc<()>>,
+
+    /// The position within the page's content the next read or write takes place at.
+    #[cfg_attr(not(feature = "rwpage"), allow(dead_code))]
+    cursor_pos: u64,
+
+    /// Where the page's descriptor is sent when it's flushed, to be recorded in the database's inode table.
+    requests: Sender<PageRequest>,
+
+    /// The modification time of the descriptor last sent to the database.
+    synced: SystemTime,
+
+    /// The chunks the page had when it was opened or last flushed, which the database's inode table is expected to still list when the page is next flushed.
+    synced_inodes: Vec<Array>,
+
+    /// Set once the page has been closed, so that closing it again does nothing.
+    closed: bool,
+
+    /// The database's write-ahead log, which writes are recorded in before they take place. See `Database::enable_wal`.
+    wal: Option<Arc<Mutex<Wal>>>,
+
+    /// Whether the database keeps a journal, in which case the page's content is kept in `before` ahead of its first write since it was opened or last flushed. See `Database::enable_journal`.
+    journaled: bool,
+
+    /// The page's content from before its first write since it was opened or last flushed, sent along with its descriptor so the database can record the change in its journal.
+    before: Option<Vec<u8>>,
+
+    /// The entire content of compressed or encrypted pages, decompressed and decrypted when the page is opened.
+    /// Such pages are read from and written to here, and recompressed and re-encrypted as a whole when they're flushed.
+    buffered: Option<Vec<u8>>,
+
+    /// Set when the buffered content is written to, until it's next stored in the page's chunks.
+    buffer_dirty: bool,
+
+    /// The length the page may not grow beyond, if any.
+    max_page_size: Option<u64>,
+
+    /// The largest chunk the page allocates at once when it grows, if it's limited. Larger growth is spread across several chunks.
+    max_chunk_size: Option<u64>,
 }
 
 impl<Backing> Page<Backing> where Backing: Read + Write + Seek + 'static {
+    /// Construct a page over the chunks listed in `descriptor`, accessing the backing object through `mediator`.
+    /// Flushing sends the page's descriptor to `requests`.
+    pub(crate) fn new(descriptor: PageDescriptor, mediator: Arc<Mediator<Backing>>, token: Arc<()>, requests: Sender<PageRequest>) -> Self {
+        Self {
+            synced: descriptor.modified,
+            synced_inodes: descriptor.inodes.clone(),
+            mediator,
+            dirty: vec![],
+            allocated: vec![],
+            token: Some(token),
+            cursor_pos: 0,
+            requests,
+            closed: false,
+            wal: None,
+            journaled: false,
+            before: None,
+            buffered: None,
+            buffer_dirty: false,
+            max_page_size: None,
+            max_chunk_size: None,
+            descriptor,
+        }
+    }
+
+    /// Identifies the page's access to the range locks, for as long as it's open. Exclusive locks taken out by `Database::open_read_write_locked` admit access on behalf of this owner only.
+    pub(crate) fn lock_owner(&self) -> Option<usize> {
+        self.token.as_ref().map(|token| Arc::as_ptr(token) as usize)
+    }
+
+    /// Load the content of compressed and encrypted pages, which can only be accessed as a whole. Does nothing for other pages.
+    /// Fails with `Corrupted` if an encrypted chunk fails authentication.
+    pub(crate) fn load(mut self) -> Result<Self, Error> {
+        let encrypted = self.mediator.is_encrypted() || self.descriptor.inodes.iter().any(|chunk| chunk.nonce.is_some());
+        if self.descriptor.compression == CompressionAlgorithm::None && !encrypted {
+            return Ok(self);
+        }
+
+        let mut content = vec![];
+        for chunk in self.descriptor.inodes.iter() {
+            content.extend(self.mediator.try_read_chunk(*chunk, self.lock_owner())?);
+        }
+        content.truncate(self.descriptor.content_length as usize);
+
+        self.buffered = Some(self.descriptor.compression.decompress(&content)?);
+        Ok(self)
+    }
+
+    /// Fail for compressed and encrypted pages, whose content can't be accessed piecemeal by `operation`.
+    fn require_unbuffered(&self, operation: &str) -> Result<(), Error> {
+        match self.buffered {
+            Some(_) => Err(Error::misc(format!("'{}' is compressed or encrypted, so it doesn't support {}", self.descriptor.name, operation))),
+            None => Ok(()),
+        }
+    }
+
+    /// The length of the page's content, which for compressed pages is its decompressed length.
+    #[cfg(feature = "rwpage")]
+    fn content_length(&self) -> u64 {
+        match self.buffered.as_ref() {
+            Some(content) => content.len() as u64,
+            None => self.descriptor.content_length,
+        }
+    }
+
+    /// Record the page's writes in `wal` before they take place.
+    pub(crate) fn with_wal(mut self, wal: Option<Arc<Mutex<Wal>>>) -> Self {
+        self.wal = wal;
+        self
+    }
+
+    /// Keep the page's content from before its writes, for the database to record in its journal.
+    pub(crate) fn with_journal(mut self, journaled: bool) -> Self {
+        self.journaled = journaled;
+        self
+    }
+
+    /// Keep the page's current content in `before`, unless it's not journaled or already holds the content from before an earlier write.
+    fn keep_before(&mut self) -> Result<(), Error> {
+        if !self.journaled || self.before.is_some() {
+            return Ok(());
+        }
+
+        let mut before = vec![];
+        self.export(&mut before)?;
+        // Chunks may hold more than the page's content
+        if self.buffered.is_none() {
+            before.truncate(self.descriptor.content_length as usize);
+        }
+
+        self.before = Some(before);
+        Ok(())
+    }
+
+    /// Stop the page from growing beyond `max_page_size` bytes. See `set_max_page_size`.
+    pub(crate) fn with_max_page_size(mut self, max_page_size: Option<u64>) -> Self {
+        self.max_page_size = max_page_size;
+        self
+    }
+
+    /// Allocate at most `max_chunk_size` bytes at a time when the page grows. `None` lifts the limit.
+    pub(crate) fn with_max_chunk_size(mut self, max_chunk_size: Option<u64>) -> Self {
+        self.max_chunk_size = max_chunk_size;
+        self
+    }
+
+    /// The number of bytes the page's chunks hold. For compressed and encrypted pages, the length of their content instead.
     pub fn len(&self) -> usize {
+        if let Some(content) = self.buffered.as_ref() {
+            return content.len();
+        }
+
         self.descriptor
             .inodes
             .iter()
             .map(|i| i.length)
             .sum::<u64>() as usize
     }
+
+    /// Whether the page holds no bytes. See `len`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     
+    /// The SHA-256 hash of the page's content, as recorded in its checksum. Only the content counts, not the bytes stored for it, so the hash is the same however the page is compressed or split into chunks, and matches `Database::page_content_hash`.
+    /// Chunks are hashed one at a time, so the content of plain pages is never held in memory as a whole. The hash is kept as the page's checksum until the page is next written to, so it's only computed again after the content changes.
+    pub fn content_hash(&mut self) -> Result<[u8; 32], Error> {
+        if let Some(checksum) = self.descriptor.checksum {
+            return Ok(checksum);
+        }
+
+        let mut hasher = Sha256::new();
+        match self.buffered.as_ref() {
+            Some(content) => hasher.update(content),
+            None => {
+                // Chunks may extend past the end of the content, such as after the page was truncated
+                let mut remaining = self.descriptor.content_length;
+                for chunk in self.descriptor.inodes.iter() {
+                    if remaining == 0 {
+                        break;
+                    }
+
+                    let content = self.mediator.try_read_chunk(*chunk, self.lock_owner())?;
+                    let len = remaining.min(content.len() as u64);
+
+                    hasher.update(&content[..len as usize]);
+                    remaining -= len;
+                }
+            }
+        }
+
+        let hash: [u8; 32] = hasher.finalize().into();
+        self.descriptor.checksum = Some(hash);
+
+        Ok(hash)
+    }
+
+    /// Write the page's content to `writer` as plain bytes, returning the number written. Chunks are copied one at a time, so the content is never held in memory as a whole.
+    pub fn export<W: Write>(&self, mut writer: W) -> Result<u64, Error> {
+        if let Some(content) = self.buffered.as_ref() {
+            writer.write_all(content)?;
+            return Ok(content.len() as u64);
+        }
+
+        let mut total = 0u64;
+        for chunk in self.descriptor.inodes.iter() {
+            let content = self.mediator.try_read_chunk(*chunk, self.lock_owner())?;
+            writer.write_all(&content)?;
+            total += content.len() as u64;
+        }
+
+        Ok(total)
+    }
+
+    /// The MIME type recorded for this page, if any.
+    pub fn content_type(&self) -> Result<Option<String>, Error> {
+        self.descriptor.content_type()
+    }
+
+    /// Record the MIME type of the page's content at the start of its user data, replacing any previously recorded type.
+    /// The rest of the user data is preserved. The change is persisted once the page is synchronised with the database.
+    pub fn set_content_type(&mut self, mime: &str) -> Result<(), Error> {
+        let len = u8::try_from(mime.len())
+            .map_err(|_| Error::TooLarge)?;
+
+        let rest = match self.descriptor.user_data.first() {
+            Some(&prev) => self.descriptor.user_data.get(1 + prev as usize..).unwrap_or(&[]),
+            None => &[],
+        };
+
+        self.descriptor.user_data = [&[len][..], mime.as_bytes(), rest].concat();
+        self.descriptor.modified = SystemTime::now();
+
+        Ok(())
+    }
+
+    /// The size of the page's fixed-size records, failing if it isn't a record page.
+    fn require_record_size(&self) -> Result<u64, Error> {
+        self.descriptor.record_size()
+            .ok_or(Error::misc(format!("'{}' is not a record page", self.descriptor.name)))
+    }
+
+    /// The number of complete records the page holds. Always 0 for pages which aren't record pages.
+    pub fn record_count(&self) -> u64 {
+        match self.descriptor.record_size() {
+            Some(size) => self.descriptor.content_length / size,
+            None => 0,
+        }
+    }
+
+    /// Read the record at `index`, i.e. the `record_size` bytes at `index * record_size`. Fails if the page isn't a record page or the record lies beyond its end.
+    pub fn read_record(&self, index: u64) -> Result<Vec<u8>, Error> {
+        let size = self.require_record_size()?;
+        if index >= self.record_count() {
+            return Err(Error::NotFound);
+        }
+        self.require_unbuffered("reading records")?;
+
+        let mut record = vec![0u8; size as usize];
+
+        let mut remaining = &mut record[..];
+        for region in self.physical_regions(index * size, size) {
+            let (head, tail) = remaining.split_at_mut(region.length as usize);
+            self.mediator.try_read_range(&mut *head, region.offset, self.lock_owner())?;
+            remaining = tail;
+        }
+
+        Ok(record)
+    }
+
+    /// Overwrite the record at `index` with `data`, which must be exactly `record_size` bytes long. Writing past the last record grows the page.
+    pub fn write_record(&mut self, index: u64, data: &[u8]) -> Result<(), Error> {
+        let size = self.require_record_size()?;
+        if data.len() as u64 != size {
+            return Err(Error::misc(format!("Records of '{}' are {} bytes long, got {}", self.descriptor.name, size, data.len())));
+        }
+
+        self.write_sparse(index * size, data)
+    }
+
+    /// Consume the page, returning its entire (decompressed) content. The page is closed afterwards.
+    pub fn into_vec(mut self) -> Result<Vec<u8>, Error> {
+        // The buffer is taken, so it has to be stored before the page is closed
+        self.store_buffer()?;
+        if let Some(content) = self.buffered.take() {
+            return Ok(content);
+        }
+
+        let mut content = vec![0u8; self.len()];
+
+        let mut remaining = &mut content[..];
+        for region in self.physical_regions(0, self.len() as u64) {
+            let (head, tail) = remaining.split_at_mut(region.length as usize);
+            self.mediator.try_read_range(&mut *head, region.offset, self.lock_owner())?;
+            remaining = tail;
+        }
+
+        Ok(self.descriptor.compression.decompress(&content)?)
+    }
+
+    /// Consume the page, returning its entire content as a string. Fails if the content isn't valid UTF-8.
+    pub fn into_string(self) -> Result<String, Error> {
+        String::from_utf8(self.into_vec()?)
+            .map_err(Error::other)
+    }
+
     pub fn read_all(&self) -> Result<(), Error> {
-        let chunks = &self.descriptor
-            .inodes;
-        
         Ok(())
     }
     
-    pub fn read_stream<Data: AsRef<[u8]>>(&self) -> Result<ReadStream<Data>, Error> {
-        todo!()
+    /// Translate a page-relative range into the regions of the backing object it occupies, in order.
+    /// The part of the range extending beyond the end of the page is ignored.
+    fn physical_regions(&self, offset: u64, length: u64) -> Vec<Array> {
+        self.descriptor.physical_regions(offset, length)
+    }
+
+    /// Write `data` at the page-relative `offset`, touching only the chunks the affected range falls within.
+    /// If the range extends beyond the end of the page, a new chunk is allocated covering the (zero-filled) gap as well as the remaining data.
+    /// If the database has a write-ahead log enabled, the write is recorded in it first.
+    pub fn write_sparse(&mut self, offset: u64, data: &[u8]) -> Result<(), Error> {
+        if data.is_empty() { return Ok(()); }
+
+        let len = self.len() as u64;
+        let end = offset + data.len() as u64;
+        if self.max_page_size.is_some_and(|max| end > len.max(max)) {
+            return Err(Error::TooLarge);
+        }
+
+        self.keep_before()?;
+
+        if let Some(wal) = self.wal.as_ref() {
+            wal.lock()?.append(&WalRecord {
+                op: WalOp::Write,
+                page_name: self.descriptor.name.clone(),
+                offset,
+                data: data.to_vec(),
+            })?;
+        }
+
+        if let Some(content) = self.buffered.as_mut() {
+            let end = offset as usize + data.len();
+            if end > content.len() {
+                content.resize(end, 0x00);
+            }
+            content[offset as usize..end].copy_from_slice(data);
+
+            self.buffer_dirty = true;
+            self.descriptor.checksum = None;
+            self.descriptor.modified = SystemTime::now();
+            return Ok(());
+        }
+
+        let mut remaining = data;
+        for region in self.physical_regions(offset, data.len() as u64) {
+            let (head, tail) = remaining.split_at(region.length as usize);
+            self.mediator.try_write_range(head, region.offset, self.lock_owner())?;
+            self.dirty.push(region);
+            remaining = tail;
+        }
+
+        // Whatever lies past the end is written to new chunks of at most the maximum chunk size. Any gap between the end and `offset` is left zeroed
+        let mut pos = len;
+        while pos < end {
+            let chunk = self.allocate((end - pos).min(self.max_chunk_size.unwrap_or(u64::MAX)))?;
+            let from = offset.max(pos);
+
+            if from < chunk.length + pos {
+                let (head, tail) = remaining.split_at((chunk.length + pos - from) as usize);
+                self.mediator.try_write_range(head, chunk.offset + (from - pos), self.lock_owner())?;
+                remaining = tail;
+            }
+
+            self.descriptor.inodes.push(chunk);
+            self.dirty.push(chunk);
+            pos += chunk.length;
+        }
+
+        self.descriptor.content_length = self.descriptor.content_length.max(end);
+
+        self.descriptor.checksum = None;
+        self.descriptor.modified = SystemTime::now();
+
+        Ok(())
+    }
+
+    /// Grow the backing object by `length` zeroed bytes for the page to use. See `allocate_filled`.
+    fn allocate(&mut self, length: u64) -> Result<Array, Error> {
+        self.allocate_filled(length, 0)
+    }
+
+    /// Grow the backing object by `length` bytes set to `byte` for the page to use, keeping track of the chunk until the page is next flushed.
+    fn allocate_filled(&mut self, length: u64, byte: u8) -> Result<Array, Error> {
+        let chunk = self.mediator.allocate_filled(length, byte)?;
+        self.allocated.push(chunk);
+
+        Ok(chunk)
+    }
+
+    /// Compress the buffered content with the page's algorithm into a newly allocated chunk, encrypting it if the database is encrypted, which replaces the page's chunks.
+    /// Does nothing unless the buffer was written to since it was last stored. The chunks it replaces are released by the database once the page's descriptor reaches it.
+    fn store_buffer(&mut self) -> Result<(), Error> {
+        let Some(content) = self.buffered.as_ref().filter(|_| self.buffer_dirty) else { return Ok(()); };
+        let compressed = self.descriptor.compression.compress(content)?;
+
+        let chunk = self.allocate(self.mediator.stored_length(compressed.len() as u64))?;
+        let chunk = self.mediator.try_write_chunk(&compressed, chunk, self.lock_owner())?;
+
+        self.descriptor.inodes = vec![chunk];
+        self.descriptor.content_length = compressed.len() as u64;
+        self.dirty.push(chunk);
+        self.buffer_dirty = false;
+
+        Ok(())
+    }
+
+    /// Set the first `len` bytes of the page to `byte`, growing the page if it's shorter - the page equivalent of `calloc`.
+    /// The content is written in fixed-size blocks rather than buffered, so large pages can be initialised without holding their content in memory.
+    pub fn fill(&mut self, byte: u8, len: u64) -> Result<(), Error> {
+        if len == 0 { return Ok(()); }
+        self.require_unbuffered("filling")?;
+
+        let current = self.len() as u64;
+        self.keep_before()?;
+
+        for region in self.physical_regions(0, len) {
+            self.mediator.try_fill_range(region, byte, self.lock_owner())?;
+            self.dirty.push(region);
+        }
+
+        if len > current {
+            let chunk = self.allocate_filled(len - current, byte)?;
+            self.descriptor.inodes.push(chunk);
+            self.dirty.push(chunk);
+        }
+
+        self.descriptor.content_length = self.descriptor.content_length.max(len);
+
+        self.descriptor.checksum = None;
+        self.descriptor.modified = SystemTime::now();
+
+        Ok(())
+    }
+
+    /// Append `data` to the end of the page, returning the number of bytes written.
+    pub fn extend_from_slice(&mut self, data: &[u8]) -> Result<usize, Error> {
+        let len = self.len() as u64;
+        self.write_sparse(len, data)?;
+
+        Ok(data.len())
+    }
+
+    /// Append everything `reader` yields to the end of the page, 64KiB at a time. Returns the total number of bytes written.
+    pub fn extend_from_reader<R: Read>(&mut self, mut reader: R) -> Result<u64, Error> {
+        let mut buffer = vec![0u8; 0x10000];
+        let mut total = 0u64;
+
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            total += self.extend_from_slice(&buffer[..read])? as u64;
+        }
+
+        Ok(total)
+    }
+
+    /// Insert `data` before the page's existing content. The data is placed in a new chunk at the front of the chunk list, so the existing content isn't copied.
+    /// > **Note**: Chunks are concatenated in the order they're listed, regardless of where they lie in the backing object.
+    pub fn prepend(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.is_empty() { return Ok(()); }
+        self.require_unbuffered("prepending")?;
+        self.keep_before()?;
+
+        let chunk = self.allocate(data.len() as u64)?;
+        self.mediator.try_write_range(data, chunk.offset, self.lock_owner())?;
+
+        self.descriptor.inodes.insert(0, chunk);
+        self.descriptor.content_length += chunk.length;
+        self.dirty.push(chunk);
+        self.descriptor.checksum = None;
+        self.descriptor.modified = SystemTime::now();
+
+        Ok(())
+    }
+
+    /// Atomically replace the bytes at `offset` with `new`, provided they currently equal `expected`. Returns whether the swap took place.
+    /// The affected range stays write-locked for the duration, so no other access can interleave. Both slices must have the same length.
+    pub fn compare_and_swap(&mut self, offset: u64, expected: &[u8], new: &[u8]) -> Result<bool, Error> {
+        if expected.len() != new.len() {
+            return Err(Error::misc("Expected and new content must be the same length"));
+        }
+
+        let mut swapped = false;
+        self.fetch_and_update(offset, expected.len(), |current| if current == expected {
+            swapped = true;
+            new.to_vec()
+        } else {
+            current.to_vec()
+        })?;
+
+        Ok(swapped)
+    }
+
+    /// Atomically replace the `len` bytes at `offset` with the result of `update`, returning the previous content.
+    /// The affected range stays write-locked for the duration. `update` must return exactly `len` bytes, as the page isn't reallocated.
+    pub fn fetch_and_update<Update: FnOnce(&[u8]) -> Vec<u8>>(&mut self, offset: u64, len: usize, update: Update) -> Result<Vec<u8>, Error> {
+        if offset + len as u64 > self.len() as u64 {
+            return Err(Error::misc("Range extends beyond the end of the page"));
+        }
+        self.require_unbuffered("atomic updates")?;
+        self.keep_before()?;
+
+        let regions = self.physical_regions(offset, len as u64);
+        let mut changed = false;
+        let previous = self.mediator.try_update_ranges(&regions, |current| {
+            let new = update(current);
+            changed = new != current;
+            new
+        }, self.lock_owner())?;
+
+        if changed {
+            self.dirty.extend(regions);
+            self.descriptor.checksum = None;
+            self.descriptor.modified = SystemTime::now();
+        }
+
+        Ok(previous)
+    }
+    
+    /// Stream the page's content in pieces of up to 64KiB. See `read_stream_chunked`.
+    pub fn read_stream<Data: AsRef<[u8]> + From<Vec<u8>>>(&self) -> Result<ReadStream<Data, Backing>, Error> {
+        Ok(self.stream(0x10000))
+    }
+
+    /// Stream the page's content in pieces of up to `chunk_size` bytes, each read from the backing object as it's reached. The stream reads the chunks the page had when it was created, independently of the page's cursor.
+    pub fn read_stream_chunked(&self, chunk_size: usize) -> ReadStream<Vec<u8>, Backing> {
+        self.stream(chunk_size)
+    }
+
+    fn stream<Data: AsRef<[u8]>>(&self, chunk_size: usize) -> ReadStream<Data, Backing> {
+        ReadStream {
+            chunk_size,
+            buffered: self.buffered.clone(),
+            inodes: self.descriptor.inodes.clone(),
+            mediator: self.mediator.clone(),
+            owner: self.lock_owner(),
+            chunk_index: 0,
+            intra_chunk_offset: 0,
+            remaining: match self.buffered.as_ref() {
+                Some(content) => content.len() as u64,
+                None => self.descriptor.content_length,
+            },
+            data: PhantomData,
+        }
     }
     
+    /// Limit how large the page may grow. Writes which would grow it further fail with `TooLarge`. `None` lifts the limit.
+    pub fn set_max_page_size(&mut self, max_page_size: Option<u64>) {
+        self.max_page_size = max_page_size;
+    }
+
+    /// Append every item `content` yields to the end of the page, without collecting them in memory first. Items are written into 64KiB chunks (or chunks of the maximum chunk size, if that's smaller), allocated as the previous one fills up; the last is cut down to what was written to it.
+    /// The page is flushed once `content` is exhausted. If an item would grow the page beyond its maximum size, the items before it are kept and flushed, and `TooLarge` is returned.
+    /// Fails for compressed and encrypted pages, which can only be written as a whole.
     pub fn write_stream<Iter: Iterator<Item=Source>, Source: AsRef<[u8]>>(&mut self, content: Iter) -> Result<(), Error> {
-        todo!()
+        self.require_unbuffered("streaming writes")?;
+
+        // The chunk currently being filled at its allocated length, and how much of it has been. The copy in the descriptor only covers what's been written to it
+        let mut tail: Option<Array> = None;
+        let mut filled = 0u64;
+
+        for item in content {
+            let mut data = item.as_ref();
+            if data.is_empty() { continue; }
+
+            let len = self.len() as u64;
+            if self.max_page_size.is_some_and(|max| len + data.len() as u64 > max) {
+                self.flush()?;
+                return Err(Error::TooLarge);
+            }
+
+            self.keep_before()?;
+
+            if let Some(wal) = self.wal.as_ref() {
+                wal.lock()?.append(&WalRecord {
+                    op: WalOp::Write,
+                    page_name: self.descriptor.name.clone(),
+                    offset: len,
+                    data: data.to_vec(),
+                })?;
+            }
+
+            while !data.is_empty() {
+                let chunk = match tail {
+                    Some(chunk) if filled < chunk.length => chunk,
+                    _ => {
+                        let chunk = self.allocate(self.max_chunk_size.unwrap_or(u64::MAX).min(0x10000))?;
+                        self.descriptor.inodes.push(Array { length: 0, ..chunk });
+                        tail = Some(chunk);
+                        filled = 0;
+                        chunk
+                    }
+                };
+
+                let (head, rest) = data.split_at(data.len().min((chunk.length - filled) as usize));
+                let region = Array { offset: chunk.offset + filled, length: head.len() as u64, nonce: None };
+                self.mediator.try_write_range(head, region.offset, self.lock_owner())?;
+
+                if let Some(last) = self.descriptor.inodes.last_mut() {
+                    last.length += region.length;
+                }
+                self.dirty.push(region);
+                filled += region.length;
+
+                self.descriptor.content_length += region.length;
+                self.descriptor.checksum = None;
+                self.descriptor.modified = SystemTime::now();
+
+                data = rest;
+            }
+        }
+
+        self.flush()
     }
     
+    /// Synchronise the page with the database, which records the page's chunk list, content length and user data in its inode table the next time the header is written.
+    /// Writes go straight through to the backing object, so only the descriptor needs sending. Compressed and encrypted pages store their buffered content first. Nothing is sent if the page hasn't changed since it was opened or last flushed.
+    /// > **Note**: The descriptor replaces whatever the inode table holds for the page, including changes made through the database since the page was opened. If those changed the page's chunks, such as `create_or_replace_page`, the descriptor is discarded instead.
     pub fn flush(&mut self) -> Result<(), Error> {
-        todo!()
+        self.store_buffer()?;
+
+        if self.descriptor.modified == self.synced && self.allocated.is_empty() {
+            return Ok(());
+        }
+
+        self.requests.send(PageRequest::Sync {
+            descriptor: Box::new(self.descriptor.clone()),
+            previous: self.synced_inodes.clone(),
+            allocated: std::mem::take(&mut self.allocated),
+            before: self.before.take(),
+        })
+            .map_err(|_| Error::misc(format!("The database '{}' belongs to was closed", self.descriptor.name)))?;
+
+        self.synced = self.descriptor.modified;
+        self.synced_inodes = self.descriptor.inodes.clone();
+        self.dirty.clear();
+
+        Ok(())
     }
     
-    pub fn close(&mut self) {
-        todo!()
+    /// Flush the page and tell the database it was closed. Closing a page which is already closed does nothing.
+    /// The database is told even if flushing fails, in which case the error is returned. Pages are closed automatically when they're dropped.
+    pub fn close(&mut self) -> Result<(), Error> {
+        if self.closed { return Ok(()); }
+        self.closed = true;
+
+        let flushed = self.flush();
+
+        // The database may already be gone, in which case there's nobody to tell
+        let _ = self.requests.send(PageRequest::Close);
+        self.token = None;
+
+        flushed
     }
 }
 
-impl<Backing> Drop for Page<Backing> where Backing: Read + Write + Seek + 'static  {
-    fn drop(&mut self) {
-        self.close();
+/// Generates a pair of methods reading and writing a single integer at the page's cursor, in the given byte order.
+#[cfg(feature = "rwpage")]
+macro_rules! typed_io {
+    ($($int:ty: $read:ident / $write:ident => $from:ident / $to:ident),* $(,)?) => {$(
+        #[doc = concat!("Read a `", stringify!($int), "` at the cursor, advancing it past the value.")]
+        #[inline]
+        pub fn $read(&mut self) -> Result<$int, Error> {
+            let mut buf = [0u8; std::mem::size_of::<$int>()];
+            self.read_exact(&mut buf)?;
+            Ok(<$int>::$from(buf))
+        }
+
+        #[doc = concat!("Write a `", stringify!($int), "` at the cursor, advancing it past the value.")]
+        #[inline]
+        pub fn $write(&mut self, value: $int) -> Result<(), Error> {
+            Ok(self.write_all(&value.$to())?)
+        }
+    )*};
+}
+
+/// Typed reads and writes of integers, for pages holding binary protocol data or index structures, and delimited reads for pages holding text or logs.
+#[cfg(feature = "rwpage")]
+impl<Backing> Page<Backing> where Backing: Read + Write + Seek + 'static {
+    typed_io! {
+        u8: read_u8 / write_u8 => from_le_bytes / to_le_bytes,
+        u16: read_u16_le / write_u16_le => from_le_bytes / to_le_bytes,
+        u32: read_u32_le / write_u32_le => from_le_bytes / to_le_bytes,
+        u64: read_u64_le / write_u64_le => from_le_bytes / to_le_bytes,
+        u16: read_u16_be / write_u16_be => from_be_bytes / to_be_bytes,
+        u32: read_u32_be / write_u32_be => from_be_bytes / to_be_bytes,
+        u64: read_u64_be / write_u64_be => from_be_bytes / to_be_bytes,
+    }
+
+    /// Read from the cursor until `delimiter` or the end of the page is reached, appending the bytes read (including the delimiter) to `buf`. Returns the number of bytes read.
+    /// Bytes are read one at a time through the page's cursor, so delimiters are found regardless of how the content is split into chunks.
+    pub fn read_until(&mut self, delimiter: u8, buf: &mut Vec<u8>) -> Result<usize, Error> {
+        let mut byte = [0u8; 1];
+        let mut read = 0;
+
+        loop {
+            match self.read(&mut byte) {
+                Ok(0) => break,
+                Ok(_) => {
+                    read += 1;
+                    buf.push(byte[0]);
+
+                    if byte[0] == delimiter { break; }
+                },
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(read)
+    }
+
+    /// Read a line from the cursor, appending it (including the `\n`, if found) to `buf`. Fails without touching `buf` if the line isn't valid UTF-8.
+    pub fn read_line(&mut self, buf: &mut String) -> Result<usize, Error> {
+        let mut line = vec![];
+        let read = self.read_until(b'\n', &mut line)?;
+
+        buf.push_str(&String::from_utf8(line)
+            .map_err(Error::other)?);
+
+        Ok(read)
+    }
+}
+
+/// A page opened with `Database::open_read_write_locked`. No other instance of the page can be opened while it's alive, giving it exclusive access to all of the page's chunks at once.
+/// The exclusivity is released when it's dropped. Otherwise, it behaves exactly like the `Page` it wraps.
+pub struct ExclusivePage<Backing> where Backing: Read + Write + Seek + 'static {
+    page: Page<Backing>,
+    /// Exclusive range locks over the chunks the page had when it was opened, admitting access on behalf of the page only. Released after the page is closed.
+    _locks: Vec<RangeLockGuard>,
+}
+
+impl<Backing> ExclusivePage<Backing> where Backing: Read + Write + Seek + 'static {
+    pub(crate) fn new(page: Page<Backing>, locks: Vec<RangeLockGuard>) -> Self {
+        Self { page, _locks: locks }
     }
 }
 
-impl<Backing> AsRef<[u8]> for Page<Backing> where Backing: Read + Write + Seek + 'static  {
-    fn as_ref(&self) -> &[u8] {
-        // self.large_buffer.borrow()
-        todo!()
+impl<Backing> Deref for ExclusivePage<Backing> where Backing: Read + Write + Seek + 'static {
+    type Target = Page<Backing>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.page
+    }
+}
+
+impl<Backing> DerefMut for ExclusivePage<Backing> where Backing: Read + Write + Seek + 'static {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.page
+    }
+}
+
+#[cfg(feature = "rwpage")]
+impl<Backing> Read for ExclusivePage<Backing> where Backing: Read + Write + Seek + 'static {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.page.read(buf)
+    }
+}
+
+#[cfg(feature = "rwpage")]
+impl<Backing> Write for ExclusivePage<Backing> where Backing: Read + Write + Seek + 'static {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.page.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Write::flush(&mut self.page)
+    }
+}
+
+#[cfg(feature = "rwpage")]
+impl<Backing> Seek for ExclusivePage<Backing> where Backing: Read + Write + Seek + 'static {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.page.seek(pos)
     }
 }
 
-impl<Backing> AsMut<[u8]> for Page<Backing> where Backing: Read + Write + Seek + 'static  {
-    fn as_mut(&mut self) -> &mut [u8] {
-        // self.large_buffer.borrow_mut()
-        todo!()
+impl<Backing> Drop for Page<Backing> where Backing: Read + Write + Seek + 'static  {
+    fn drop(&mut self) {
+        let closed = self.close();
+
+        #[cfg(feature = "tracing")]
+        if let Err(err) = closed {
+            tracing::warn!("Failed to flush '{}' while closing it: {}", self.descriptor.name, err);
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = closed;
     }
 }
 
 #[cfg(feature = "rwpage")]
 impl<Backing> Read for Page<Backing> where Backing: Read + Write + Seek + 'static  {
+    /// Read from the cursor, continuing across chunk boundaries. Like a file, fewer bytes than requested are read if the end of the page's content is reached, and none once the cursor is past it.
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        todo!()
+        let len = (buf.len() as u64).min(self.content_length().saturating_sub(self.cursor_pos));
+
+        if let Some(content) = self.buffered.as_ref() {
+            let start = self.cursor_pos as usize;
+            buf[..len as usize].copy_from_slice(&content[start..start + len as usize]);
+
+            self.cursor_pos += len;
+            return Ok(len as usize);
+        }
+
+        let mut read = 0;
+        for region in self.physical_regions(self.cursor_pos, len) {
+            self.mediator.try_read_range(&mut buf[read..read + region.length as usize], region.offset, self.lock_owner())?;
+            read += region.length as usize;
+        }
+
+        self.cursor_pos += read as u64;
+        Ok(read)
     }
 }
 
 #[cfg(feature = "rwpage")]
 impl<Backing> Write for Page<Backing> where Backing: Read + Write + Seek + 'static  {
+    /// Write at the cursor, overwriting the chunks the range falls within and allocating a new chunk for whatever extends past the end of the page. The whole buffer is always written.
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        todo!()
+        self.write_sparse(self.cursor_pos, buf)?;
+        self.cursor_pos += buf.len() as u64;
+
+        Ok(buf.len())
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
-        todo!()
+        Ok(Page::flush(self)?)
     }
 }
 
 #[cfg(feature = "rwpage")]
 impl<Backing> Seek for Page<Backing> where Backing: Read + Write + Seek + 'static  {
+    /// Move the cursor. The end of the page is the end of its content rather than of its chunks.
+    /// The cursor is a position within the content, which is only mapped onto chunks when reading or writing. Seeking past the end is therefore allowed, and doesn't allocate anything until the next write.
     fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
-        todo!()
+        let (base, delta) = match pos {
+            std::io::SeekFrom::Start(offset) => (offset, 0),
+            std::io::SeekFrom::Current(delta) => (self.cursor_pos, delta),
+            std::io::SeekFrom::End(delta) => (self.content_length(), delta),
+        };
+
+        self.cursor_pos = base.checked_add_signed(delta)
+            .ok_or(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Seek to a negative or overflowing position"))?;
+
+        Ok(self.cursor_pos)
     }
 }
@@ -0,0 +1,455 @@
+//! Round-trips databases through their binary format: everything written must parse back identically.
+//! Every test creates its own database in a temporary file or in memory, so tests are independent of each other and of the files in `test/`.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Result;
+use std::io::Seek;
+use std::io::Write;
+
+use proptest::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use tempfile::NamedTempFile;
+
+use datastore_provider::access::Access;
+use datastore_provider::format::database::ConflictPolicy;
+use datastore_provider::format::database::Database;
+use datastore_provider::format::database::IntegrityError;
+use datastore_provider::format::schema::PageSchema;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Metadata {
+    friendly_name: String,
+    max_page_size: u64,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            friendly_name: "roundtrip".to_owned(),
+            max_page_size: 0x1_000_000,
+        }
+    }
+}
+
+/// Create a fresh database in a temporary file, which is deleted once dropped.
+fn create() -> Result<(NamedTempFile, Database<File, Metadata>)> {
+    let file = NamedTempFile::new()?;
+    let db = Database::create(file.reopen()?, Metadata::default())?;
+
+    Ok((file, db))
+}
+
+/// Parse the database stored in `file` afresh.
+fn reopen(file: &NamedTempFile) -> Result<Database<File, Metadata>> {
+    Database::open(file.reopen()?)
+}
+
+fn read<Backing: Read + Write + Seek>(db: &Database<Backing, Metadata>, name: &str) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    db.pipe_page(name, &mut out)?;
+    Ok(out)
+}
+
+/// The schema of every page.
+fn schema<Backing: Read + Write + Seek>(db: &Database<Backing, Metadata>) -> Result<Vec<PageSchema>> {
+    ron::de::from_str::<Vec<PageSchema>>(&db.export_schema()?)
+        .map_err(std::io::Error::other)
+}
+
+fn pages() -> impl Strategy<Value = BTreeMap<String, Vec<u8>>> {
+    prop::collection::btree_map("[a-z0-9/._-]{1,24}", prop::collection::vec(any::<u8>(), 0..0x800), 0..16)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn pages_survive_reopening(pages in pages()) {
+        let (file, mut db) = create()?;
+        for (name, content) in pages.iter() {
+            db.create_or_replace_page(name, content)?;
+        }
+        drop(db);
+
+        let db = reopen(&file)?;
+        prop_assert_eq!(db.list_pages_with_prefix("").len(), pages.len());
+        for (name, content) in pages.iter() {
+            prop_assert_eq!(&read(&db, name)?, content);
+        }
+        db.verify_format()?;
+    }
+
+    #[test]
+    fn pages_survive_copying_into_memory(pages in pages()) {
+        let mut db = Database::create(Cursor::new(vec![]), Metadata::default())?;
+        for (name, content) in pages.iter() {
+            db.create_or_replace_page(name, content)?;
+        }
+
+        let copy = db.clone_to_memory()?;
+        prop_assert_eq!(copy.list_pages_with_prefix("").len(), pages.len());
+        for (name, content) in pages.iter() {
+            prop_assert_eq!(&read(&copy, name)?, content);
+        }
+        prop_assert_eq!(&copy.meta, &db.meta);
+    }
+}
+
+#[test]
+fn metadata_survives_reopening() -> Result<()> {
+    let (file, mut db) = create()?;
+    let meta = Metadata { friendly_name: "x".repeat(0x200), max_page_size: 42 };
+    db.set_metadata(meta.clone())?;
+    drop(db);
+
+    assert_eq!(reopen(&file)?.meta, meta);
+
+    Ok(())
+}
+
+#[test]
+fn blank_database_survives_reopening() -> Result<()> {
+    let file = NamedTempFile::new()?;
+    let blank = datastore_provider::format::blank::<Metadata>()?;
+    drop(blank.change_buffer(file.reopen()?)?);
+
+    let db = reopen(&file)?;
+    assert_eq!(db.list_pages_with_prefix(""), vec!["/"]);
+    assert_eq!(schema(&db)?[0].access_control_list, vec![Access::ReadWriteExecute("*".to_owned())]);
+    assert_eq!(db.meta, Metadata::default());
+
+    Ok(())
+}
+
+#[test]
+fn page_names_survive_reopening() -> Result<()> {
+    let (file, mut db) = create()?;
+
+    // Enough strings that indices no longer fit in a byte, and names long enough to need both bytes of their length
+    let mut names = (0..300)
+        .map(|i| format!("/pages/{}/{}", i, "x".repeat(i * 3)))
+        .collect::<Vec<_>>();
+    for name in names.iter() {
+        db.create_page(name)?;
+    }
+
+    let owned = PageSchema {
+        name: "owned".to_owned(),
+        access_control_list: vec![Access::ReadWrite("the-last-string".to_owned())],
+        chunks: 0,
+        size: 0,
+        created: 0,
+        modified: 0,
+        user_data: vec![],
+    };
+    db.apply_schema(&ron::ser::to_string(&vec![owned.clone()]).map_err(std::io::Error::other)?)?;
+    names.push(owned.name.clone());
+    drop(db);
+
+    let db = reopen(&file)?;
+    names.sort_unstable();
+    assert_eq!(db.list_pages_with_prefix(""), names);
+    // The page is timestamped when it's created, not with the schema's timestamps
+    assert!(schema(&db)?.into_iter().any(|page| PageSchema { created: 0, modified: 0, ..page } == owned));
+
+    Ok(())
+}
+
+#[test]
+fn create_read_write_delete() -> Result<()> {
+    let (file, mut db) = create()?;
+
+    db.create_or_replace_page("a", b"Hello")?;
+    db.create_or_replace_page("b", b"World")?;
+    assert_eq!(read(&db, "a")?, b"Hello");
+
+    db.create_or_replace_page("a", b"Goodbye")?;
+    db.delete_page("b")?;
+    assert_eq!(read(&db, "a")?, b"Goodbye");
+    assert!(read(&db, "b").is_err());
+    drop(db);
+
+    let mut db = reopen(&file)?;
+    assert_eq!(db.list_pages_with_prefix(""), vec!["a"]);
+    assert_eq!(read(&db, "a")?, b"Goodbye");
+
+    db.delete_page("a")?;
+    drop(db);
+
+    assert!(reopen(&file)?.list_pages_with_prefix("").is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn history_survives_reopening() -> Result<()> {
+    let (file, mut db) = create()?;
+
+    db.create_or_replace_page("a", b"Hello")?;
+    db.create_or_replace_page("a", b"World")?;
+    db.rename_page("a", "b")?;
+    let expected = (db.get_history("a")?, db.get_history("b")?);
+    drop(db);
+
+    let db = reopen(&file)?;
+    assert_eq!((db.get_history("a")?, db.get_history("b")?), expected);
+    db.verify_format()?;
+
+    Ok(())
+}
+
+#[test]
+fn write_ahead_log_is_replayed_on_open() -> Result<()> {
+    let (file, mut db) = create()?;
+    let wal = datastore_provider::format::wal::wal_path(file.path());
+
+    db.create_or_replace_page("a", b"Hello")?;
+    db.enable_wal(&wal)?;
+    drop(db);
+
+    // A write which was logged, but never reached the database file
+    let mut log = std::fs::OpenOptions::new().append(true).open(&wal)?;
+    datastore_provider::format::wal::WalRecord { op: datastore_provider::format::wal::WalOp::Write, page_name: "a".to_owned(), offset: 0, data: b"J".to_vec() }.write(&mut log)?;
+    drop(log);
+
+    let db = Database::<File, Metadata>::open_many(&[file.path()])?.remove(0);
+    assert_eq!(read(&db, "a")?, b"Jello");
+    assert_eq!(std::fs::metadata(&wal)?.len(), 0);
+
+    std::fs::remove_file(&wal)?;
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_can_be_opened() -> Result<()> {
+    let (_file, mut db) = create()?;
+
+    db.create_or_replace_page("a", &[1u8; 0x2000])?;
+    db.create_or_replace_page("b", b"Hello")?;
+    db.delete_page("a")?;
+
+    let mut snapshot = Cursor::new(vec![]);
+    db.snapshot(&mut snapshot)?;
+
+    let copy = Database::<_, Metadata>::open(Cursor::new(snapshot.into_inner()))?;
+    assert_eq!(copy.list_pages_with_prefix(""), vec!["b"]);
+    assert_eq!(read(&copy, "b")?, b"Hello");
+    copy.verify_format()?;
+
+    Ok(())
+}
+
+#[test]
+fn access_control_lists_survive_reopening() -> Result<()> {
+    let (file, mut db) = create()?;
+
+    let acl = [
+        Access::None("guest".to_owned()),
+        Access::Read("*".to_owned()),
+        Access::ReadWrite("editor".to_owned()),
+        Access::ReadWriteExecute("admin".to_owned()),
+        Access::ReadExecute("runner".to_owned()),
+        Access::Custom("auditor".to_owned(), 0b100),
+    ];
+    let pages = (0..acl.len())
+        .map(|i| PageSchema {
+            name: format!("page-{}", i),
+            access_control_list: acl[..=i].to_vec(),
+            chunks: 0,
+            size: 0,
+            created: 0,
+            modified: 0,
+            user_data: vec![],
+        })
+        .collect::<Vec<_>>();
+
+    db.apply_schema(&ron::ser::to_string(&pages).map_err(std::io::Error::other)?)?;
+    let expected = schema(&db)?;
+    drop(db);
+
+    // Decoding and re-encoding must be lossless, no matter how often it's done
+    let mut db = reopen(&file)?;
+    assert_eq!(schema(&db)?, expected);
+    db.write_header()?;
+    drop(db);
+
+    assert_eq!(schema(&reopen(&file)?)?, expected);
+
+    Ok(())
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn string_table_is_deduplicated() -> Result<()> {
+    let (file, mut db) = create()?;
+
+    let pages = ["a", "b", "c"]
+        .into_iter()
+        .map(|name| PageSchema {
+            name: name.to_owned(),
+            access_control_list: vec![Access::Read("*".to_owned()), Access::ReadWrite(name.to_owned())],
+            chunks: 0,
+            size: 0,
+            created: 0,
+            modified: 0,
+            user_data: vec![],
+        })
+        .collect::<Vec<_>>();
+    db.apply_schema(&ron::ser::to_string(&pages).map_err(std::io::Error::other)?)?;
+    drop(db);
+
+    let db = reopen(&file)?;
+    let mut strings = serde_json::from_str::<Vec<String>>(&db.export_string_table_as_json()?)
+        .map_err(std::io::Error::other)?;
+    strings.sort();
+    assert_eq!(strings, vec!["*", "a", "b", "c"]);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "encryption")]
+fn encrypted_database_requires_its_key() -> Result<()> {
+    let file = NamedTempFile::new()?;
+    let mut db = Database::create_encrypted(file.reopen()?, [7u8; 32], Metadata::default())?;
+    db.create_or_replace_page("a", b"Hello, World")?;
+    drop(db);
+
+    let err = Database::<_, Metadata>::open_encrypted(file.reopen()?, [8u8; 32]).err().unwrap();
+    assert!(matches!(err.get_ref().and_then(|i| i.downcast_ref()), Some(datastore_provider::error::Error::Corrupted)));
+
+    let db = Database::<_, Metadata>::open_encrypted(file.reopen()?, [7u8; 32])?;
+    assert_eq!(read(&db, "a")?, b"Hello, World");
+
+    Ok(())
+}
+
+#[test]
+fn inode_table_entries_are_checksummed() -> Result<()> {
+    let (file, mut db) = create()?;
+    db.create_or_replace_page("a", b"Hello")?;
+    db.create_or_replace_page("b", b"World")?;
+    assert_eq!(db.verify_integrity()?, vec![]);
+    drop(db);
+
+    // Damage the checksum recorded for "a", which is only stored in its inode table entry. Earlier copies of the table may linger where it used to be, so every copy is damaged.
+    let mut bytes = std::fs::read(file.path())?;
+    let checksum: [u8; 32] = sha2::Sha256::digest(b"Hello").into();
+    let copies = bytes.windows(32)
+        .enumerate()
+        .filter(|(_, window)| *window == checksum)
+        .map(|(at, _)| at)
+        .collect::<Vec<_>>();
+    assert!(!copies.is_empty(), "Checksum not found");
+    for at in copies {
+        bytes[at] ^= 0xFF;
+    }
+
+    let db = Database::<_, Metadata>::open(Cursor::new(bytes))?;
+    assert!(matches!(db.verify_integrity()?[..], [IntegrityError::InodeEntry { ref name, .. }] if name.as_deref() == Some("a")));
+
+    Ok(())
+}
+
+#[test]
+fn pages_survive_merging() -> Result<()> {
+    let mut source = Database::create(Cursor::new(vec![]), Metadata::default())?;
+    source.create_or_replace_page("a", b"Hello")?;
+    source.create_or_replace_page("b", b"World")?;
+    let mut bytes = vec![];
+    source.snapshot(Cursor::new(&mut bytes))?;
+
+    let (file, mut db) = create()?;
+    db.create_or_replace_page("b", b"Mine")?;
+
+    // Conflicting pages are reported rather than overwritten, but the rest are still merged
+    let err = db.merge_from::<Metadata, _>(Cursor::new(bytes.clone())).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(read(&db, "a")?, b"Hello");
+    assert_eq!(read(&db, "b")?, b"Mine");
+    drop(db);
+
+    let mut db = reopen(&file)?;
+    assert_eq!(read(&db, "a")?, b"Hello");
+    db.delete_page("a")?;
+    db.delete_page("b")?;
+    assert_eq!(db.merge_from::<Metadata, _>(Cursor::new(bytes))?, 2);
+    assert_eq!(read(&db, "b")?, b"World");
+    db.verify_format()?;
+
+    Ok(())
+}
+
+#[test]
+fn newer_pages_win_merges_after_reopening() -> Result<()> {
+    let (file, mut db) = create()?;
+    db.create_or_replace_page("a", b"Older")?;
+    drop(db);
+
+    let mut other = Database::create(Cursor::new(vec![]), Metadata::default())?;
+    other.create_or_replace_page("a", b"Newer")?;
+
+    // Reopening keeps the page's modification time rather than making it look freshly written
+    let mut db = reopen(&file)?;
+    db.merge(&other, ConflictPolicy::KeepNewer)?;
+    assert_eq!(read(&db, "a")?, b"Newer");
+
+    Ok(())
+}
+
+#[test]
+fn pages_stream_across_chunks() -> Result<()> {
+    let mut db = Database::create(Cursor::new(vec![]), Metadata::default())?;
+    let mut page = db.create_page("a")?;
+    page.extend_from_slice(&[0x01; 0x300]).map_err(std::io::Error::other)?;
+    page.extend_from_slice(&[0x02; 0x280]).map_err(std::io::Error::other)?;
+    page.prepend(&[0x03; 0x10]).map_err(std::io::Error::other)?;
+
+    let pieces = page.read_stream_chunked(0x100).collect::<Vec<_>>();
+    assert!(pieces.iter().all(|piece| !piece.is_empty() && piece.len() <= 0x100));
+    assert_eq!(pieces.concat(), [vec![0x03; 0x10], vec![0x01; 0x300], vec![0x02; 0x280]].concat());
+
+    // Pieces end at chunk boundaries
+    assert_eq!(pieces.iter().map(|piece| piece.len()).collect::<Vec<_>>(), vec![0x10, 0x100, 0x100, 0x100, 0x100, 0x100, 0x80]);
+
+    let mut exported = vec![];
+    page.export(&mut exported).map_err(std::io::Error::other)?;
+    assert_eq!(pieces.concat(), exported);
+
+    Ok(())
+}
+
+#[test]
+fn free_list_survives_reopening() -> Result<()> {
+    let (file, mut db) = create()?;
+    for (name, len) in [("a", 0x800), ("b", 0x400), ("c", 0x100)] {
+        db.create_or_replace_page(name, &vec![0xAA; len])?;
+    }
+    db.delete_page("a")?;
+    db.delete_page("b")?;
+    let len = db.free_list_len();
+    assert!(len > 0);
+    drop(db);
+
+    let mut db = reopen(&file)?;
+    assert_eq!(db.free_list_len(), len);
+
+    // Free chunks are reused before the file grows
+    let free = db.get_free_regions();
+    let size = file.as_file().metadata()?.len();
+    db.create_or_replace_page("d", &[0xBB; 0x400])?;
+    let chunk = db.get_history("d")?[0].chunks_snapshot[0];
+    assert!(free.iter().any(|region| region.intersect(&chunk) == Some(chunk)));
+    assert_eq!(file.as_file().metadata()?.len(), size);
+    assert_eq!(read(&db, "c")?, vec![0xAA; 0x100]);
+    assert_eq!(read(&db, "d")?, vec![0xBB; 0x400]);
+    db.verify_format()?;
+
+    Ok(())
+}